@@ -4,7 +4,8 @@
 //! application for running Saya.
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use saya_config::{ConfigArgs, ConfigCommand};
 
 mod core_contract;
 use core_contract::CoreContract;
@@ -15,6 +16,8 @@ use celestia::Celestia;
 #[derive(Debug, Parser)]
 #[clap(about, version)]
 struct Cli {
+    #[clap(flatten)]
+    config: ConfigArgs,
     #[clap(subcommand)]
     command: Subcommands,
 }
@@ -26,15 +29,21 @@ enum Subcommands {
     CoreContract(CoreContract),
     /// Celestia utilities for namespace conversion and blob retrieval.
     Celestia(Celestia),
+    /// Inspect and validate configuration files.
+    Config(ConfigCommand),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    // Options from the configuration file are the defaults of their flags, so they have to be
+    // loaded before parsing the command line.
+    let matches = saya_config::init(Cli::command())?.get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
 
     saya_tracing::init("info,saya=trace,saya_core=trace,rpc_client=info")?;
     match cli.command {
         Subcommands::CoreContract(cmd) => cmd.run().await,
         Subcommands::Celestia(cmd) => cmd.run().await,
+        Subcommands::Config(cmd) => Ok(cmd.run(&cli.config, &Cli::command())?),
     }
 }
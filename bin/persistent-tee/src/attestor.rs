@@ -6,16 +6,17 @@
 use std::time::Duration;
 
 use anyhow::Result;
-use katana_tee_client::KatanaRpcClient;
+use katana_tee_client::TeeQuoteResponse;
+use starknet::providers::jsonrpc::JsonRpcResponse;
 use tokio::sync::mpsc::{Receiver, Sender};
 use tracing::{debug, error, info};
-use url::Url;
 
 #[allow(unused_imports)]
 use saya_core::prover::HasBlockNumber;
 use saya_core::{
     block_ingestor::BlockInfo,
     prover::{PipelineStage, PipelineStageBuilder},
+    rpc::FailoverTransport,
     service::{Daemon, FinishHandle, ShutdownHandle},
     tee::{L1ToL2Message, L2ToL1Message, TeeAttestation},
 };
@@ -24,7 +25,7 @@ use saya_core::{
 /// [`BlockInfo`].
 #[derive(Debug)]
 pub struct TeeAttestor {
-    katana_rpc: Url,
+    katana_rpc: FailoverTransport,
     _poll_interval: Duration,
     input_channel: Receiver<Vec<BlockInfo>>,
     output_channel: Sender<TeeAttestation>,
@@ -33,14 +34,14 @@ pub struct TeeAttestor {
 
 #[derive(Debug)]
 pub struct TeeAttestorBuilder {
-    katana_rpc: Url,
+    katana_rpc: FailoverTransport,
     poll_interval: Duration,
     input_channel: Option<Receiver<Vec<BlockInfo>>>,
     output_channel: Option<Sender<TeeAttestation>>,
 }
 
 impl TeeAttestorBuilder {
-    pub fn new(katana_rpc: Url, poll_interval: Duration) -> Self {
+    pub fn new(katana_rpc: FailoverTransport, poll_interval: Duration) -> Self {
         Self {
             katana_rpc,
             poll_interval,
@@ -89,7 +90,7 @@ impl Daemon for TeeAttestor {
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
 
@@ -129,7 +130,6 @@ impl TeeAttestor {
     }
 
     async fn fetch_attestation(&self, blocks: Vec<BlockInfo>) -> Result<TeeAttestation> {
-        let rpc_client = KatanaRpcClient::new(self.katana_rpc.clone());
         let block_number = blocks.last().expect("non-empty batch").number;
         let prev_block_number = blocks.first().expect("non-empty batch").number;
         let prev_block = if prev_block_number == 0 {
@@ -137,9 +137,20 @@ impl TeeAttestor {
         } else {
             Some(prev_block_number.saturating_sub(1))
         };
-        let attestation = rpc_client
-            .fetch_attestation(prev_block, block_number)
-            .await?;
+        // Katana's TEE methods aren't part of the Starknet specification, so they're sent as custom
+        // requests through the shared transport.
+        let attestation: TeeQuoteResponse = match self
+            .katana_rpc
+            .send_custom_request("tee_generateQuote", (prev_block, block_number))
+            .await?
+        {
+            JsonRpcResponse::Success { result, .. } => result,
+            JsonRpcResponse::Error { error, .. } => anyhow::bail!(
+                "Katana failed to generate a quote: JSON-RPC error {}: {}",
+                error.code,
+                error.message
+            ),
+        };
         let l2_to_l1_messages = attestation
             .l2_to_l1_messages
             .into_iter()
@@ -4,7 +4,8 @@
 //! attestation, SP1 proof generation, and on-chain settlement.
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use saya_config::{ConfigArgs, ConfigCommand};
 
 mod attestor;
 mod common;
@@ -19,6 +20,8 @@ use tee::Tee;
 #[derive(Debug, Parser)]
 #[clap(about, version)]
 struct Cli {
+    #[clap(flatten)]
+    config: ConfigArgs,
     #[clap(subcommand)]
     command: Subcommands,
 }
@@ -29,15 +32,21 @@ enum Subcommands {
     /// Run Saya in TEE mode where blocks are proved inside a trusted execution
     /// environment and settled on-chain.
     Tee(Tee),
+    /// Inspect and validate configuration files.
+    Config(ConfigCommand),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    // Options from the configuration file are the defaults of their flags, so they have to be
+    // loaded before parsing the command line.
+    let matches = saya_config::init(Cli::command())?.get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
 
     saya_tracing::init("info,persistent_tee=trace,saya_core=trace")?;
 
     match cli.command {
         Subcommands::Tee(cmd) => cmd.run().await,
+        Subcommands::Config(cmd) => Ok(cmd.run(&cli.config, &Cli::command())?),
     }
 }
@@ -16,6 +16,7 @@ use katana_tee_client::ProverConfig;
 use katana_tee_client::TeeQuoteResponse;
 use saya_core::{
     prover::{HasBlockNumber, PipelineStage, PipelineStageBuilder, TeeProof},
+    rpc::FailoverTransport,
     service::{Daemon, FinishHandle, ShutdownHandle},
     tee::TeeAttestation,
 };
@@ -26,7 +27,7 @@ use tracing::{debug, error, info};
 /// Submits a [`TeeAttestation`] to the TEE proving service and emits the resulting [`TeeProof`].
 #[derive(Debug)]
 pub struct TeeProver {
-    provider_url: String,
+    settlement_rpc: FailoverTransport,
     registry_address: Felt,
     private_key: String,
     /// When `true`, skip the real KDS/cert/SP1 pipeline and synthesize a stub
@@ -39,7 +40,7 @@ pub struct TeeProver {
 
 #[derive(Debug)]
 pub struct TeeProverBuilder {
-    provider_url: String,
+    settlement_rpc: FailoverTransport,
     registry_address: Felt,
     private_key: String,
     mock_prove: bool,
@@ -49,13 +50,13 @@ pub struct TeeProverBuilder {
 
 impl TeeProverBuilder {
     pub fn new(
-        provider_url: String,
+        settlement_rpc: FailoverTransport,
         registry_address: Felt,
         private_key: String,
         mock_prove: bool,
     ) -> Self {
         Self {
-            provider_url,
+            settlement_rpc,
             registry_address,
             private_key,
             mock_prove,
@@ -70,7 +71,7 @@ impl PipelineStageBuilder for TeeProverBuilder {
 
     fn build(self) -> Result<Self::Stage> {
         Ok(TeeProver {
-            provider_url: self.provider_url,
+            settlement_rpc: self.settlement_rpc,
             registry_address: self.registry_address,
             private_key: self.private_key,
             mock_prove: self.mock_prove,
@@ -106,7 +107,7 @@ impl Daemon for TeeProver {
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
 
@@ -193,7 +194,7 @@ impl TeeProver {
                 skip_time_validity_check: false,
             };
             let proof = tee
-                .generate_proof(&self.provider_url, self.registry_address, config)
+                .generate_proof(&self.settlement_rpc, self.registry_address, config)
                 .await?;
             let proof_raw = proof.encode_json()?;
             info!(
@@ -4,11 +4,12 @@
 //! to generate verifiable proofs of TEE execution.
 //!
 //! We run KDS cert fetch in a plain OS thread (no tokio) so that reqwest::blocking
-//! inside KDS does not create a nested runtime; then we create a tokio runtime
-//! only for registry lookup and proof generation.
+//! inside KDS does not create a nested runtime; the registry lookup then goes through
+//! the shared settlement RPC transport before proof generation.
 
 use anyhow::Result;
 use katana_tee_client::{OnchainProof, ProverConfig, StarknetRegistryClient};
+use saya_core::rpc::FailoverTransport;
 use starknet_types_core::felt::Felt;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tracing::{debug, info};
@@ -64,21 +65,23 @@ impl TeeAttestation {
     /// Generate SP1 Groth16 proof for this attestation.
     pub async fn generate_proof(
         &self,
-        provider_url: &str,
+        settlement_rpc: &FailoverTransport,
         registry_address: Felt,
         prover_config: ProverConfig,
     ) -> Result<OnchainProof, AttestationError> {
-        self.generate_proof_with_storage(provider_url, registry_address, prover_config)
+        self.generate_proof_with_storage(settlement_rpc, registry_address, prover_config)
             .await
     }
 
     /// Generate SP1 Groth16 proof for this attestation, optionally including storage/event proofs.
     ///
-    /// Architecture: we spawn a dedicated OS thread because `reqwest::blocking` inside KDS
-    /// panics if called from within a tokio runtime.
+    /// Architecture: we spawn a dedicated OS thread for the KDS cert fetch because
+    /// `reqwest::blocking` inside KDS panics if called from within a tokio runtime. The registry
+    /// client can only be built from a URL, so it is run against each settlement endpoint in turn
+    /// to keep the failover, rate limit and timeout of the shared transport.
     pub async fn generate_proof_with_storage(
         &self,
-        provider_url: &str,
+        settlement_rpc: &FailoverTransport,
         registry_address: Felt,
         prover_config: ProverConfig,
     ) -> Result<OnchainProof, AttestationError> {
@@ -89,10 +92,9 @@ impl TeeAttestation {
         );
 
         let quote_bytes = self.quote_bytes.clone();
-        let provider_url = provider_url.to_string();
 
-        let handle = std::thread::spawn(move || -> Result<OnchainProof> {
-            // Phase 1: KDS cert fetch — must run outside tokio.
+        // Phase 1: KDS cert fetch — must run outside tokio.
+        let handle = std::thread::spawn(move || -> Result<(u8, CertChain, u64)> {
             let report = AttestationReportBytes::new(&quote_bytes)
                 .map_err(|e| anyhow::anyhow!("Invalid attestation report: {e}"))?;
             let report_struct = AttestationReport::from_bytes(report.as_bytes())
@@ -117,72 +119,83 @@ impl TeeAttestation {
                 .map(|d| d.as_secs())
                 .map_err(|e| anyhow::anyhow!("System time error: {e}"))?;
 
-            // Phase 2: Registry lookup + proof generation (needs async).
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .map_err(|e| anyhow::anyhow!("Failed to create tokio runtime: {e}"))?;
-
-            rt.block_on(async {
-                let registry_client = StarknetRegistryClient::new(&provider_url, registry_address);
-                let trusted_prefix_len = registry_client
-                    .fetch_trusted_prefix_len(processor_model_u8, cert_chain.digest())
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Registry fetch failed: {e}"))?;
-
-                if !prover_config.skip_time_validity_check {
-                    cert_chain
-                        .check_valid(timestamp)
-                        .map_err(|e| anyhow::anyhow!("Cert chain time validation failed: {e}"))?;
-                }
-
-                let report_bytes = quote_bytes.clone();
-                let vek_der_chain = cert_chain.to_ders();
-                let sp1_config = SP1ProverConfig {
-                    private_key: prover_config.private_key.clone(),
-                    rpc_url: prover_config.rpc_url.clone(),
-                    prover_mode: Some("network".to_string()),
-                };
-                let mut sdk_config = SdkProverConfig::sp1_with(sp1_config);
-                sdk_config.skip_time_validity_check = prover_config.skip_time_validity_check;
+            Ok((processor_model_u8, cert_chain, timestamp))
+        });
 
+        let report_bytes = self.quote_bytes.clone();
+        let proof = tokio::time::timeout(PROOF_GENERATION_TIMEOUT, async move {
+            let (processor_model_u8, cert_chain, timestamp) =
                 tokio::task::spawn_blocking(move || {
-                    let prover = AmdSevSnpProver::new(sdk_config, None);
-                    let input: amd_sev_snp_attestation_verifier::stub::VerifierInput =
-                        prepare_verifier_input_with_storage(
-                            timestamp,
-                            Bytes::from(report_bytes),
-                            vek_der_chain,
-                            trusted_prefix_len,
-                            None,
-                            None,
-                        );
-                    debug!("{:?} {}", input, "SP1 Groth16 prover input");
-                    let raw_proof = prover
-                        .verifier
-                        .gen_proof(&input, RawProofType::Groth16, None)
-                        .map_err(|e| anyhow::anyhow!("Proof generation failed: {e}"))?;
-                    prover
-                        .create_onchain_proof(raw_proof)
-                        .map_err(|e| anyhow::anyhow!("Onchain proof creation failed: {e}"))
+                    handle
+                        .join()
+                        .map_err(|_| AttestationError::ThreadPanicked)?
+                        .map_err(|e| AttestationError::ProofGenerationFailed(e.to_string()))
                 })
                 .await
-                .map_err(|e| anyhow::anyhow!("SP1 proof task panicked: {e}"))?
-            })
-        });
+                .map_err(|e| {
+                    AttestationError::Other(format!("spawn_blocking join failed: {e}"))
+                })??;
+
+            // Phase 2: Registry lookup + proof generation.
+            let trusted_prefix_len = settlement_rpc
+                .try_urls(|url| {
+                    let registry_client =
+                        StarknetRegistryClient::new(url.as_str(), registry_address);
+                    let digest = cert_chain.digest();
+                    async move {
+                        registry_client
+                            .fetch_trusted_prefix_len(processor_model_u8, digest)
+                            .await
+                    }
+                })
+                .await
+                .map_err(|e| {
+                    AttestationError::ProofGenerationFailed(format!("Registry fetch failed: {e}"))
+                })?;
+
+            if !prover_config.skip_time_validity_check {
+                cert_chain.check_valid(timestamp).map_err(|e| {
+                    AttestationError::ProofGenerationFailed(format!(
+                        "Cert chain time validation failed: {e}"
+                    ))
+                })?;
+            }
+
+            let vek_der_chain = cert_chain.to_ders();
+            let sp1_config = SP1ProverConfig {
+                private_key: prover_config.private_key.clone(),
+                rpc_url: prover_config.rpc_url.clone(),
+                prover_mode: Some("network".to_string()),
+            };
+            let mut sdk_config = SdkProverConfig::sp1_with(sp1_config);
+            sdk_config.skip_time_validity_check = prover_config.skip_time_validity_check;
 
-        let proof = tokio::time::timeout(
-            PROOF_GENERATION_TIMEOUT,
             tokio::task::spawn_blocking(move || {
-                handle
-                    .join()
-                    .map_err(|_| AttestationError::ThreadPanicked)?
-                    .map_err(|e| AttestationError::ProofGenerationFailed(e.to_string()))
-            }),
-        )
+                let prover = AmdSevSnpProver::new(sdk_config, None);
+                let input: amd_sev_snp_attestation_verifier::stub::VerifierInput =
+                    prepare_verifier_input_with_storage(
+                        timestamp,
+                        Bytes::from(report_bytes),
+                        vek_der_chain,
+                        trusted_prefix_len,
+                        None,
+                        None,
+                    );
+                debug!("{:?} {}", input, "SP1 Groth16 prover input");
+                let raw_proof = prover
+                    .verifier
+                    .gen_proof(&input, RawProofType::Groth16, None)
+                    .map_err(|e| anyhow::anyhow!("Proof generation failed: {e}"))?;
+                prover
+                    .create_onchain_proof(raw_proof)
+                    .map_err(|e| anyhow::anyhow!("Onchain proof creation failed: {e}"))
+            })
+            .await
+            .map_err(|e| AttestationError::Other(format!("SP1 proof task panicked: {e}")))?
+            .map_err(|e| AttestationError::ProofGenerationFailed(e.to_string()))
+        })
         .await
-        .map_err(|_| AttestationError::Timeout(PROOF_GENERATION_TIMEOUT))?
-        .map_err(|e| AttestationError::Other(format!("spawn_blocking join failed: {e}")))??;
+        .map_err(|_| AttestationError::Timeout(PROOF_GENERATION_TIMEOUT))??;
 
         info!("SP1 proof generated successfully");
         Ok(proof)
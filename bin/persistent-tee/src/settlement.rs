@@ -10,18 +10,20 @@ use starknet::{
     accounts::{Account, ExecutionEncoding, SingleOwnerAccount},
     core::types::{BlockId, BlockTag, Call, Felt, FunctionCall, TransactionReceipt},
     macros::selector,
-    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider},
+    providers::{JsonRpcClient, Provider},
     signers::{LocalWallet, SigningKey},
 };
 use starknet_types_core::hash::{Poseidon, StarkHash};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tracing::{debug, error, warn};
-use url::Url;
 
 use saya_core::{
+    metrics,
     prover::TeeProof,
+    rpc::FailoverTransport,
     service::{Daemon, FinishHandle, ShutdownHandle},
     settlement::{SettlementBackend, SettlementCursor, TeeSettlementBackendBuilder},
+    storage::BlockStatus,
     tee::{L1ToL2Message, L2ToL1Message},
 };
 
@@ -195,7 +197,7 @@ trait SettlementChain {
 
 /// Read the Piltover contract's current block number (`get_state()[1]`).
 async fn piltover_block_number(
-    provider: &Arc<JsonRpcClient<HttpTransport>>,
+    provider: &Arc<JsonRpcClient<FailoverTransport>>,
     piltover_address: Felt,
 ) -> Result<Felt> {
     let raw = provider
@@ -214,23 +216,23 @@ async fn piltover_block_number(
         .ok_or_else(|| anyhow::anyhow!("get_state returned fewer than 2 felts"))
 }
 
-/// Poll a tx until it is accepted (or reverted / errored).
-async fn watch_tx(provider: &Arc<JsonRpcClient<HttpTransport>>, tx_hash: Felt) -> Result<()> {
+/// Poll a tx until it is accepted (or reverted / errored), returning its receipt.
+async fn watch_tx(
+    provider: &Arc<JsonRpcClient<FailoverTransport>>,
+    tx_hash: Felt,
+) -> Result<TransactionReceipt> {
     loop {
         tokio::time::sleep(POLLING_INTERVAL).await;
         match provider.get_transaction_receipt(tx_hash).await {
-            Ok(receipt) => match receipt.receipt {
-                TransactionReceipt::Invoke(r) => {
+            Ok(receipt) => {
+                if let TransactionReceipt::Invoke(r) = &receipt.receipt {
                     use starknet::core::types::ExecutionResult;
-                    match r.execution_result {
-                        ExecutionResult::Succeeded => return Ok(()),
-                        ExecutionResult::Reverted { reason } => {
-                            return Err(anyhow::anyhow!("Transaction reverted: {reason}"))
-                        }
+                    if let ExecutionResult::Reverted { reason } = &r.execution_result {
+                        return Err(anyhow::anyhow!("Transaction reverted: {reason}"));
                     }
                 }
-                _ => return Ok(()),
-            },
+                return Ok(receipt.receipt);
+            }
             Err(starknet::providers::ProviderError::StarknetError(
                 starknet::core::types::StarknetError::TransactionHashNotFound,
             )) => continue,
@@ -250,8 +252,8 @@ async fn cooldown(finish: &FinishHandle, poll_interval: Duration) -> bool {
 
 /// The real chain: submits `update_state` to a Piltover contract on Starknet.
 struct PiltoverChain {
-    provider: Arc<JsonRpcClient<HttpTransport>>,
-    account: SingleOwnerAccount<Arc<JsonRpcClient<HttpTransport>>, LocalWallet>,
+    provider: Arc<JsonRpcClient<FailoverTransport>>,
+    account: SingleOwnerAccount<Arc<JsonRpcClient<FailoverTransport>>, LocalWallet>,
     piltover_address: Felt,
 }
 
@@ -269,7 +271,10 @@ impl SettlementChain for PiltoverChain {
         let execution = self.account.execute_v3(vec![call]);
         execution.estimate_fee().await?;
         let transaction = execution.send().await?;
-        watch_tx(&self.provider, transaction.transaction_hash).await?;
+        let receipt = watch_tx(&self.provider, transaction.transaction_hash).await?;
+        if let TransactionReceipt::Invoke(receipt) = &receipt {
+            metrics::record_settlement_fee("update_state", receipt.actual_fee.amount);
+        }
         Ok(transaction.transaction_hash)
     }
 }
@@ -301,6 +306,8 @@ async fn run_settlement<C: SettlementChain>(
             },
         };
 
+        let stage_timer = metrics::stage_timer("settlement");
+
         // Calldata is a pure function of the proof; a build failure means a malformed
         // proof and retrying can't help, so skip it. The settlement errors below are
         // the opposite — transient — so we RETRY the same proof and never drop it.
@@ -362,7 +369,11 @@ async fn run_settlement<C: SettlementChain>(
             }
 
             match chain.submit(&proof, calldata.clone()).await {
-                Ok(h) => break Some(h),
+                Ok(h) => {
+                    stage_timer.observe();
+                    metrics::record_block_status(&BlockStatus::Settled);
+                    break Some(h);
+                }
                 Err(e) => {
                     warn!(
                         "Settlement of block {} failed: {}; retrying",
@@ -400,8 +411,8 @@ async fn run_settlement<C: SettlementChain>(
 /// Settlement backend that submits TEE proofs to the Piltover contract via `update_state`.
 #[derive(Debug)]
 pub struct TeePiltoverSettlementBackend {
-    provider: Arc<JsonRpcClient<HttpTransport>>,
-    account: SingleOwnerAccount<Arc<JsonRpcClient<HttpTransport>>, LocalWallet>,
+    provider: Arc<JsonRpcClient<FailoverTransport>>,
+    account: SingleOwnerAccount<Arc<JsonRpcClient<FailoverTransport>>, LocalWallet>,
     piltover_address: Felt,
     /// When `true`, decode `TeeProof.data` as a raw felt buffer (mock journal)
     /// instead of `OnchainProof` JSON. Must be paired with the upstream
@@ -414,7 +425,7 @@ pub struct TeePiltoverSettlementBackend {
 
 #[derive(Debug)]
 pub struct TeePiltoverSettlementBackendBuilder {
-    rpc_url: Url,
+    rpc: FailoverTransport,
     piltover_address: Felt,
     account_address: Felt,
     account_private_key: Felt,
@@ -425,14 +436,14 @@ pub struct TeePiltoverSettlementBackendBuilder {
 
 impl TeePiltoverSettlementBackendBuilder {
     pub fn new(
-        rpc_url: Url,
+        rpc: FailoverTransport,
         piltover_address: Felt,
         account_address: Felt,
         account_private_key: Felt,
         mock_prove: bool,
     ) -> Self {
         Self {
-            rpc_url,
+            rpc,
             piltover_address,
             account_address,
             account_private_key,
@@ -447,7 +458,7 @@ impl TeeSettlementBackendBuilder for TeePiltoverSettlementBackendBuilder {
     type Backend = TeePiltoverSettlementBackend;
 
     async fn build(self) -> Result<Self::Backend> {
-        let provider = Arc::new(JsonRpcClient::new(HttpTransport::new(self.rpc_url)));
+        let provider = Arc::new(JsonRpcClient::new(self.rpc));
         let chain_id = provider.chain_id().await?;
 
         let mut account = SingleOwnerAccount::new(
@@ -528,7 +539,7 @@ impl Daemon for TeePiltoverSettlementBackend {
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
 
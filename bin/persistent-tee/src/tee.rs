@@ -4,9 +4,18 @@ use std::{path::PathBuf, time::Duration};
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
+use saya_config::{
+    AdminConfiguration, HealthConfiguration, RpcConfiguration, SupervisionConfiguration,
+};
 use saya_core::{
-    block_ingestor::BatchingPollingBlockIngestorBuilder, orchestrator::TeeOrchestratorBuilder,
-    service::Daemon, storage::SqliteDb,
+    block_ingestor::{
+        BatchingBlockIngestorBuilder, BatchingPollingBlockIngestorBuilder, FlushOnMessages,
+        MaxMessages, MaxStateDiffSize, MaxTransactions,
+    },
+    health::HealthReporter,
+    orchestrator::TeeOrchestratorBuilder,
+    service::{Daemon, PauseHandle, Supervisor},
+    storage::SqliteDb,
 };
 
 use crate::settlement::TeePiltoverSettlementBackendBuilder;
@@ -37,12 +46,14 @@ enum Subcommands {
 
 #[derive(Debug, Parser, Clone)]
 struct Start {
-    /// Rollup network Starknet JSON-RPC URL (v0.7.1)
-    #[clap(long, env)]
-    rollup_rpc: Url,
-    /// Settlement network Starknet JSON-RPC URL (v0.7.1)
-    #[clap(long, env)]
-    settlement_rpc: Url,
+    /// Rollup network Starknet JSON-RPC URLs (v0.7.1), comma-separated. Requests fail over to the
+    /// next URL when one doesn't answer
+    #[clap(long, env, value_delimiter = ',', required = true)]
+    rollup_rpc: Vec<Url>,
+    /// Settlement network Starknet JSON-RPC URLs (v0.7.1), comma-separated. Requests fail over to
+    /// the next URL when one doesn't answer
+    #[clap(long, env, value_delimiter = ',', required = true)]
+    settlement_rpc: Vec<Url>,
     /// Settlement network piltover contract address
     #[clap(long, env)]
     settlement_piltover_address: Felt,
@@ -67,6 +78,21 @@ struct Start {
     /// Flush a partial batch after this many seconds without a new block
     #[clap(long, env, default_value_t = 120)]
     idle_timeout_secs: u64,
+    /// Close a batch once its blocks hold this many transactions
+    #[clap(long, env)]
+    batch_max_transactions: Option<usize>,
+    /// Close a batch once its blocks hold this many L1 to L2 and L2 to L1 messages
+    #[clap(long, env)]
+    batch_max_messages: Option<usize>,
+    /// Close a batch once the state diffs of its blocks hold this many entries
+    #[clap(long, env)]
+    batch_max_state_diff_size: Option<usize>,
+    /// Close a batch as soon as a block consumes an L1 to L2 message or sends an L2 to L1 message
+    #[clap(long, env, default_value_t = false)]
+    batch_flush_on_messages: bool,
+    /// Last block to settle. Saya exits once this block has been settled, runs forever if not set
+    #[clap(long, env)]
+    end_block: Option<u64>,
     /// Path to the database directory
     #[clap(long, env)]
     db_dir: Option<PathBuf>,
@@ -83,6 +109,18 @@ struct Start {
     /// SEV-SNP hardware. Do not use in production.
     #[clap(long, env)]
     mock_prove: bool,
+    /// RPC client configuration
+    #[clap(flatten)]
+    rpc: RpcConfiguration,
+    /// Supervision configuration
+    #[clap(flatten)]
+    supervision: SupervisionConfiguration,
+    /// Health server configuration
+    #[clap(flatten)]
+    health: HealthConfiguration,
+    /// Admin server configuration
+    #[clap(flatten)]
+    admin: AdminConfiguration,
 }
 
 impl Tee {
@@ -95,71 +133,137 @@ impl Tee {
 
 impl Start {
     pub async fn run(self) -> Result<()> {
-        let saya_path = self
-            .db_dir
+        let restart_policy = self.supervision.restart_policy();
+        let health = self.health.start().await?;
+        let health_reporter = health.as_ref().map(|(reporter, _)| reporter.clone());
+        let admin = self.admin.clone();
+        let db_path = self.db_path();
+
+        // Shared across restarts so that a pause requested via the admin API outlives them.
+        let pause_handle = PauseHandle::new();
+        let orchestrator_pause_handle = pause_handle.clone();
+
+        // The orchestrator is rebuilt on every restart so that it resumes from the last block
+        // settled on the base layer.
+        let supervisor = Supervisor::new(restart_policy, move || {
+            self.clone()
+                .build_orchestrator(health_reporter.clone(), orchestrator_pause_handle.clone())
+        });
+        let supervisor_shutdown = supervisor.shutdown_handle();
+        let admin = admin
+            .start(&db_path, pause_handle, supervisor_shutdown.clone())
+            .await?;
+        supervisor.start();
+
+        let mut sigterm_handle =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        let ctrl_c_handle = tokio::signal::ctrl_c();
+
+        tokio::select! {
+            _ = sigterm_handle.recv() => {},
+            _ = ctrl_c_handle => {},
+            _ = supervisor_shutdown.finished() => {},
+        }
+
+        // Graceful shutdown
+        supervisor_shutdown.shutdown();
+        let result = tokio::select! {
+            _ = tokio::time::sleep(GRACEFUL_SHUTDOWN_TIMEOUT) => {
+                Err(anyhow::anyhow!("timeout waiting for graceful shutdown"))
+            },
+            _ = supervisor_shutdown.finished() => {
+                match supervisor_shutdown.failure() {
+                    Some(failure) => Err(anyhow::anyhow!("orchestrator failed: {}", failure)),
+                    None => Ok(()),
+                }
+            },
+        };
+
+        if let Some(admin_shutdown) = admin {
+            admin_shutdown.shutdown();
+            admin_shutdown.finished().await;
+        }
+        if let Some((_, health_shutdown)) = health {
+            health_shutdown.shutdown();
+            health_shutdown.finished().await;
+        }
+
+        result
+    }
+
+    fn db_path(&self) -> String {
+        self.db_dir
+            .as_ref()
             .map(|db_dir| format!("{}/{}", db_dir.display(), SAYA_DB_PATH))
-            .unwrap_or_else(|| SAYA_DB_PATH.to_string());
+            .unwrap_or_else(|| SAYA_DB_PATH.to_string())
+    }
+
+    async fn build_orchestrator(
+        self,
+        health_reporter: Option<HealthReporter>,
+        pause_handle: PauseHandle,
+    ) -> Result<impl Daemon> {
+        let saya_path = self.db_path();
 
         let db = SqliteDb::new(&saya_path).await?;
+        let rollup_rpc = self.rpc.transport(self.rollup_rpc)?;
+        let settlement_rpc = self.rpc.transport(self.settlement_rpc)?;
 
-        let block_ingestor_builder = BatchingPollingBlockIngestorBuilder::new(
-            self.rollup_rpc.clone(),
+        let mut block_ingestor_builder = BatchingPollingBlockIngestorBuilder::new(
+            rollup_rpc.clone(),
             db.clone(),
             self.batch_size,
             Duration::from_secs(self.idle_timeout_secs),
-        );
+        )
+        .pause_handle(pause_handle);
+        if let Some(max_transactions) = self.batch_max_transactions {
+            block_ingestor_builder =
+                block_ingestor_builder.batch_policy(MaxTransactions(max_transactions));
+        }
+        if let Some(max_messages) = self.batch_max_messages {
+            block_ingestor_builder = block_ingestor_builder.batch_policy(MaxMessages(max_messages));
+        }
+        if let Some(max_state_diff_size) = self.batch_max_state_diff_size {
+            block_ingestor_builder =
+                block_ingestor_builder.batch_policy(MaxStateDiffSize(max_state_diff_size));
+        }
+        if self.batch_flush_on_messages {
+            block_ingestor_builder = block_ingestor_builder.batch_policy(FlushOnMessages);
+        }
 
         let attestor_builder = TeeAttestorBuilder::new(
-            self.rollup_rpc.clone(),
+            rollup_rpc,
             Duration::from_millis(self.attestor_poll_interval_ms),
         );
 
         let prover_builder = TeeProverBuilder::new(
-            self.settlement_rpc.to_string(),
+            settlement_rpc.clone(),
             self.tee_registry_address,
             self.prover_private_key,
             self.mock_prove,
         );
 
         let settlement_builder = TeePiltoverSettlementBackendBuilder::new(
-            self.settlement_rpc,
+            settlement_rpc,
             self.settlement_piltover_address,
             self.settlement_account_address,
             self.settlement_account_private_key,
             self.mock_prove,
         );
 
-        let orchestrator = TeeOrchestratorBuilder::new(
+        let mut orchestrator_builder = TeeOrchestratorBuilder::new(
             block_ingestor_builder,
             attestor_builder,
             prover_builder,
             settlement_builder,
-        )
-        .build()
-        .await?;
-
-        let orchestrator_shutdown = orchestrator.shutdown_handle();
-        orchestrator.start();
-
-        let mut sigterm_handle =
-            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
-        let ctrl_c_handle = tokio::signal::ctrl_c();
-
-        tokio::select! {
-            _ = sigterm_handle.recv() => {},
-            _ = ctrl_c_handle => {},
-            _ = orchestrator_shutdown.finished() => {},
+        );
+        if let Some(reporter) = health_reporter {
+            orchestrator_builder = orchestrator_builder.health_reporter(reporter);
         }
-
-        // Graceful shutdown
-        orchestrator_shutdown.shutdown();
-        tokio::select! {
-            _ = tokio::time::sleep(GRACEFUL_SHUTDOWN_TIMEOUT) => {
-                Err(anyhow::anyhow!("timeout waiting for graceful shutdown"))
-            },
-            _ = orchestrator_shutdown.finished() => {
-                Ok(())
-            },
+        if let Some(end_block) = self.end_block {
+            orchestrator_builder = orchestrator_builder.end_block(end_block);
         }
+
+        orchestrator_builder.build().await
     }
 }
@@ -1,32 +1,93 @@
 use crate::{
-    atlantic::{AtlanticLayoutBridgeProver, AtlanticLayoutBridgeProverBuilder},
+    atlantic::{
+        AtlanticLayoutBridgeProver, AtlanticLayoutBridgeProverBuilder, AtlanticSnosProver,
+        AtlanticSnosProverBuilder,
+    },
     mock::{MockLayoutBridgeProver, MockLayoutBridgeProverBuilder},
+    stone::{
+        StoneLayoutBridgeProver, StoneLayoutBridgeProverBuilder, StoneSnosProver,
+        StoneSnosProverBuilder,
+    },
+    stwo::{StwoProofForwarder, StwoProofForwarderBuilder, StwoSnosProver, StwoSnosProverBuilder},
+    verifier::{ProofVerifier, ProofVerifierBuilder},
 };
 use anyhow::Result;
 use saya_core::{
-    block_ingestor::BlockInfo,
+    block_ingestor::{
+        BlockFilter, BlockInfo, BlockIngestor, BlockIngestorBuilder, FileBlockIngestor,
+        FileBlockIngestorBuilder, PollingBlockIngestor, PollingBlockIngestorBuilder,
+        SubscriptionBlockIngestor, SubscriptionBlockIngestorBuilder,
+    },
     data_availability::{
         CelestiaDataAvailabilityBackend, CelestiaDataAvailabilityBackendBuilder,
         DataAvailabilityBackend, DataAvailabilityBackendBuilder, DataAvailabilityCursor,
         DataAvailabilityPayload, DataAvailabilityPointer, NoopDataAvailabilityBackend,
         NoopDataAvailabilityBackendBuilder,
     },
-    prover::{PipelineStage, PipelineStageBuilder, SnosProof},
-    service::{Daemon, ShutdownHandle},
+    health::HealthReporter,
+    prover::{MapStage, MapStageBuilder, PipelineStage, PipelineStageBuilder, SnosProof},
+    service::{Daemon, PauseHandle, ShutdownHandle},
     storage::PersistantStorage,
 };
 use tokio::sync::mpsc::{Receiver, Sender};
 
+#[derive(Debug)]
+pub enum AnyBlockIngestor<DB> {
+    Polling(PollingBlockIngestor<DB>),
+    Subscription(SubscriptionBlockIngestor<DB>),
+    File(FileBlockIngestor<DB>),
+}
+
+#[derive(Debug)]
+pub enum AnyBlockIngestorBuilder<DB> {
+    Polling(PollingBlockIngestorBuilder<DB>),
+    Subscription(SubscriptionBlockIngestorBuilder<DB>),
+    File(FileBlockIngestorBuilder<DB>),
+}
+
+#[derive(Debug)]
+pub enum AnySnosProver<DB> {
+    Atlantic(AtlanticSnosProver<String, DB>),
+    Stone(StoneSnosProver<DB>),
+    Stwo(StwoSnosProver<DB>),
+}
+
+#[derive(Debug)]
+pub enum AnySnosProverBuilder<DB> {
+    Atlantic(AtlanticSnosProverBuilder<String, DB>),
+    Stone(StoneSnosProverBuilder<DB>),
+    Stwo(StwoSnosProverBuilder<DB>),
+}
+
 #[derive(Debug)]
 pub enum AnyLayoutBridgeProver<DB> {
     Atlantic(AtlanticLayoutBridgeProver<DB>),
     Mock(MockLayoutBridgeProver<DB>),
+    Stone(StoneLayoutBridgeProver<DB>),
+    Stwo(StwoProofForwarder<DB>),
 }
 
 #[derive(Debug)]
 pub enum AnyLayoutBridgeProverBuilder<DB> {
     Atlantic(AtlanticLayoutBridgeProverBuilder<DB>),
     Mock(MockLayoutBridgeProverBuilder<DB>),
+    Stone(StoneLayoutBridgeProverBuilder<DB>),
+    Stwo(StwoProofForwarderBuilder<DB>),
+}
+
+/// Blocks are forwarded as is when proofs aren't verified locally.
+type SkipVerification = fn(BlockInfo) -> BlockInfo;
+
+#[derive(Debug)]
+pub enum AnyProofVerifier<DB> {
+    Swiftness(ProofVerifier<DB>),
+    Skipped(MapStage<BlockInfo, BlockInfo, SkipVerification>),
+}
+
+#[derive(Debug)]
+pub enum AnyProofVerifierBuilder<DB> {
+    Swiftness(ProofVerifierBuilder<DB>),
+    Skipped(MapStageBuilder<BlockInfo, BlockInfo, SkipVerification>),
 }
 
 #[derive(Debug)]
@@ -41,6 +102,100 @@ pub enum AnyDataAvailabilityLayerBuilder<P> {
     Noop(NoopDataAvailabilityBackendBuilder<P>),
 }
 
+impl<DB> BlockIngestor for AnyBlockIngestor<DB> where
+    DB: PersistantStorage + Send + Sync + Clone + 'static
+{
+}
+
+impl<DB> Daemon for AnyBlockIngestor<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        match self {
+            Self::Polling(inner) => inner.shutdown_handle(),
+            Self::Subscription(inner) => inner.shutdown_handle(),
+            Self::File(inner) => inner.shutdown_handle(),
+        }
+    }
+
+    fn start(self) {
+        match self {
+            Self::Polling(inner) => inner.start(),
+            Self::Subscription(inner) => inner.start(),
+            Self::File(inner) => inner.start(),
+        }
+    }
+}
+
+impl<DB> BlockIngestorBuilder for AnyBlockIngestorBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Ingestor = AnyBlockIngestor<DB>;
+
+    fn build(self) -> Result<Self::Ingestor> {
+        Ok(match self {
+            Self::Polling(inner) => AnyBlockIngestor::Polling(inner.build()?),
+            Self::Subscription(inner) => AnyBlockIngestor::Subscription(inner.build()?),
+            Self::File(inner) => AnyBlockIngestor::File(inner.build()?),
+        })
+    }
+
+    fn start_block(self, start_block: u64) -> Self {
+        match self {
+            Self::Polling(inner) => Self::Polling(inner.start_block(start_block)),
+            Self::Subscription(inner) => Self::Subscription(inner.start_block(start_block)),
+            Self::File(inner) => Self::File(inner.start_block(start_block)),
+        }
+    }
+
+    fn end_block(self, end_block: u64) -> Self {
+        match self {
+            Self::Polling(inner) => Self::Polling(inner.end_block(end_block)),
+            Self::Subscription(inner) => Self::Subscription(inner.end_block(end_block)),
+            Self::File(inner) => Self::File(inner.end_block(end_block)),
+        }
+    }
+
+    fn channel(self, channel: Sender<BlockInfo>) -> Self {
+        match self {
+            Self::Polling(inner) => Self::Polling(inner.channel(channel)),
+            Self::Subscription(inner) => Self::Subscription(inner.channel(channel)),
+            Self::File(inner) => Self::File(inner.channel(channel)),
+        }
+    }
+
+    fn health_reporter(self, reporter: HealthReporter) -> Self {
+        match self {
+            Self::Polling(inner) => Self::Polling(inner.health_reporter(reporter)),
+            Self::Subscription(inner) => Self::Subscription(inner.health_reporter(reporter)),
+            Self::File(inner) => Self::File(inner.health_reporter(reporter)),
+        }
+    }
+
+    fn pause_handle(self, pause_handle: PauseHandle) -> Self {
+        match self {
+            Self::Polling(inner) => Self::Polling(inner.pause_handle(pause_handle)),
+            Self::Subscription(inner) => Self::Subscription(inner.pause_handle(pause_handle)),
+            Self::File(inner) => Self::File(inner.pause_handle(pause_handle)),
+        }
+    }
+}
+
+impl<DB> AnyBlockIngestorBuilder<DB> {
+    pub fn block_filter<F>(self, filter: F) -> Self
+    where
+        F: BlockFilter + 'static,
+    {
+        match self {
+            Self::Polling(inner) => Self::Polling(inner.block_filter(filter)),
+            Self::Subscription(inner) => Self::Subscription(inner.block_filter(filter)),
+            Self::File(inner) => Self::File(inner.block_filter(filter)),
+        }
+    }
+}
+
 impl<P> DataAvailabilityBackend for AnyDataAvailabilityLayer<P>
 where
     P: DataAvailabilityPayload + 'static,
@@ -110,6 +265,69 @@ where
     }
 }
 
+impl<DB> PipelineStage for AnySnosProver<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Input = BlockInfo;
+    type Output = SnosProof<String>;
+}
+
+impl<DB> Daemon for AnySnosProver<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        match self {
+            Self::Atlantic(inner) => inner.shutdown_handle(),
+            Self::Stone(inner) => inner.shutdown_handle(),
+            Self::Stwo(inner) => inner.shutdown_handle(),
+        }
+    }
+
+    fn start(self) {
+        match self {
+            Self::Atlantic(inner) => inner.start(),
+            Self::Stone(inner) => inner.start(),
+            Self::Stwo(inner) => inner.start(),
+        }
+    }
+}
+
+impl<DB> PipelineStageBuilder for AnySnosProverBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Stage = AnySnosProver<DB>;
+
+    fn build(self) -> Result<Self::Stage> {
+        Ok(match self {
+            Self::Atlantic(inner) => AnySnosProver::Atlantic(inner.build()?),
+            Self::Stone(inner) => AnySnosProver::Stone(inner.build()?),
+            Self::Stwo(inner) => AnySnosProver::Stwo(inner.build()?),
+        })
+    }
+
+    fn input_channel(self, block_channel: Receiver<<Self::Stage as PipelineStage>::Input>) -> Self {
+        match self {
+            Self::Atlantic(inner) => Self::Atlantic(inner.input_channel(block_channel)),
+            Self::Stone(inner) => Self::Stone(inner.input_channel(block_channel)),
+            Self::Stwo(inner) => Self::Stwo(inner.input_channel(block_channel)),
+        }
+    }
+
+    fn output_channel(
+        self,
+        output_channel: Sender<<Self::Stage as PipelineStage>::Output>,
+    ) -> Self {
+        match self {
+            Self::Atlantic(inner) => Self::Atlantic(inner.output_channel(output_channel)),
+            Self::Stone(inner) => Self::Stone(inner.output_channel(output_channel)),
+            Self::Stwo(inner) => Self::Stwo(inner.output_channel(output_channel)),
+        }
+    }
+}
+
 impl<DB> PipelineStage for AnyLayoutBridgeProver<DB>
 where
     DB: PersistantStorage + Send + Sync + Clone + 'static,
@@ -126,6 +344,8 @@ where
         match self {
             Self::Atlantic(inner) => inner.shutdown_handle(),
             Self::Mock(inner) => inner.shutdown_handle(),
+            Self::Stone(inner) => inner.shutdown_handle(),
+            Self::Stwo(inner) => inner.shutdown_handle(),
         }
     }
 
@@ -133,6 +353,8 @@ where
         match self {
             Self::Atlantic(inner) => inner.start(),
             Self::Mock(inner) => inner.start(),
+            Self::Stone(inner) => inner.start(),
+            Self::Stwo(inner) => inner.start(),
         }
     }
 }
@@ -147,6 +369,8 @@ where
         Ok(match self {
             Self::Atlantic(inner) => AnyLayoutBridgeProver::Atlantic(inner.build()?),
             Self::Mock(inner) => AnyLayoutBridgeProver::Mock(inner.build()?),
+            Self::Stone(inner) => AnyLayoutBridgeProver::Stone(inner.build()?),
+            Self::Stwo(inner) => AnyLayoutBridgeProver::Stwo(inner.build()?),
         })
     }
 
@@ -154,6 +378,8 @@ where
         match self {
             Self::Atlantic(inner) => Self::Atlantic(inner.input_channel(block_channel)),
             Self::Mock(inner) => Self::Mock(inner.input_channel(block_channel)),
+            Self::Stone(inner) => Self::Stone(inner.input_channel(block_channel)),
+            Self::Stwo(inner) => Self::Stwo(inner.input_channel(block_channel)),
         }
     }
 
@@ -164,6 +390,74 @@ where
         match self {
             Self::Atlantic(inner) => Self::Atlantic(inner.output_channel(output_channel)),
             Self::Mock(inner) => Self::Mock(inner.output_channel(output_channel)),
+            Self::Stone(inner) => Self::Stone(inner.output_channel(output_channel)),
+            Self::Stwo(inner) => Self::Stwo(inner.output_channel(output_channel)),
+        }
+    }
+}
+
+impl<DB> AnyProofVerifierBuilder<DB> {
+    pub fn skipped() -> Self {
+        Self::Skipped(MapStageBuilder::new(
+            std::convert::identity as SkipVerification,
+        ))
+    }
+}
+
+impl<DB> PipelineStage for AnyProofVerifier<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Input = BlockInfo;
+    type Output = BlockInfo;
+}
+
+impl<DB> Daemon for AnyProofVerifier<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        match self {
+            Self::Swiftness(inner) => inner.shutdown_handle(),
+            Self::Skipped(inner) => inner.shutdown_handle(),
+        }
+    }
+
+    fn start(self) {
+        match self {
+            Self::Swiftness(inner) => inner.start(),
+            Self::Skipped(inner) => inner.start(),
+        }
+    }
+}
+
+impl<DB> PipelineStageBuilder for AnyProofVerifierBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Stage = AnyProofVerifier<DB>;
+
+    fn build(self) -> Result<Self::Stage> {
+        Ok(match self {
+            Self::Swiftness(inner) => AnyProofVerifier::Swiftness(inner.build()?),
+            Self::Skipped(inner) => AnyProofVerifier::Skipped(inner.build()?),
+        })
+    }
+
+    fn input_channel(self, block_channel: Receiver<<Self::Stage as PipelineStage>::Input>) -> Self {
+        match self {
+            Self::Swiftness(inner) => Self::Swiftness(inner.input_channel(block_channel)),
+            Self::Skipped(inner) => Self::Skipped(inner.input_channel(block_channel)),
+        }
+    }
+
+    fn output_channel(
+        self,
+        output_channel: Sender<<Self::Stage as PipelineStage>::Output>,
+    ) -> Self {
+        match self {
+            Self::Swiftness(inner) => Self::Swiftness(inner.output_channel(output_channel)),
+            Self::Skipped(inner) => Self::Skipped(inner.output_channel(output_channel)),
         }
     }
 }
@@ -1,14 +1,16 @@
 use std::{borrow::Cow, time::Duration};
 
 use crate::error::ProverError;
+use clap::ValueEnum;
 use reqwest::{
     multipart::{Form, Part},
     Client, ClientBuilder,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use url::Url;
 
-const ATLANTIC_API_BASE: &str = "https://atlantic.api.herodotus.cloud/";
+/// URL of the hosted Atlantic API.
+pub const ATLANTIC_API_BASE: &str = "https://atlantic.api.herodotus.cloud/";
 const ATLANTIC_HTTP_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Clone)]
@@ -27,17 +29,20 @@ pub enum AtlanticQueryStatus {
     InProgress,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AtlanticQueryResponse {
     pub atlantic_query: AtlanticQuery,
     pub metadata_urls: Vec<String>,
 }
-#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AtlanticQuery {
     pub id: String,
     pub status: AtlanticQueryStatus,
+    /// The credits charged for the query, reported once it ended.
+    #[serde(default, deserialize_with = "deserialize_credits")]
+    pub credits_used: Option<f64>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
@@ -48,7 +53,7 @@ pub enum AtlanticJobStatus {
     Failed,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum AtlanticJobSize {
     XS,
@@ -68,6 +73,23 @@ impl AtlanticJobSize {
     }
 }
 
+/// Starknet network Atlantic verifies proofs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AtlanticNetwork {
+    Testnet,
+    Mainnet,
+}
+
+impl AtlanticNetwork {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Testnet => "TESTNET",
+            Self::Mainnet => "MAINNET",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum AtlanticCairoVersion {
@@ -159,6 +181,21 @@ struct AtlanticProofGenerationResponse {
     atlantic_query_id: String,
 }
 
+/// Reads the credits of a query, which may be reported as a number or a string, ignoring values
+/// that can't be parsed rather than failing to read the whole query.
+fn deserialize_credits<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(
+        match Option::<serde_json::Value>::deserialize(deserializer)? {
+            Some(serde_json::Value::Number(credits)) => credits.as_f64(),
+            Some(serde_json::Value::String(credits)) => credits.parse().ok(),
+            _ => None,
+        },
+    )
+}
+
 impl AtlanticQueryResponse {
     pub async fn get_proof(&self, atlantic_client: &AtlanticClient) -> Result<String, ProverError> {
         let url = if let Some(url) = self
@@ -176,11 +213,10 @@ impl AtlanticQueryResponse {
 
         let response = atlantic_client.http_client.get(url).send().await?;
         if !response.status().is_success() {
-            return Err(ProverError::Prover(format!(
-                "unsuccessful status code: {}\n{}",
-                response.status(),
-                response.text().await?
-            )));
+            return Err(ProverError::Status {
+                status: response.status(),
+                body: response.text().await?,
+            });
         }
         Ok(response.text().await?)
     }
@@ -200,34 +236,46 @@ impl AtlanticQueryResponse {
         };
 
         let response = atlantic_client.http_client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(ProverError::Status {
+                status: response.status(),
+                body: response.text().await?,
+            });
+        }
         Ok(response.bytes().await?.to_vec())
     }
 }
 
 impl AtlanticClient {
-    pub fn new(api_key: String) -> Self {
+    pub fn new(api_base: Url, api_key: String) -> Self {
         Self {
             http_client: ClientBuilder::new()
                 .timeout(ATLANTIC_HTTP_TIMEOUT)
                 .build()
                 .unwrap(),
-            api_base: Url::parse(ATLANTIC_API_BASE).unwrap(),
+            api_base,
             api_key,
         }
     }
 
+    /// Submits a PIE to be proven, and to have the proof verified on Starknet by Atlantic if
+    /// `l2_verification` is set.
     pub async fn submit_proof_generation<T>(
         &self,
         compressed_pie: T,
         layout: Layout,
         label: String,
         atlantic_job_size: AtlanticJobSize,
+        l2_verification: Option<AtlanticNetwork>,
     ) -> Result<String, ProverError>
     where
         T: Into<Cow<'static, [u8]>>,
     {
         let mut url = self.api_base.clone();
-        url.path_segments_mut().unwrap().push("atlantic-query");
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .push("atlantic-query");
         url.query_pairs_mut().append_pair("apiKey", &self.api_key);
 
         let form = Form::new()
@@ -240,16 +288,22 @@ impl AtlanticClient {
             )
             .text("layout", layout.to_str())
             .text("externalId", label)
-            .text("declaredJobSize", atlantic_job_size.as_str())
-            .text("result", AtlanticQueryResult::ProofGeneration.as_str());
+            .text("declaredJobSize", atlantic_job_size.as_str());
+        let form = match l2_verification {
+            Some(network) => {
+                let result = AtlanticQueryResult::ProofVerificationOnL2;
+                form.text("result", result.as_str())
+                    .text("network", network.as_str())
+            }
+            None => form.text("result", AtlanticQueryResult::ProofGeneration.as_str()),
+        };
 
         let response = self.http_client.post(url).multipart(form).send().await?;
         if !response.status().is_success() {
-            return Err(ProverError::Prover(format!(
-                "unsuccessful status code: {}\n{}",
-                response.status(),
-                response.text().await?
-            )));
+            return Err(ProverError::Status {
+                status: response.status(),
+                body: response.text().await?,
+            });
         }
 
         let response = response.json::<AtlanticProofGenerationResponse>().await?;
@@ -261,18 +315,22 @@ impl AtlanticClient {
         label: &str,
         program: P,
         input: I,
+        atlantic_job_size: AtlanticJobSize,
     ) -> Result<String, ProverError>
     where
         P: Into<Cow<'static, [u8]>>,
         I: Into<Cow<'static, [u8]>>,
     {
         let mut url = self.api_base.clone();
-        url.path_segments_mut().unwrap().push("atlantic-query");
+        url.path_segments_mut()
+            .unwrap()
+            .pop_if_empty()
+            .push("atlantic-query");
         url.query_pairs_mut().append_pair("apiKey", &self.api_key);
         let form = Form::new()
             .text("cairoVersion", AtlanticCairoVersion::Cairo0.as_str())
             .text("result", AtlanticQueryResult::TraceGeneration.as_str())
-            .text("declaredJobSize", AtlanticJobSize::XS.as_str())
+            .text("declaredJobSize", atlantic_job_size.as_str())
             .text("cairoVm", AtlanticCairoVmVersion::Python.as_str())
             .text("externalId", label.to_string())
             .part(
@@ -291,11 +349,10 @@ impl AtlanticClient {
             );
         let response = self.http_client.post(url).multipart(form).send().await?;
         if !response.status().is_success() {
-            return Err(ProverError::Prover(format!(
-                "unsuccessful status code: {}\n{}",
-                response.status(),
-                response.text().await?
-            )));
+            return Err(ProverError::Status {
+                status: response.status(),
+                body: response.text().await?,
+            });
         }
         let response = response.json::<AtlanticProofGenerationResponse>().await?;
         Ok(response.atlantic_query_id)
@@ -305,14 +362,15 @@ impl AtlanticClient {
         let mut url = self.api_base.clone();
         url.path_segments_mut()
             .unwrap()
+            .pop_if_empty()
             .push("atlantic-query")
             .push(id);
         let response = self.http_client.get(url).send().await?;
         if !response.status().is_success() {
-            return Err(ProverError::Prover(format!(
-                "unsuccessful status code: {}",
-                response.status()
-            )));
+            return Err(ProverError::Status {
+                status: response.status(),
+                body: response.text().await?,
+            });
         }
         let response = response.json::<AtlanticQueryResponse>().await?;
         Ok(response)
@@ -1,9 +1,12 @@
-use std::{borrow::Cow, sync::Arc, time::Duration};
+use std::borrow::Cow;
 
 use crate::{
     atlantic::{
-        client::{AtlanticClient, Layout},
-        shared::{calculate_job_size, parse_and_store_proof, wait_for_query},
+        client::{AtlanticClient, AtlanticNetwork, Layout, ATLANTIC_API_BASE},
+        shared::{
+            download_result, parse_and_store_proof, run_query, submit_pie, submit_trace,
+            AtlanticQueryPolicy,
+        },
         snos::compress_pie,
     },
     error::ProverError,
@@ -12,15 +15,14 @@ use anyhow::Result;
 use cairo_vm::vm::runners::cairo_pie::CairoPie;
 use saya_core::{
     block_ingestor::BlockInfo,
+    metrics,
     prover::{PipelineStage, PipelineStageBuilder, SnosProof},
-    service::{Daemon, FinishHandle, ShutdownHandle},
-    storage::{PersistantStorage, Step},
+    service::{Daemon, FinishHandle, ShutdownHandle, WorkerHandle, WorkerPool, WorkerPoolConfig},
+    storage::{PersistantStorage, Query, Step},
 };
-use tokio::sync::{
-    mpsc::{Receiver, Sender},
-    Mutex,
-};
-use tracing::{debug, error, info, trace, warn};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{debug, error, trace, warn};
+use url::Url;
 /// Prover implementation as a client to the hosted [Atlantic Prover](https://atlanticprover.com/)
 /// service.
 #[derive(Debug)]
@@ -30,38 +32,46 @@ pub struct AtlanticLayoutBridgeProver<DB> {
     input_channel: Receiver<SnosProof<String>>,
     output_channel: Sender<BlockInfo>,
     finish_handle: FinishHandle,
+    query_policy: AtlanticQueryPolicy,
+    l2_verification: Option<AtlanticNetwork>,
     db: DB,
-    workers_count: usize,
+    workers: WorkerPoolConfig,
 }
 
 #[derive(Debug)]
 pub struct AtlanticLayoutBridgeProverBuilder<DB> {
+    api_base: Url,
     api_key: String,
     layout_bridge: Cow<'static, [u8]>,
     input_channel: Option<Receiver<SnosProof<String>>>,
     output_channel: Option<Sender<BlockInfo>>,
+    query_policy: AtlanticQueryPolicy,
+    l2_verification: Option<AtlanticNetwork>,
     db: DB,
-    workers_count: usize,
+    workers: WorkerPoolConfig,
 }
 
 impl<DB> AtlanticLayoutBridgeProver<DB>
 where
     DB: PersistantStorage + Send + Sync + Clone + 'static,
 {
+    #[allow(clippy::too_many_arguments)]
     async fn worker(
-        task_rx: Arc<Mutex<Receiver<SnosProof<String>>>>,
+        worker: WorkerHandle<SnosProof<String>>,
         task_tx: Sender<BlockInfo>,
         client: AtlanticClient,
         layout_bridge: Cow<'static, [u8]>,
         finish_handle: FinishHandle,
+        query_policy: AtlanticQueryPolicy,
+        l2_verification: Option<AtlanticNetwork>,
         db: DB,
     ) -> Result<(), ProverError>
     where
         DB: PersistantStorage + Send + Sync + 'static,
     {
         loop {
-            let new_snos_proof = if let Some(new_block) = task_rx.lock().await.recv().await {
-                new_block
+            let (new_snos_proof, _task) = if let Some(task) = worker.recv().await {
+                task
             } else {
                 break;
             };
@@ -89,6 +99,7 @@ where
                             number: new_snos_proof.block_number,
                             status: saya_core::storage::BlockStatus::SnosProofGenerated,
                             state_update: Some(state_update.clone()),
+                            first_block: new_snos_proof.first_block,
                         };
 
                         task_tx.send(block_info).await.unwrap();
@@ -109,72 +120,7 @@ where
                 }
             }
 
-            match db
-                .get_query_id(block_number_u32, saya_core::storage::Query::BridgeProof)
-                .await
-            {
-                Ok(atlantic_query_id) => {
-                    info!(
-                        block_number = new_snos_proof.block_number,
-                        "Proof generation already submitted for block"
-                    );
-                    let query_response = match wait_for_query(
-                        client.clone(),
-                        atlantic_query_id.clone(),
-                        finish_handle.clone(),
-                    )
-                    .await
-                    {
-                        Err(ProverError::Shutdown) => {
-                            break;
-                        }
-                        Err(ProverError::BlockFail(e)) => {
-                            error!(error = %e, atlantic_query_id = %atlantic_query_id, "Proof generation failed");
-                            db.add_failed_block(block_number_u32, e).await.unwrap();
-                            continue;
-                        }
-                        Err(e) => {
-                            error!(
-                                "Unreachable error: {:?} while processing query {}",
-                                e, atlantic_query_id
-                            );
-                            unreachable!("Unexpected ProverError: {:?}", e);
-                        }
-                        Ok(response) => response,
-                    };
-
-                    debug!(
-                        atlantic_query_id = ?atlantic_query_id,
-                        "Atlantic layout bridge proof generation finished"
-                    );
-
-                    let raw_proof = query_response.get_proof(&client).await?;
-
-                    let _: SnosProof<String> = parse_and_store_proof(
-                        raw_proof,
-                        db.clone(),
-                        block_number_u32,
-                        Step::Bridge,
-                    )
-                    .await?;
-
-                    let output = BlockInfo {
-                        number: new_snos_proof.block_number,
-                        status: saya_core::storage::BlockStatus::SnosProofGenerated,
-                        state_update: Some(state_update.clone()),
-                    };
-
-                    task_tx.send(output).await.unwrap();
-                    continue;
-                }
-                Err(_) => {
-                    trace!(
-                        block_number = new_snos_proof.block_number,
-                        "Proof generation not submitted for block"
-                    );
-                }
-            }
-
+            let stage_timer = metrics::stage_timer("layout_bridge");
             let compressed_pie = match db.get_pie(block_number_u32, Step::Bridge).await {
                 Ok(pie) => pie,
                 Err(_) => {
@@ -183,49 +129,22 @@ where
                     let input = format!("{{\n\t\"proof\": {}\n}}", new_snos_proof.proof);
                     let label = format!("layout-trace-{}", new_snos_proof.block_number);
 
-                    let atlantic_query_id = match db
-                        .get_query_id(block_number_u32, saya_core::storage::Query::BridgeTrace)
-                        .await
-                    {
-                        Ok(query_id) => query_id,
-                        Err(_) => {
-                            let atlantic_query_id = crate::utils::retry_with_backoff(
-                                || {
-                                    client.submit_trace_generation(
-                                        &label,
-                                        layout_bridge.clone().to_vec(),
-                                        input.clone().into_bytes(),
-                                    )
-                                },
-                                "trace_gen",
-                                3,
-                                Duration::from_secs(5),
-                            )
-                            .await
-                            .unwrap();
-
-                            db.add_query_id(
-                                block_number_u32,
-                                atlantic_query_id.clone(),
-                                saya_core::storage::Query::BridgeTrace,
+                    let query_response = match run_query(
+                        &client,
+                        &db,
+                        block_number_u32,
+                        Query::BridgeTrace,
+                        query_policy,
+                        &finish_handle,
+                        || {
+                            submit_trace(
+                                &client,
+                                &label,
+                                layout_bridge.to_vec(),
+                                input.clone().into_bytes(),
+                                query_policy.job_sizes.bridge_trace,
                             )
-                            .await
-                            .unwrap();
-
-                            atlantic_query_id
-                        }
-                    };
-
-                    info!(
-                        block_number = new_snos_proof.block_number,
-                        atlantic_query_id = ?atlantic_query_id,
-                        "Atlantic trace generation submitted",
-                    );
-
-                    let query_response = match wait_for_query(
-                        client.clone(),
-                        atlantic_query_id.clone(),
-                        finish_handle.clone(),
+                        },
                     )
                     .await
                     {
@@ -233,21 +152,35 @@ where
                             break;
                         }
                         Err(ProverError::BlockFail(e)) => {
-                            error!("{}", e);
+                            error!(
+                                block_number = new_snos_proof.block_number,
+                                error = %e,
+                                "Layout bridge trace generation failed"
+                            );
                             db.add_failed_block(block_number_u32, e).await.unwrap();
                             continue;
                         }
-                        Err(e) => {
+                        Err(e) => return Err(e),
+                        Ok(response) => response,
+                    };
+
+                    let pie_bytes = match download_result("get_pie", || {
+                        query_response.get_pie(&client)
+                    })
+                    .await
+                    {
+                        Err(ProverError::BlockFail(e)) => {
                             error!(
-                                "Unreachable error: {:?} while processing query {}",
-                                e, atlantic_query_id
+                                block_number = new_snos_proof.block_number,
+                                error = %e,
+                                "Layout bridge PIE download failed"
                             );
-                            unreachable!("Unexpected ProverError: {:?}", e);
+                            db.add_failed_block(block_number_u32, e).await.unwrap();
+                            continue;
                         }
-                        Ok(response) => response,
+                        Err(e) => return Err(e),
+                        Ok(pie_bytes) => pie_bytes,
                     };
-
-                    let pie_bytes = query_response.get_pie(&client).await?;
                     let layout_bridge_pie = CairoPie::from_bytes(&pie_bytes).unwrap();
 
                     let compressed_pie = compress_pie(layout_bridge_pie).await.unwrap();
@@ -259,67 +192,62 @@ where
                     compressed_pie
                 }
             };
-            let atlantic_job_size =
-                calculate_job_size(CairoPie::from_bytes(&compressed_pie).unwrap());
-            let atlantic_query_id = crate::utils::retry_with_backoff(
+
+            let label = format!("layout-{}", new_snos_proof.block_number);
+            let query_response = match run_query(
+                &client,
+                &db,
+                block_number_u32,
+                Query::BridgeProof,
+                query_policy,
+                &finish_handle,
                 || {
-                    client.submit_proof_generation(
+                    submit_pie(
+                        &client,
                         compressed_pie.clone(),
                         Layout::recursive_with_poseidon,
-                        format!("layout-{}", new_snos_proof.block_number),
-                        atlantic_job_size,
+                        label.clone(),
+                        query_policy.job_sizes.bridge_proof,
+                        l2_verification,
                     )
                 },
-                "submit_proof_generation",
-                3,
-                Duration::from_secs(5),
-            )
-            .await
-            .unwrap();
-
-            db.add_query_id(
-                new_snos_proof.block_number.try_into().unwrap(),
-                atlantic_query_id.clone(),
-                saya_core::storage::Query::BridgeProof,
-            )
-            .await
-            .unwrap();
-
-            info!(
-                block_number = new_snos_proof.block_number,
-                atlantic_query_id = ?atlantic_query_id,
-                "Atlantic layout bridge proof generation submitted",
-            );
-
-            // Wait for bridge layout proof to be done
-            let query_response = match wait_for_query(
-                client.clone(),
-                atlantic_query_id.clone(),
-                finish_handle.clone(),
             )
             .await
             {
                 Err(ProverError::Shutdown) => break,
                 Err(ProverError::BlockFail(e)) => {
-                    error!(error = %e, atlantic_query_id = %atlantic_query_id, "Proof generation failed");
-                    db.add_failed_block(block_number_u32, e).await.unwrap();
-                    continue;
-                }
-                Err(e) => {
                     error!(
-                        "Unreachable error: {:?} while processing query {}",
-                        e, atlantic_query_id
+                        block_number = new_snos_proof.block_number,
+                        error = %e,
+                        "Proof generation failed"
                     );
-                    unreachable!("Unexpected ProverError: {:?}", e);
+                    db.add_failed_block(block_number_u32, e).await.unwrap();
+                    continue;
                 }
+                Err(e) => return Err(e),
                 Ok(response) => response,
             };
-            let raw_proof = query_response.get_proof(&client).await?;
+            let atlantic_query_id = &query_response.atlantic_query.id;
+            let raw_proof =
+                match download_result("get_proof", || query_response.get_proof(&client)).await {
+                    Err(ProverError::BlockFail(e)) => {
+                        error!(
+                            block_number = new_snos_proof.block_number,
+                            error = %e,
+                            "Layout bridge proof download failed"
+                        );
+                        db.add_failed_block(block_number_u32, e).await.unwrap();
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                    Ok(raw_proof) => raw_proof,
+                };
 
             let _: SnosProof<String> =
                 parse_and_store_proof(raw_proof, db.clone(), block_number_u32, Step::Bridge)
                     .await
                     .unwrap();
+            stage_timer.observe();
 
             debug!(
                 block_number = new_snos_proof.block_number,
@@ -331,6 +259,7 @@ where
                 number: new_snos_proof.block_number,
                 status: saya_core::storage::BlockStatus::SnosProofGenerated,
                 state_update: Some(state_update.clone()),
+                first_block: new_snos_proof.first_block,
             };
 
             tokio::select! {
@@ -342,24 +271,34 @@ where
     }
 
     async fn run(self) {
-        let mut workers = Vec::new();
-        let task_rx = Arc::new(Mutex::new(self.input_channel));
-        for _ in 0..self.workers_count {
-            let worker_task_rx = task_rx.clone();
-            let task_tx = self.output_channel.clone();
-            let client = self.client.clone();
-            let layout_bridge = self.layout_bridge.clone();
+        let pool = WorkerPool::new(
+            "layout_bridge",
+            self.input_channel,
+            self.workers,
+            self.finish_handle.clone(),
+        );
+        pool.run(|worker| {
             let finish_handle = self.finish_handle.clone();
-            workers.push(tokio::spawn(Self::worker(
-                worker_task_rx,
-                task_tx,
-                client,
-                layout_bridge,
-                finish_handle,
+            let worker = Self::worker(
+                worker,
+                self.output_channel.clone(),
+                self.client.clone(),
+                self.layout_bridge.clone(),
+                self.finish_handle.clone(),
+                self.query_policy,
+                self.l2_verification,
                 self.db.clone(),
-            )));
-        }
-        futures_util::future::join_all(workers).await;
+            );
+
+            async move {
+                if let Err(err) = worker.await {
+                    error!(error = %err, "Layout bridge proof worker failed");
+                    finish_handle.fail(format!("worker failed: {}", err));
+                    finish_handle.shutdown_handle().shutdown();
+                }
+            }
+        })
+        .await;
 
         debug!("Graceful shutdown finished");
         self.finish_handle.finish();
@@ -367,20 +306,42 @@ where
 }
 
 impl<DB> AtlanticLayoutBridgeProverBuilder<DB> {
-    pub fn new<P>(api_key: String, layout_bridge: P, db: DB, workers_count: usize) -> Self
+    pub fn new<P>(api_key: String, layout_bridge: P, db: DB, workers: WorkerPoolConfig) -> Self
     where
         P: Into<Cow<'static, [u8]>>,
         DB: PersistantStorage + Send + Sync + Clone + 'static,
     {
         Self {
+            api_base: Url::parse(ATLANTIC_API_BASE).unwrap(),
             api_key,
             layout_bridge: layout_bridge.into(),
             input_channel: None,
             output_channel: None,
+            query_policy: AtlanticQueryPolicy::default(),
+            l2_verification: None,
             db,
-            workers_count,
+            workers,
         }
     }
+
+    /// Overrides the URL of the Atlantic API, e.g. to use a mock server.
+    pub fn api_base(mut self, api_base: Url) -> Self {
+        self.api_base = api_base;
+        self
+    }
+
+    /// Sets the timeout and attempts of the Atlantic queries.
+    pub fn query_policy(mut self, query_policy: AtlanticQueryPolicy) -> Self {
+        self.query_policy = query_policy;
+        self
+    }
+
+    /// Has Atlantic verify the layout bridge proofs on the given Starknet network, registering
+    /// their facts in the integrity fact registry.
+    pub fn l2_verification(mut self, l2_verification: Option<AtlanticNetwork>) -> Self {
+        self.l2_verification = l2_verification;
+        self
+    }
 }
 
 impl<DB> PipelineStageBuilder for AtlanticLayoutBridgeProverBuilder<DB>
@@ -391,7 +352,7 @@ where
 
     fn build(self) -> Result<Self::Stage> {
         Ok(AtlanticLayoutBridgeProver {
-            client: AtlanticClient::new(self.api_key),
+            client: AtlanticClient::new(self.api_base, self.api_key),
             layout_bridge: self.layout_bridge,
             input_channel: self
                 .input_channel
@@ -400,8 +361,10 @@ where
                 .output_channel
                 .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
             finish_handle: FinishHandle::new(),
+            query_policy: self.query_policy,
+            l2_verification: self.l2_verification,
             db: self.db,
-            workers_count: self.workers_count,
+            workers: self.workers,
         })
     }
 
@@ -433,6 +396,152 @@ where
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use saya_atlantic_mock::{MockAtlantic, QueryOutcome};
+    use saya_core::storage::SqliteDb;
+    use starknet::core::types::{Felt, StateDiff, StateUpdate};
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    const API_KEY: &str = "key";
+    const LAYOUT_BRIDGE: &[u8] = b"layout-bridge-program";
+
+    /// Starts a prover sending its queries to `mock`, returning the channels it takes SNOS proofs
+    /// from and outputs blocks to.
+    fn start_prover(
+        mock: &MockAtlantic,
+        db: SqliteDb,
+        query_policy: AtlanticQueryPolicy,
+    ) -> (
+        Sender<SnosProof<String>>,
+        Receiver<BlockInfo>,
+        ShutdownHandle,
+    ) {
+        let (proof_tx, proof_rx) = mpsc::channel(1);
+        let (block_tx, block_rx) = mpsc::channel(1);
+        let prover = AtlanticLayoutBridgeProverBuilder::new(
+            API_KEY.to_string(),
+            LAYOUT_BRIDGE,
+            db,
+            WorkerPoolConfig::fixed(1),
+        )
+        .api_base(mock.url())
+        .query_policy(query_policy)
+        .input_channel(proof_rx)
+        .output_channel(block_tx)
+        .build()
+        .unwrap();
+        let shutdown_handle = prover.shutdown_handle();
+        prover.start();
+
+        (proof_tx, block_rx, shutdown_handle)
+    }
+
+    async fn db_with_block(block_number: u32) -> SqliteDb {
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        db.initialize_block(block_number).await.unwrap();
+        db.add_state_update(
+            block_number,
+            StateUpdate {
+                block_hash: Felt::from(block_number),
+                new_root: Felt::ZERO,
+                old_root: Felt::ZERO,
+                state_diff: StateDiff {
+                    storage_diffs: vec![],
+                    deprecated_declared_classes: vec![],
+                    declared_classes: vec![],
+                    deployed_contracts: vec![],
+                    replaced_classes: vec![],
+                    nonces: vec![],
+                },
+            },
+        )
+        .await
+        .unwrap();
+        db
+    }
+
+    fn snos_proof(block_number: u64) -> SnosProof<String> {
+        SnosProof {
+            block_number,
+            first_block: None,
+            proof: "{}".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resumes_stored_query() {
+        let mock = MockAtlantic::start(API_KEY).await.unwrap();
+        let query_id = mock.insert_query(QueryOutcome::Done {
+            proof: "bridge-proof".to_string(),
+            pie: vec![],
+        });
+        // The trace of the layout bridge was generated by the previous run, along with the query
+        // proving it.
+        let db = db_with_block(1).await;
+        db.add_pie(1, vec![1, 2, 3], Step::Bridge).await.unwrap();
+        db.add_query_id(1, query_id.clone(), Query::BridgeProof)
+            .await
+            .unwrap();
+        let (proof_tx, mut block_rx, shutdown_handle) =
+            start_prover(&mock, db.clone(), AtlanticQueryPolicy::default());
+
+        proof_tx.send(snos_proof(1)).await.unwrap();
+        let block = block_rx.recv().await.unwrap();
+        assert_eq!(block.number, 1);
+        assert_eq!(block.state_update.unwrap().block_hash, Felt::ONE);
+        assert_eq!(
+            db.get_proof(1, Step::Bridge).await.unwrap(),
+            b"bridge-proof"
+        );
+
+        // The query of the previous run is waited on instead of submitting a new one.
+        let queries = mock.queries();
+        assert_eq!(queries.len(), 1);
+        assert_eq!(queries[0].id, query_id);
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+        assert!(shutdown_handle.failure().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_records_block_once_queries_failed() {
+        let mock = MockAtlantic::start(API_KEY).await.unwrap();
+        mock.set_default_outcome(QueryOutcome::Failed);
+        let db = db_with_block(1).await;
+        let query_policy = AtlanticQueryPolicy {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let (proof_tx, mut block_rx, shutdown_handle) =
+            start_prover(&mock, db.clone(), query_policy);
+
+        proof_tx.send(snos_proof(1)).await.unwrap();
+        drop(proof_tx);
+        shutdown_handle.finished().await;
+        assert!(shutdown_handle.failure().is_none());
+        assert!(block_rx.recv().await.is_none());
+
+        // The trace generation failed, so the layout bridge was never proven.
+        let queries = mock.queries();
+        assert_eq!(queries.len(), 2);
+        for query in queries {
+            assert_eq!(query.fields["result"], "TRACE_GENERATION");
+            assert_eq!(query.files["programFile"], LAYOUT_BRIDGE);
+            assert_eq!(query.files["inputFile"], b"{\n\t\"proof\": {}\n}");
+        }
+        assert!(db.get_pie(1, Step::Bridge).await.is_err());
+
+        let failed_blocks = db.get_failed_blocks().await.unwrap();
+        assert_eq!(failed_blocks.len(), 1);
+        assert_eq!(failed_blocks[0].0, 1);
+        assert!(failed_blocks[0].1.contains("gave up after 2 attempts"));
     }
 }
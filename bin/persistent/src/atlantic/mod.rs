@@ -5,13 +5,17 @@ use swiftness_stark::types::StarkProof;
 mod client;
 
 mod snos;
-pub use snos::AtlanticSnosProverBuilder;
+pub use snos::{AtlanticSnosProver, AtlanticSnosProverBuilder};
 
 mod shared;
 
 mod layout_bridge;
-pub use client::AtlanticClient;
+pub use client::{AtlanticClient, AtlanticJobSize, AtlanticNetwork, ATLANTIC_API_BASE};
 pub use layout_bridge::{AtlanticLayoutBridgeProver, AtlanticLayoutBridgeProverBuilder};
+pub use shared::{
+    AtlanticBudget, AtlanticJobSizes, AtlanticQueryPolicy, DEFAULT_QUERY_MAX_ATTEMPTS,
+    DEFAULT_QUERY_TIMEOUT_SECS,
+};
 pub use snos::compress_pie;
 
 pub trait AtlanticProof: Sized {
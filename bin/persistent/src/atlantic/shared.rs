@@ -1,18 +1,123 @@
 use super::{
-    client::{AtlanticJobSize, AtlanticQueryResponse, AtlanticQueryStatus},
+    client::{
+        AtlanticJobSize, AtlanticNetwork, AtlanticQueryResponse, AtlanticQueryStatus, Layout,
+    },
     AtlanticClient, AtlanticProof,
 };
 use crate::error::ProverError;
 use cairo_vm::vm::runners::cairo_pie::CairoPie;
+use chrono::{Datelike, NaiveTime, Utc};
 use saya_core::{
     prover::SnosProof,
     service::FinishHandle,
-    storage::{PersistantStorage, Step},
+    storage::{PersistantStorage, Query, QueryRecord, Step},
 };
-use std::time::Duration;
-use tracing::info;
+use std::{future::Future, time::Duration};
+use tokio::time::Instant;
+use tracing::{info, warn};
 
 const PROOF_STATUS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+const REQUEST_ATTEMPTS: u32 = 3;
+const REQUEST_RETRY_DELAY: Duration = Duration::from_secs(5);
+const BUDGET_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Default time an Atlantic query has to complete before it's resubmitted.
+pub const DEFAULT_QUERY_TIMEOUT_SECS: u64 = 3 * 60 * 60;
+/// Default number of queries submitted for a job before its block is marked as failed.
+pub const DEFAULT_QUERY_MAX_ATTEMPTS: u32 = 3;
+
+/// Limits on the Atlantic queries of a job, so that workers don't wait forever on queries that
+/// are stuck on the Atlantic side, and that proving doesn't cost more than budgeted.
+#[derive(Debug, Clone, Copy)]
+pub struct AtlanticQueryPolicy {
+    /// Time a query has to complete before it's considered stuck and resubmitted. Queries resumed
+    /// from the database after a restart get the whole timeout again.
+    pub timeout: Duration,
+    /// Number of queries submitted for a job, including the first one and the ones of previous
+    /// runs, before the block is marked as failed.
+    pub max_attempts: u32,
+    pub job_sizes: AtlanticJobSizes,
+    pub budget: AtlanticBudget,
+}
+
+impl Default for AtlanticQueryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(DEFAULT_QUERY_TIMEOUT_SECS),
+            max_attempts: DEFAULT_QUERY_MAX_ATTEMPTS,
+            job_sizes: AtlanticJobSizes::default(),
+            budget: AtlanticBudget::default(),
+        }
+    }
+}
+
+/// Job sizes declared for each kind of query, instead of the ones derived from the number of
+/// steps of the PIEs.
+#[derive(Debug, Clone, Copy)]
+pub struct AtlanticJobSizes {
+    pub snos_proof: Option<AtlanticJobSize>,
+    pub bridge_trace: AtlanticJobSize,
+    pub bridge_proof: Option<AtlanticJobSize>,
+}
+
+impl Default for AtlanticJobSizes {
+    fn default() -> Self {
+        Self {
+            snos_proof: None,
+            bridge_trace: AtlanticJobSize::XS,
+            bridge_proof: None,
+        }
+    }
+}
+
+/// Credits that may be spent on queries ending in a UTC day or month, after which no query is
+/// submitted until the next day or month. The estimated cost of the queries in flight is reserved
+/// from both budgets, as they're expected to end within the current period.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AtlanticBudget {
+    pub daily: Option<f64>,
+    pub monthly: Option<f64>,
+}
+
+impl AtlanticBudget {
+    /// Returns the period whose budget has been spent along with its spend, including the cost
+    /// reserved for the queries in flight, if any.
+    async fn exceeded<DB>(&self, db: &DB) -> Result<Option<(&'static str, f64)>, ProverError>
+    where
+        DB: PersistantStorage,
+    {
+        if self.daily.is_none() && self.monthly.is_none() {
+            return Ok(None);
+        }
+
+        let reserved = db
+            .get_in_flight_query_cost()
+            .await
+            .map_err(|e| ProverError::Prover(e.to_string()))?;
+        let now = Utc::now();
+        let today = now.date_naive();
+        let periods = [
+            ("daily", self.daily, today),
+            ("monthly", self.monthly, today.with_day(1).unwrap_or(today)),
+        ];
+
+        for (period, budget, start) in periods {
+            let Some(budget) = budget else {
+                continue;
+            };
+            let spent = db
+                .get_query_cost_since(start.and_time(NaiveTime::MIN).and_utc().timestamp())
+                .await
+                .map_err(|e| ProverError::Prover(e.to_string()))?
+                + reserved;
+            if spent >= budget {
+                return Ok(Some((period, spent)));
+            }
+        }
+
+        Ok(None)
+    }
+}
 
 /// Calculate the job size based on the number of steps in the pie.
 /// Refer to the [Atlantic Prover](https://docs.herodotus.cloud/atlantic/sending-query) documentation for more details.
@@ -29,32 +134,366 @@ pub fn calculate_job_size(pie: CairoPie) -> AtlanticJobSize {
     }
 }
 
-pub async fn wait_for_query(
-    client: AtlanticClient,
-    atlantic_query_id: String,
-    finish_handle: FinishHandle,
-) -> Result<AtlanticQueryResponse, ProverError> {
-    let response = loop {
-        tokio::time::sleep(PROOF_STATUS_POLL_INTERVAL).await;
+/// Submits a PIE for proving, with the job size matching its number of steps unless overridden.
+/// The proof is also verified on Starknet by Atlantic if `l2_verification` is set.
+///
+/// Returns the query ID along with the declared job size.
+pub async fn submit_pie(
+    client: &AtlanticClient,
+    compressed_pie: Vec<u8>,
+    layout: Layout,
+    label: String,
+    job_size: Option<AtlanticJobSize>,
+    l2_verification: Option<AtlanticNetwork>,
+) -> Result<(String, AtlanticJobSize), ProverError> {
+    let atlantic_job_size = match job_size {
+        Some(job_size) => job_size,
+        None => calculate_job_size(
+            CairoPie::from_bytes(&compressed_pie)
+                .map_err(|e| ProverError::ProofParse(e.to_string()))?,
+        ),
+    };
+
+    let atlantic_query_id = client
+        .submit_proof_generation(
+            compressed_pie,
+            layout,
+            label,
+            atlantic_job_size,
+            l2_verification,
+        )
+        .await?;
+    Ok((atlantic_query_id, atlantic_job_size))
+}
+
+/// Submits a Cairo 0 program run for trace generation.
+///
+/// Returns the query ID along with the declared job size.
+pub async fn submit_trace(
+    client: &AtlanticClient,
+    label: &str,
+    program: Vec<u8>,
+    input: Vec<u8>,
+    job_size: AtlanticJobSize,
+) -> Result<(String, AtlanticJobSize), ProverError> {
+    let atlantic_query_id = client
+        .submit_trace_generation(label, program, input, job_size)
+        .await?;
+    Ok((atlantic_query_id, job_size))
+}
+
+/// Sends an Atlantic request again with a linear backoff as long as it fails with a retryable
+/// error.
+pub async fn retry_request<F, Fut, T>(label: &str, mut request: F) -> Result<T, ProverError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ProverError>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        match request().await {
+            Ok(value) => return Ok(value),
+            Err(err) if err.is_retryable() && attempts < REQUEST_ATTEMPTS => {
+                let delay = REQUEST_RETRY_DELAY * attempts;
+                warn!(
+                    request = label,
+                    attempt = attempts,
+                    error = %err,
+                    ?delay,
+                    "Atlantic request failed, retrying"
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
 
-        if finish_handle.is_shutdown_requested() {
-            return Err(ProverError::Shutdown);
+/// Runs an Atlantic job until its query is done.
+///
+/// The query stored in the database for the block is waited on if there's one, otherwise a new
+/// query is submitted with `submit` and stored, once the spend is within the policy's budget.
+/// Queries which fail, can't be found anymore or don't complete within the policy's timeout are
+/// resubmitted, replacing the stored query, and so are submissions which are rejected or keep
+/// failing, until `max_attempts` attempts were made and [`ProverError::BlockFail`] is returned.
+/// Attempts are counted in the database, so that they carry over restarts.
+///
+/// Submitted queries are recorded along with their job size, end and reported cost.
+pub async fn run_query<DB, F, Fut>(
+    client: &AtlanticClient,
+    db: &DB,
+    block_number: u32,
+    query: Query,
+    policy: AtlanticQueryPolicy,
+    finish_handle: &FinishHandle,
+    mut submit: F,
+) -> Result<AtlanticQueryResponse, ProverError>
+where
+    DB: PersistantStorage,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(String, AtlanticJobSize), ProverError>>,
+{
+    let mut stored_query_id = db.get_query_id(block_number, query).await.ok();
+    let previous_attempts = db
+        .get_query_attempts(block_number, query)
+        .await
+        .map_err(|e| ProverError::Prover(e.to_string()))?;
+    let mut attempts = match &stored_query_id {
+        Some(atlantic_query_id) => {
+            info!(
+                block_number,
+                %atlantic_query_id,
+                ?query,
+                attempt = previous_attempts,
+                "Atlantic query already submitted for block"
+            );
+            // Databases predating the count of attempts have none for the stored query.
+            previous_attempts.max(1)
         }
+        None => previous_attempts + 1,
+    };
 
-        if let Ok(query) = client.clone().get_atlantic_query(&atlantic_query_id).await {
-            match query.atlantic_query.status {
-                AtlanticQueryStatus::Done => break query,
-                AtlanticQueryStatus::Failed => {
-                    return Err(ProverError::BlockFail(format!(
-                        "Proof generation failed for query: {}",
-                        atlantic_query_id
-                    )));
+    loop {
+        let submitted = match stored_query_id.take() {
+            Some(atlantic_query_id) => Ok(atlantic_query_id),
+            None => {
+                wait_for_budget(db, policy.budget, finish_handle).await?;
+                submit_query(db, block_number, query, attempts, &mut submit).await
+            }
+        };
+
+        let reason = match submitted {
+            Ok(atlantic_query_id) => {
+                match wait_for_query(client, &atlantic_query_id, policy.timeout, finish_handle)
+                    .await
+                {
+                    Ok(response) => {
+                        let cost = response.atlantic_query.credits_used;
+                        db.complete_query_record(&atlantic_query_id, Utc::now().timestamp(), cost)
+                            .await
+                            .map_err(|e| ProverError::Prover(e.to_string()))?;
+                        info!(
+                            block_number,
+                            %atlantic_query_id,
+                            ?query,
+                            ?cost,
+                            status = ?response.atlantic_query.status,
+                            "Atlantic query ended"
+                        );
+
+                        if response.atlantic_query.status == AtlanticQueryStatus::Done {
+                            return Ok(response);
+                        }
+                        format!("Proof generation failed for query: {}", atlantic_query_id)
+                    }
+                    Err(ProverError::BlockFail(reason)) => {
+                        give_up_query(client, db, &atlantic_query_id).await?;
+                        reason
+                    }
+                    Err(err) => return Err(err),
                 }
-                _ => continue,
             }
+            Err(ProverError::BlockFail(reason)) => reason,
+            Err(err) => return Err(err),
+        };
+
+        if attempts >= policy.max_attempts {
+            return Err(ProverError::BlockFail(format!(
+                "{} (gave up after {} attempts)",
+                reason, attempts
+            )));
+        }
+        warn!(
+            block_number,
+            ?query,
+            attempt = attempts,
+            error = %reason,
+            "Atlantic query did not complete, resubmitting"
+        );
+        attempts += 1;
+    }
+}
+
+/// Submits and stores a query, returning its ID.
+///
+/// Submissions which are rejected or keep failing end with [`ProverError::BlockFail`], and are
+/// counted as attempts all the same.
+async fn submit_query<DB, F, Fut>(
+    db: &DB,
+    block_number: u32,
+    query: Query,
+    attempt: u32,
+    submit: F,
+) -> Result<String, ProverError>
+where
+    DB: PersistantStorage,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(String, AtlanticJobSize), ProverError>>,
+{
+    let (atlantic_query_id, job_size) = match retry_request("submit", submit).await {
+        Ok(submitted) => submitted,
+        Err(ProverError::Shutdown) => return Err(ProverError::Shutdown),
+        Err(err) => {
+            db.add_query_attempt(block_number, query)
+                .await
+                .map_err(|e| ProverError::Prover(e.to_string()))?;
+            return Err(ProverError::BlockFail(format!(
+                "Failed to submit query: {}",
+                err
+            )));
+        }
+    };
+
+    db.add_query_id(block_number, atlantic_query_id.clone(), query)
+        .await
+        .map_err(|e| ProverError::Prover(e.to_string()))?;
+    db.add_query_record(QueryRecord {
+        query_id: atlantic_query_id.clone(),
+        block_number,
+        query_type: query,
+        job_size: job_size.as_str().to_string(),
+        submitted_at: Utc::now().timestamp(),
+        completed_at: None,
+        cost: None,
+        abandoned: false,
+    })
+    .await
+    .map_err(|e| ProverError::Prover(e.to_string()))?;
+
+    info!(
+        block_number,
+        %atlantic_query_id,
+        ?query,
+        job_size = job_size.as_str(),
+        attempt,
+        "Atlantic query submitted for block"
+    );
+    Ok(atlantic_query_id)
+}
+
+/// Downloads a result of a done query, retrying as [`retry_request`] does, and failing the block
+/// if it still can't be downloaded.
+pub async fn download_result<F, Fut, T>(label: &str, request: F) -> Result<T, ProverError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ProverError>>,
+{
+    retry_request(label, request)
+        .await
+        .map_err(|err| match err {
+            ProverError::Shutdown => err,
+            err => ProverError::BlockFail(format!("Failed to download query result: {}", err)),
+        })
+}
+
+/// Records the end of a query given up on, with its reported cost if it ended in the meantime, or
+/// as abandoned otherwise so that its cost is no longer reserved from the budget.
+async fn give_up_query<DB>(
+    client: &AtlanticClient,
+    db: &DB,
+    atlantic_query_id: &str,
+) -> Result<(), ProverError>
+where
+    DB: PersistantStorage,
+{
+    let now = Utc::now().timestamp();
+    let result = match client.clone().get_atlantic_query(atlantic_query_id).await {
+        Ok(response)
+            if matches!(
+                response.atlantic_query.status,
+                AtlanticQueryStatus::Done | AtlanticQueryStatus::Failed
+            ) =>
+        {
+            db.complete_query_record(atlantic_query_id, now, response.atlantic_query.credits_used)
+                .await
+        }
+        _ => {
+            warn!(
+                %atlantic_query_id,
+                "Abandoning Atlantic query, its cost won't be accounted for"
+            );
+            db.abandon_query_record(atlantic_query_id, now).await
         }
     };
-    Ok(response)
+    result.map_err(|e| ProverError::Prover(e.to_string()))
+}
+
+/// Waits until the spend of the current day and month is within `budget`.
+async fn wait_for_budget<DB>(
+    db: &DB,
+    budget: AtlanticBudget,
+    finish_handle: &FinishHandle,
+) -> Result<(), ProverError>
+where
+    DB: PersistantStorage,
+{
+    let mut paused = false;
+    while let Some((period, spent)) = budget.exceeded(db).await? {
+        if !paused {
+            warn!(
+                period,
+                spent, "Atlantic budget exceeded, pausing query submission"
+            );
+            paused = true;
+        }
+
+        tokio::select! {
+            _ = finish_handle.shutdown_requested() => return Err(ProverError::Shutdown),
+            _ = tokio::time::sleep(BUDGET_CHECK_INTERVAL) => {},
+        }
+    }
+
+    if paused {
+        info!("Atlantic spend is within budget, resuming query submission");
+    }
+    Ok(())
+}
+
+/// Polls a query until it ends, either done or failed.
+///
+/// Retryable errors are logged and the query polled again, while a fatal error or the query not
+/// ending within `timeout` end the wait with [`ProverError::BlockFail`].
+pub async fn wait_for_query(
+    client: &AtlanticClient,
+    atlantic_query_id: &str,
+    timeout: Duration,
+    finish_handle: &FinishHandle,
+) -> Result<AtlanticQueryResponse, ProverError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        tokio::select! {
+            _ = finish_handle.shutdown_requested() => return Err(ProverError::Shutdown),
+            _ = tokio::time::sleep(PROOF_STATUS_POLL_INTERVAL) => {},
+        }
+
+        match client.clone().get_atlantic_query(atlantic_query_id).await {
+            Ok(query) => match query.atlantic_query.status {
+                AtlanticQueryStatus::Done | AtlanticQueryStatus::Failed => return Ok(query),
+                AtlanticQueryStatus::Received | AtlanticQueryStatus::InProgress => {}
+            },
+            Err(err) if err.is_retryable() => {
+                warn!(
+                    %atlantic_query_id,
+                    error = %err,
+                    "Failed to fetch Atlantic query status"
+                );
+            }
+            Err(err) => {
+                return Err(ProverError::BlockFail(format!(
+                    "Failed to fetch query {}: {}",
+                    atlantic_query_id, err
+                )));
+            }
+        }
+
+        if Instant::now() >= deadline {
+            return Err(ProverError::BlockFail(format!(
+                "Query {} did not complete within {:?}",
+                atlantic_query_id, timeout
+            )));
+        }
+    }
 }
 
 pub async fn parse_and_store_proof<P, DB>(
@@ -85,6 +524,322 @@ where
 
     Ok(SnosProof {
         block_number: block_number as u64,
+        first_block: None,
         proof: parsed_proof,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use reqwest::StatusCode;
+    use saya_atlantic_mock::{MockAtlantic, QueryOutcome};
+    use saya_core::storage::SqliteDb;
+
+    use super::*;
+
+    const API_KEY: &str = "key";
+
+    fn unavailable() -> ProverError {
+        ProverError::Status {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            body: String::new(),
+        }
+    }
+
+    async fn submit_trace_query(
+        client: &AtlanticClient,
+    ) -> Result<(String, AtlanticJobSize), ProverError> {
+        submit_trace(client, "trace", vec![], vec![], AtlanticJobSize::XS).await
+    }
+
+    async fn record_query(
+        db: &SqliteDb,
+        query_id: &str,
+        job_size: AtlanticJobSize,
+        completed_at: Option<i64>,
+        cost: Option<f64>,
+    ) {
+        db.add_query_record(QueryRecord {
+            query_id: query_id.to_string(),
+            block_number: 1,
+            query_type: Query::SnosProof,
+            job_size: job_size.as_str().to_string(),
+            submitted_at: completed_at.unwrap_or_else(|| Utc::now().timestamp()),
+            completed_at,
+            cost,
+            abandoned: false,
+        })
+        .await
+        .unwrap();
+    }
+
+    async fn db_with_block(block_number: u32) -> SqliteDb {
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        db.initialize_block(block_number).await.unwrap();
+        db
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_request_retries_retryable_errors() {
+        let attempts = &AtomicU32::new(0);
+        let value = retry_request("test", || async move {
+            match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err(unavailable()),
+                _ => Ok(42),
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(value, 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_request_gives_up() {
+        let attempts = &AtomicU32::new(0);
+        let result: Result<(), _> = retry_request("test", || async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(unavailable())
+        })
+        .await;
+        assert!(result.unwrap_err().is_retryable());
+        assert_eq!(attempts.load(Ordering::SeqCst), REQUEST_ATTEMPTS);
+
+        // Errors coming from the request itself aren't retried.
+        let attempts = &AtomicU32::new(0);
+        let result: Result<(), _> = retry_request("test", || async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(ProverError::Status {
+                status: StatusCode::BAD_REQUEST,
+                body: String::new(),
+            })
+        })
+        .await;
+        assert!(!result.unwrap_err().is_retryable());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_query_resubmits_stuck_queries() {
+        let mock = MockAtlantic::start(API_KEY).await.unwrap();
+        // Queries are stuck in the `RECEIVED` status, and the first submission fails.
+        mock.set_status_polls(1000);
+        mock.fail_next_submissions(1);
+        let client = AtlanticClient::new(mock.url(), API_KEY.to_string());
+        let db = db_with_block(1).await;
+        let policy = AtlanticQueryPolicy {
+            timeout: Duration::ZERO,
+            max_attempts: 2,
+            ..Default::default()
+        };
+
+        let result = run_query(
+            &client,
+            &db,
+            1,
+            Query::BridgeTrace,
+            policy,
+            &FinishHandle::new(),
+            || submit_trace_query(&client),
+        )
+        .await;
+        match result {
+            Err(ProverError::BlockFail(reason)) => {
+                assert!(reason.contains("did not complete"), "{reason}");
+                assert!(reason.contains("gave up after 2 attempts"), "{reason}");
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        // Each query is given up on after its first poll, then polled once more for its end.
+        let queries = mock.queries();
+        assert_eq!(queries.len(), 2);
+        assert!(queries.iter().all(|query| query.polls == 2));
+        assert_eq!(
+            db.get_query_id(1, Query::BridgeTrace).await.unwrap(),
+            queries[1].id
+        );
+        assert_eq!(
+            db.get_query_attempts(1, Query::BridgeTrace).await.unwrap(),
+            2
+        );
+        let records = db.get_query_records(1).await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|record| record.abandoned));
+    }
+
+    #[tokio::test]
+    async fn test_run_query_counts_rejected_submissions() {
+        let mock = MockAtlantic::start(API_KEY).await.unwrap();
+        // Submissions with another API key are rejected, and not retried.
+        let client = AtlanticClient::new(mock.url(), "other-key".to_string());
+        let db = db_with_block(1).await;
+        let policy = AtlanticQueryPolicy {
+            max_attempts: 2,
+            ..Default::default()
+        };
+
+        let result = run_query(
+            &client,
+            &db,
+            1,
+            Query::BridgeTrace,
+            policy,
+            &FinishHandle::new(),
+            || submit_trace_query(&client),
+        )
+        .await;
+        match result {
+            Err(ProverError::BlockFail(reason)) => {
+                assert!(reason.contains("Failed to submit query"), "{reason}");
+                assert!(reason.contains("gave up after 2 attempts"), "{reason}");
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        assert!(mock.queries().is_empty());
+        assert!(db.get_query_records(1).await.unwrap().is_empty());
+        assert_eq!(
+            db.get_query_attempts(1, Query::BridgeTrace).await.unwrap(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_query_resubmits_after_failing_submissions() {
+        let mock = MockAtlantic::start(API_KEY).await.unwrap();
+        // The first attempt runs out of retries.
+        mock.fail_next_submissions(REQUEST_ATTEMPTS as usize);
+        let client = AtlanticClient::new(mock.url(), API_KEY.to_string());
+        let db = db_with_block(1).await;
+        let policy = AtlanticQueryPolicy {
+            max_attempts: 2,
+            ..Default::default()
+        };
+
+        run_query(
+            &client,
+            &db,
+            1,
+            Query::BridgeTrace,
+            policy,
+            &FinishHandle::new(),
+            || submit_trace_query(&client),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(mock.queries().len(), 1);
+        assert_eq!(
+            db.get_query_attempts(1, Query::BridgeTrace).await.unwrap(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_query_counts_attempts_of_previous_runs() {
+        let mock = MockAtlantic::start(API_KEY).await.unwrap();
+        let client = AtlanticClient::new(mock.url(), API_KEY.to_string());
+        let db = db_with_block(1).await;
+        // A previous run submitted two queries, the last of which fails.
+        let query_id = mock.insert_query(QueryOutcome::Failed);
+        for query_id in ["previous-query".to_string(), query_id] {
+            db.add_query_id(1, query_id, Query::SnosProof)
+                .await
+                .unwrap();
+        }
+        let policy = AtlanticQueryPolicy {
+            max_attempts: 2,
+            ..Default::default()
+        };
+
+        let result = run_query(
+            &client,
+            &db,
+            1,
+            Query::SnosProof,
+            policy,
+            &FinishHandle::new(),
+            || submit_trace_query(&client),
+        )
+        .await;
+        match result {
+            Err(ProverError::BlockFail(reason)) => {
+                assert!(reason.contains("gave up after 2 attempts"), "{reason}")
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        // The stored query was the last attempt, so no other one is submitted.
+        assert_eq!(mock.queries().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_budget_reserves_in_flight_queries() {
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let now = Utc::now().timestamp();
+        // Ended before the current month.
+        record_query(
+            &db,
+            "old",
+            AtlanticJobSize::L,
+            Some(now - 40 * 24 * 60 * 60),
+            Some(100.0),
+        )
+        .await;
+        record_query(&db, "ended", AtlanticJobSize::S, Some(now), Some(2.0)).await;
+        let daily = AtlanticBudget {
+            daily: Some(5.0),
+            monthly: None,
+        };
+        let monthly = AtlanticBudget {
+            daily: None,
+            monthly: Some(5.0),
+        };
+
+        assert_eq!(AtlanticBudget::default().exceeded(&db).await.unwrap(), None);
+        assert_eq!(daily.exceeded(&db).await.unwrap(), None);
+        assert_eq!(monthly.exceeded(&db).await.unwrap(), None);
+
+        // Queries in flight are reserved the cost of the ended query of the same job size.
+        record_query(&db, "in-flight-1", AtlanticJobSize::S, None, None).await;
+        assert_eq!(daily.exceeded(&db).await.unwrap(), None);
+        record_query(&db, "in-flight-2", AtlanticJobSize::S, None, None).await;
+        assert_eq!(daily.exceeded(&db).await.unwrap(), Some(("daily", 6.0)));
+        assert_eq!(monthly.exceeded(&db).await.unwrap(), Some(("monthly", 6.0)));
+
+        db.abandon_query_record("in-flight-2", now).await.unwrap();
+        assert_eq!(daily.exceeded(&db).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_budget_pauses_until_shutdown() {
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let now = Utc::now().timestamp();
+        record_query(&db, "ended", AtlanticJobSize::S, Some(now), Some(2.0)).await;
+        let finish_handle = FinishHandle::new();
+
+        let budget = AtlanticBudget {
+            daily: Some(5.0),
+            monthly: None,
+        };
+        wait_for_budget(&db, budget, &finish_handle).await.unwrap();
+
+        let budget = AtlanticBudget {
+            daily: None,
+            monthly: Some(1.0),
+        };
+        let mut wait = std::pin::pin!(wait_for_budget(&db, budget, &finish_handle));
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), wait.as_mut())
+                .await
+                .is_err()
+        );
+
+        finish_handle.shutdown_handle().shutdown();
+        assert!(matches!(wait.await, Err(ProverError::Shutdown)));
+    }
+}
@@ -1,32 +1,32 @@
-use std::{io::Write, sync::Arc, time::Duration};
+use std::io::Write;
 
 use anyhow::Result;
 use cairo_vm::vm::runners::cairo_pie::CairoPie;
-use starknet::core::types::Felt;
 use tokio::{
-    sync::{
-        mpsc::{Receiver, Sender},
-        Mutex,
-    },
+    sync::mpsc::{Receiver, Sender},
     task,
 };
 use tracing::{debug, error, info, trace};
+use url::Url;
 use zip::{write::FileOptions, ZipWriter};
 
 use crate::{
     atlantic::{
-        client::{AtlanticClient, Layout},
-        shared::{calculate_job_size, parse_and_store_proof, wait_for_query},
+        client::{AtlanticClient, Layout, ATLANTIC_API_BASE},
+        shared::{
+            download_result, parse_and_store_proof, run_query, submit_pie, AtlanticQueryPolicy,
+        },
         AtlanticProof,
     },
     error::ProverError,
-    utils::{compute_program_hash_from_pie, extract_pie_output, stark_proof_mock},
+    utils::{bootloader_snos_output, stark_proof_mock},
 };
 use saya_core::{
     block_ingestor::BlockInfo,
+    metrics,
     prover::{PipelineStage, PipelineStageBuilder, SnosProof},
-    service::{Daemon, FinishHandle, ShutdownHandle},
-    storage::{PersistantStorage, Step},
+    service::{Daemon, FinishHandle, ShutdownHandle, WorkerHandle, WorkerPool, WorkerPoolConfig},
+    storage::{PersistantStorage, Query, Step},
 };
 /// Prover implementation as a client to the hosted [Atlantic Prover](https://atlanticprover.com/)
 /// service.
@@ -38,18 +38,21 @@ pub struct AtlanticSnosProver<P, DB> {
     finish_handle: FinishHandle,
     /// Whether to extract the output and compute the program hash from the PIE or use the one from the SHARP bootloader returned by the prover service.
     mock_snos_from_pie: bool,
+    query_policy: AtlanticQueryPolicy,
     db: DB,
-    worker_count: usize,
+    workers: WorkerPoolConfig,
 }
 
 #[derive(Debug)]
 pub struct AtlanticSnosProverBuilder<P, DB> {
+    api_base: Url,
     api_key: String,
     input_channel: Option<Receiver<BlockInfo>>,
     output_channel: Option<Sender<SnosProof<P>>>,
     mock_snos_from_pie: bool,
+    query_policy: AtlanticQueryPolicy,
     db: DB,
-    worker_count: usize,
+    workers: WorkerPoolConfig,
 }
 
 impl<P, DB> AtlanticSnosProver<P, DB>
@@ -58,11 +61,12 @@ where
     DB: PersistantStorage + Send + Sync + Clone + 'static,
 {
     async fn worker(
-        task_rx: Arc<Mutex<Receiver<BlockInfo>>>,
+        worker: WorkerHandle<BlockInfo>,
         task_tx: Sender<SnosProof<P>>,
         client: AtlanticClient,
         finish_handle: FinishHandle,
         mock_snos_from_pie: bool,
+        query_policy: AtlanticQueryPolicy,
         db: DB,
     ) -> Result<(), ProverError>
     where
@@ -70,8 +74,8 @@ where
         DB: PersistantStorage,
     {
         loop {
-            let new_block = if let Some(new_block) = task_rx.lock().await.recv().await {
-                new_block
+            let (new_block, _task) = if let Some(task) = worker.recv().await {
+                task
             } else {
                 break;
             };
@@ -92,6 +96,7 @@ where
                     let parsed_proof: P = P::parse(raw_proof).unwrap();
                     let new_proof = SnosProof {
                         block_number: new_block.number,
+                        first_block: new_block.first_block,
                         proof: parsed_proof,
                     };
                     let _ = task_tx.send(new_proof).await;
@@ -112,104 +117,35 @@ where
                 continue;
             }
 
-            match db
-                .get_query_id(block_number_u32, saya_core::storage::Query::SnosProof)
-                .await
-            {
-                Ok(atlantic_query_id) => {
-                    info!(
-                        block_number = new_block.number,
-                        atlantic_query_id = %atlantic_query_id,
-                        "Atlantic proof generation already submitted for block",
-                    );
-                    let query_response = match wait_for_query(
-                        client.clone(),
-                        atlantic_query_id.clone(),
-                        finish_handle.clone(),
-                    )
-                    .await
-                    {
-                        Err(ProverError::Shutdown) => {
-                            break;
-                        }
-                        Err(ProverError::BlockFail(e)) => {
-                            error!("{}", e);
-                            db.add_failed_block(block_number_u32, e).await.unwrap();
-                            continue;
-                        }
-                        Err(e) => {
-                            error!(
-                                "Unreachable error: {:?} while processing query {}",
-                                e, atlantic_query_id
-                            );
-                            unreachable!("Unexpected ProverError: {:?}", e);
-                        }
-                        Ok(response) => response,
-                    };
-
-                    let raw_proof = query_response.get_proof(&client).await?;
-
-                    let new_proof =
-                        parse_and_store_proof(raw_proof, db.clone(), block_number_u32, Step::Snos)
-                            .await?;
-
-                    tokio::select! {
-                        _ = finish_handle.shutdown_requested() => break,
-                        _ = task_tx.send(new_proof) => {},
-                    }
-                    continue;
-                }
-                Err(_) => {
-                    // Not found in db, we continue.
-                }
-            }
-            // TODO: error handling
-
+            let stage_timer = metrics::stage_timer("snos_proof");
             let compressed_pie: Vec<u8> = db
-                .get_pie(block_number_u32, saya_core::storage::Step::Snos)
+                .get_pie(block_number_u32, Step::Snos)
                 .await
-                .unwrap();
+                .map_err(|e| ProverError::MetadataFetch(e.to_string()))?;
 
             debug!(
                 "Compressed PIE size for block #{}: {} bytes",
                 new_block.number,
                 compressed_pie.len()
             );
-            let atlantic_job_size =
-                calculate_job_size(CairoPie::from_bytes(&compressed_pie).unwrap());
-            let atlantic_query_id = crate::utils::retry_with_backoff(
+            let label = format!("snos_{}", new_block.number);
+            let query_response = match run_query(
+                &client,
+                &db,
+                block_number_u32,
+                Query::SnosProof,
+                query_policy,
+                &finish_handle,
                 || {
-                    client.submit_proof_generation(
+                    submit_pie(
+                        &client,
                         compressed_pie.clone(),
                         Layout::dynamic,
-                        format!("snos_{}", new_block.number),
-                        atlantic_job_size,
+                        label.clone(),
+                        query_policy.job_sizes.snos_proof,
+                        None,
                     )
                 },
-                "submit_proof_generation",
-                3,
-                Duration::from_secs(5),
-            )
-            .await?;
-
-            db.add_query_id(
-                new_block.number.try_into().unwrap(),
-                atlantic_query_id.clone(),
-                saya_core::storage::Query::SnosProof,
-            )
-            .await
-            .unwrap();
-
-            info!(
-                block_number = new_block.number,
-                atlantic_query_id = %atlantic_query_id,
-                "Atlantic proof generation submitted for block"
-            );
-
-            let query_response = match wait_for_query(
-                client.clone(),
-                atlantic_query_id.clone(),
-                finish_handle.clone(),
             )
             .await
             {
@@ -217,28 +153,41 @@ where
                     break;
                 }
                 Err(ProverError::BlockFail(e)) => {
-                    error!("{}", e);
-                    db.add_failed_block(block_number_u32, e).await.unwrap();
-                    continue;
-                }
-                Err(e) => {
                     error!(
-                        "Unreachable error: {:?} while processing query {}",
-                        e, atlantic_query_id
+                        block_number = new_block.number,
+                        error = %e,
+                        "SNOS proof generation failed"
                     );
-                    unreachable!("Unexpected ProverError: {:?}", e);
+                    db.add_failed_block(block_number_u32, e).await.unwrap();
+                    continue;
                 }
+                Err(e) => return Err(e),
                 Ok(response) => response,
             };
 
             debug!(
                 "Atlantic PIE proof generation finished for query: {}",
-                atlantic_query_id
+                query_response.atlantic_query.id
             );
-            let raw_proof = query_response.get_proof(&client).await?;
+            let raw_proof =
+                match download_result("get_proof", || query_response.get_proof(&client)).await {
+                    Err(ProverError::BlockFail(e)) => {
+                        error!(
+                            block_number = new_block.number,
+                            error = %e,
+                            "SNOS proof download failed"
+                        );
+                        db.add_failed_block(block_number_u32, e).await.unwrap();
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                    Ok(raw_proof) => raw_proof,
+                };
 
-            let new_proof =
+            let mut new_proof =
                 parse_and_store_proof(raw_proof, db.clone(), block_number_u32, Step::Snos).await?;
+            new_proof.first_block = new_block.first_block;
+            stage_timer.observe();
 
             tokio::select! {
                 _ = finish_handle.shutdown_requested() => break,
@@ -249,20 +198,33 @@ where
     }
 
     async fn run(self) {
-        let mut workers = Vec::new();
-        let task_rx = Arc::new(Mutex::new(self.input_channel));
-        for _ in 0..self.worker_count {
-            let worker_task_tx = self.output_channel.clone();
-            workers.push(task::spawn(Self::worker(
-                task_rx.clone(),
-                worker_task_tx,
+        let pool = WorkerPool::new(
+            "snos_proof",
+            self.input_channel,
+            self.workers,
+            self.finish_handle.clone(),
+        );
+        pool.run(|worker| {
+            let finish_handle = self.finish_handle.clone();
+            let worker = Self::worker(
+                worker,
+                self.output_channel.clone(),
                 self.client.clone(),
                 self.finish_handle.clone(),
                 self.mock_snos_from_pie,
+                self.query_policy,
                 self.db.clone(),
-            )));
-        }
-        futures_util::future::join_all(workers).await;
+            );
+
+            async move {
+                if let Err(err) = worker.await {
+                    error!(error = %err, "SNOS proof worker failed");
+                    finish_handle.fail(format!("worker failed: {}", err));
+                    finish_handle.shutdown_handle().shutdown();
+                }
+            }
+        })
+        .await;
         debug!("Graceful shutdown finished");
         self.finish_handle.finish();
     }
@@ -286,6 +248,7 @@ where
 
         let new_proof = SnosProof {
             block_number: new_block.number,
+            first_block: new_block.first_block,
             proof: P::parse(serde_json::to_string(&mock_proof).unwrap()).unwrap(),
         };
 
@@ -295,16 +258,35 @@ where
 }
 
 impl<P, DB> AtlanticSnosProverBuilder<P, DB> {
-    pub fn new(api_key: String, mock_snos_from_pie: bool, db: DB, worker_count: usize) -> Self {
+    pub fn new(
+        api_key: String,
+        mock_snos_from_pie: bool,
+        db: DB,
+        workers: WorkerPoolConfig,
+    ) -> Self {
         Self {
+            api_base: Url::parse(ATLANTIC_API_BASE).unwrap(),
             api_key,
             input_channel: None,
             output_channel: None,
             mock_snos_from_pie,
+            query_policy: AtlanticQueryPolicy::default(),
             db,
-            worker_count,
+            workers,
         }
     }
+
+    /// Overrides the URL of the Atlantic API, e.g. to use a mock server.
+    pub fn api_base(mut self, api_base: Url) -> Self {
+        self.api_base = api_base;
+        self
+    }
+
+    /// Sets the timeout and attempts of the Atlantic queries.
+    pub fn query_policy(mut self, query_policy: AtlanticQueryPolicy) -> Self {
+        self.query_policy = query_policy;
+        self
+    }
 }
 
 impl<P, DB> PipelineStageBuilder for AtlanticSnosProverBuilder<P, DB>
@@ -316,7 +298,7 @@ where
 
     fn build(self) -> Result<Self::Stage> {
         Ok(AtlanticSnosProver {
-            client: AtlanticClient::new(self.api_key),
+            client: AtlanticClient::new(self.api_base, self.api_key),
             input_channel: self
                 .input_channel
                 .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
@@ -325,8 +307,9 @@ where
                 .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
             finish_handle: FinishHandle::new(),
             mock_snos_from_pie: self.mock_snos_from_pie,
+            query_policy: self.query_policy,
             db: self.db,
-            worker_count: self.worker_count,
+            workers: self.workers,
         })
     }
 
@@ -360,7 +343,7 @@ where
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
 
@@ -392,22 +375,130 @@ pub async fn compress_pie(pie: CairoPie) -> std::result::Result<Vec<u8>, std::io
     .await?
 }
 
-/// Mocks a bootloaded execution of SNOS.
-fn bootloader_snos_output(pie: &CairoPie) -> Vec<Felt> {
-    let snos_program_hash =
-        compute_program_hash_from_pie(pie).expect("Failed to compute program hash from PIE");
-    debug!(snos_program_hash = %snos_program_hash, "SNOS program hash from PIE");
+#[cfg(test)]
+mod tests {
+    use saya_atlantic_mock::{MockAtlantic, QueryOutcome};
+    use saya_core::storage::{BlockStatus, SqliteDb};
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::atlantic::{AtlanticJobSize, AtlanticJobSizes};
+
+    const API_KEY: &str = "key";
+
+    /// Starts a prover sending its queries to `mock`, returning the channels it takes blocks from
+    /// and outputs proofs to.
+    fn start_prover(
+        mock: &MockAtlantic,
+        db: SqliteDb,
+        query_policy: AtlanticQueryPolicy,
+    ) -> (
+        Sender<BlockInfo>,
+        Receiver<SnosProof<String>>,
+        ShutdownHandle,
+    ) {
+        let (block_tx, block_rx) = mpsc::channel(1);
+        let (proof_tx, proof_rx) = mpsc::channel(1);
+        let prover = AtlanticSnosProverBuilder::new(
+            API_KEY.to_string(),
+            false,
+            db,
+            WorkerPoolConfig::fixed(1),
+        )
+        .api_base(mock.url())
+        .query_policy(query_policy)
+        .input_channel(block_rx)
+        .output_channel(proof_tx)
+        .build()
+        .unwrap();
+        let shutdown_handle = prover.shutdown_handle();
+        prover.start();
+
+        (block_tx, proof_rx, shutdown_handle)
+    }
+
+    async fn db_with_pie(block_number: u32) -> SqliteDb {
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        db.initialize_block(block_number).await.unwrap();
+        db.add_pie(block_number, vec![1, 2, 3], Step::Snos)
+            .await
+            .unwrap();
+        db
+    }
+
+    fn block(number: u64) -> BlockInfo {
+        BlockInfo {
+            number,
+            status: BlockStatus::SnosPieGenerated,
+            state_update: None,
+            first_block: None,
+        }
+    }
 
-    let snos_output = extract_pie_output(pie);
+    #[tokio::test]
+    async fn test_resumes_stored_query() {
+        let mock = MockAtlantic::start(API_KEY).await.unwrap();
+        let query_id = mock.insert_query(QueryOutcome::Done {
+            proof: "snos-proof".to_string(),
+            pie: vec![],
+        });
+        let db = db_with_pie(1).await;
+        db.add_query_id(1, query_id.clone(), Query::SnosProof)
+            .await
+            .unwrap();
+        let (block_tx, mut proof_rx, shutdown_handle) =
+            start_prover(&mock, db.clone(), AtlanticQueryPolicy::default());
+
+        block_tx.send(block(1)).await.unwrap();
+        let proof = proof_rx.recv().await.unwrap();
+        assert_eq!(proof.block_number, 1);
+        assert_eq!(proof.proof, "snos-proof");
+        assert_eq!(db.get_proof(1, Step::Snos).await.unwrap(), b"snos-proof");
+
+        // The query of the previous run is waited on instead of submitting a new one.
+        let queries = mock.queries();
+        assert_eq!(queries.len(), 1);
+        assert_eq!(queries[0].id, query_id);
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+        assert!(shutdown_handle.failure().is_none());
+    }
 
-    let mut bootloader_output = vec![
-        // Bootloader constants (number of task executed by bootloader, in case of herodotus its always 1)
-        Felt::ONE,
-        // bootloader output len (not checked by piltover, set to 0)
-        Felt::ZERO,
-        snos_program_hash,
-    ];
+    #[tokio::test]
+    async fn test_records_block_once_queries_failed() {
+        let mock = MockAtlantic::start(API_KEY).await.unwrap();
+        mock.set_default_outcome(QueryOutcome::Failed);
+        let db = db_with_pie(1).await;
+        let query_policy = AtlanticQueryPolicy {
+            max_attempts: 2,
+            // The mock PIE has no steps to derive the job size from.
+            job_sizes: AtlanticJobSizes {
+                snos_proof: Some(AtlanticJobSize::S),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (block_tx, mut proof_rx, shutdown_handle) =
+            start_prover(&mock, db.clone(), query_policy);
+
+        block_tx.send(block(1)).await.unwrap();
+        drop(block_tx);
+        shutdown_handle.finished().await;
+        assert!(shutdown_handle.failure().is_none());
+        assert!(proof_rx.recv().await.is_none());
+
+        let queries = mock.queries();
+        assert_eq!(queries.len(), 2);
+        for query in queries {
+            assert_eq!(query.files["pieFile"], vec![1, 2, 3]);
+            assert_eq!(query.fields["declaredJobSize"], "S");
+        }
+        assert_eq!(db.get_query_records(1).await.unwrap().len(), 2);
 
-    bootloader_output.extend(snos_output);
-    bootloader_output
+        let failed_blocks = db.get_failed_blocks().await.unwrap();
+        assert_eq!(failed_blocks.len(), 1);
+        assert_eq!(failed_blocks[0].0, 1);
+        assert!(failed_blocks[0].1.contains("gave up after 2 attempts"));
+    }
 }
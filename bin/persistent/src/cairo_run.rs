@@ -0,0 +1,224 @@
+//! Runs of Cairo programs by the simple bootloader in proof mode, generating the traces proven by
+//! the local provers.
+//!
+//! Proving through the bootloader yields the same outputs as Atlantic does, so that proofs are
+//! interchangeable for the layout bridge and Piltover.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use saya_core::service::FinishHandle;
+use serde_json::{json, Value};
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::error::ProverError;
+
+/// Runs programs by the bootloader with `cairo-run`.
+#[derive(Debug, Clone)]
+pub struct CairoRunner {
+    cairo_run: PathBuf,
+    bootloader_program: PathBuf,
+}
+
+/// A task run by the bootloader, whose execution gets proven.
+#[derive(Debug)]
+pub enum BootloaderTask {
+    /// A compressed Cairo PIE, as stored in the DB.
+    CairoPie(Vec<u8>),
+    /// A compiled Cairo program along with its input.
+    Program {
+        program: Value,
+        program_input: Value,
+    },
+}
+
+/// The AIR inputs of a run, as expected by the provers.
+#[derive(Debug)]
+pub struct AirInputs {
+    pub public_input: PathBuf,
+    pub private_input: PathBuf,
+    /// Length of the trace.
+    pub n_steps: u64,
+}
+
+/// A temporary directory holding the files of a single run, removed once dropped as traces take
+/// a lot of space.
+#[derive(Debug)]
+pub struct WorkDir(PathBuf);
+
+impl CairoRunner {
+    pub fn new(cairo_run: PathBuf, bootloader_program: PathBuf) -> Self {
+        Self {
+            cairo_run,
+            bootloader_program,
+        }
+    }
+
+    /// Runs `task` by the bootloader in `layout`, writing the trace to `work_dir`.
+    ///
+    /// Failures of `cairo-run` are reported as [`ProverError::BlockFail`], as they're specific to
+    /// the task, while failing to run it at all is a [`ProverError::Prover`].
+    pub async fn run(
+        &self,
+        work_dir: &WorkDir,
+        task: BootloaderTask,
+        layout: &str,
+        layout_params: Option<&Path>,
+        finish_handle: &FinishHandle,
+    ) -> Result<AirInputs, ProverError> {
+        let task = match task {
+            BootloaderTask::CairoPie(pie) => {
+                let pie_path = work_dir.join("pie.zip");
+                write_file(&pie_path, &pie).await?;
+                json!({ "type": "CairoPiePath", "path": pie_path, "use_poseidon": true })
+            }
+            BootloaderTask::Program {
+                program,
+                program_input,
+            } => json!({
+                "type": "RunProgramTask",
+                "program": program,
+                "program_input": program_input,
+                "use_poseidon": true,
+            }),
+        };
+
+        let input_path = work_dir.join("bootloader_input.json");
+        let air_inputs = AirInputs {
+            public_input: work_dir.join("public_input.json"),
+            private_input: work_dir.join("private_input.json"),
+            n_steps: 0,
+        };
+        write_json(
+            &input_path,
+            &json!({ "tasks": [task], "single_page": true }),
+        )
+        .await?;
+
+        let mut cairo_run = Command::new(&self.cairo_run);
+        cairo_run
+            .arg("--program")
+            .arg(&self.bootloader_program)
+            .arg("--program_input")
+            .arg(&input_path)
+            .arg("--layout")
+            .arg(layout)
+            .arg("--proof_mode")
+            .arg("--trace_file")
+            .arg(work_dir.join("trace.bin"))
+            .arg("--memory_file")
+            .arg(work_dir.join("memory.bin"))
+            .arg("--air_public_input")
+            .arg(&air_inputs.public_input)
+            .arg("--air_private_input")
+            .arg(&air_inputs.private_input);
+        if let Some(layout_params) = layout_params {
+            cairo_run
+                .arg("--cairo_layout_params_file")
+                .arg(layout_params);
+        }
+        run_command(cairo_run, finish_handle).await?;
+
+        let public_input: Value =
+            serde_json::from_slice(&read_file(&air_inputs.public_input).await?).map_err(|err| {
+                ProverError::BlockFail(format!("invalid AIR public input: {err}"))
+            })?;
+        let n_steps = public_input["n_steps"].as_u64().ok_or_else(|| {
+            ProverError::BlockFail("AIR public input is missing `n_steps`".to_string())
+        })?;
+        debug!(n_steps, layout, "Trace generated");
+
+        Ok(AirInputs {
+            n_steps,
+            ..air_inputs
+        })
+    }
+}
+
+impl WorkDir {
+    /// Creates the directory of the run labelled `label`.
+    pub async fn create(label: &str) -> Result<Self, ProverError> {
+        let path =
+            std::env::temp_dir().join(format!("saya-prover-{}-{}", std::process::id(), label));
+        tokio::fs::create_dir_all(&path)
+            .await
+            .map_err(|err| io_error(&path, err))?;
+
+        Ok(Self(path))
+    }
+
+    pub fn join(&self, file_name: &str) -> PathBuf {
+        self.0.join(file_name)
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        let path = std::mem::take(&mut self.0);
+        // Removing a trace can take a while, which mustn't block the runtime.
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || std::fs::remove_dir_all(path));
+            }
+            Err(_) => {
+                let _ = std::fs::remove_dir_all(path);
+            }
+        }
+    }
+}
+
+/// Runs `command` to completion, killing it if shutdown is requested in the meantime.
+pub async fn run_command(
+    mut command: Command,
+    finish_handle: &FinishHandle,
+) -> Result<(), ProverError> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let child = command
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| ProverError::Prover(format!("failed to run `{program}`: {err}")))?;
+
+    let output = tokio::select! {
+        _ = finish_handle.shutdown_requested() => return Err(ProverError::Shutdown),
+        output = child.wait_with_output() => output
+            .map_err(|err| ProverError::Prover(format!("failed to run `{program}`: {err}")))?,
+    };
+    if !output.status.success() {
+        return Err(ProverError::BlockFail(format!(
+            "`{program}` failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn read_file(path: &Path) -> Result<Vec<u8>, ProverError> {
+    tokio::fs::read(path)
+        .await
+        .map_err(|err| io_error(path, err))
+}
+
+pub async fn write_file(path: &Path, contents: &[u8]) -> Result<(), ProverError> {
+    tokio::fs::write(path, contents)
+        .await
+        .map_err(|err| io_error(path, err))
+}
+
+pub async fn write_json(path: &Path, value: &Value) -> Result<(), ProverError> {
+    write_file(path, value.to_string().as_bytes()).await
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> ProverError {
+    ProverError::Prover(format!("failed to access {}: {}", path.display(), err))
+}
@@ -1,3 +1,14 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+use saya_core::service::WorkerPoolConfig;
+
+use crate::atlantic::{
+    AtlanticBudget, AtlanticJobSize, AtlanticJobSizes, AtlanticQueryPolicy,
+    DEFAULT_QUERY_MAX_ATTEMPTS, DEFAULT_QUERY_TIMEOUT_SECS,
+};
+
 pub const SAYA_DB_PATH: &str = "saya.db";
 
 // All time values are in seconds
@@ -8,10 +19,11 @@ const PIE_GENERATION_TIME: u32 = 60;
 // Number of stages in the process
 pub const NUMBER_OF_STAGES: usize = 3;
 
-/// Calculates the number of workers required for each stage of the pipeline.
+/// Calculates the initial number of workers of each stage of the pipeline.
 ///
-/// The distribution is based on the relative proof generation times of each stage,
-/// ensuring a proportional allocation of workers.
+/// The distribution is based on the expected proof generation times of each stage,
+/// ensuring a proportional allocation of workers. Stages then scale at runtime based on their
+/// measured latency and backlog, see [`ScalingConfiguration`].
 ///
 /// # Parameters
 /// - `num_blocks_in_pipeline`: The total number of blocks that need processing in the pipeline.
@@ -41,6 +53,91 @@ pub fn calculate_workers_per_stage(num_blocks_in_pipeline: usize) -> [usize; NUM
 
     workers_count
 }
+
+#[derive(Debug, Parser, Clone)]
+pub struct ScalingConfiguration {
+    /// Minimum number of workers kept by each pipeline stage
+    #[clap(long, env, default_value_t = 1)]
+    min_workers_per_stage: usize,
+    /// Maximum number of workers of each pipeline stage. Defaults to the number of blocks
+    /// processed in parallel
+    #[clap(long, env)]
+    max_workers_per_stage: Option<usize>,
+    /// Keep the initial number of workers of each stage instead of scaling them at runtime
+    #[clap(long, env, default_value_t = false)]
+    fixed_workers: bool,
+}
+
+impl ScalingConfiguration {
+    /// Returns the worker pool of each stage, in the order of [`calculate_workers_per_stage`].
+    pub fn worker_pools(
+        &self,
+        num_blocks_in_pipeline: usize,
+    ) -> [WorkerPoolConfig; NUMBER_OF_STAGES] {
+        let max_workers = self.max_workers_per_stage.unwrap_or(num_blocks_in_pipeline);
+
+        calculate_workers_per_stage(num_blocks_in_pipeline).map(|initial_workers| {
+            if self.fixed_workers {
+                WorkerPoolConfig::fixed(initial_workers)
+            } else {
+                WorkerPoolConfig::adaptive(initial_workers, self.min_workers_per_stage, max_workers)
+            }
+        })
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct AtlanticQueryConfiguration {
+    /// Timeout in seconds for an Atlantic query to complete before it's considered stuck and
+    /// resubmitted
+    #[clap(long, env, default_value_t = DEFAULT_QUERY_TIMEOUT_SECS)]
+    atlantic_query_timeout_secs: u64,
+    /// Maximum number of Atlantic queries submitted for a proof before the block is marked as
+    /// failed
+    #[clap(long, env, default_value_t = DEFAULT_QUERY_MAX_ATTEMPTS)]
+    atlantic_max_attempts: u32,
+    /// Job size declared for SNOS proofs. Derived from the number of steps of the PIE if not set
+    #[clap(long, env, value_enum, ignore_case = true)]
+    atlantic_snos_job_size: Option<AtlanticJobSize>,
+    /// Job size declared for the layout bridge trace generation
+    #[clap(long, env, value_enum, ignore_case = true, default_value_t = AtlanticJobSize::XS)]
+    atlantic_bridge_trace_job_size: AtlanticJobSize,
+    /// Job size declared for layout bridge proofs. Derived from the number of steps of the PIE if
+    /// not set
+    #[clap(long, env, value_enum, ignore_case = true)]
+    atlantic_bridge_proof_job_size: Option<AtlanticJobSize>,
+    /// Atlantic credits that may be spent per UTC day, after which no query is submitted until the
+    /// next day. Unlimited if not set
+    #[clap(long, env)]
+    atlantic_daily_budget: Option<f64>,
+    /// Atlantic credits that may be spent per UTC month, after which no query is submitted until
+    /// the next month. Unlimited if not set
+    #[clap(long, env)]
+    atlantic_monthly_budget: Option<f64>,
+}
+
+impl AtlanticQueryConfiguration {
+    pub fn query_policy(&self) -> Result<AtlanticQueryPolicy> {
+        if self.atlantic_max_attempts == 0 {
+            anyhow::bail!("invalid config: `--atlantic-max-attempts` must be at least 1");
+        }
+
+        Ok(AtlanticQueryPolicy {
+            timeout: Duration::from_secs(self.atlantic_query_timeout_secs),
+            max_attempts: self.atlantic_max_attempts,
+            job_sizes: AtlanticJobSizes {
+                snos_proof: self.atlantic_snos_job_size,
+                bridge_trace: self.atlantic_bridge_trace_job_size,
+                bridge_proof: self.atlantic_bridge_proof_job_size,
+            },
+            budget: AtlanticBudget {
+                daily: self.atlantic_daily_budget,
+                monthly: self.atlantic_monthly_budget,
+            },
+        })
+    }
+}
+
 #[test]
 fn test_split_workers() {
     let num_blocks_in_pipeline = 110;
@@ -1,3 +1,4 @@
+use reqwest::StatusCode;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -6,6 +7,8 @@ pub enum ProverError {
     Prover(String),
     #[error("reqwest error: {0}")]
     Reqwest(#[from] reqwest::Error),
+    #[error("unsuccessful status code: {status}\n{body}")]
+    Status { status: StatusCode, body: String },
     #[error("Shutdown signal received")]
     Shutdown,
     #[error("Block fail in Prover: {0}")]
@@ -15,3 +18,90 @@ pub enum ProverError {
     #[error("{0}")]
     ProofParse(String),
 }
+
+impl ProverError {
+    /// Whether the failed request is worth sending again as is, i.e. the error comes from the
+    /// network or from the service being unavailable rather than from the request itself.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Reqwest(err) => {
+                err.is_timeout()
+                    || err.is_connect()
+                    || err.status().is_some_and(is_retryable_status)
+            }
+            Self::Status { status, .. } => is_retryable_status(*status),
+            _ => false,
+        }
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+#[cfg(test)]
+mod tests {
+    use saya_atlantic_mock::MockAtlantic;
+    use url::Url;
+
+    use super::*;
+    use crate::atlantic::AtlanticClient;
+
+    #[test]
+    fn test_unavailable_service_statuses_are_retryable() {
+        for (status, retryable) in [
+            (StatusCode::INTERNAL_SERVER_ERROR, true),
+            (StatusCode::SERVICE_UNAVAILABLE, true),
+            (StatusCode::TOO_MANY_REQUESTS, true),
+            (StatusCode::REQUEST_TIMEOUT, true),
+            (StatusCode::BAD_REQUEST, false),
+            (StatusCode::UNAUTHORIZED, false),
+            (StatusCode::NOT_FOUND, false),
+        ] {
+            let err = ProverError::Status {
+                status,
+                body: String::new(),
+            };
+            assert_eq!(err.is_retryable(), retryable, "{status}");
+        }
+    }
+
+    #[test]
+    fn test_non_request_errors_are_not_retryable() {
+        for err in [
+            ProverError::Prover(String::new()),
+            ProverError::Shutdown,
+            ProverError::BlockFail(String::new()),
+            ProverError::MetadataFetch(String::new()),
+            ProverError::ProofParse(String::new()),
+        ] {
+            assert!(!err.is_retryable(), "{err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_errors() {
+        let mock = MockAtlantic::start("key").await.unwrap();
+        let client = AtlanticClient::new(mock.url(), "key".to_string());
+        let err = client.get_atlantic_query("unknown").await.unwrap_err();
+        assert!(matches!(
+            err,
+            ProverError::Status {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }
+        ));
+        assert!(!err.is_retryable());
+
+        // Nothing listens on the port of a dropped listener.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        drop(listener);
+        let client = AtlanticClient::new(url, "key".to_string());
+        let err = client.get_atlantic_query("query").await.unwrap_err();
+        assert!(matches!(err, ProverError::Reqwest(_)));
+        assert!(err.is_retryable());
+    }
+}
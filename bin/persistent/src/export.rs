@@ -0,0 +1,106 @@
+//! `saya export-blocks` — dumps the state updates of a range of blocks, for replaying them
+//! offline with `--replay-dir`.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{ArgGroup, Parser};
+use saya_core::{
+    block_ingestor::write_state_update,
+    rpc::FailoverTransport,
+    storage::{PersistantStorage, SqliteDb},
+};
+use starknet::{
+    core::types::{BlockId, MaybePreConfirmedStateUpdate, StateUpdate},
+    providers::{JsonRpcClient, Provider},
+};
+use tracing::info;
+use url::Url;
+
+use crate::common::SAYA_DB_PATH;
+
+// Options don't read the environment, so that a configuration meant for running Saya doesn't
+// pick where blocks are exported from.
+#[derive(Debug, Parser, Clone)]
+#[clap(group(ArgGroup::new("source").required(true).args(["rollup_rpc", "db_dir"])))]
+pub struct ExportBlocks {
+    /// Rollup network Starknet JSON-RPC URLs to export the blocks from, comma-separated
+    #[clap(long, value_delimiter = ',')]
+    rollup_rpc: Vec<Url>,
+    /// Path to the database directory to export the blocks from, instead of a node
+    #[clap(long)]
+    db_dir: Option<PathBuf>,
+    /// First block to export
+    #[clap(long)]
+    start_block: u64,
+    /// Last block to export
+    #[clap(long)]
+    end_block: u64,
+    /// Directory to write the blocks to, created if missing
+    #[clap(long)]
+    output_dir: PathBuf,
+}
+
+enum BlockSource {
+    Node(JsonRpcClient<FailoverTransport>),
+    Db(SqliteDb),
+}
+
+impl ExportBlocks {
+    pub async fn run(self) -> Result<()> {
+        if self.start_block > self.end_block {
+            anyhow::bail!("`--start-block` must not be greater than `--end-block`");
+        }
+
+        let source = match &self.db_dir {
+            Some(db_dir) => {
+                let saya_path = db_dir.join(SAYA_DB_PATH);
+                if !saya_path.try_exists()? {
+                    anyhow::bail!("database not found at {}", saya_path.display());
+                }
+                BlockSource::Db(SqliteDb::new(&saya_path.display().to_string()).await?)
+            }
+            None => BlockSource::Node(JsonRpcClient::new(FailoverTransport::new(
+                self.rollup_rpc.clone(),
+            )?)),
+        };
+
+        tokio::fs::create_dir_all(&self.output_dir).await?;
+        for block_number in self.start_block..=self.end_block {
+            let state_update = source.state_update(block_number).await?;
+            write_state_update(&self.output_dir, block_number, &state_update).await?;
+            info!(block_number, "Block exported");
+        }
+
+        info!(
+            start_block = self.start_block,
+            end_block = self.end_block,
+            output_dir = %self.output_dir.display(),
+            "Blocks exported"
+        );
+        Ok(())
+    }
+}
+
+impl BlockSource {
+    async fn state_update(&self, block_number: u64) -> Result<StateUpdate> {
+        match self {
+            Self::Node(provider) => match provider
+                .get_state_update(BlockId::Number(block_number))
+                .await?
+            {
+                MaybePreConfirmedStateUpdate::Update(state_update) => Ok(state_update),
+                MaybePreConfirmedStateUpdate::PreConfirmedUpdate(_) => {
+                    anyhow::bail!("block {block_number} is not confirmed yet")
+                }
+            },
+            // Only blocks still in flight are kept in the database, settled ones are removed.
+            Self::Db(db) => db
+                .get_state_update(block_number.try_into()?)
+                .await
+                .map_err(|err| {
+                    anyhow::anyhow!("block {block_number} not found in the database: {err}")
+                }),
+        }
+    }
+}
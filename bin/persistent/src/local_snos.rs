@@ -0,0 +1,244 @@
+//! SNOS proving stage shared by the provers running on the same machine as Saya.
+
+use std::{fmt::Debug, future::Future};
+
+use anyhow::Result;
+use saya_core::{
+    block_ingestor::BlockInfo,
+    metrics,
+    prover::{PipelineStage, PipelineStageBuilder, SnosProof},
+    service::{Daemon, FinishHandle, ShutdownHandle, WorkerHandle, WorkerPool, WorkerPoolConfig},
+    storage::{PersistantStorage, Step},
+};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{debug, error, info, trace};
+
+use crate::error::ProverError;
+
+/// A prover of SNOS PIEs running locally.
+pub trait LocalPieProver: Debug + Clone + Send + Sync + 'static {
+    /// Name of the prover, as logged.
+    const NAME: &'static str;
+
+    /// Proves the bootloaded execution of the SNOS `pie`, returning the proof to be stored.
+    ///
+    /// Failures specific to the PIE are reported as [`ProverError::BlockFail`].
+    fn prove_snos(
+        &self,
+        label: &str,
+        pie: Vec<u8>,
+        finish_handle: &FinishHandle,
+    ) -> impl Future<Output = Result<String, ProverError>> + Send;
+}
+
+/// Prover implementation proving the SNOS PIEs locally with `P`.
+#[derive(Debug)]
+pub struct LocalSnosProver<P, DB> {
+    prover: P,
+    input_channel: Receiver<BlockInfo>,
+    output_channel: Sender<SnosProof<String>>,
+    finish_handle: FinishHandle,
+    db: DB,
+    workers: WorkerPoolConfig,
+}
+
+#[derive(Debug)]
+pub struct LocalSnosProverBuilder<P, DB> {
+    prover: P,
+    input_channel: Option<Receiver<BlockInfo>>,
+    output_channel: Option<Sender<SnosProof<String>>>,
+    db: DB,
+    workers: WorkerPoolConfig,
+}
+
+impl<P, DB> LocalSnosProver<P, DB>
+where
+    P: LocalPieProver,
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    async fn worker(
+        worker: WorkerHandle<BlockInfo>,
+        task_tx: Sender<SnosProof<String>>,
+        prover: P,
+        finish_handle: FinishHandle,
+        db: DB,
+    ) -> Result<(), ProverError> {
+        loop {
+            let (new_block, _task) = if let Some(task) = worker.recv().await {
+                task
+            } else {
+                break;
+            };
+            let block_number_u32 = new_block.number.try_into().map_err(|_| {
+                ProverError::Prover("Block number too large to fit in u32".to_string())
+            })?;
+
+            let proof = match db.get_proof(block_number_u32, Step::Snos).await {
+                Ok(proof) => {
+                    info!(
+                        block_number = new_block.number,
+                        "Proof already generated for block"
+                    );
+                    String::from_utf8(proof).map_err(|e| ProverError::ProofParse(e.to_string()))?
+                }
+                Err(_) => {
+                    trace!(
+                        block_number = block_number_u32,
+                        "Proof not found in db for block",
+                    );
+
+                    let stage_timer = metrics::stage_timer("snos_proof");
+                    let compressed_pie = db
+                        .get_pie(block_number_u32, Step::Snos)
+                        .await
+                        .map_err(|e| ProverError::MetadataFetch(e.to_string()))?;
+
+                    debug!(
+                        block_number = new_block.number,
+                        pie_size = compressed_pie.len(),
+                        "Proving SNOS PIE with {}",
+                        P::NAME
+                    );
+                    let proof = match prover
+                        .prove_snos(
+                            &format!("snos-{}", new_block.number),
+                            compressed_pie,
+                            &finish_handle,
+                        )
+                        .await
+                    {
+                        Err(ProverError::Shutdown) => break,
+                        Err(ProverError::BlockFail(e)) => {
+                            error!(block_number = new_block.number, error = %e, "SNOS proof generation failed");
+                            db.add_failed_block(block_number_u32, e).await.unwrap();
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                        Ok(proof) => proof,
+                    };
+
+                    db.add_proof(block_number_u32, proof.as_bytes().to_vec(), Step::Snos)
+                        .await
+                        .unwrap();
+                    stage_timer.observe();
+
+                    info!(
+                        block_number = new_block.number,
+                        "SNOS proof generated with {}",
+                        P::NAME
+                    );
+                    proof
+                }
+            };
+
+            let new_proof = SnosProof {
+                block_number: new_block.number,
+                first_block: new_block.first_block,
+                proof,
+            };
+            tokio::select! {
+                _ = finish_handle.shutdown_requested() => break,
+                _ = task_tx.send(new_proof) => {},
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(self) {
+        let pool = WorkerPool::new(
+            "snos_proof",
+            self.input_channel,
+            self.workers,
+            self.finish_handle.clone(),
+        );
+        pool.run(|worker| {
+            let finish_handle = self.finish_handle.clone();
+            let worker = Self::worker(
+                worker,
+                self.output_channel.clone(),
+                self.prover.clone(),
+                self.finish_handle.clone(),
+                self.db.clone(),
+            );
+
+            async move {
+                if let Err(err) = worker.await {
+                    error!(error = %err, "SNOS proof worker failed");
+                    finish_handle.fail(format!("worker failed: {}", err));
+                    finish_handle.shutdown_handle().shutdown();
+                }
+            }
+        })
+        .await;
+        debug!("Graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+}
+
+impl<P, DB> LocalSnosProverBuilder<P, DB> {
+    pub fn new(prover: P, db: DB, workers: WorkerPoolConfig) -> Self {
+        Self {
+            prover,
+            input_channel: None,
+            output_channel: None,
+            db,
+            workers,
+        }
+    }
+}
+
+impl<P, DB> PipelineStageBuilder for LocalSnosProverBuilder<P, DB>
+where
+    P: LocalPieProver,
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Stage = LocalSnosProver<P, DB>;
+
+    fn build(self) -> Result<Self::Stage> {
+        Ok(LocalSnosProver {
+            prover: self.prover,
+            input_channel: self
+                .input_channel
+                .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
+            output_channel: self
+                .output_channel
+                .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
+            finish_handle: FinishHandle::new(),
+            db: self.db,
+            workers: self.workers,
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<BlockInfo>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<SnosProof<String>>) -> Self {
+        self.output_channel = Some(output_channel);
+        self
+    }
+}
+
+impl<P, DB> PipelineStage for LocalSnosProver<P, DB>
+where
+    P: LocalPieProver,
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Input = BlockInfo;
+    type Output = SnosProof<String>;
+}
+
+impl<P, DB> Daemon for LocalSnosProver<P, DB>
+where
+    P: LocalPieProver,
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
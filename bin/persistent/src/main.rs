@@ -4,14 +4,20 @@
 //! application for running Saya.
 
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use saya_config::{ConfigArgs, ConfigCommand};
 
 mod atlantic;
+mod cairo_run;
 mod error;
+mod local_snos;
 mod mock;
 mod orchestrator;
 mod settlement;
+mod stone;
+mod stwo;
 mod utils;
+mod verifier;
 
 mod sovereign;
 use sovereign::Sovereign;
@@ -19,6 +25,9 @@ use sovereign::Sovereign;
 mod persistent;
 use persistent::Start;
 
+mod export;
+use export::ExportBlocks;
+
 mod any;
 
 mod common;
@@ -28,6 +37,8 @@ mod snos_pie_generator;
 #[derive(Debug, Parser)]
 #[clap(about, version)]
 struct Cli {
+    #[clap(flatten)]
+    config: ConfigArgs,
     #[clap(subcommand)]
     command: Subcommands,
 }
@@ -40,11 +51,18 @@ enum Subcommands {
     Sovereign(Sovereign),
     /// Start Saya in persistent L3 mode where proofs are settled in a "base layer" network.
     Start(Start),
+    /// Export the state updates of a range of blocks, for replaying them offline.
+    ExportBlocks(ExportBlocks),
+    /// Inspect and validate configuration files.
+    Config(ConfigCommand),
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Cli::parse();
+    // Options from the configuration file are the defaults of their flags, so they have to be
+    // loaded before parsing the command line.
+    let matches = saya_config::init(Cli::command())?.get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
 
     saya_tracing::init(
         "info,persistent=trace,saya_core=trace,rpc_client=info,prove_block=info,blockifier=off,\
@@ -53,5 +71,7 @@ async fn main() -> Result<()> {
     match cli.command {
         Subcommands::Sovereign(cmd) => cmd.run().await,
         Subcommands::Start(cmd) => cmd.run().await,
+        Subcommands::ExportBlocks(cmd) => cmd.run().await,
+        Subcommands::Config(cmd) => Ok(cmd.run(&cli.config, &Cli::command())?),
     }
 }
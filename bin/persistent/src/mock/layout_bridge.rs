@@ -104,6 +104,7 @@ where
                 number: new_snos_proof.block_number,
                 status: saya_core::storage::BlockStatus::BridgeProofGenerated,
                 state_update: Some(state_update),
+                first_block: new_snos_proof.first_block,
             };
             tokio::select! {
                 _ = self.finish_handle.shutdown_requested() => break,
@@ -175,6 +176,6 @@ where
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
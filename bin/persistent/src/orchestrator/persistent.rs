@@ -1,14 +1,16 @@
 use anyhow::Result;
 use tokio::sync::mpsc::Receiver;
-use tracing::{debug, info};
+use tracing::{debug, error, info};
 
 use saya_core::{
     block_ingestor::{BlockInfo, BlockIngestor, BlockIngestorBuilder},
     data_availability::{
         DataAvailabilityBackend, DataAvailabilityBackendBuilder, DataAvailabilityCursor,
     },
+    health::HealthReporter,
+    metrics,
     prover::{PipelineStage, PipelineStageBuilder},
-    service::{Daemon, FinishHandle, ShutdownHandle},
+    service::{ChildServices, Daemon, FinishHandle, ShutdownHandle},
     settlement::{SettlementBackend, SettlementBackendBuilder, SettlementCursor},
 };
 
@@ -45,6 +47,9 @@ pub struct PersistentOrchestrator<I, P, D, S> {
     da: D,
     settlement: S,
     finish_handle: FinishHandle,
+    health_reporter: Option<HealthReporter>,
+    next_block: u64,
+    end_block: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -53,15 +58,17 @@ pub struct PersistentOrchestratorBuilder<I, P, D, S> {
     pipeline_builder: P,
     da_builder: D,
     settlement_builder: S,
+    health_reporter: Option<HealthReporter>,
+    end_block: Option<u64>,
 }
 
 struct PersistentOrchestratorState {
     cursor_channel: Receiver<SettlementCursor>,
-    ingestor_handle: ShutdownHandle,
-    pipeline_handle: ShutdownHandle,
-    da_handle: ShutdownHandle,
-    settlement_handle: ShutdownHandle,
+    children: ChildServices,
     finish_handle: FinishHandle,
+    health_reporter: Option<HealthReporter>,
+    next_block: u64,
+    end_block: Option<u64>,
 }
 
 impl<I, P, D, S> PersistentOrchestratorBuilder<I, P, D, S> {
@@ -76,8 +83,23 @@ impl<I, P, D, S> PersistentOrchestratorBuilder<I, P, D, S> {
             pipeline_builder,
             da_builder,
             settlement_builder,
+            health_reporter: None,
+            end_block: None,
         }
     }
+
+    /// Sets a reporter for publishing the state of the orchestrator.
+    pub fn health_reporter(mut self, reporter: HealthReporter) -> Self {
+        self.health_reporter = Some(reporter);
+        self
+    }
+
+    /// Sets the last block to process, after which the orchestrator shuts down once the block
+    /// has been settled.
+    pub fn end_block(mut self, end_block: u64) -> Self {
+        self.end_block = Some(end_block);
+        self
+    }
 }
 
 impl<I, P, PV, D, DB, S> PersistentOrchestratorBuilder<I, P, D, S>
@@ -100,6 +122,11 @@ where
         let (settle_cursor_tx, settle_cursor_rx) =
             tokio::sync::mpsc::channel::<SettlementCursor>(SETTLE_CURSOR_BUFFER_SIZE);
 
+        metrics::observe_channel("new_blocks", &new_block_tx);
+        metrics::observe_channel("proofs", &output_tx);
+        metrics::observe_channel("da_cursors", &da_cursor_tx);
+        metrics::observe_channel("settlement_cursors", &settle_cursor_tx);
+
         let settlement = self
             .settlement_builder
             .da_channel(da_cursor_rx)
@@ -117,8 +144,22 @@ where
         // Now that the special value of `Felt::MAX` is handled, we can use the block number as `u64`.
         let start_block: u64 = start_block.try_into()?;
 
-        let ingestor = self
-            .ingestor_builder
+        let ingestor_builder = match &self.health_reporter {
+            Some(reporter) => {
+                if start_block > 0 {
+                    reporter.record_settled_block(start_block - 1);
+                }
+                self.ingestor_builder.health_reporter(reporter.clone())
+            }
+            None => self.ingestor_builder,
+        };
+
+        let ingestor_builder = match self.end_block {
+            Some(end_block) => ingestor_builder.end_block(end_block),
+            None => ingestor_builder,
+        };
+
+        let ingestor = ingestor_builder
             .start_block(start_block)
             .channel(new_block_tx)
             .build()
@@ -146,6 +187,9 @@ where
             da,
             settlement,
             finish_handle: FinishHandle::new(),
+            health_reporter: self.health_reporter,
+            next_block: start_block,
+            end_block: self.end_block,
         })
     }
 }
@@ -153,37 +197,52 @@ where
 impl PersistentOrchestratorState {
     async fn run(mut self) {
         loop {
-            // TODO: handle unexpected exit of descendant services
+            if let Some(end_block) = self.end_block {
+                if self.next_block > end_block {
+                    info!(end_block, "Last block of the range settled, shutting down");
+                    break;
+                }
+            }
+
             let new_cursor = tokio::select! {
                 _ = self.finish_handle.shutdown_requested() => break,
+                (service, failure) = self.children.exited() => {
+                    error!(service, ?failure, "Service exited unexpectedly");
+                    self.finish_handle.fail(format!(
+                        "`{}` exited unexpectedly: {}",
+                        service,
+                        failure.as_deref().unwrap_or("no failure reported")
+                    ));
+                    break;
+                },
                 new_cursor = self.cursor_channel.recv() => new_cursor,
             };
 
-            // This should be fine for now as da backends wouldn't drop senders. This might change
-            // in the future.
-            let new_cursor = new_cursor.unwrap();
+            let Some(new_cursor) = new_cursor else {
+                error!("Settlement cursor channel closed unexpectedly");
+                self.finish_handle
+                    .fail("settlement cursor channel closed unexpectedly");
+                break;
+            };
+
+            if let Some(reporter) = &self.health_reporter {
+                reporter.record_settlement(&new_cursor);
+            }
 
             info!(
                 block_number = new_cursor.block_number,
                 transaction_hash = %format!("{:#064x}", new_cursor.transaction_hash),
                 "Chain advanced to new block"
             );
+
+            self.next_block = new_cursor.block_number + 1;
         }
 
         // Request graceful shutdown for all descendant services
-        self.ingestor_handle.shutdown();
-        self.pipeline_handle.shutdown();
-        self.da_handle.shutdown();
-        self.settlement_handle.shutdown();
+        self.children.shutdown();
 
         // Wait for all descendant services to finish graceful shutdown
-        futures_util::future::join_all([
-            self.ingestor_handle.finished(),
-            self.pipeline_handle.finished(),
-            self.da_handle.finished(),
-            self.settlement_handle.finished(),
-        ])
-        .await;
+        self.children.finished().await;
 
         debug!("Graceful shutdown finished");
         self.finish_handle.finish();
@@ -204,18 +263,77 @@ where
     fn start(self) {
         let state = PersistentOrchestratorState {
             cursor_channel: self.cursor_channel,
-            ingestor_handle: self.ingestor.shutdown_handle(),
-            pipeline_handle: self.pipeline.shutdown_handle(),
-            da_handle: self.da.shutdown_handle(),
-            settlement_handle: self.settlement.shutdown_handle(),
+            children: ChildServices::new()
+                .with("ingestor", self.ingestor.shutdown_handle())
+                .with("pipeline", self.pipeline.shutdown_handle())
+                .with("da", self.da.shutdown_handle())
+                .with("settlement", self.settlement.shutdown_handle()),
             finish_handle: self.finish_handle,
+            health_reporter: self.health_reporter,
+            next_block: self.next_block,
+            end_block: self.end_block,
         };
 
+        if let Some(reporter) = &state.health_reporter {
+            reporter.set_services(&state.children);
+        }
+
         self.ingestor.start();
         self.pipeline.start();
         self.da.start();
         self.settlement.start();
 
+        state.finish_handle.clone().spawn(state.run());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+
+    /// Returns the handle of a service idling until asked to shut down.
+    fn idle_service() -> ShutdownHandle {
+        let finish_handle = FinishHandle::new();
+        let shutdown_handle = finish_handle.shutdown_handle();
+        finish_handle.clone().spawn(async move {
+            finish_handle.shutdown_requested().await;
+            finish_handle.finish();
+        });
+        shutdown_handle
+    }
+
+    #[tokio::test]
+    async fn test_shuts_down_once_end_block_settled() {
+        let (cursor_tx, cursor_rx) = tokio::sync::mpsc::channel(SETTLE_CURSOR_BUFFER_SIZE);
+        let settlement = idle_service();
+        let finish_handle = FinishHandle::new();
+        let shutdown_handle = finish_handle.shutdown_handle();
+        let state = PersistentOrchestratorState {
+            cursor_channel: cursor_rx,
+            children: ChildServices::new().with("settlement", settlement.clone()),
+            finish_handle,
+            health_reporter: None,
+            next_block: 1,
+            end_block: Some(2),
+        };
         tokio::spawn(state.run());
+
+        for block_number in 1..=2 {
+            cursor_tx
+                .send(SettlementCursor {
+                    block_number,
+                    transaction_hash: Felt::ZERO,
+                })
+                .await
+                .unwrap();
+        }
+
+        shutdown_handle.finished().await;
+        assert!(shutdown_handle.failure().is_none());
+        assert!(settlement.is_finished());
+        // The orchestrator stopped listening for cursors on its own.
+        assert!(cursor_tx.is_closed());
     }
 }
@@ -1,7 +1,7 @@
 use anyhow::Result;
 use swiftness_stark::types::StarkProof;
 use tokio::sync::mpsc::Receiver;
-use tracing::{debug, info};
+use tracing::{debug, error, info};
 
 use saya_core::{
     block_ingestor::{BlockInfo, BlockIngestor, BlockIngestorBuilder},
@@ -9,9 +9,11 @@ use saya_core::{
         DataAvailabilityBackend, DataAvailabilityBackendBuilder, DataAvailabilityCursor,
         DataAvailabilityPointer,
     },
+    health::HealthReporter,
+    metrics,
     orchestrator::Genesis,
     prover::{PipelineStage, PipelineStageBuilder, SnosProof},
-    service::{Daemon, FinishHandle, ShutdownHandle},
+    service::{ChildServices, Daemon, FinishHandle, ShutdownHandle},
     storage::{BlockWithDa, ChainHead, StorageBackend},
 };
 
@@ -41,6 +43,9 @@ pub struct SovereignOrchestrator<I, P, D, S> {
     da: D,
     storage: S,
     finish_handle: FinishHandle,
+    health_reporter: Option<HealthReporter>,
+    next_block: u64,
+    end_block: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -50,15 +55,19 @@ pub struct SovereignOrchestratorBuilder<I, P, D, S> {
     da_builder: D,
     storage: S,
     genesis: Option<Genesis>,
+    health_reporter: Option<HealthReporter>,
+    start_block: Option<u64>,
+    end_block: Option<u64>,
 }
 
 struct SovereignOrchestratorState<S> {
     cursor_channel: Receiver<DataAvailabilityCursor<SnosProof<StarkProof>>>,
     storage: S,
-    ingestor_handle: ShutdownHandle,
-    pipeline_handle: ShutdownHandle,
-    da_handle: ShutdownHandle,
+    children: ChildServices,
     finish_handle: FinishHandle,
+    health_reporter: Option<HealthReporter>,
+    next_block: u64,
+    end_block: Option<u64>,
 }
 
 impl<I, P, D, S> SovereignOrchestratorBuilder<I, P, D, S> {
@@ -75,8 +84,32 @@ impl<I, P, D, S> SovereignOrchestratorBuilder<I, P, D, S> {
             da_builder,
             storage,
             genesis,
+            health_reporter: None,
+            start_block: None,
+            end_block: None,
         }
     }
+
+    /// Sets a reporter for publishing the state of the orchestrator.
+    pub fn health_reporter(mut self, reporter: HealthReporter) -> Self {
+        self.health_reporter = Some(reporter);
+        self
+    }
+
+    /// Sets the first block to process, taking precedence over the genesis block.
+    ///
+    /// Only allowed before the chain head has been persisted, as blocks can't be skipped past it.
+    pub fn start_block(mut self, start_block: u64) -> Self {
+        self.start_block = Some(start_block);
+        self
+    }
+
+    /// Sets the last block to process, after which the orchestrator shuts down once the block
+    /// has been published.
+    pub fn end_block(mut self, end_block: u64) -> Self {
+        self.end_block = Some(end_block);
+        self
+    }
 }
 
 impl<I, P, PV, D, DB, S> SovereignOrchestratorBuilder<I, P, D, S>
@@ -99,20 +132,37 @@ where
             DataAvailabilityCursor<SnosProof<StarkProof>>,
         >(CURSOR_BUFFER_SIZE);
 
+        metrics::observe_channel("new_blocks", &new_block_tx);
+        metrics::observe_channel("proofs", &output_tx);
+        metrics::observe_channel("da_cursors", &cursor_tx);
+
         let chain_head = self.storage.get_chain_head().await;
         let (start_block, da_builder) = match chain_head {
-            ChainHead::Genesis => match self.genesis {
-                Some(genesis) => (
-                    genesis.first_block_number,
-                    self.da_builder.last_pointer(None),
-                ),
+            ChainHead::Genesis => match self
+                .start_block
+                .or(self.genesis.map(|genesis| genesis.first_block_number))
+            {
+                Some(start_block) => (start_block, self.da_builder.last_pointer(None)),
                 None => {
                     // In sovereign mode the chain is not settled in a decentralized manner. Without
                     // a pointer to the last published DA we can only rely on the optionally
-                    // supplied genesis info for starting the orchestrator.
-                    anyhow::bail!("genesis not provided when chain head has not been persisted")
+                    // supplied start block or genesis info for starting the orchestrator.
+                    anyhow::bail!(
+                        "neither start block nor genesis provided when chain head has not been \
+                        persisted"
+                    )
                 }
             },
+            ChainHead::Block(block_with_da)
+                if self
+                    .start_block
+                    .is_some_and(|start_block| start_block != block_with_da.height + 1) =>
+            {
+                anyhow::bail!(
+                    "start block must follow the persisted chain head #{}",
+                    block_with_da.height
+                )
+            }
             ChainHead::Block(block_with_da) => (
                 block_with_da.height + 1,
                 self.da_builder.last_pointer(Some(DataAvailabilityPointer {
@@ -123,8 +173,23 @@ where
             ),
         };
 
-        let ingestor = self
-            .ingestor_builder
+        // Blocks published to DA are considered settled in this mode.
+        let ingestor_builder = match &self.health_reporter {
+            Some(reporter) => {
+                if let ChainHead::Block(block_with_da) = &chain_head {
+                    reporter.record_settled_block(block_with_da.height);
+                }
+                self.ingestor_builder.health_reporter(reporter.clone())
+            }
+            None => self.ingestor_builder,
+        };
+
+        let ingestor_builder = match self.end_block {
+            Some(end_block) => ingestor_builder.end_block(end_block),
+            None => ingestor_builder,
+        };
+
+        let ingestor = ingestor_builder
             .start_block(start_block)
             .channel(new_block_tx)
             .build()
@@ -151,6 +216,9 @@ where
             da,
             storage: self.storage,
             finish_handle: FinishHandle::new(),
+            health_reporter: self.health_reporter,
+            next_block: start_block,
+            end_block: self.end_block,
         })
     }
 }
@@ -161,18 +229,48 @@ where
 {
     async fn run(mut self) {
         loop {
-            // TODO: handle unexpected exit of descendant services
+            if let Some(end_block) = self.end_block {
+                if self.next_block > end_block {
+                    info!(
+                        end_block,
+                        "Last block of the range published, shutting down"
+                    );
+                    break;
+                }
+            }
+
             let new_cursor = tokio::select! {
                 _ = self.finish_handle.shutdown_requested() => break,
+                (service, failure) = self.children.exited() => {
+                    error!(service, ?failure, "Service exited unexpectedly");
+                    self.finish_handle.fail(format!(
+                        "`{}` exited unexpectedly: {}",
+                        service,
+                        failure.as_deref().unwrap_or("no failure reported")
+                    ));
+                    break;
+                },
                 new_cursor = self.cursor_channel.recv() => new_cursor,
             };
 
-            // This should be fine for now as da backends wouldn't drop senders. This might change
-            // in the future.
-            let new_cursor = new_cursor.unwrap();
+            let Some(new_cursor) = new_cursor else {
+                error!("Data availability cursor channel closed unexpectedly");
+                self.finish_handle
+                    .fail("data availability cursor channel closed unexpectedly");
+                break;
+            };
 
-            // TODO: error handling
-            let da_pointer = new_cursor.pointer.unwrap();
+            let Some(da_pointer) = new_cursor.pointer else {
+                error!(
+                    block_number = new_cursor.block_number,
+                    "Data availability cursor without pointer"
+                );
+                self.finish_handle.fail(format!(
+                    "block #{} published without data availability pointer",
+                    new_cursor.block_number
+                ));
+                break;
+            };
 
             self.storage
                 .set_chain_head(BlockWithDa {
@@ -180,21 +278,19 @@ where
                     da_pointer,
                 })
                 .await;
+            if let Some(reporter) = &self.health_reporter {
+                reporter.record_settled_block(new_cursor.block_number);
+            }
             info!(block_number = new_cursor.block_number, "Chain advanced");
+
+            self.next_block = new_cursor.block_number + 1;
         }
 
         // Request graceful shutdown for all descendant services
-        self.ingestor_handle.shutdown();
-        self.pipeline_handle.shutdown();
-        self.da_handle.shutdown();
+        self.children.shutdown();
 
         // Wait for all descendant services to finish graceful shutdown
-        futures_util::future::join_all([
-            self.ingestor_handle.finished(),
-            self.pipeline_handle.finished(),
-            self.da_handle.finished(),
-        ])
-        .await;
+        self.children.finished().await;
 
         debug!("Graceful shutdown finished");
         self.finish_handle.finish();
@@ -216,16 +312,24 @@ where
         let state = SovereignOrchestratorState {
             cursor_channel: self.cursor_channel,
             storage: self.storage,
-            ingestor_handle: self.ingestor.shutdown_handle(),
-            pipeline_handle: self.pipeline.shutdown_handle(),
-            da_handle: self.da.shutdown_handle(),
+            children: ChildServices::new()
+                .with("ingestor", self.ingestor.shutdown_handle())
+                .with("pipeline", self.pipeline.shutdown_handle())
+                .with("da", self.da.shutdown_handle()),
             finish_handle: self.finish_handle,
+            health_reporter: self.health_reporter,
+            next_block: self.next_block,
+            end_block: self.end_block,
         };
 
+        if let Some(reporter) = &state.health_reporter {
+            reporter.set_services(&state.children);
+        }
+
         self.ingestor.start();
         self.pipeline.start();
         self.da.start();
 
-        tokio::spawn(state.run());
+        state.finish_handle.clone().spawn(state.run());
     }
 }
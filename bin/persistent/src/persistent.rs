@@ -3,30 +3,48 @@ use std::{io::Read, path::PathBuf, time::Duration};
 use anyhow::Result;
 use clap::Parser;
 use generate_pie::types::OsHintsConfiguration;
+use saya_config::{
+    AdminConfiguration, HealthConfiguration, RpcConfiguration, SupervisionConfiguration,
+};
 use saya_core::{
-    block_ingestor::PollingBlockIngestorBuilder,
+    block_ingestor::{
+        BlockIngestorBuilder, FileBlockIngestorBuilder, MergeEmptyBlocks,
+        PollingBlockIngestorBuilder, SubscriptionBlockIngestorBuilder,
+    },
     data_availability::{
         CelestiaDataAvailabilityBackendBuilder, NoopDataAvailabilityBackendBuilder,
     },
-    prover::{BlockOrdererBuilder, PipelineChainBuilder},
-    service::Daemon,
+    health::HealthReporter,
+    prover::{BlockBatcherBuilder, BlockOrdererBuilder, DurableStageBuilder, PipelineChainBuilder},
+    service::{Daemon, PauseHandle, Supervisor},
     storage::SqliteDb,
     ChainId,
 };
 
 use crate::{
-    any::{AnyDataAvailabilityLayerBuilder, AnyLayoutBridgeProverBuilder},
-    atlantic::{AtlanticLayoutBridgeProverBuilder, AtlanticSnosProverBuilder},
-    common::{calculate_workers_per_stage, NUMBER_OF_STAGES, SAYA_DB_PATH},
+    any::{
+        AnyBlockIngestorBuilder, AnyDataAvailabilityLayerBuilder, AnyLayoutBridgeProverBuilder,
+        AnyProofVerifierBuilder, AnySnosProverBuilder,
+    },
+    atlantic::{
+        AtlanticLayoutBridgeProverBuilder, AtlanticNetwork, AtlanticSnosProverBuilder,
+        ATLANTIC_API_BASE,
+    },
+    cairo_run::CairoRunner,
+    common::{AtlanticQueryConfiguration, ScalingConfiguration, SAYA_DB_PATH},
     mock::MockLayoutBridgeProverBuilder,
     orchestrator::PersistentOrchestratorBuilder,
-    settlement::PiltoverSettlementBackendBuilder,
+    settlement::{IntegrityVerifier, PiltoverSettlementBackendBuilder, ProofSystem},
     snos_pie_generator::SnosPieGeneratorBuilder,
     sovereign::validate_non_empty,
+    stone::{StoneLayoutBridgeProverBuilder, StoneProver, StoneSnosProverBuilder},
+    stwo::{StwoProofForwarderBuilder, StwoProver, StwoSnosProverBuilder},
+    utils::compute_program_hash,
+    verifier::ProofVerifierBuilder,
 };
 use starknet::{
-    core::utils::parse_cairo_short_string,
-    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider},
+    core::utils::{cairo_short_string_to_felt, parse_cairo_short_string},
+    providers::{JsonRpcClient, Provider},
 };
 use starknet_types_core::felt::Felt;
 use url::Url;
@@ -35,12 +53,23 @@ use url::Url;
 const GRACEFUL_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 #[derive(Debug, Parser, Clone)]
 pub struct Start {
-    /// Rollup network Starknet JSON-RPC URL (v0.7.1)
+    /// Rollup network Starknet JSON-RPC URLs (v0.7.1), comma-separated. Requests fail over to the
+    /// next URL when one doesn't answer
+    #[clap(long, env, value_delimiter = ',', required = true)]
+    rollup_rpc: Vec<Url>,
+    /// Rollup network Starknet WebSocket URL for subscribing to new blocks. New blocks are polled
+    /// from `--rollup-rpc` if not set
     #[clap(long, env)]
-    rollup_rpc: Url,
-    /// Settlement network Starknet JSON-RPC URL (v0.7.1)
-    #[clap(long, env)]
-    settlement_rpc: Url,
+    rollup_ws: Option<Url>,
+    /// Directory of blocks exported by `saya export-blocks` to replay instead of ingesting blocks
+    /// from the network. PIEs are still generated from the rollup RPC, which must serve the
+    /// replayed blocks
+    #[clap(long, env, conflicts_with = "rollup_ws")]
+    replay_dir: Option<PathBuf>,
+    /// Settlement network Starknet JSON-RPC URLs (v0.7.1), comma-separated. Requests fail over to
+    /// the next URL when one doesn't answer
+    #[clap(long, env, value_delimiter = ',', required = true)]
+    settlement_rpc: Vec<Url>,
     /// Whether to mock the SNOS proof by extracting the output from the PIE and using it from a proof.
     #[clap(long)]
     mock_snos_from_pie: bool,
@@ -50,12 +79,36 @@ pub struct Start {
     /// Atlantic prover API key
     #[clap(long, env)]
     atlantic_key: Option<String>,
+    /// Atlantic prover API URL
+    #[clap(long, env, default_value = ATLANTIC_API_BASE)]
+    atlantic_url: Url,
+    /// Atlantic query limits and budget configuration
+    #[clap(flatten)]
+    atlantic_query: AtlanticQueryConfiguration,
     /// Settlement network integrity contract address
     #[clap(long, env)]
     settlement_integrity_address: Option<Felt>,
+    /// Have Atlantic verify the layout bridge proofs on this Starknet network, and wait for their
+    /// facts in the integrity contract instead of verifying them from the settlement account
+    #[clap(
+        long,
+        env,
+        value_enum,
+        conflicts_with_all = ["mock_layout_bridge_program_hash", "stone_prover", "stwo_prover"]
+    )]
+    atlantic_fact_registration: Option<AtlanticNetwork>,
     /// Generate mock layout bridge proof and skip on-chain fact registration if provided
     #[clap(long, env)]
     mock_layout_bridge_program_hash: Option<Felt>,
+    /// Verify the SNOS and layout bridge proofs locally before settling them, marking the blocks
+    /// whose proofs don't verify as failed
+    #[clap(
+        long,
+        env,
+        default_value_t = false,
+        conflicts_with_all = ["mock_snos_from_pie", "mock_layout_bridge_program_hash", "stwo_prover"]
+    )]
+    verify_proofs: bool,
     /// Settlement network piltover contract address
     #[clap(long, env)]
     settlement_piltover_address: Felt,
@@ -71,12 +124,45 @@ pub struct Start {
     /// Number of blocks processed in parallel evenly distributed between the stages
     #[clap(long, env, default_value_t = 60)]
     blocks_processed_in_parallel: usize,
+    /// Number of blocks proven by a single SNOS run and settled together
+    #[clap(long, env, default_value_t = 1)]
+    snos_batch_size: u64,
+    /// Flush a partial SNOS batch after this many seconds without a new block
+    #[clap(long, env, default_value_t = 120)]
+    snos_batch_idle_timeout_secs: u64,
+    /// Prove blocks with an empty state diff along with the next block changing the state
+    #[clap(long, env, default_value_t = false)]
+    merge_empty_blocks: bool,
+    /// Worker scaling configuration
+    #[clap(flatten)]
+    scaling: ScalingConfiguration,
+    /// RPC client configuration
+    #[clap(flatten)]
+    rpc: RpcConfiguration,
+    /// Last block to settle. Saya exits once this block has been settled, runs forever if not set
+    #[clap(long, env)]
+    end_block: Option<u64>,
     /// Configuration for OS pie generation
     #[clap(flatten)]
     hints: HintsConfiguration,
     /// Celestia configuration
     #[clap(flatten)]
     celestia: CelestiaConfiguration,
+    /// Local prover configuration
+    #[clap(flatten)]
+    local_prover: LocalProverConfiguration,
+    /// Integrity verifier configuration
+    #[clap(flatten)]
+    integrity: IntegrityConfiguration,
+    /// Supervision configuration
+    #[clap(flatten)]
+    supervision: SupervisionConfiguration,
+    /// Health server configuration
+    #[clap(flatten)]
+    health: HealthConfiguration,
+    /// Admin server configuration
+    #[clap(flatten)]
+    admin: AdminConfiguration,
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -97,6 +183,115 @@ struct CelestiaConfiguration {
     celestia_namespace: String,
 }
 
+#[derive(Debug, Parser, Clone)]
+struct LocalProverConfiguration {
+    /// Path to the Stone `cpu_air_prover` binary. Proofs are generated locally instead of with
+    /// Atlantic if set
+    #[clap(long, env, conflicts_with = "mock_snos_from_pie")]
+    stone_prover: Option<PathBuf>,
+    /// Path to the Stwo Cairo prover binary. SNOS proofs are generated locally instead of with
+    /// Atlantic if set, and settled without the layout bridge
+    #[clap(
+        long,
+        env,
+        conflicts_with_all = ["mock_snos_from_pie", "mock_layout_bridge_program_hash", "stone_prover"]
+    )]
+    stwo_prover: Option<PathBuf>,
+    /// Path to the `cairo-run` binary generating the traces proven by the local prover
+    #[clap(long, env, default_value = "cairo-run")]
+    cairo_run: PathBuf,
+    /// Path to the compiled Cairo simple bootloader program
+    #[clap(long, env)]
+    bootloader_program: Option<PathBuf>,
+    /// Path to the `dynamic` layout parameters SNOS is run with by Stone
+    #[clap(long, env)]
+    stone_snos_layout_params: Option<PathBuf>,
+}
+
+/// Prover generating the proofs locally instead of with Atlantic.
+#[derive(Debug)]
+enum LocalProver {
+    Stone(StoneProver),
+    Stwo(StwoProver),
+}
+
+impl LocalProverConfiguration {
+    fn prover(self) -> Result<Option<LocalProver>> {
+        if self.stone_prover.is_none() && self.stwo_prover.is_none() {
+            return Ok(None);
+        }
+
+        let bootloader_program = self.bootloader_program.ok_or_else(|| {
+            anyhow::anyhow!(
+                "invalid config: `--bootloader-program` must be provided with `--stone-prover` or `--stwo-prover`"
+            )
+        })?;
+        let runner = CairoRunner::new(self.cairo_run, bootloader_program);
+
+        match (self.stone_prover, self.stwo_prover) {
+            (Some(cpu_air_prover), _) => {
+                let snos_layout_params = self.stone_snos_layout_params.ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "invalid config: `--stone-snos-layout-params` must be provided with `--stone-prover`"
+                    )
+                })?;
+
+                Ok(Some(LocalProver::Stone(StoneProver::new(
+                    runner,
+                    cpu_air_prover,
+                    snos_layout_params,
+                ))))
+            }
+            (None, Some(stwo_prover)) => Ok(Some(LocalProver::Stwo(StwoProver::new(
+                runner,
+                stwo_prover,
+            )))),
+            (None, None) => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+struct IntegrityConfiguration {
+    /// Layout of the integrity verifier proofs are registered with
+    #[clap(long, env)]
+    integrity_layout: Option<String>,
+    /// Hasher of the integrity verifier proofs are registered with
+    #[clap(long, env)]
+    integrity_hasher: Option<String>,
+    /// Stone version of the integrity verifier proofs are registered with
+    #[clap(long, env)]
+    integrity_stone_version: Option<String>,
+    /// Memory verification of the integrity verifier proofs are registered with
+    #[clap(long, env)]
+    integrity_memory_verification: Option<String>,
+}
+
+impl IntegrityConfiguration {
+    /// Overrides the verifier of the layout bridge proofs with the settings provided.
+    fn verifier(self, proof_system: ProofSystem) -> Result<IntegrityVerifier> {
+        let mut verifier = IntegrityVerifier {
+            proof_system,
+            ..Default::default()
+        };
+        for (setting, value) in [
+            (&mut verifier.layout, self.integrity_layout),
+            (&mut verifier.hasher, self.integrity_hasher),
+            (&mut verifier.stone_version, self.integrity_stone_version),
+            (
+                &mut verifier.memory_verification,
+                self.integrity_memory_verification,
+            ),
+        ] {
+            if let Some(value) = value {
+                *setting = cairo_short_string_to_felt(&value)?;
+            }
+        }
+
+        Ok(verifier)
+    }
+}
+
 #[derive(Debug, Parser, Clone)]
 struct HintsConfiguration {
     /// Enable debug mode for OS hints generation
@@ -112,30 +307,102 @@ struct HintsConfiguration {
 
 impl Start {
     pub async fn run(self) -> Result<()> {
-        let saya_path = self
-            .db_dir
+        let restart_policy = self.supervision.restart_policy();
+        let health = self.health.start().await?;
+        let health_reporter = health.as_ref().map(|(reporter, _)| reporter.clone());
+        let admin = self.admin.clone();
+        let db_path = self.db_path();
+
+        // Shared across restarts so that a pause requested via the admin API outlives them.
+        let pause_handle = PauseHandle::new();
+        let orchestrator_pause_handle = pause_handle.clone();
+
+        // The orchestrator is rebuilt on every restart so that it resumes from the last block
+        // settled on the base layer.
+        let supervisor = Supervisor::new(restart_policy, move || {
+            self.clone()
+                .build_orchestrator(health_reporter.clone(), orchestrator_pause_handle.clone())
+        });
+        let supervisor_shutdown = supervisor.shutdown_handle();
+        let admin = admin
+            .start(&db_path, pause_handle, supervisor_shutdown.clone())
+            .await?;
+        supervisor.start();
+
+        let mut sigterm_handle =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        let ctrl_c_handle = tokio::signal::ctrl_c();
+
+        tokio::select! {
+            _ = sigterm_handle.recv() => {},
+            _ = ctrl_c_handle => {},
+            _ = supervisor_shutdown.finished() => {},
+        }
+
+        // Graceful shutdown
+        supervisor_shutdown.shutdown();
+        let result = tokio::select! {
+            _ = tokio::time::sleep(GRACEFUL_SHUTDOWN_TIMEOUT) => {
+                Err(anyhow::anyhow!("timeout waiting for graceful shutdown"))
+            },
+            _ = supervisor_shutdown.finished() => {
+                match supervisor_shutdown.failure() {
+                    Some(failure) => Err(anyhow::anyhow!("orchestrator failed: {}", failure)),
+                    None => Ok(()),
+                }
+            },
+        };
+
+        if let Some(admin_shutdown) = admin {
+            admin_shutdown.shutdown();
+            admin_shutdown.finished().await;
+        }
+        if let Some((_, health_shutdown)) = health {
+            health_shutdown.shutdown();
+            health_shutdown.finished().await;
+        }
+
+        result
+    }
+
+    fn db_path(&self) -> String {
+        self.db_dir
+            .as_ref()
             .map(|db_dir| format!("{}/{}", db_dir.display(), SAYA_DB_PATH))
-            .unwrap_or_else(|| SAYA_DB_PATH.to_string());
+            .unwrap_or_else(|| SAYA_DB_PATH.to_string())
+    }
 
-        let workers_distribution: [usize; NUMBER_OF_STAGES] =
-            calculate_workers_per_stage(self.blocks_processed_in_parallel);
-        let [snos_worker_count, layout_bridge_workers_count, ingestor_worker_count] =
-            workers_distribution;
+    async fn build_orchestrator(
+        self,
+        health_reporter: Option<HealthReporter>,
+        pause_handle: PauseHandle,
+    ) -> Result<impl Daemon> {
+        let saya_path = self.db_path();
+
+        let [snos_workers, layout_bridge_workers, ingestor_workers] =
+            self.scaling.worker_pools(self.blocks_processed_in_parallel);
+        let atlantic_query_policy = self.atlantic_query.query_policy()?;
 
         tracing::info!(
-            snos_worker_count,
-            layout_bridge_workers_count,
-            ingestor_worker_count,
+            snos_worker_count = snos_workers.initial_workers(),
+            layout_bridge_workers_count = layout_bridge_workers.initial_workers(),
+            ingestor_worker_count = ingestor_workers.initial_workers(),
             "workers distribution"
         );
 
-        let rollup_chain_id = parse_cairo_short_string(
-            &JsonRpcClient::new(HttpTransport::new(self.rollup_rpc.clone()))
-                .chain_id()
-                .await?,
-        )?;
+        let rollup_rpc = self.rpc.transport(self.rollup_rpc)?;
+        let settlement_rpc = self.rpc.transport(self.settlement_rpc)?;
+
+        let rollup_chain_id =
+            parse_cairo_short_string(&JsonRpcClient::new(rollup_rpc.clone()).chain_id().await?)?;
 
         let mut atlantic_key: String = String::new();
+        let mut layout_bridge_program_hash = None;
+        let local_prover = self.local_prover.prover()?;
+        let proof_system = match local_prover {
+            Some(LocalProver::Stwo(_)) => ProofSystem::Stwo,
+            _ => ProofSystem::Stone,
+        };
         let db = SqliteDb::new(&saya_path).await?;
         let layout_bridge_pipeline_builder =
             match (self.mock_layout_bridge_program_hash, self.layout_bridge_program) {
@@ -146,41 +413,74 @@ impl Start {
                     db.clone(),
                     ))
                 }
+                // Stwo proofs are verified as is, without the layout bridge.
+                (None, _) if proof_system == ProofSystem::Stwo => {
+                    AnyLayoutBridgeProverBuilder::Stwo(StwoProofForwarderBuilder::new(db.clone()))
+                }
                 (None, Some(layout_bridge_program)) => {
-                    atlantic_key = match self.atlantic_key.clone() {
-                        Some(key) => key,
-                        None => return Err(anyhow::anyhow!("`atlantic-key` must be provided unless `--mock-layout-bridge-program-hash` is used"))
-                    };
-
                     let mut layout_bridge_file = std::fs::File::open(layout_bridge_program)?;
                     let mut layout_bridge =
                         Vec::with_capacity(layout_bridge_file.metadata()?.len() as usize);
                     layout_bridge_file.read_to_end(&mut layout_bridge)?;
+                    if self.verify_proofs {
+                        layout_bridge_program_hash = Some(compute_program_hash(&layout_bridge)?);
+                    }
 
-                    AnyLayoutBridgeProverBuilder::Atlantic(AtlanticLayoutBridgeProverBuilder::new(
-                        atlantic_key.clone(),
-                        layout_bridge,
-                        db.clone(),
-                        layout_bridge_workers_count,
-                    ))
+                    if let Some(LocalProver::Stone(stone_prover)) = &local_prover {
+                        AnyLayoutBridgeProverBuilder::Stone(StoneLayoutBridgeProverBuilder::new(
+                            stone_prover.clone(),
+                            layout_bridge,
+                            db.clone(),
+                            layout_bridge_workers,
+                        ))
+                    } else {
+                        atlantic_key = match self.atlantic_key.clone() {
+                            Some(key) => key,
+                            None => return Err(anyhow::anyhow!("`atlantic-key` must be provided unless `--mock-layout-bridge-program-hash` or `--stone-prover` is used"))
+                        };
+
+                        AnyLayoutBridgeProverBuilder::Atlantic(AtlanticLayoutBridgeProverBuilder::new(
+                            atlantic_key.clone(),
+                            layout_bridge,
+                            db.clone(),
+                            layout_bridge_workers,
+                        ).api_base(self.atlantic_url.clone()).query_policy(atlantic_query_policy).l2_verification(self.atlantic_fact_registration))
+                    }
                 }
                 (None, None) => anyhow::bail!(
-                    "invalid config: `--layout-bridge-program` must be provided unless `--mock-layout-bridge-program-hash` is used"
+                    "invalid config: `--layout-bridge-program` must be provided unless `--mock-layout-bridge-program-hash` or `--stwo-prover` is used"
                 ),
             };
 
         // TODO: make impls of these providers configurable
 
-        let block_ingestor_builder = PollingBlockIngestorBuilder::new(
-            self.rollup_rpc.clone(),
-            db.clone(),
-            ingestor_worker_count,
-        );
+        let mut block_ingestor_builder = match (self.replay_dir, self.rollup_ws) {
+            (Some(replay_dir), _) => {
+                AnyBlockIngestorBuilder::File(FileBlockIngestorBuilder::new(replay_dir, db.clone()))
+            }
+            (None, Some(rollup_ws)) => {
+                AnyBlockIngestorBuilder::Subscription(SubscriptionBlockIngestorBuilder::new(
+                    rollup_rpc.clone(),
+                    rollup_ws,
+                    db.clone(),
+                    ingestor_workers,
+                ))
+            }
+            (None, None) => AnyBlockIngestorBuilder::Polling(PollingBlockIngestorBuilder::new(
+                rollup_rpc.clone(),
+                db.clone(),
+                ingestor_workers,
+            )),
+        }
+        .pause_handle(pause_handle);
+        if self.merge_empty_blocks {
+            block_ingestor_builder = block_ingestor_builder.block_filter(MergeEmptyBlocks);
+        }
 
         let pie_gen_builder = SnosPieGeneratorBuilder::new(
-            self.rollup_rpc,
+            rollup_rpc,
             db.clone(),
-            ingestor_worker_count,
+            ingestor_workers,
             OsHintsConfiguration {
                 debug_mode: self.hints.debug_mode,
                 full_output: self.hints.full_output,
@@ -189,17 +489,62 @@ impl Start {
             ChainId::Other(rollup_chain_id),
         );
 
+        let snos_prover_builder = match local_prover {
+            Some(LocalProver::Stone(stone_prover)) => AnySnosProverBuilder::Stone(
+                StoneSnosProverBuilder::new(stone_prover, db.clone(), snos_workers),
+            ),
+            Some(LocalProver::Stwo(stwo_prover)) => AnySnosProverBuilder::Stwo(
+                StwoSnosProverBuilder::new(stwo_prover, db.clone(), snos_workers),
+            ),
+            None => AnySnosProverBuilder::Atlantic(
+                AtlanticSnosProverBuilder::new(
+                    atlantic_key,
+                    self.mock_snos_from_pie,
+                    db.clone(),
+                    snos_workers,
+                )
+                .api_base(self.atlantic_url)
+                .query_policy(atlantic_query_policy),
+            ),
+        };
+
+        let proof_verifier_builder = match layout_bridge_program_hash {
+            Some(layout_bridge_program_hash) => AnyProofVerifierBuilder::Swiftness(
+                ProofVerifierBuilder::new(layout_bridge_program_hash, db.clone()),
+            ),
+            None => AnyProofVerifierBuilder::skipped(),
+        };
+
+        let batcher_builder = BlockBatcherBuilder::new(
+            db.clone(),
+            self.snos_batch_size,
+            Duration::from_secs(self.snos_batch_idle_timeout_secs),
+        );
+
+        // Blocks in flight are persisted in front of each stage, so that a restart resumes each block
+        // from the stage it was left at.
         let pipeline_builder = PipelineChainBuilder::new(
             PipelineChainBuilder::new(
-                pie_gen_builder,
+                batcher_builder,
                 PipelineChainBuilder::new(
-                    AtlanticSnosProverBuilder::new(
-                        atlantic_key,
-                        self.mock_snos_from_pie,
-                        db.clone(),
-                        snos_worker_count,
+                    DurableStageBuilder::new(pie_gen_builder, db.clone(), "snos_pie", 0),
+                    PipelineChainBuilder::new(
+                        DurableStageBuilder::new(snos_prover_builder, db.clone(), "snos_proof", 1),
+                        PipelineChainBuilder::new(
+                            DurableStageBuilder::new(
+                                layout_bridge_pipeline_builder,
+                                db.clone(),
+                                "layout_bridge",
+                                2,
+                            ),
+                            DurableStageBuilder::new(
+                                proof_verifier_builder,
+                                db.clone(),
+                                "proof_verification",
+                                3,
+                            ),
+                        ),
                     ),
-                    layout_bridge_pipeline_builder,
                 ),
             ),
             BlockOrdererBuilder::new(),
@@ -208,6 +553,14 @@ impl Start {
         let da_builder = if let (Some(celestia_rpc), Some(celestia_token)) =
             (self.celestia.celestia_rpc, self.celestia.celestia_token)
         {
+            // Packets only hold the state update of the last block of a batch.
+            if self.snos_batch_size > 1 {
+                anyhow::bail!("invalid config: `--snos-batch-size` can't be used with Celestia");
+            }
+            if self.merge_empty_blocks {
+                anyhow::bail!("invalid config: `--merge-empty-blocks` can't be used with Celestia");
+            }
+
             AnyDataAvailabilityLayerBuilder::Celestia(Box::new(
                 CelestiaDataAvailabilityBackendBuilder::new(
                     celestia_rpc,
@@ -222,12 +575,13 @@ impl Start {
         };
 
         let settlement_builder = PiltoverSettlementBackendBuilder::new(
-            self.settlement_rpc,
+            settlement_rpc,
             self.settlement_piltover_address,
             self.settlement_account_address,
             self.settlement_account_private_key,
             db.clone(),
-        );
+        )
+        .integrity_verifier(self.integrity.verifier(proof_system)?);
 
         let settlement_builder = match (
             self.mock_layout_bridge_program_hash,
@@ -235,45 +589,28 @@ impl Start {
         ) {
             // We don't need `integrity` address but it's okay if it's given.
             (Some(_), _) => settlement_builder.skip_fact_registration(true),
-            (None, Some(integrity_address)) => {
-                settlement_builder.integrity_address(integrity_address)
-            }
+            (None, Some(integrity_address)) => settlement_builder
+                .integrity_address(integrity_address)
+                .atlantic_fact_registration(self.atlantic_fact_registration.is_some()),
             (None, None) => anyhow::bail!(
                 "invalid config: `integrity` address must be \
                 provided unless `--mock-layout-bridge-program-hash` is used"
             ),
         };
 
-        let orchestrator = PersistentOrchestratorBuilder::new(
+        let mut orchestrator_builder = PersistentOrchestratorBuilder::new(
             block_ingestor_builder,
             pipeline_builder,
             da_builder,
             settlement_builder,
-        )
-        .build()
-        .await?;
-        let orchestrator_shutdown = orchestrator.shutdown_handle();
-        orchestrator.start();
-
-        let mut sigterm_handle =
-            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
-        let ctrl_c_handle = tokio::signal::ctrl_c();
-
-        tokio::select! {
-            _ = sigterm_handle.recv() => {},
-            _ = ctrl_c_handle => {},
-            _ = orchestrator_shutdown.finished() => {},
+        );
+        if let Some(reporter) = health_reporter {
+            orchestrator_builder = orchestrator_builder.health_reporter(reporter);
         }
-
-        // Graceful shutdown
-        orchestrator_shutdown.shutdown();
-        tokio::select! {
-            _ = tokio::time::sleep(GRACEFUL_SHUTDOWN_TIMEOUT) => {
-                Err(anyhow::anyhow!("timeout waiting for graceful shutdown"))
-            },
-            _ = orchestrator_shutdown.finished() => {
-                Ok(())
-            },
+        if let Some(end_block) = self.end_block {
+            orchestrator_builder = orchestrator_builder.end_block(end_block);
         }
+
+        orchestrator_builder.build().await
     }
 }
@@ -1,2 +1,2 @@
 mod piltover;
-pub use piltover::PiltoverSettlementBackendBuilder;
+pub use piltover::{IntegrityVerifier, PiltoverSettlementBackendBuilder, ProofSystem};
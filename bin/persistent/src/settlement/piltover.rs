@@ -1,5 +1,9 @@
-use crate::utils::{
-    calculate_output, felt_to_bigdecimal, retry_with_backoff, split_calls, watch_tx,
+use crate::{
+    stwo,
+    utils::{
+        calculate_fact_hash, calculate_output, felt_to_bigdecimal, retry_with_backoff, split_calls,
+        watch_tx,
+    },
 };
 use anyhow::Result;
 use integrity::{split_proof, VerifierConfiguration};
@@ -7,6 +11,8 @@ use piltover::{DaLayerInfo, PiltoverInput};
 use saya_core::{
     block_ingestor::BlockInfo,
     data_availability::DataAvailabilityCursor,
+    metrics,
+    rpc::FailoverTransport,
     service::{Daemon, FinishHandle},
     settlement::{SettlementBackend, SettlementBackendBuilder, SettlementCursor},
     storage::PersistantStorage,
@@ -18,7 +24,7 @@ use starknet::{
         types::{BlockId, BlockTag, Call, FunctionCall, TransactionReceipt},
     },
     macros::{selector, short_string},
-    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider},
+    providers::{JsonRpcClient, Provider},
     signers::{LocalWallet, SigningKey},
 };
 use starknet_types_core::felt::Felt;
@@ -29,15 +35,21 @@ use std::{
 use swiftness::types::StarkProof;
 use swiftness::TransformTo;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tracing::{debug, info};
-use url::Url;
+use tracing::{debug, error, info};
 
 const POLLING_INTERVAL: Duration = Duration::from_secs(1);
+/// Interval between the checks of the fact registry for facts registered by Atlantic.
+const FACT_POLLING_INTERVAL: Duration = Duration::from_secs(10);
+/// Time Atlantic has to register the fact of a proof once its query is done.
+const FACT_REGISTRATION_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Layout of the Stone proofs saya is able to split into calls to the integrity verifier.
+const STONE_LAYOUT: Felt = short_string!("recursive_with_poseidon");
 
 #[derive(Debug)]
 pub struct PiltoverSettlementBackend<DB> {
-    provider: Arc<JsonRpcClient<HttpTransport>>,
-    account: SingleOwnerAccount<Arc<JsonRpcClient<HttpTransport>>, LocalWallet>,
+    provider: Arc<JsonRpcClient<FailoverTransport>>,
+    account: SingleOwnerAccount<Arc<JsonRpcClient<FailoverTransport>>, LocalWallet>,
     fact_registration: FactRegistrationConfig,
     piltover_address: Felt,
     da_channel: Receiver<DataAvailabilityCursor<BlockInfo>>,
@@ -48,9 +60,11 @@ pub struct PiltoverSettlementBackend<DB> {
 
 #[derive(Debug)]
 pub struct PiltoverSettlementBackendBuilder<DB> {
-    rpc_url: Url,
+    rpc: FailoverTransport,
     integrity_address: Option<Felt>,
+    integrity_verifier: IntegrityVerifier,
     skip_fact_registration: bool,
+    atlantic_fact_registration: bool,
     piltover_address: Felt,
     account_address: Felt,
     account_private_key: Felt,
@@ -68,10 +82,105 @@ struct AppchainState {
     block_hash: Felt,
 }
 
+/// Proof systems whose proofs can be verified on integrity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProofSystem {
+    /// Stone proofs of the layout bridge, split into several transactions.
+    Stone,
+    /// Stwo proofs of SNOS in their Cairo serialization, verified in a single call.
+    Stwo,
+}
+
+/// Verifier of the integrity contracts the settled proofs are registered with, as
+/// `VerifierConfiguration` short strings.
+#[derive(Debug, Clone, Copy)]
+pub struct IntegrityVerifier {
+    pub proof_system: ProofSystem,
+    pub layout: Felt,
+    pub hasher: Felt,
+    pub stone_version: Felt,
+    pub memory_verification: Felt,
+}
+
 #[derive(Debug)]
 enum FactRegistrationConfig {
-    Integrity(Felt),
-    Skipped,
+    Integrity(Felt, IntegrityVerifier),
+    /// Proofs are verified on L2 by Atlantic, whose facts are waited for in the fact registry.
+    Atlantic(Felt),
+    Skipped(ProofSystem),
+}
+
+impl Default for IntegrityVerifier {
+    /// The verifier of the layout bridge proofs generated by Atlantic.
+    fn default() -> Self {
+        Self {
+            proof_system: ProofSystem::Stone,
+            layout: STONE_LAYOUT,
+            hasher: short_string!("keccak_160_lsb"),
+            stone_version: short_string!("stone6"),
+            memory_verification: short_string!("relaxed"),
+        }
+    }
+}
+
+impl IntegrityVerifier {
+    fn configuration(&self) -> VerifierConfiguration {
+        VerifierConfiguration {
+            layout: self.layout,
+            hasher: self.hasher,
+            stone_version: self.stone_version,
+            memory_verification: self.memory_verification,
+        }
+    }
+
+    /// Builds the calls verifying `raw_proof`, along with the program output the proof registers
+    /// the fact of.
+    fn verification_calls(
+        &self,
+        integrity_address: Felt,
+        raw_proof: String,
+    ) -> Result<(Vec<Call>, Vec<Felt>)> {
+        match self.proof_system {
+            ProofSystem::Stone => {
+                let layout_bridge_proof = swiftness::parse(raw_proof)?.transform_to();
+                let split_proof = split_proof::<
+                    swiftness_air::layout::recursive_with_poseidon::Layout,
+                >(layout_bridge_proof.clone())?;
+                let program_output = calculate_output(&layout_bridge_proof);
+
+                let integrity_job_id = SigningKey::from_random().secret_scalar();
+                debug!(
+                    integrity_job_id = %format!("{:#064x}", integrity_job_id),
+                    "Splitting proof for integrity verifier"
+                );
+                let calls = split_proof
+                    .into_calls(integrity_job_id, self.configuration())
+                    .collect_calls(integrity_address);
+
+                Ok((calls, program_output))
+            }
+            ProofSystem::Stwo => {
+                let proof: Vec<Felt> = serde_json::from_str(&raw_proof)?;
+                let program_output = stwo::program_output(&proof)?;
+
+                let configuration = self.configuration();
+                let mut calldata = vec![
+                    configuration.layout,
+                    configuration.hasher,
+                    configuration.stone_version,
+                    configuration.memory_verification,
+                ];
+                calldata.extend(proof);
+                let call = Call {
+                    to: integrity_address,
+                    selector: selector!("verify_proof_full_and_register_fact"),
+                    calldata,
+                };
+
+                Ok((vec![call], program_output))
+            }
+        }
+    }
 }
 
 impl<DB> PiltoverSettlementBackend<DB>
@@ -94,6 +203,53 @@ where
         Ok(AppchainState::decode(&raw_result)?)
     }
 
+    /// Waits for `fact_hash` to be registered in `fact_registry`.
+    ///
+    /// Returns `false` if shutdown was requested before the fact was registered. Settlement runs
+    /// after the block orderer, so a block whose fact isn't registered in time can't be failed
+    /// and proven again without the next blocks settling out of order: the stage fails instead.
+    async fn wait_for_fact(&self, fact_registry: Felt, fact_hash: Felt) -> Result<bool> {
+        let deadline = Instant::now() + FACT_REGISTRATION_TIMEOUT;
+        loop {
+            let verifications = retry_with_backoff(
+                || {
+                    self.provider.call(
+                        FunctionCall {
+                            contract_address: fact_registry,
+                            entry_point_selector: selector!("get_all_verifications_for_fact_hash"),
+                            calldata: vec![fact_hash],
+                        },
+                        BlockId::Tag(BlockTag::Latest),
+                    )
+                },
+                "get_all_verifications_for_fact_hash",
+                3,
+                Duration::from_secs(3),
+            )
+            .await?;
+            // The verifications are returned as an array, prefixed by its length.
+            if verifications
+                .first()
+                .is_some_and(|length| *length != Felt::ZERO)
+            {
+                return Ok(true);
+            }
+
+            if Instant::now() >= deadline {
+                anyhow::bail!(
+                    "fact {:#064x} not registered after {} seconds",
+                    fact_hash,
+                    FACT_REGISTRATION_TIMEOUT.as_secs()
+                );
+            }
+
+            tokio::select! {
+                _ = self.finish_handle.shutdown_requested() => return Ok(false),
+                _ = tokio::time::sleep(FACT_POLLING_INTERVAL) => {},
+            }
+        }
+    }
+
     async fn run(mut self) {
         loop {
             let new_da = tokio::select! {
@@ -106,6 +262,7 @@ where
             };
 
             debug!("Received new DA cursor");
+            let stage_timer = metrics::stage_timer("settlement");
             let layout_bridge_proof = self
                 .db
                 .get_proof(
@@ -125,32 +282,14 @@ where
             {
                 saya_core::storage::BlockStatus::BridgeProofGenerated => {
                     match self.fact_registration {
-                        FactRegistrationConfig::Integrity(integrity_address) => {
-                            let layout_bridge_proof =
-                                swiftness::parse(raw_proof).unwrap().transform_to();
+                        FactRegistrationConfig::Integrity(integrity_address, verifier) => {
                             // TODO: error handling
-                            let split_proof = split_proof::<
-                                swiftness_air::layout::recursive_with_poseidon::Layout,
-                            >(
-                                layout_bridge_proof.clone()
-                            )
-                            .unwrap();
-                            program_output = calculate_output(&layout_bridge_proof);
-                            let integrity_job_id = SigningKey::from_random().secret_scalar();
-                            let integrity_calls = split_proof
-                                .into_calls(
-                                    integrity_job_id,
-                                    VerifierConfiguration {
-                                        layout: short_string!("recursive_with_poseidon"),
-                                        hasher: short_string!("keccak_160_lsb"),
-                                        stone_version: short_string!("stone6"),
-                                        memory_verification: short_string!("relaxed"),
-                                    },
-                                )
-                                .collect_calls(integrity_address);
+                            let (integrity_calls, output) = verifier
+                                .verification_calls(integrity_address, raw_proof)
+                                .unwrap();
+                            program_output = output;
                             let integrity_call_chunks = split_calls(integrity_calls);
                             debug!(
-                                integrity_job_id = %format!("{:#064x}",integrity_job_id),
                                 "{} transactions to integrity verifier generated",
                                 integrity_call_chunks.len()
                             );
@@ -185,15 +324,7 @@ where
                                         .await
                                         .unwrap();
 
-                                let fee = match &receipt.receipt {
-                                    TransactionReceipt::Invoke(receipt) => &receipt.actual_fee,
-                                    TransactionReceipt::L1Handler(receipt) => &receipt.actual_fee,
-                                    TransactionReceipt::Declare(receipt) => &receipt.actual_fee,
-                                    TransactionReceipt::Deploy(receipt) => &receipt.actual_fee,
-                                    TransactionReceipt::DeployAccount(receipt) => {
-                                        &receipt.actual_fee
-                                    }
-                                };
+                                let fee = actual_fee(&receipt.receipt);
 
                                 debug!(
                                     transaction_hash = %format!("{:#064x}", tx.transaction_hash),
@@ -203,7 +334,8 @@ where
                                 );
 
                                 nonce += Felt::ONE;
-                                total_fee += fee.amount;
+                                total_fee += fee;
+                                metrics::record_settlement_fee("integrity", fee);
                             }
 
                             let proof_end = Instant::now();
@@ -221,10 +353,60 @@ where
                                 .await
                                 .unwrap();
                         }
-                        FactRegistrationConfig::Skipped => {
-                            let layout_bridge_proof =
-                                serde_json::from_str::<StarkProof>(&raw_proof).unwrap();
-                            let output = calculate_output(&layout_bridge_proof);
+                        FactRegistrationConfig::Atlantic(fact_registry) => {
+                            // TODO: error handling
+                            let layout_bridge_proof: StarkProof =
+                                swiftness::parse(raw_proof).unwrap().transform_to();
+                            let fact_hash = calculate_fact_hash(&layout_bridge_proof);
+                            program_output = calculate_output(&layout_bridge_proof);
+
+                            info!(
+                                block_number = new_da.block_number,
+                                fact_hash = %format!("{:#064x}", fact_hash),
+                                "Waiting for Atlantic to register the proof fact"
+                            );
+                            let proof_start = Instant::now();
+                            match self.wait_for_fact(fact_registry, fact_hash).await {
+                                Ok(true) => {}
+                                Ok(false) => break,
+                                Err(err) => {
+                                    error!(
+                                        block_number = new_da.block_number,
+                                        error = %err,
+                                        "Failed to wait for the proof fact"
+                                    );
+                                    self.finish_handle.fail(format!("{err:#}"));
+                                    break;
+                                }
+                            }
+                            info!(
+                                block_number = new_da.block_number,
+                                "Proof fact registered by Atlantic after {:.2} seconds",
+                                proof_start.elapsed().as_secs_f32()
+                            );
+
+                            self.db
+                                .set_status(
+                                    new_da.block_number.try_into().unwrap(),
+                                    "verified_proof".to_string(),
+                                )
+                                .await
+                                .unwrap();
+                        }
+                        FactRegistrationConfig::Skipped(proof_system) => {
+                            let output = match proof_system {
+                                ProofSystem::Stone => {
+                                    let layout_bridge_proof =
+                                        serde_json::from_str::<StarkProof>(&raw_proof).unwrap();
+                                    calculate_output(&layout_bridge_proof)
+                                }
+                                // Stwo proofs aren't wrapped by the layout bridge, so the output
+                                // settled is the bootloader one, public in the SNOS proof.
+                                ProofSystem::Stwo => stwo::program_output(
+                                    &serde_json::from_str::<Vec<Felt>>(&raw_proof).unwrap(),
+                                )
+                                .unwrap(),
+                            };
 
                             let (messages_to_l1, messages_to_l2) =
                                 crate::utils::extract_messages_from_program_output(
@@ -333,13 +515,16 @@ where
 
             // TODO: timeout
             // TODO: error handling
-            watch_tx(
+            let receipt = watch_tx(
                 &self.provider,
                 transaction.transaction_hash,
                 POLLING_INTERVAL,
             )
             .await
             .unwrap();
+            stage_timer.observe();
+            metrics::record_settlement_fee("update_state", actual_fee(&receipt.receipt));
+            metrics::record_block_status(&saya_core::storage::BlockStatus::Settled);
 
             info!(
                 block_number = new_da.block_number,
@@ -347,10 +532,17 @@ where
                 "Piltover statement transaction confirmed",
             );
 
-            self.db
-                .remove_block(new_da.block_number.try_into().unwrap())
-                .await
-                .unwrap();
+            // A batch proven by a single SNOS run is settled as a whole.
+            let first_block = new_da
+                .full_payload
+                .first_block
+                .unwrap_or(new_da.block_number);
+            for block_number in first_block..=new_da.block_number {
+                self.db
+                    .remove_block(block_number.try_into().unwrap())
+                    .await
+                    .unwrap();
+            }
             let new_cursor = SettlementCursor {
                 block_number: new_da.block_number,
                 transaction_hash: transaction.transaction_hash,
@@ -370,16 +562,18 @@ where
 
 impl<DB> PiltoverSettlementBackendBuilder<DB> {
     pub fn new(
-        rpc_url: Url,
+        rpc: FailoverTransport,
         piltover_address: Felt,
         account_address: Felt,
         account_private_key: Felt,
         db: DB,
     ) -> Self {
         Self {
-            rpc_url,
+            rpc,
             integrity_address: None,
+            integrity_verifier: IntegrityVerifier::default(),
             skip_fact_registration: false,
+            atlantic_fact_registration: false,
             piltover_address,
             account_address,
             account_private_key,
@@ -394,10 +588,22 @@ impl<DB> PiltoverSettlementBackendBuilder<DB> {
         self
     }
 
+    pub fn integrity_verifier(mut self, integrity_verifier: IntegrityVerifier) -> Self {
+        self.integrity_verifier = integrity_verifier;
+        self
+    }
+
     pub fn skip_fact_registration(mut self, skip_fact_registration: bool) -> Self {
         self.skip_fact_registration = skip_fact_registration;
         self
     }
+
+    /// Waits for the facts of the proofs verified on L2 by Atlantic to be registered in the
+    /// integrity contract, instead of verifying the proofs from the settlement account.
+    pub fn atlantic_fact_registration(mut self, atlantic_fact_registration: bool) -> Self {
+        self.atlantic_fact_registration = atlantic_fact_registration;
+        self
+    }
 }
 
 impl<DB> SettlementBackendBuilder for PiltoverSettlementBackendBuilder<DB>
@@ -407,7 +613,31 @@ where
     type Backend = PiltoverSettlementBackend<DB>;
 
     async fn build(self) -> Result<Self::Backend> {
-        let provider = Arc::new(JsonRpcClient::new(HttpTransport::new(self.rpc_url)));
+        if self.integrity_verifier.proof_system == ProofSystem::Stone
+            && self.integrity_verifier.layout != STONE_LAYOUT
+        {
+            anyhow::bail!(
+                "only `recursive_with_poseidon` Stone proofs can be verified on integrity"
+            );
+        }
+
+        let fact_registration = if self.skip_fact_registration {
+            FactRegistrationConfig::Skipped(self.integrity_verifier.proof_system)
+        } else {
+            let integrity_address = self
+                .integrity_address
+                .ok_or_else(|| anyhow::anyhow!("`integrity_address` not set"))?;
+            if self.atlantic_fact_registration {
+                if self.integrity_verifier.proof_system != ProofSystem::Stone {
+                    anyhow::bail!("only Stone proofs can be verified on L2 by Atlantic");
+                }
+                FactRegistrationConfig::Atlantic(integrity_address)
+            } else {
+                FactRegistrationConfig::Integrity(integrity_address, self.integrity_verifier)
+            }
+        };
+
+        let provider = Arc::new(JsonRpcClient::new(self.rpc));
         let chain_id = provider.chain_id().await?;
 
         let mut account = SingleOwnerAccount::new(
@@ -422,14 +652,7 @@ where
         Ok(PiltoverSettlementBackend {
             provider,
             account,
-            fact_registration: if self.skip_fact_registration {
-                FactRegistrationConfig::Skipped
-            } else {
-                FactRegistrationConfig::Integrity(
-                    self.integrity_address
-                        .ok_or_else(|| anyhow::anyhow!("`integrity_address` not set"))?,
-                )
-            },
+            fact_registration,
             piltover_address: self.piltover_address,
             da_channel: self
                 .da_channel
@@ -472,6 +695,16 @@ where
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
+
+fn actual_fee(receipt: &TransactionReceipt) -> Felt {
+    match receipt {
+        TransactionReceipt::Invoke(receipt) => receipt.actual_fee.amount,
+        TransactionReceipt::L1Handler(receipt) => receipt.actual_fee.amount,
+        TransactionReceipt::Declare(receipt) => receipt.actual_fee.amount,
+        TransactionReceipt::Deploy(receipt) => receipt.actual_fee.amount,
+        TransactionReceipt::DeployAccount(receipt) => receipt.actual_fee.amount,
     }
 }
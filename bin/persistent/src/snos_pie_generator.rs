@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 use anyhow::Result;
 use cairo_vm::vm::runners::cairo_pie::CairoPie;
 use generate_pie::{
@@ -10,20 +8,15 @@ use generate_pie::{
 use crate::atlantic::compress_pie;
 use saya_core::{
     block_ingestor::BlockInfo,
+    metrics,
     prover::{PipelineStage, PipelineStageBuilder},
-    service::{Daemon, FinishHandle, ShutdownHandle},
+    rpc::FailoverTransport,
+    service::{Daemon, FinishHandle, ShutdownHandle, WorkerHandle, WorkerPool, WorkerPoolConfig},
     storage::{BlockStatus, PersistantStorage, Step},
 };
 use starknet_api::{contract_address, core::ChainId};
-use tokio::{
-    sync::{
-        mpsc::{Receiver, Sender},
-        Mutex,
-    },
-    task,
-};
+use tokio::sync::mpsc::{Receiver, Sender};
 use tracing::{debug, error, info, trace};
-use url::Url;
 
 const KATANA_DEFAULT_TOKEN_ADDRESS: &str =
     "0x2e7442625bab778683501c0eadbc1ea17b3535da040a12ac7d281066e915eea";
@@ -34,25 +27,28 @@ const KATANA_DEFAULT_TOKEN_ADDRESS: &str =
 /// This is the stage that was previously embedded inside `PollingBlockIngestor`. Extracting it
 /// allows the block ingestor to remain proving-strategy-agnostic and makes it straightforward
 /// to swap in a different preparation step (e.g. TEE attestation) without touching block control.
+///
+/// A `BlockInfo` covering a batch of blocks (see [`BlockInfo::first_block`]) yields a single PIE
+/// for the whole batch, stored under the last block.
 #[derive(Debug)]
 pub struct SnosPieGenerator<DB> {
-    rpc_url: Url,
+    rpc: FailoverTransport,
     input_channel: Receiver<BlockInfo>,
     output_channel: Sender<BlockInfo>,
     finish_handle: FinishHandle,
     db: DB,
-    workers_count: usize,
+    workers: WorkerPoolConfig,
     os_hints_config: OsHintsConfiguration,
     chain_id: ChainId,
 }
 
 #[derive(Debug)]
 pub struct SnosPieGeneratorBuilder<DB> {
-    rpc_url: Url,
+    rpc: FailoverTransport,
     input_channel: Option<Receiver<BlockInfo>>,
     output_channel: Option<Sender<BlockInfo>>,
     db: DB,
-    workers_count: usize,
+    workers: WorkerPoolConfig,
     os_hints_config: OsHintsConfiguration,
     chain_id: ChainId,
 }
@@ -62,23 +58,24 @@ where
     DB: PersistantStorage + Send + Sync + Clone + 'static,
 {
     async fn worker(
-        task_rx: Arc<Mutex<Receiver<BlockInfo>>>,
+        worker: WorkerHandle<BlockInfo>,
         task_tx: Sender<BlockInfo>,
-        rpc_url: Url,
+        rpc: FailoverTransport,
         finish_handle: FinishHandle,
         db: DB,
         os_hints_config: OsHintsConfiguration,
         chain_id: ChainId,
     ) {
         loop {
-            let block_info = if let Some(b) = task_rx.lock().await.recv().await {
-                b
+            let (block_info, _task) = if let Some(task) = worker.recv().await {
+                task
             } else {
                 break;
             };
 
             let block_number = block_info.number;
             let block_number_u32: u32 = block_number.try_into().unwrap();
+            let first_block = block_info.first_block.unwrap_or(block_number);
 
             if finish_handle.is_shutdown_requested() {
                 break;
@@ -93,6 +90,7 @@ where
                             number: block_number,
                             status: BlockStatus::SnosPieGenerated,
                             state_update: block_info.state_update,
+                            first_block: block_info.first_block,
                         };
                         if task_tx.send(out).await.is_err() {
                             error!(block_number, "Failed to forward block after PIE resume");
@@ -108,9 +106,11 @@ where
                 }
             }
 
+            let stage_timer = metrics::stage_timer("snos_pie");
+            // PIE generation talks to a single endpoint, the one currently in use.
             let pie_input = generate_pie::types::PieGenerationInput {
-                rpc_url: rpc_url.to_string(),
-                blocks: vec![block_number],
+                rpc_url: rpc.url().to_string(),
+                blocks: (first_block..=block_number).collect(),
                 versioned_constants: None,
                 chain_config: ChainConfig {
                     chain_id: chain_id.clone(),
@@ -133,13 +133,15 @@ where
             db.add_pie(block_number_u32, pie_bytes, Step::Snos)
                 .await
                 .unwrap();
+            stage_timer.observe();
 
-            info!(block_number, "SNOS PIE generated for block");
+            info!(first_block, block_number, "SNOS PIE generated for block");
 
             let out = BlockInfo {
                 number: block_number,
                 status: BlockStatus::SnosPieGenerated,
                 state_update: block_info.state_update,
+                first_block: block_info.first_block,
             };
 
             if task_tx.send(out).await.is_err() {
@@ -149,22 +151,24 @@ where
     }
 
     async fn run(self) {
-        let mut workers = Vec::new();
-        let task_rx = Arc::new(Mutex::new(self.input_channel));
-
-        for _ in 0..self.workers_count {
-            workers.push(task::spawn(Self::worker(
-                task_rx.clone(),
+        let pool = WorkerPool::new(
+            "snos_pie",
+            self.input_channel,
+            self.workers,
+            self.finish_handle.clone(),
+        );
+        pool.run(|worker| {
+            Self::worker(
+                worker,
                 self.output_channel.clone(),
-                self.rpc_url.clone(),
+                self.rpc.clone(),
                 self.finish_handle.clone(),
                 self.db.clone(),
                 self.os_hints_config.clone(),
                 self.chain_id.clone(),
-            )));
-        }
-
-        futures_util::future::join_all(workers).await;
+            )
+        })
+        .await;
         debug!("Graceful shutdown finished");
         self.finish_handle.finish();
     }
@@ -172,18 +176,18 @@ where
 
 impl<DB> SnosPieGeneratorBuilder<DB> {
     pub fn new(
-        rpc_url: Url,
+        rpc: FailoverTransport,
         db: DB,
-        workers_count: usize,
+        workers: WorkerPoolConfig,
         os_hints_config: OsHintsConfiguration,
         chain_id: ChainId,
     ) -> Self {
         Self {
-            rpc_url,
+            rpc,
             input_channel: None,
             output_channel: None,
             db,
-            workers_count,
+            workers,
             os_hints_config,
             chain_id,
         }
@@ -198,7 +202,7 @@ where
 
     fn build(self) -> Result<Self::Stage> {
         Ok(SnosPieGenerator {
-            rpc_url: self.rpc_url,
+            rpc: self.rpc,
             input_channel: self
                 .input_channel
                 .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
@@ -207,7 +211,7 @@ where
                 .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
             finish_handle: FinishHandle::new(),
             db: self.db,
-            workers_count: self.workers_count,
+            workers: self.workers,
             os_hints_config: self.os_hints_config,
             chain_id: self.chain_id,
         })
@@ -241,6 +245,6 @@ where
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
@@ -3,25 +3,30 @@ use std::{path::PathBuf, time::Duration};
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use generate_pie::types::OsHintsConfiguration;
+use saya_config::{AdminConfiguration, HealthConfiguration, RpcConfiguration};
 use saya_core::{
-    block_ingestor::PollingBlockIngestorBuilder,
+    block_ingestor::{
+        BlockIngestorBuilder, FileBlockIngestorBuilder, PollingBlockIngestorBuilder,
+        SubscriptionBlockIngestorBuilder,
+    },
     data_availability::CelestiaDataAvailabilityBackendBuilder,
     orchestrator::Genesis,
     prover::{BlockOrdererBuilder, PipelineChainBuilder},
-    service::Daemon,
+    service::{Daemon, PauseHandle},
     storage::{InMemoryStorageBackend, SqliteDb},
     ChainId,
 };
 
 use crate::{
-    atlantic::AtlanticSnosProverBuilder,
-    common::{calculate_workers_per_stage, SAYA_DB_PATH},
+    any::AnyBlockIngestorBuilder,
+    atlantic::{AtlanticSnosProverBuilder, ATLANTIC_API_BASE},
+    common::{AtlanticQueryConfiguration, ScalingConfiguration, SAYA_DB_PATH},
     orchestrator::SovereignOrchestratorBuilder,
     snos_pie_generator::SnosPieGeneratorBuilder,
 };
 use starknet::{
     core::utils::parse_cairo_short_string,
-    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider},
+    providers::{JsonRpcClient, Provider},
 };
 use url::Url;
 
@@ -42,15 +47,31 @@ enum Subcommands {
 
 #[derive(Debug, Parser)]
 struct Start {
-    /// Starknet JSON-RPC URL (v0.7.1)
+    /// Starknet JSON-RPC URLs (v0.7.1), comma-separated. Requests fail over to the next URL when
+    /// one doesn't answer
+    #[clap(long, env, value_delimiter = ',', required = true)]
+    starknet_rpc: Vec<Url>,
+    /// Rollup network Starknet WebSocket URL for subscribing to new blocks. New blocks are polled
+    /// from `--starknet-rpc` if not set
     #[clap(long, env)]
-    starknet_rpc: Url,
+    rollup_ws: Option<Url>,
+    /// Directory of blocks exported by `saya export-blocks` to replay instead of ingesting blocks
+    /// from the network. PIEs are still generated from the rollup RPC, which must serve the
+    /// replayed blocks
+    #[clap(long, env, conflicts_with = "rollup_ws")]
+    replay_dir: Option<PathBuf>,
     /// Whether to mock the SNOS proof by extracting the output from the PIE and using it from a proof.
     #[clap(long)]
     mock_snos_from_pie: bool,
     /// Atlantic prover API key
     #[clap(long, env)]
     atlantic_key: String,
+    /// Atlantic prover API URL
+    #[clap(long, env, default_value = ATLANTIC_API_BASE)]
+    atlantic_url: Url,
+    /// Atlantic query limits and budget configuration
+    #[clap(flatten)]
+    atlantic_query: AtlanticQueryConfiguration,
     /// Celestia RPC endpoint URL
     #[clap(long, env)]
     celestia_rpc: Url,
@@ -68,12 +89,31 @@ struct Start {
     /// Genesis options
     #[clap(flatten)]
     genesis: GenesisOptions,
+    /// First block to process, overriding the genesis block
+    #[clap(long, env)]
+    start_block: Option<u64>,
+    /// Last block to publish. Saya exits once this block has been published, runs forever if not
+    /// set
+    #[clap(long, env)]
+    end_block: Option<u64>,
     /// Number of blocks to process in parallel
     #[clap(long, env)]
     blocks_processed_in_parallel: usize,
+    /// Worker scaling configuration
+    #[clap(flatten)]
+    scaling: ScalingConfiguration,
+    /// RPC client configuration
+    #[clap(flatten)]
+    rpc: RpcConfiguration,
     /// Path to the database directory
     #[clap(long, env)]
     db_dir: Option<PathBuf>,
+    /// Health server configuration
+    #[clap(flatten)]
+    health: HealthConfiguration,
+    /// Admin server configuration
+    #[clap(flatten)]
+    admin: AdminConfiguration,
 }
 
 /// Validate that the value is not empty.
@@ -104,33 +144,53 @@ impl Sovereign {
 
 impl Start {
     pub async fn run(self) -> Result<()> {
+        if let (Some(start_block), Some(end_block)) = (self.start_block, self.end_block) {
+            if start_block > end_block {
+                anyhow::bail!("`--start-block` must not be greater than `--end-block`");
+            }
+        }
+
+        let health = self.health.start().await?;
+
         let saya_path = self
             .db_dir
             .map(|db_dir| format!("{}/{}", db_dir.display(), SAYA_DB_PATH))
             .unwrap_or_else(|| SAYA_DB_PATH.to_string());
         let db = SqliteDb::new(&saya_path).await?;
 
-        let workers_distribution: [usize; 3] =
-            calculate_workers_per_stage(self.blocks_processed_in_parallel);
-        let [snos_worker_count, _layout_bridge_workers_count, ingestor_worker_count] =
-            workers_distribution;
+        let [snos_workers, _layout_bridge_workers, ingestor_workers] =
+            self.scaling.worker_pools(self.blocks_processed_in_parallel);
+        let atlantic_query_policy = self.atlantic_query.query_policy()?;
 
-        let chain_id = parse_cairo_short_string(
-            &JsonRpcClient::new(HttpTransport::new(self.starknet_rpc.clone()))
-                .chain_id()
-                .await?,
-        )?;
+        let starknet_rpc = self.rpc.transport(self.starknet_rpc)?;
+        let chain_id =
+            parse_cairo_short_string(&JsonRpcClient::new(starknet_rpc.clone()).chain_id().await?)?;
 
-        let block_ingestor_builder = PollingBlockIngestorBuilder::new(
-            self.starknet_rpc.clone(),
-            db.clone(),
-            ingestor_worker_count,
-        );
+        let pause_handle = PauseHandle::new();
+        let block_ingestor_builder = match (self.replay_dir, self.rollup_ws) {
+            (Some(replay_dir), _) => {
+                AnyBlockIngestorBuilder::File(FileBlockIngestorBuilder::new(replay_dir, db.clone()))
+            }
+            (None, Some(rollup_ws)) => {
+                AnyBlockIngestorBuilder::Subscription(SubscriptionBlockIngestorBuilder::new(
+                    starknet_rpc.clone(),
+                    rollup_ws,
+                    db.clone(),
+                    ingestor_workers,
+                ))
+            }
+            (None, None) => AnyBlockIngestorBuilder::Polling(PollingBlockIngestorBuilder::new(
+                starknet_rpc.clone(),
+                db.clone(),
+                ingestor_workers,
+            )),
+        }
+        .pause_handle(pause_handle.clone());
 
         let pie_gen_builder = SnosPieGeneratorBuilder::new(
-            self.starknet_rpc,
+            starknet_rpc,
             db.clone(),
-            ingestor_worker_count,
+            ingestor_workers,
             OsHintsConfiguration {
                 debug_mode: false,
                 full_output: false,
@@ -146,8 +206,10 @@ impl Start {
                     self.atlantic_key,
                     self.mock_snos_from_pie,
                     db.clone(),
-                    snos_worker_count,
-                ),
+                    snos_workers,
+                )
+                .api_base(self.atlantic_url)
+                .query_policy(atlantic_query_policy),
             ),
             BlockOrdererBuilder::new(),
         );
@@ -159,16 +221,29 @@ impl Start {
         )?;
         let storage = InMemoryStorageBackend::new();
 
-        let orchestrator = SovereignOrchestratorBuilder::new(
+        let mut orchestrator_builder = SovereignOrchestratorBuilder::new(
             block_ingestor_builder,
             pipeline_builder,
             da_builder,
             storage,
             self.genesis.into(),
-        )
-        .build()
-        .await?;
+        );
+        if let Some((reporter, _)) = &health {
+            orchestrator_builder = orchestrator_builder.health_reporter(reporter.clone());
+        }
+        if let Some(start_block) = self.start_block {
+            orchestrator_builder = orchestrator_builder.start_block(start_block);
+        }
+        if let Some(end_block) = self.end_block {
+            orchestrator_builder = orchestrator_builder.end_block(end_block);
+        }
+
+        let orchestrator = orchestrator_builder.build().await?;
         let orchestrator_shutdown = orchestrator.shutdown_handle();
+        let admin = self
+            .admin
+            .start(&saya_path, pause_handle, orchestrator_shutdown.clone())
+            .await?;
         orchestrator.start();
 
         let mut sigterm_handle =
@@ -183,14 +258,30 @@ impl Start {
 
         // Graceful shutdown
         orchestrator_shutdown.shutdown();
-        tokio::select! {
+        let result = tokio::select! {
             _ = tokio::time::sleep(GRACEFUL_SHUTDOWN_TIMEOUT) => {
                 Err(anyhow::anyhow!("timeout waiting for graceful shutdown"))
             },
             _ = orchestrator_shutdown.finished() => {
-                Ok(())
+                // Chain head is only kept in memory in this mode, so there's nothing to resume from
+                // and failures are always fatal.
+                match orchestrator_shutdown.failure() {
+                    Some(failure) => Err(anyhow::anyhow!("orchestrator failed: {}", failure)),
+                    None => Ok(()),
+                }
             },
+        };
+
+        if let Some(admin_shutdown) = admin {
+            admin_shutdown.shutdown();
+            admin_shutdown.finished().await;
+        }
+        if let Some((_, health_shutdown)) = health {
+            health_shutdown.shutdown();
+            health_shutdown.finished().await;
         }
+
+        result
     }
 }
 
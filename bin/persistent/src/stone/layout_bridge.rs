@@ -0,0 +1,251 @@
+use std::{borrow::Cow, sync::Arc};
+
+use anyhow::Result;
+use saya_core::{
+    block_ingestor::BlockInfo,
+    metrics,
+    prover::{PipelineStage, PipelineStageBuilder, SnosProof},
+    service::{Daemon, FinishHandle, ShutdownHandle, WorkerHandle, WorkerPool, WorkerPoolConfig},
+    storage::{BlockStatus, PersistantStorage, Step},
+};
+use serde_json::{json, Value};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::{
+    cairo_run::BootloaderTask,
+    error::ProverError,
+    stone::{StoneProver, LAYOUT_BRIDGE_LAYOUT},
+};
+
+/// Prover implementation running the `layout_bridge` program on SNOS proofs and proving it locally
+/// with Stone.
+#[derive(Debug)]
+pub struct StoneLayoutBridgeProver<DB> {
+    prover: StoneProver,
+    layout_bridge: Arc<Value>,
+    input_channel: Receiver<SnosProof<String>>,
+    output_channel: Sender<BlockInfo>,
+    finish_handle: FinishHandle,
+    db: DB,
+    workers: WorkerPoolConfig,
+}
+
+#[derive(Debug)]
+pub struct StoneLayoutBridgeProverBuilder<DB> {
+    prover: StoneProver,
+    layout_bridge: Cow<'static, [u8]>,
+    input_channel: Option<Receiver<SnosProof<String>>>,
+    output_channel: Option<Sender<BlockInfo>>,
+    db: DB,
+    workers: WorkerPoolConfig,
+}
+
+impl<DB> StoneLayoutBridgeProver<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    async fn worker(
+        worker: WorkerHandle<SnosProof<String>>,
+        task_tx: Sender<BlockInfo>,
+        prover: StoneProver,
+        layout_bridge: Arc<Value>,
+        finish_handle: FinishHandle,
+        db: DB,
+    ) -> Result<(), ProverError> {
+        loop {
+            let (new_snos_proof, _task) = if let Some(task) = worker.recv().await {
+                task
+            } else {
+                break;
+            };
+
+            let block_number_u32 = new_snos_proof.block_number.try_into().map_err(|_| {
+                ProverError::Prover("Block number too large to fit in u32".to_string())
+            })?;
+            let state_update = db
+                .get_state_update(block_number_u32)
+                .await
+                .map_err(|e| ProverError::MetadataFetch(e.to_string()))?;
+
+            match db.get_proof(block_number_u32, Step::Bridge).await {
+                Ok(_) => {
+                    trace!(
+                        block_number = new_snos_proof.block_number,
+                        "Proof already generated for block"
+                    );
+                }
+                Err(_) => {
+                    let stage_timer = metrics::stage_timer("layout_bridge");
+                    let snos_proof: Value = match serde_json::from_str(&new_snos_proof.proof) {
+                        Ok(proof) => proof,
+                        Err(e) => {
+                            warn!(block_number = new_snos_proof.block_number, error = %e, "Invalid SNOS proof");
+                            db.add_failed_block(
+                                block_number_u32,
+                                format!("invalid SNOS proof: {e}"),
+                            )
+                            .await
+                            .unwrap();
+                            continue;
+                        }
+                    };
+
+                    debug!(
+                        block_number = new_snos_proof.block_number,
+                        "Proving layout bridge with Stone"
+                    );
+                    let task = BootloaderTask::Program {
+                        program: layout_bridge.as_ref().clone(),
+                        program_input: json!({ "proof": snos_proof }),
+                    };
+                    let proof = match prover
+                        .prove(
+                            &format!("layout-{}", new_snos_proof.block_number),
+                            task,
+                            LAYOUT_BRIDGE_LAYOUT,
+                            &finish_handle,
+                        )
+                        .await
+                    {
+                        Err(ProverError::Shutdown) => break,
+                        Err(ProverError::BlockFail(e)) => {
+                            error!(block_number = new_snos_proof.block_number, error = %e, "Layout bridge proof generation failed");
+                            db.add_failed_block(block_number_u32, e).await.unwrap();
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                        Ok(proof) => proof,
+                    };
+
+                    db.add_proof(block_number_u32, proof.into_bytes(), Step::Bridge)
+                        .await
+                        .unwrap();
+                    stage_timer.observe();
+
+                    info!(
+                        block_number = new_snos_proof.block_number,
+                        "Layout bridge proof generated with Stone"
+                    );
+                }
+            }
+
+            let output = BlockInfo {
+                number: new_snos_proof.block_number,
+                status: BlockStatus::SnosProofGenerated,
+                state_update: Some(state_update),
+                first_block: new_snos_proof.first_block,
+            };
+            tokio::select! {
+                _ = finish_handle.shutdown_requested() => break,
+                _ = task_tx.send(output) => {},
+            }
+        }
+        Ok(())
+    }
+
+    async fn run(self) {
+        let pool = WorkerPool::new(
+            "layout_bridge",
+            self.input_channel,
+            self.workers,
+            self.finish_handle.clone(),
+        );
+        pool.run(|worker| {
+            let finish_handle = self.finish_handle.clone();
+            let worker = Self::worker(
+                worker,
+                self.output_channel.clone(),
+                self.prover.clone(),
+                self.layout_bridge.clone(),
+                self.finish_handle.clone(),
+                self.db.clone(),
+            );
+
+            async move {
+                if let Err(err) = worker.await {
+                    error!(error = %err, "Layout bridge proof worker failed");
+                    finish_handle.fail(format!("worker failed: {}", err));
+                    finish_handle.shutdown_handle().shutdown();
+                }
+            }
+        })
+        .await;
+
+        debug!("Graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+}
+
+impl<DB> StoneLayoutBridgeProverBuilder<DB> {
+    pub fn new<P>(prover: StoneProver, layout_bridge: P, db: DB, workers: WorkerPoolConfig) -> Self
+    where
+        P: Into<Cow<'static, [u8]>>,
+    {
+        Self {
+            prover,
+            layout_bridge: layout_bridge.into(),
+            input_channel: None,
+            output_channel: None,
+            db,
+            workers,
+        }
+    }
+}
+
+impl<DB> PipelineStageBuilder for StoneLayoutBridgeProverBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Stage = StoneLayoutBridgeProver<DB>;
+
+    fn build(self) -> Result<Self::Stage> {
+        let layout_bridge = serde_json::from_slice(&self.layout_bridge)
+            .map_err(|err| anyhow::anyhow!("invalid `layout_bridge` program: {}", err))?;
+
+        Ok(StoneLayoutBridgeProver {
+            prover: self.prover,
+            layout_bridge: Arc::new(layout_bridge),
+            input_channel: self
+                .input_channel
+                .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
+            output_channel: self
+                .output_channel
+                .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
+            finish_handle: FinishHandle::new(),
+            db: self.db,
+            workers: self.workers,
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<SnosProof<String>>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<BlockInfo>) -> Self {
+        self.output_channel = Some(output_channel);
+        self
+    }
+}
+
+impl<DB> PipelineStage for StoneLayoutBridgeProver<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Input = SnosProof<String>;
+    type Output = BlockInfo;
+}
+
+impl<DB> Daemon for StoneLayoutBridgeProver<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
@@ -0,0 +1,183 @@
+//! Local proving with the [Stone](https://github.com/starkware-libs/stone-prover) prover, for
+//! deployments which can't send PIEs to a hosted service.
+//!
+//! Traces are generated by [`CairoRunner`] and proven by `cpu_air_prover`.
+
+use std::path::PathBuf;
+
+use saya_core::service::FinishHandle;
+use serde_json::{json, Value};
+use tokio::process::Command;
+
+use crate::{
+    cairo_run::{read_file, run_command, write_json, BootloaderTask, CairoRunner, WorkDir},
+    error::ProverError,
+    local_snos::{LocalPieProver, LocalSnosProver, LocalSnosProverBuilder},
+};
+
+mod layout_bridge;
+pub use layout_bridge::{StoneLayoutBridgeProver, StoneLayoutBridgeProverBuilder};
+
+/// Prover implementation proving the SNOS PIEs locally with Stone.
+pub type StoneSnosProver<DB> = LocalSnosProver<StoneProver, DB>;
+pub type StoneSnosProverBuilder<DB> = LocalSnosProverBuilder<StoneProver, DB>;
+
+/// Layout SNOS is proven in, which is the one verified by the `layout_bridge` program.
+const SNOS_LAYOUT: &str = "dynamic";
+/// Layout the layout bridge is proven in, which is the one verified by the integrity contracts.
+const LAYOUT_BRIDGE_LAYOUT: &str = "recursive_with_poseidon";
+
+const LAST_LAYER_DEGREE_BOUND: u64 = 128;
+const MAX_FRI_STEP: u32 = 4;
+const N_QUERIES: u32 = 18;
+const LOG_N_COSETS: u32 = 4;
+const PROOF_OF_WORK_BITS: u32 = 24;
+
+/// Proves bootloader runs with a local `cpu_air_prover`.
+#[derive(Debug, Clone)]
+pub struct StoneProver {
+    runner: CairoRunner,
+    cpu_air_prover: PathBuf,
+    snos_layout_params: PathBuf,
+}
+
+impl StoneProver {
+    pub fn new(runner: CairoRunner, cpu_air_prover: PathBuf, snos_layout_params: PathBuf) -> Self {
+        Self {
+            runner,
+            cpu_air_prover,
+            snos_layout_params,
+        }
+    }
+
+    /// Proves the execution of `task` by the bootloader in `layout`, returning the proof as
+    /// written by `cpu_air_prover`.
+    ///
+    /// Failures of the tools are reported as [`ProverError::BlockFail`], as they're specific to
+    /// the task, while failing to run them at all is a [`ProverError::Prover`].
+    async fn prove(
+        &self,
+        label: &str,
+        task: BootloaderTask,
+        layout: &str,
+        finish_handle: &FinishHandle,
+    ) -> Result<String, ProverError> {
+        let work_dir = WorkDir::create(label).await?;
+        let layout_params = (layout == SNOS_LAYOUT).then_some(self.snos_layout_params.as_path());
+        let air_inputs = self
+            .runner
+            .run(&work_dir, task, layout, layout_params, finish_handle)
+            .await?;
+
+        let parameters_path = work_dir.join("cpu_air_params.json");
+        let prover_config_path = work_dir.join("cpu_air_prover_config.json");
+        let proof_path = work_dir.join("proof.json");
+        write_json(&parameters_path, &prover_parameters(air_inputs.n_steps)).await?;
+        write_json(&prover_config_path, &prover_config()).await?;
+
+        let mut cpu_air_prover = Command::new(&self.cpu_air_prover);
+        cpu_air_prover
+            .arg("--out_file")
+            .arg(&proof_path)
+            .arg("--private_input_file")
+            .arg(&air_inputs.private_input)
+            .arg("--public_input_file")
+            .arg(&air_inputs.public_input)
+            .arg("--prover_config_file")
+            .arg(&prover_config_path)
+            .arg("--parameter_file")
+            .arg(&parameters_path)
+            // Annotations are needed for parsing the proof into calls to the verifier.
+            .arg("--generate_annotations");
+        run_command(cpu_air_prover, finish_handle).await?;
+
+        String::from_utf8(read_file(&proof_path).await?)
+            .map_err(|err| ProverError::BlockFail(format!("invalid proof: {err}")))
+    }
+}
+
+impl LocalPieProver for StoneProver {
+    const NAME: &'static str = "Stone";
+
+    async fn prove_snos(
+        &self,
+        label: &str,
+        pie: Vec<u8>,
+        finish_handle: &FinishHandle,
+    ) -> Result<String, ProverError> {
+        self.prove(
+            label,
+            BootloaderTask::CairoPie(pie),
+            SNOS_LAYOUT,
+            finish_handle,
+        )
+        .await
+    }
+}
+
+/// Parameters of the proof, with FRI steps fitted to the length of the trace.
+fn prover_parameters(n_steps: u64) -> Value {
+    json!({
+        "field": "PrimeField0",
+        "channel_hash": "poseidon3",
+        "commitment_hash": "keccak256_masked160_lsb",
+        "n_verifier_friendly_commitment_layers": 9999,
+        "pow_hash": "keccak256",
+        "statement": { "page_hash": "pedersen" },
+        "stark": {
+            "fri": {
+                "fri_step_list": fri_step_list(n_steps),
+                "last_layer_degree_bound": LAST_LAYER_DEGREE_BOUND,
+                "n_queries": N_QUERIES,
+                "proof_of_work_bits": PROOF_OF_WORK_BITS,
+            },
+            "log_n_cosets": LOG_N_COSETS,
+        },
+        "use_extension_field": false,
+        "verifier_friendly_channel_updates": true,
+        "verifier_friendly_commitment_hash": "poseidon3",
+    })
+}
+
+fn prover_config() -> Value {
+    json!({
+        "cached_lde_config": { "store_full_lde": false, "use_fft_for_eval": false },
+        "constraint_polynomial_task_size": 256,
+        "n_out_of_memory_merkle_layers": 1,
+        "table_prover_n_tasks_per_segment": 32,
+    })
+}
+
+/// Splits the FRI folding of a trace of `n_steps` steps down to the last layer, the first step
+/// being 0 as expected by the verifiers.
+fn fri_step_list(n_steps: u64) -> Vec<u32> {
+    let mut remaining = (n_steps / LAST_LAYER_DEGREE_BOUND).max(1).ilog2() + 4;
+    let mut steps = vec![0];
+    while remaining > 0 {
+        let step = remaining.min(MAX_FRI_STEP);
+        steps.push(step);
+        remaining -= step;
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fri_step_list_folds_down_to_last_layer() {
+        for log_n_steps in 7..=26 {
+            let steps = fri_step_list(1 << log_n_steps);
+
+            assert_eq!(steps[0], 0);
+            assert!(steps[1..]
+                .iter()
+                .all(|step| (1..=MAX_FRI_STEP).contains(step)));
+            assert_eq!(
+                steps.iter().sum::<u32>(),
+                log_n_steps + 4 - LAST_LAYER_DEGREE_BOUND.ilog2()
+            );
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::Result;
+use saya_core::{
+    block_ingestor::BlockInfo,
+    prover::{PipelineStage, PipelineStageBuilder, SnosProof},
+    service::{Daemon, FinishHandle, ShutdownHandle},
+    storage::{BlockStatus, PersistantStorage, Step},
+};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{debug, error};
+
+/// Pipeline stage taking the place of the layout bridge for Stwo proofs, which are settled as is.
+///
+/// SNOS proofs are stored as the proofs to settle, and their blocks forwarded downstream.
+#[derive(Debug)]
+pub struct StwoProofForwarder<DB> {
+    input_channel: Receiver<SnosProof<String>>,
+    output_channel: Sender<BlockInfo>,
+    finish_handle: FinishHandle,
+    db: DB,
+}
+
+#[derive(Debug)]
+pub struct StwoProofForwarderBuilder<DB> {
+    input_channel: Option<Receiver<SnosProof<String>>>,
+    output_channel: Option<Sender<BlockInfo>>,
+    db: DB,
+}
+
+impl<DB> StwoProofForwarder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    async fn run(mut self) {
+        loop {
+            let new_snos_proof = tokio::select! {
+                _ = self.finish_handle.shutdown_requested() => break,
+                new_snos_proof = self.input_channel.recv() => new_snos_proof,
+            };
+            let Some(new_snos_proof) = new_snos_proof else {
+                break;
+            };
+
+            if let Err(err) = self.forward(new_snos_proof).await {
+                error!(error = %err, "Failed to forward Stwo proof");
+                self.finish_handle.fail(format!("{err:#}"));
+                break;
+            }
+        }
+
+        debug!("Graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+
+    async fn forward(&self, new_snos_proof: SnosProof<String>) -> Result<()> {
+        let block_number = new_snos_proof.block_number.try_into()?;
+        let state_update = self.db.get_state_update(block_number).await?;
+        self.db
+            .add_proof(
+                block_number,
+                new_snos_proof.proof.into_bytes(),
+                Step::Bridge,
+            )
+            .await?;
+
+        let output = BlockInfo {
+            number: new_snos_proof.block_number,
+            status: BlockStatus::BridgeProofGenerated,
+            state_update: Some(state_update),
+            first_block: new_snos_proof.first_block,
+        };
+        tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => {},
+            _ = self.output_channel.send(output) => {},
+        }
+
+        Ok(())
+    }
+}
+
+impl<DB> StwoProofForwarderBuilder<DB> {
+    pub fn new(db: DB) -> Self {
+        Self {
+            input_channel: None,
+            output_channel: None,
+            db,
+        }
+    }
+}
+
+impl<DB> PipelineStageBuilder for StwoProofForwarderBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Stage = StwoProofForwarder<DB>;
+
+    fn build(self) -> Result<Self::Stage> {
+        Ok(StwoProofForwarder {
+            input_channel: self
+                .input_channel
+                .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
+            output_channel: self
+                .output_channel
+                .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
+            finish_handle: FinishHandle::new(),
+            db: self.db,
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<SnosProof<String>>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<BlockInfo>) -> Self {
+        self.output_channel = Some(output_channel);
+        self
+    }
+}
+
+impl<DB> PipelineStage for StwoProofForwarder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Input = SnosProof<String>;
+    type Output = BlockInfo;
+}
+
+impl<DB> Daemon for StwoProofForwarder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
@@ -0,0 +1,89 @@
+//! Local proving of SNOS with the [Stwo](https://github.com/starkware-libs/stwo-cairo) prover.
+//!
+//! Traces are generated by [`CairoRunner`] and proven by the Stwo Cairo prover binary. Stwo proofs
+//! aren't wrapped by the layout bridge: they're verified as is on integrity, with the verifier
+//! configuration given to the settlement backend.
+
+use std::path::PathBuf;
+
+use saya_core::service::FinishHandle;
+use tokio::process::Command;
+
+use crate::{
+    cairo_run::{read_file, run_command, BootloaderTask, CairoRunner, WorkDir},
+    error::ProverError,
+    local_snos::{LocalPieProver, LocalSnosProver, LocalSnosProverBuilder},
+};
+
+mod forwarder;
+pub use forwarder::{StwoProofForwarder, StwoProofForwarderBuilder};
+
+mod proof;
+pub use proof::program_output;
+
+/// Prover implementation proving the SNOS PIEs locally with Stwo.
+pub type StwoSnosProver<DB> = LocalSnosProver<StwoProver, DB>;
+pub type StwoSnosProverBuilder<DB> = LocalSnosProverBuilder<StwoProver, DB>;
+
+/// Layout SNOS is run in, which is the only one supported by the Stwo Cairo prover.
+const STWO_LAYOUT: &str = "all_cairo_stwo";
+
+/// Proves bootloader runs with a local Stwo Cairo prover.
+#[derive(Debug, Clone)]
+pub struct StwoProver {
+    runner: CairoRunner,
+    stwo_prover: PathBuf,
+}
+
+impl StwoProver {
+    pub fn new(runner: CairoRunner, stwo_prover: PathBuf) -> Self {
+        Self {
+            runner,
+            stwo_prover,
+        }
+    }
+}
+
+impl LocalPieProver for StwoProver {
+    const NAME: &'static str = "Stwo";
+
+    /// Proves the execution of `pie` by the bootloader, returning the Cairo serialization of the
+    /// proof as a JSON array of felts, as sent to the verifier.
+    ///
+    /// Failures of the tools are reported as [`ProverError::BlockFail`], as they're specific to
+    /// the PIE, while failing to run them at all is a [`ProverError::Prover`].
+    async fn prove_snos(
+        &self,
+        label: &str,
+        pie: Vec<u8>,
+        finish_handle: &FinishHandle,
+    ) -> Result<String, ProverError> {
+        let work_dir = WorkDir::create(label).await?;
+        let air_inputs = self
+            .runner
+            .run(
+                &work_dir,
+                BootloaderTask::CairoPie(pie),
+                STWO_LAYOUT,
+                None,
+                finish_handle,
+            )
+            .await?;
+
+        let proof_path = work_dir.join("proof.json");
+        let mut stwo_prover = Command::new(&self.stwo_prover);
+        stwo_prover
+            .arg("--pub_json")
+            .arg(&air_inputs.public_input)
+            .arg("--priv_json")
+            .arg(&air_inputs.private_input)
+            .arg("--proof_path")
+            .arg(&proof_path)
+            .arg("--proof-format")
+            .arg("cairo-serde");
+        run_command(stwo_prover, finish_handle).await?;
+
+        String::from_utf8(read_file(&proof_path).await?)
+            .map_err(|err| ProverError::BlockFail(format!("invalid proof: {err}")))
+    }
+}
@@ -0,0 +1,176 @@
+//! Reading of the public data of Stwo Cairo proofs in their `cairo-serde` serialization.
+
+use anyhow::Result;
+use num_traits::ToPrimitive;
+use starknet_types_core::felt::Felt;
+
+/// Number of 32-bit limbs a memory value is split into.
+const N_VALUE_LIMBS: usize = 8;
+/// Number of builtin segments following the output segment in the public segment ranges, each of
+/// them optional.
+const N_OPTIONAL_SEGMENTS: usize = 10;
+
+/// Extracts the program output from the public memory of a Stwo proof, which for a bootloader run
+/// is the bootloader output as it's registered in the fact.
+///
+/// The proof is expected to start with its claim, whose public memory is made of the program
+/// section, the public segment ranges, then the output section.
+pub fn program_output(proof: &[Felt]) -> Result<Vec<Felt>> {
+    let mut reader = ProofReader { proof };
+
+    // Program section, which is the bootloader.
+    let program_len = reader.read_usize()?;
+    reader.skip(program_len * (1 + N_VALUE_LIMBS))?;
+
+    // Public segment ranges, starting with the output one.
+    let output_segment_size = reader.read_segment_range()?;
+    for _ in 0..N_OPTIONAL_SEGMENTS {
+        // `Option`s are serialized as in Cairo, with `Some` as the first variant.
+        match reader.read_usize()? {
+            0 => {
+                reader.read_segment_range()?;
+            }
+            1 => {}
+            variant => anyhow::bail!("invalid optional segment variant: {}", variant),
+        }
+    }
+
+    let output_len = reader.read_usize()?;
+    if output_len != output_segment_size {
+        anyhow::bail!(
+            "output section of {} values doesn't match the output segment of {} values",
+            output_len,
+            output_segment_size
+        );
+    }
+
+    (0..output_len)
+        .map(|_| {
+            // Memory ids of the values aren't needed.
+            reader.read_usize()?;
+            reader.read_value()
+        })
+        .collect()
+}
+
+struct ProofReader<'a> {
+    proof: &'a [Felt],
+}
+
+impl ProofReader<'_> {
+    fn read(&mut self, len: usize) -> Result<&[Felt]> {
+        if self.proof.len() < len {
+            anyhow::bail!("proof too short");
+        }
+        let (read, rest) = self.proof.split_at(len);
+        self.proof = rest;
+        Ok(read)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.read(len).map(|_| ())
+    }
+
+    fn read_usize(&mut self) -> Result<usize> {
+        self.read(1)?[0]
+            .to_usize()
+            .ok_or_else(|| anyhow::anyhow!("invalid length in proof"))
+    }
+
+    /// Reads a segment range, returning its size.
+    fn read_segment_range(&mut self) -> Result<usize> {
+        // Pointers are serialized as their memory id followed by their value.
+        let range = self.read(4)?;
+        let start = range[1].to_usize();
+        let stop = range[3].to_usize();
+
+        start
+            .zip(stop)
+            .and_then(|(start, stop)| stop.checked_sub(start))
+            .ok_or_else(|| anyhow::anyhow!("invalid segment range in proof"))
+    }
+
+    /// Reads a memory value, serialized as little-endian 32-bit limbs.
+    fn read_value(&mut self) -> Result<Felt> {
+        let mut bytes = [0; 32];
+        for (limb, bytes) in self
+            .read(N_VALUE_LIMBS)?
+            .iter()
+            .zip(bytes.chunks_exact_mut(4))
+        {
+            let limb = limb
+                .to_u32()
+                .ok_or_else(|| anyhow::anyhow!("invalid memory value limb in proof"))?;
+            bytes.copy_from_slice(&limb.to_le_bytes());
+        }
+
+        Ok(Felt::from_bytes_le(&bytes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet::macros::felt;
+
+    use super::*;
+
+    fn push_section(proof: &mut Vec<Felt>, values: &[Felt]) {
+        proof.push(Felt::from(values.len()));
+        for (id, value) in values.iter().enumerate() {
+            proof.push(Felt::from(id));
+            proof.extend(
+                value
+                    .to_bytes_le()
+                    .chunks_exact(4)
+                    .map(|limb| Felt::from(u32::from_le_bytes(limb.try_into().unwrap()))),
+            );
+        }
+    }
+
+    /// Serializes the beginning of a proof of a bootloader run with `output` as public output.
+    fn proof_with_output(output: &[Felt]) -> Vec<Felt> {
+        let mut proof = vec![];
+
+        push_section(&mut proof, &[felt!("0x40780017fff7fff"), Felt::ONE]);
+        // Output segment, then an absent and a present builtin segment each.
+        proof.extend([1, 100, 2, 100 + output.len()].map(Felt::from));
+        for segment in 0..N_OPTIONAL_SEGMENTS {
+            if segment % 2 == 0 {
+                proof.push(Felt::ONE);
+            } else {
+                proof.extend([0, 3, 200, 4, 210].map(Felt::from));
+            }
+        }
+        push_section(&mut proof, output);
+        // Safe call ids and the rest of the claim.
+        proof.extend([5, 6, 7, 8].map(Felt::from));
+
+        proof
+    }
+
+    #[test]
+    fn test_program_output_is_bootloader_output() {
+        // Output of the bootloader for a single task: the number of tasks, the size of the task
+        // output including its header, the hash of the task program, then the task output.
+        let bootloader_output = [
+            Felt::ONE,
+            Felt::from(5),
+            felt!("0x1e324682835e60c4779a683b32713504aed894fd73842f7d05b18e7bd29cd70"),
+            felt!("0x5d6b6b1c2e4f7a0c9e1b3d5f7a9c1e3b5d7f9a1c3e5b7d9f1a3c5e7b9d1f3a"),
+            Felt::ZERO,
+            Felt::MAX,
+        ];
+
+        assert_eq!(
+            program_output(&proof_with_output(&bootloader_output)).unwrap(),
+            bootloader_output
+        );
+    }
+
+    #[test]
+    fn test_program_output_rejects_truncated_proof() {
+        let proof = proof_with_output(&[Felt::ONE, Felt::from(2), Felt::TWO]);
+
+        assert!(program_output(&proof[..proof.len() - 10]).is_err());
+    }
+}
@@ -6,7 +6,8 @@ use bigdecimal::{
     BigDecimal,
 };
 use cairo_vm::{
-    program_hash::compute_program_hash_chain, types::relocatable::MaybeRelocatable,
+    program_hash::compute_program_hash_chain,
+    types::{program::Program, relocatable::MaybeRelocatable},
     vm::runners::cairo_pie::CairoPie,
 };
 use integrity::Felt;
@@ -15,6 +16,7 @@ use starknet::{
     core::types::{Call, ExecutionResult, StarknetError, TransactionReceiptWithBlockInfo},
     providers::{Provider, ProviderError},
 };
+use starknet_crypto::poseidon_hash_many;
 use swiftness_air::types::SegmentInfo;
 use swiftness_stark::types::StarkProof;
 use tracing::debug;
@@ -40,6 +42,24 @@ pub fn calculate_output(proof: &StarkProof) -> Vec<Felt> {
         .collect::<Vec<_>>()
 }
 
+/// Computes the fact integrity registers for a bootloaded proof, i.e. the Poseidon hash of the
+/// program hash and of the output hash.
+///
+/// The program is the start of the public memory, up to the two cells preceding the initial `fp`.
+pub fn calculate_fact_hash(proof: &StarkProof) -> Felt {
+    let initial_pc = proof.public_input.segments[0].begin_addr;
+    let initial_fp = proof.public_input.segments[1].begin_addr;
+    let program_len = (initial_fp - Felt::TWO - initial_pc).to_usize().unwrap();
+    let program = proof.public_input.main_page[..program_len]
+        .iter()
+        .map(|cell| cell.value)
+        .collect::<Vec<_>>();
+
+    let program_hash = poseidon_hash_many(&program);
+    let output_hash = poseidon_hash_many(&calculate_output(proof));
+    poseidon_hash_many(&[program_hash, output_hash])
+}
+
 pub fn felt_to_bigdecimal<D>(felt: Felt, decimals: D) -> BigDecimal
 where
     D: Into<i64>,
@@ -110,6 +130,13 @@ pub fn compute_program_hash_from_pie(pie: &CairoPie) -> Result<Felt> {
     Ok(Felt::from_bytes_be(&bytes))
 }
 
+/// Computes the program hash of a compiled Cairo program, as found in bootloader outputs.
+pub fn compute_program_hash(program: &[u8]) -> Result<Felt> {
+    let program = Program::from_bytes(program, None)?;
+    let hash = compute_program_hash_chain(&program.get_stripped_program()?, 0)?;
+    Ok(Felt::from_bytes_be(&hash.to_bytes_be()))
+}
+
 /// Extracts the output of a program from a `CairoPie`.
 ///
 /// This output is the one that is returned by the prover at the end
@@ -124,6 +151,26 @@ pub fn extract_pie_output(pie: &CairoPie) -> Vec<Felt> {
     output
 }
 
+/// Mocks a bootloaded execution of SNOS, with the output of SNOS taken from its PIE.
+pub fn bootloader_snos_output(pie: &CairoPie) -> Vec<Felt> {
+    let snos_program_hash =
+        compute_program_hash_from_pie(pie).expect("Failed to compute program hash from PIE");
+    debug!(snos_program_hash = %snos_program_hash, "SNOS program hash from PIE");
+
+    let snos_output = extract_pie_output(pie);
+
+    let mut bootloader_output = vec![
+        // Bootloader constants (number of task executed by bootloader, in case of herodotus its always 1)
+        Felt::ONE,
+        // Size of the task output, including this size and the program hash
+        Felt::from(snos_output.len() + 2),
+        snos_program_hash,
+    ];
+
+    bootloader_output.extend(snos_output);
+    bootloader_output
+}
+
 pub fn get_memory_segment(pie: &CairoPie, index: usize) -> Vec<(usize, &MaybeRelocatable)> {
     let mut segment = pie
         .memory
@@ -380,3 +427,63 @@ pub fn extract_messages_from_program_output(
 
     (messages_to_l1, messages_to_l2)
 }
+
+#[cfg(test)]
+mod tests {
+    use starknet::macros::felt;
+    use swiftness_air::types::{AddrValue, Page};
+
+    use super::*;
+
+    #[test]
+    fn test_fact_hash_of_bootloaded_proof() {
+        let program = [
+            felt!("0x40780017fff7fff"),
+            felt!("0x4"),
+            felt!("0x1104800180018000"),
+            felt!("0x10780017fff7fff"),
+        ];
+        // Return `fp` and `pc` pushed before the initial `fp`.
+        let return_cells = [felt!("0x25"), Felt::ZERO];
+        let output = [
+            Felt::ONE,
+            felt!("0x4"),
+            felt!("0x1e324682835e60c4779a683b32713504aed894fd73842f7d05b18e7bd29cd70"),
+            felt!("0x2a"),
+        ];
+
+        let mut proof = stark_proof_mock(&output);
+        proof.public_input.segments = vec![
+            SegmentInfo {
+                begin_addr: Felt::ONE,
+                stop_ptr: felt!("0x5"),
+            },
+            SegmentInfo {
+                begin_addr: Felt::from(1 + program.len() + return_cells.len()),
+                stop_ptr: felt!("0x20"),
+            },
+            SegmentInfo {
+                begin_addr: felt!("0x20"),
+                stop_ptr: Felt::from(0x20 + output.len()),
+            },
+        ];
+        // Public memory of the program, then of the execution up to the output.
+        proof.public_input.main_page = Page(
+            program
+                .iter()
+                .chain(&return_cells)
+                .chain(&output)
+                .map(|value| AddrValue {
+                    address: Default::default(),
+                    value: *value,
+                })
+                .collect(),
+        );
+
+        assert_eq!(calculate_output(&proof), output);
+        assert_eq!(
+            calculate_fact_hash(&proof),
+            poseidon_hash_many(&[poseidon_hash_many(&program), poseidon_hash_many(&output)])
+        );
+    }
+}
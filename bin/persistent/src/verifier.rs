@@ -0,0 +1,351 @@
+//! Local verification of the proofs with [swiftness](https://github.com/iosis-tech/swiftness)
+//! before settling them, so that proofs which would make the integrity verifier revert are caught
+//! before paying for the verification transactions.
+
+use anyhow::Result;
+use cairo_vm::vm::runners::cairo_pie::CairoPie;
+use integrity::Felt;
+use saya_core::{
+    block_ingestor::BlockInfo,
+    metrics,
+    prover::{PipelineStage, PipelineStageBuilder},
+    service::{Daemon, FinishHandle, ShutdownHandle},
+    storage::{PersistantStorage, Step},
+};
+use swiftness::TransformTo;
+use swiftness_stark::types::StarkProof;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{debug, error, info};
+
+use crate::{
+    error::ProverError,
+    utils::{compute_program_hash_from_pie, extract_pie_output},
+};
+
+/// Minimum number of security bits of the proofs accepted by the integrity verifiers, which
+/// reject proofs with a weaker configuration regardless of their validity.
+const MIN_SECURITY_BITS: u64 = 96;
+
+/// Pipeline stage verifying the SNOS and layout bridge proofs of each block with swiftness.
+///
+/// Both proofs are bootloaded runs, whose outputs are checked against the SNOS PIE and the
+/// `layout_bridge` program. Blocks failing verification are marked as failed instead of being
+/// forwarded to settlement, which drops their proofs so that they get proven again on retry.
+#[derive(Debug)]
+pub struct ProofVerifier<DB> {
+    layout_bridge_program_hash: Felt,
+    input_channel: Receiver<BlockInfo>,
+    output_channel: Sender<BlockInfo>,
+    finish_handle: FinishHandle,
+    db: DB,
+}
+
+#[derive(Debug)]
+pub struct ProofVerifierBuilder<DB> {
+    layout_bridge_program_hash: Felt,
+    input_channel: Option<Receiver<BlockInfo>>,
+    output_channel: Option<Sender<BlockInfo>>,
+    db: DB,
+}
+
+/// A proof verified by swiftness.
+struct VerifiedProof {
+    program_hash: Felt,
+    output: Vec<Felt>,
+}
+
+impl<DB> ProofVerifier<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    async fn run(mut self) {
+        loop {
+            let new_block = tokio::select! {
+                _ = self.finish_handle.shutdown_requested() => break,
+                new_block = self.input_channel.recv() => new_block,
+            };
+            let Some(new_block) = new_block else {
+                break;
+            };
+
+            match self.verify(&new_block).await {
+                Ok(()) => {}
+                Err(ProverError::BlockFail(reason)) => {
+                    error!(
+                        block_number = new_block.number,
+                        error = %reason,
+                        "Proof verification failed"
+                    );
+                    if let Err(err) = self
+                        .db
+                        .add_failed_block(new_block.number.try_into().unwrap(), reason)
+                        .await
+                    {
+                        self.finish_handle.fail(format!("{err:#}"));
+                        break;
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    error!(error = %err, "Failed to verify proofs");
+                    self.finish_handle.fail(format!("{err:#}"));
+                    break;
+                }
+            }
+
+            tokio::select! {
+                _ = self.finish_handle.shutdown_requested() => break,
+                _ = self.output_channel.send(new_block) => {},
+            }
+        }
+
+        debug!("Graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+
+    async fn verify(&self, block: &BlockInfo) -> Result<(), ProverError> {
+        let block_number = block
+            .number
+            .try_into()
+            .map_err(|_| ProverError::Prover("Block number too large to fit in u32".to_string()))?;
+        let stage_timer = metrics::stage_timer("proof_verification");
+
+        let pie = self
+            .db
+            .get_pie(block_number, Step::Snos)
+            .await
+            .map_err(|e| ProverError::MetadataFetch(e.to_string()))?;
+        let snos_proof = self
+            .db
+            .get_proof(block_number, Step::Snos)
+            .await
+            .map_err(|e| ProverError::MetadataFetch(e.to_string()))?;
+        let bridge_proof = self
+            .db
+            .get_proof(block_number, Step::Bridge)
+            .await
+            .map_err(|e| ProverError::MetadataFetch(e.to_string()))?;
+
+        let pie = CairoPie::from_bytes(&pie).map_err(|e| ProverError::ProofParse(e.to_string()))?;
+        let snos_program_hash = compute_program_hash_from_pie(&pie)
+            .map_err(|e| ProverError::ProofParse(e.to_string()))?;
+        let snos_output = extract_pie_output(&pie);
+
+        let snos_proof = verify_proof::<swiftness_air::layout::dynamic::Layout>(snos_proof).await?;
+        check_bootloader_output("SNOS", &snos_proof.output, snos_program_hash, &snos_output)?;
+
+        let bridge_proof =
+            verify_proof::<swiftness_air::layout::recursive_with_poseidon::Layout>(bridge_proof)
+                .await?;
+        check_bootloader_output(
+            "layout bridge",
+            &bridge_proof.output,
+            self.layout_bridge_program_hash,
+            &snos_proof.output,
+        )?;
+        // The layout bridge outputs the program hash of the proof it verified, i.e. the
+        // bootloader's, before the output of the proof.
+        if bridge_proof.output.get(3) != Some(&snos_proof.program_hash) {
+            return Err(ProverError::BlockFail(
+                "layout bridge proof verified an unexpected program".to_string(),
+            ));
+        }
+
+        stage_timer.observe();
+        info!(
+            block_number = block.number,
+            program_hash = %format!("{:#064x}", bridge_proof.program_hash),
+            "Proofs verified"
+        );
+
+        Ok(())
+    }
+}
+
+impl<DB> ProofVerifierBuilder<DB> {
+    pub fn new(layout_bridge_program_hash: Felt, db: DB) -> Self {
+        Self {
+            layout_bridge_program_hash,
+            input_channel: None,
+            output_channel: None,
+            db,
+        }
+    }
+}
+
+impl<DB> PipelineStageBuilder for ProofVerifierBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Stage = ProofVerifier<DB>;
+
+    fn build(self) -> Result<Self::Stage> {
+        Ok(ProofVerifier {
+            layout_bridge_program_hash: self.layout_bridge_program_hash,
+            input_channel: self
+                .input_channel
+                .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
+            output_channel: self
+                .output_channel
+                .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
+            finish_handle: FinishHandle::new(),
+            db: self.db,
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<BlockInfo>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<BlockInfo>) -> Self {
+        self.output_channel = Some(output_channel);
+        self
+    }
+}
+
+impl<DB> PipelineStage for ProofVerifier<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Input = BlockInfo;
+    type Output = BlockInfo;
+}
+
+impl<DB> Daemon for ProofVerifier<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
+
+/// Verifies a proof in the Stone format, off the async runtime as it's CPU bound.
+async fn verify_proof<Layout>(raw_proof: Vec<u8>) -> Result<VerifiedProof, ProverError>
+where
+    Layout: swiftness_air::layout::StaticLayoutTrait + swiftness_air::layout::LayoutTrait,
+{
+    tokio::task::spawn_blocking(move || {
+        let raw_proof =
+            String::from_utf8(raw_proof).map_err(|e| ProverError::ProofParse(e.to_string()))?;
+        let proof: StarkProof = swiftness::parse(raw_proof)
+            .map_err(|e| ProverError::BlockFail(format!("invalid proof: {e}")))?
+            .transform_to();
+
+        let (program_hash, output) = proof
+            .verify::<Layout>(Felt::from(MIN_SECURITY_BITS))
+            .map_err(|e| ProverError::BlockFail(format!("invalid proof: {e}")))?;
+
+        Ok(VerifiedProof {
+            program_hash,
+            output,
+        })
+    })
+    .await
+    .map_err(|e| ProverError::Prover(e.to_string()))?
+}
+
+/// Checks that `output` is the output of the bootloader running a single task, the program
+/// hashed `program_hash` outputting `task_output`.
+fn check_bootloader_output(
+    proof: &str,
+    output: &[Felt],
+    program_hash: Felt,
+    task_output: &[Felt],
+) -> Result<(), ProverError> {
+    if output.first() != Some(&Felt::ONE) {
+        return Err(ProverError::BlockFail(format!(
+            "{proof} proof isn't a single bootloaded task"
+        )));
+    }
+    if output.get(2) != Some(&program_hash) {
+        return Err(ProverError::BlockFail(format!(
+            "{proof} proof has unexpected program hash {}",
+            output.get(2).copied().unwrap_or_default()
+        )));
+    }
+    if !output.ends_with(task_output) {
+        return Err(ProverError::BlockFail(format!(
+            "{proof} proof has unexpected output"
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM_HASH: Felt = Felt::from_hex_unchecked("0x1234");
+
+    fn bootloader_output(n_tasks: u64, program_hash: Felt, task_output: &[Felt]) -> Vec<Felt> {
+        let mut output = vec![
+            Felt::from(n_tasks),
+            Felt::from(task_output.len() + 2),
+            program_hash,
+        ];
+        output.extend(task_output);
+        output
+    }
+
+    fn assert_block_fail(result: Result<(), ProverError>, reason: &str) {
+        match result {
+            Err(ProverError::BlockFail(err)) => assert!(err.contains(reason), "{err}"),
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_check_bootloader_output_accepts_single_task() {
+        let task_output = [Felt::from(7), Felt::from(8)];
+        let output = bootloader_output(1, PROGRAM_HASH, &task_output);
+
+        check_bootloader_output("SNOS", &output, PROGRAM_HASH, &task_output).unwrap();
+    }
+
+    #[test]
+    fn test_check_bootloader_output_rejects_several_tasks() {
+        let task_output = [Felt::from(7), Felt::from(8)];
+        let output = bootloader_output(2, PROGRAM_HASH, &task_output);
+
+        assert_block_fail(
+            check_bootloader_output("SNOS", &output, PROGRAM_HASH, &task_output),
+            "isn't a single bootloaded task",
+        );
+        assert_block_fail(
+            check_bootloader_output("SNOS", &[], PROGRAM_HASH, &[]),
+            "isn't a single bootloaded task",
+        );
+    }
+
+    #[test]
+    fn test_check_bootloader_output_rejects_unexpected_program() {
+        let task_output = [Felt::from(7), Felt::from(8)];
+        let output = bootloader_output(1, Felt::from(0x5678), &task_output);
+
+        assert_block_fail(
+            check_bootloader_output("SNOS", &output, PROGRAM_HASH, &task_output),
+            "unexpected program hash",
+        );
+        assert_block_fail(
+            check_bootloader_output("SNOS", &output[..2], PROGRAM_HASH, &[]),
+            "unexpected program hash",
+        );
+    }
+
+    #[test]
+    fn test_check_bootloader_output_rejects_unexpected_output() {
+        let output = bootloader_output(1, PROGRAM_HASH, &[Felt::from(7), Felt::from(8)]);
+
+        assert_block_fail(
+            check_bootloader_output("SNOS", &output, PROGRAM_HASH, &[Felt::from(7)]),
+            "unexpected output",
+        );
+    }
+}
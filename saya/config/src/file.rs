@@ -0,0 +1,389 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error as _,
+    ffi::OsStr,
+    fmt,
+    path::Path,
+};
+
+use clap::{Arg, Command};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, CONFIG_ENV};
+
+/// Sections options can be grouped in.
+const SECTIONS: &[&str] = &["rollup", "settlement", "prover", "da", "storage", "tuning"];
+
+/// Suffixes of options holding secrets, which are redacted when printing the configuration.
+const SECRET_SUFFIXES: &[&str] = &["_key", "_token"];
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Yaml,
+}
+
+/// A parsed configuration file.
+#[derive(Debug, Clone)]
+pub struct ConfigFile {
+    format: Format,
+    sections: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Result<Self, Error> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => Ok(Self::Toml),
+            Some("yaml" | "yml") => Ok(Self::Yaml),
+            _ => Err(Error::UnsupportedFormat(path.to_path_buf())),
+        }
+    }
+}
+
+impl ConfigFile {
+    /// Loads the file at `path`, checking that all its options are known to `command`.
+    pub fn load(path: &Path, command: &Command) -> Result<Self, Error> {
+        let format = Format::from_path(path)?;
+        let content = std::fs::read_to_string(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let file = Self::parse(&content, format)?;
+        let args = env_args(command);
+        for (section, key, _) in file.options() {
+            if !args.contains_key(&env_name(key)) {
+                return Err(Error::UnknownOption {
+                    section: section.to_string(),
+                    key: key.to_string(),
+                });
+            }
+        }
+
+        Ok(file)
+    }
+
+    pub fn parse(content: &str, format: Format) -> Result<Self, Error> {
+        let sections = match format {
+            Format::Toml => toml::from_str(content)?,
+            Format::Yaml => serde_yaml::from_str(content)?,
+        };
+        let file = Self { format, sections };
+
+        if let Some(section) = file
+            .sections
+            .keys()
+            .find(|section| !SECTIONS.contains(&section.as_str()))
+        {
+            return Err(Error::UnknownSection {
+                section: section.to_string(),
+            });
+        }
+
+        let mut seen = HashSet::new();
+        for (_, key, _) in file.options() {
+            if !seen.insert(env_name(key)) {
+                return Err(Error::DuplicateOption {
+                    key: key.to_string(),
+                });
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Checks that the values of all options are accepted by the corresponding flags of
+    /// `command`.
+    pub fn validate(&self, command: &Command) -> Result<(), Error> {
+        let args = env_args(command);
+        for (section, key, value) in self.options() {
+            let args = args
+                .get(&env_name(key))
+                .ok_or_else(|| Error::UnknownOption {
+                    section: section.to_string(),
+                    key: key.to_string(),
+                })?;
+
+            // The same variable can back flags of different subcommands.
+            for arg in args {
+                check_values(arg, &value.to_string()).map_err(|reason| Error::InvalidValue {
+                    key: key.to_string(),
+                    reason,
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets all options as the default values of the flags of `command` and its subcommands
+    /// they are named after, so that they are only used when the flags aren't given on the
+    /// command line or through their environment variables.
+    pub fn apply(&self, command: Command) -> Command {
+        let values = self
+            .options()
+            .map(|(_, key, value)| (env_name(key), value.to_string()))
+            .collect();
+        apply_defaults(command, &values)
+    }
+
+    /// Renders the options in the format of the file, with environment overrides applied and
+    /// secrets redacted.
+    pub fn effective(&self) -> Result<String, Error> {
+        let sections: BTreeMap<&str, BTreeMap<&str, Value>> = self
+            .sections
+            .iter()
+            .map(|(section, options)| {
+                let options = options
+                    .iter()
+                    .map(|(key, value)| {
+                        let value = if is_secret(key) {
+                            Value::String(REDACTED.to_string())
+                        } else {
+                            match std::env::var(env_name(key)) {
+                                Ok(value) => Value::from_env(value),
+                                Err(_) => value.clone(),
+                            }
+                        };
+                        (key.as_str(), value)
+                    })
+                    .collect();
+                (section.as_str(), options)
+            })
+            .collect();
+
+        match self.format {
+            Format::Toml => {
+                toml::to_string(&sections).map_err(|err| Error::Serialize(err.to_string()))
+            }
+            Format::Yaml => {
+                serde_yaml::to_string(&sections).map_err(|err| Error::Serialize(err.to_string()))
+            }
+        }
+    }
+
+    fn options(&self) -> impl Iterator<Item = (&str, &str, &Value)> {
+        self.sections.iter().flat_map(|(section, options)| {
+            options
+                .iter()
+                .map(move |(key, value)| (section.as_str(), key.as_str(), value))
+        })
+    }
+}
+
+impl Value {
+    fn from_env(value: String) -> Self {
+        if let Ok(value) = value.parse() {
+            Self::Bool(value)
+        } else if let Ok(value) = value.parse() {
+            Self::Integer(value)
+        } else {
+            Self::String(value)
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(value) => write!(f, "{}", value),
+            Self::Integer(value) => write!(f, "{}", value),
+            Self::Float(value) => write!(f, "{}", value),
+            Self::String(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Environment variable backing the option `key`.
+fn env_name(key: &str) -> String {
+    key.replace('-', "_").to_ascii_uppercase()
+}
+
+fn is_secret(key: &str) -> bool {
+    let key = key.replace('-', "_");
+    SECRET_SUFFIXES.iter().any(|suffix| key.ends_with(suffix))
+}
+
+/// Collects the arguments of `command` and all its subcommands that can be set through an
+/// environment variable, keyed by variable name.
+fn env_args(command: &Command) -> HashMap<String, Vec<Arg>> {
+    let mut args: HashMap<String, Vec<Arg>> = HashMap::new();
+    let mut commands = vec![command];
+    while let Some(command) = commands.pop() {
+        for arg in command.get_arguments() {
+            if let Some(env) = arg.get_env().and_then(OsStr::to_str) {
+                if env != CONFIG_ENV {
+                    args.entry(env.to_string()).or_default().push(arg.clone());
+                }
+            }
+        }
+        commands.extend(command.get_subcommands());
+    }
+    args
+}
+
+/// Sets the values keyed by environment variable name as the defaults of the arguments of
+/// `command` and all its subcommands.
+fn apply_defaults(mut command: Command, values: &HashMap<String, String>) -> Command {
+    let defaults = command
+        .get_arguments()
+        .filter_map(|arg| {
+            let value = values.get(arg.get_env()?.to_str()?)?;
+            Some((arg.get_id().clone(), value.clone()))
+        })
+        .collect::<Vec<_>>();
+    for (id, value) in defaults {
+        // Required flags are satisfied by the value from the file.
+        command = command.mut_arg(id, |arg| match arg.get_value_delimiter() {
+            Some(delimiter) => {
+                let values = value
+                    .split(delimiter)
+                    .map(str::to_string)
+                    .collect::<Vec<_>>();
+                arg.required(false).default_values(values)
+            }
+            None => arg.required(false).default_value(value),
+        });
+    }
+
+    let subcommands = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect::<Vec<_>>();
+    for name in subcommands {
+        command = command.mut_subcommand(name, |subcommand| apply_defaults(subcommand, values));
+    }
+
+    command
+}
+
+/// Parses `value` with the value parser of `arg`, checking each of the values it holds if `arg`
+/// accepts a delimited list.
+fn check_values(arg: &Arg, value: &str) -> Result<(), String> {
+    match arg.get_value_delimiter() {
+        Some(delimiter) => value
+            .split(delimiter)
+            .try_for_each(|value| check_value(arg, value)),
+        None => check_value(arg, value),
+    }
+}
+
+/// Parses `value` with the value parser of `arg`.
+fn check_value(arg: &Arg, value: &str) -> Result<(), String> {
+    Command::new("config")
+        .no_binary_name(true)
+        .disable_help_flag(true)
+        .disable_version_flag(true)
+        .arg(
+            Arg::new(arg.get_id())
+                .value_parser(arg.get_value_parser().clone())
+                .allow_hyphen_values(true)
+                .required(true),
+        )
+        .try_get_matches_from([value])
+        .map(|_| ())
+        .map_err(|err| match err.source() {
+            Some(source) => source.to_string(),
+            None => err
+                .to_string()
+                .trim()
+                .trim_start_matches("error: ")
+                .to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, FromArgMatches, Parser};
+
+    use super::*;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[clap(long, env = "TEST_CONFIG_RPC")]
+        rpc: String,
+        #[clap(long, env = "TEST_CONFIG_BATCH_SIZE", default_value_t = 10)]
+        batch_size: usize,
+        #[clap(long, env = "TEST_CONFIG_ACCOUNT_KEY")]
+        account_key: Option<String>,
+        #[clap(long, env = "TEST_CONFIG_WORKERS", value_delimiter = ',')]
+        workers: Vec<usize>,
+    }
+
+    #[test]
+    fn test_config_file_overridden_by_command_line() {
+        let file = ConfigFile::parse(
+            r#"
+            [rollup]
+            test_config_rpc = "http://localhost:5050"
+            [settlement]
+            test_config_account_key = "0x1"
+            [tuning]
+            test_config_batch_size = 20
+            test_config_workers = "1,2"
+            "#,
+            Format::Toml,
+        )
+        .unwrap();
+        file.validate(&Cli::command()).unwrap();
+
+        let command = file.apply(Cli::command());
+        let matches = command
+            .clone()
+            .try_get_matches_from(["saya", "--batch-size", "30"])
+            .unwrap();
+        let cli = Cli::from_arg_matches(&matches).unwrap();
+        assert_eq!(cli.rpc, "http://localhost:5050");
+        assert_eq!(cli.batch_size, 30);
+        assert_eq!(cli.account_key.as_deref(), Some("0x1"));
+        assert_eq!(cli.workers, [1, 2]);
+
+        let matches = command.try_get_matches_from(["saya"]).unwrap();
+        let cli = Cli::from_arg_matches(&matches).unwrap();
+        assert_eq!(cli.batch_size, 20);
+
+        let effective = file.effective().unwrap();
+        assert!(effective.contains("test_config_batch_size = 20"));
+        assert!(effective.contains("test_config_account_key = \"<redacted>\""));
+    }
+
+    #[test]
+    fn test_config_file_rejects_invalid_options() {
+        let file =
+            ConfigFile::parse("tuning:\n  test_config_batch_size: fast\n", Format::Yaml).unwrap();
+        assert!(matches!(
+            file.validate(&Cli::command()),
+            Err(Error::InvalidValue { .. })
+        ));
+
+        let file =
+            ConfigFile::parse("tuning:\n  test_config_workers: 1,x\n", Format::Yaml).unwrap();
+        assert!(matches!(
+            file.validate(&Cli::command()),
+            Err(Error::InvalidValue { .. })
+        ));
+
+        let file = ConfigFile::parse("tuning:\n  batch_size: 20\n", Format::Yaml).unwrap();
+        assert!(matches!(
+            file.validate(&Cli::command()),
+            Err(Error::UnknownOption { .. })
+        ));
+
+        let content = "[setlement]\ntest_config_rpc = \"http://localhost:5050\"\n";
+        assert!(matches!(
+            ConfigFile::parse(content, Format::Toml),
+            Err(Error::UnknownSection { section }) if section == "setlement"
+        ));
+    }
+}
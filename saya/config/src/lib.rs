@@ -0,0 +1,172 @@
+//! Configuration file support for Saya binaries.
+//!
+//! A configuration file is a TOML or YAML document made of sections (`rollup`, `settlement`,
+//! `prover`, `da`, `storage` and `tuning`), each holding options named after the environment
+//! variable of the corresponding command line flag, in lowercase:
+//!
+//! ```toml
+//! [settlement]
+//! settlement_rpc = "https://api.cartridge.gg/x/starknet/sepolia"
+//! settlement_piltover_address = "0x1234"
+//! ```
+//!
+//! Sections only group options for readability. Options from the file become the default values
+//! of their flags, so command line flags take precedence over environment variables, which in
+//! turn take precedence over the file.
+//!
+//! The `pipeline` feature also provides the option groups shared by the binaries running a Saya
+//! pipeline, such as `RpcConfiguration` and `SupervisionConfiguration`.
+
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Command, Subcommand};
+
+mod file;
+#[cfg(feature = "pipeline")]
+mod pipeline;
+
+pub use file::{ConfigFile, Format};
+#[cfg(feature = "pipeline")]
+pub use pipeline::{
+    AdminConfiguration, HealthConfiguration, RpcConfiguration, StageFailurePolicy,
+    SupervisionConfiguration,
+};
+
+/// Environment variable holding the path to the configuration file.
+pub const CONFIG_ENV: &str = "SAYA_CONFIG";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("no configuration file given, use `--config` or `{CONFIG_ENV}`")]
+    MissingConfig,
+
+    #[error("failed to read configuration file `{}`: {source}", path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("unsupported configuration file `{}`, expected a .toml, .yaml or .yml file", .0.display())]
+    UnsupportedFormat(PathBuf),
+
+    #[error("failed to parse TOML configuration: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("failed to parse YAML configuration: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("failed to serialize configuration: {0}")]
+    Serialize(String),
+
+    #[error("unknown section `{section}`")]
+    UnknownSection { section: String },
+
+    #[error("unknown option `{section}.{key}`")]
+    UnknownOption { section: String, key: String },
+
+    #[error("option `{key}` is set in more than one section")]
+    DuplicateOption { key: String },
+
+    #[error("invalid value for option `{key}`: {reason}")]
+    InvalidValue { key: String, reason: String },
+}
+
+/// The `--config` flag, meant to be flattened into the top-level command of a binary.
+#[derive(Debug, Clone, Args)]
+pub struct ConfigArgs {
+    /// Path to a TOML or YAML configuration file. Command line flags and environment variables
+    /// take precedence over values from the file
+    #[clap(long = "config", env = CONFIG_ENV, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+}
+
+/// The `config` subcommand for inspecting configuration files.
+#[derive(Debug, Clone, Args)]
+pub struct ConfigCommand {
+    #[clap(subcommand)]
+    command: Subcommands,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum Subcommands {
+    /// Print the configuration file with environment overrides applied and secrets redacted.
+    Print,
+    /// Check that all options in the configuration file exist and have valid values.
+    Validate,
+}
+
+impl ConfigCommand {
+    /// Runs the subcommand against the options of `command`, the top-level command of the binary.
+    pub fn run(&self, args: &ConfigArgs, command: &Command) -> Result<(), Error> {
+        let path = args.config.as_deref().ok_or(Error::MissingConfig)?;
+        let file = ConfigFile::load(path, command)?;
+
+        match self.command {
+            Subcommands::Print => print!("{}", file.effective()?),
+            Subcommands::Validate => {
+                file.validate(command)?;
+                println!("{} is valid", path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads the configuration file given to the process, if any, and returns `command` with the
+/// options of the file as default values of their flags.
+///
+/// `command` is the top-level command of the binary, to parse the command line with. Fails if the
+/// file contains options unknown to `command`.
+pub fn init(command: Command) -> Result<Command, Error> {
+    let Some(path) = config_path(std::env::args_os().skip(1)) else {
+        return Ok(command);
+    };
+
+    let file = ConfigFile::load(&path, &command)?;
+    Ok(file.apply(command))
+}
+
+/// Finds the configuration file path in the command line, falling back to [`CONFIG_ENV`].
+///
+/// The command line can't be parsed by clap at this point since required options might only
+/// be set by the file itself.
+fn config_path<I>(args: I) -> Option<PathBuf>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        } else if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(Path::new(path).to_path_buf());
+        }
+    }
+
+    std::env::var_os(CONFIG_ENV).map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_path_from_args() {
+        let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            config_path(args(&["start", "--config", "saya.toml"])),
+            Some(PathBuf::from("saya.toml"))
+        );
+        assert_eq!(
+            config_path(args(&["start", "--config=saya.yaml", "--db-dir", "db"])),
+            Some(PathBuf::from("saya.yaml"))
+        );
+    }
+}
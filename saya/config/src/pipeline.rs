@@ -0,0 +1,146 @@
+//! Command line options shared by the binaries running a Saya pipeline.
+
+use std::{net::SocketAddr, num::NonZeroU32, time::Duration};
+
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use saya_core::{
+    admin::AdminServerBuilder,
+    health::{HealthReporter, HealthServerBuilder},
+    rpc::{FailoverTransport, RpcClientConfig},
+    service::{Daemon, PauseHandle, RestartPolicy, ShutdownHandle},
+    storage::SqliteDb,
+};
+use url::Url;
+
+#[derive(Debug, Parser, Clone)]
+pub struct RpcConfiguration {
+    /// Timeout in seconds for an RPC endpoint to answer a request before failing over
+    #[clap(long, env, default_value_t = 30)]
+    rpc_request_timeout: u64,
+    /// Timeout in seconds for connecting to an RPC endpoint
+    #[clap(long, env, default_value_t = 10)]
+    rpc_connect_timeout: u64,
+    /// Maximum number of idle connections kept open to each RPC endpoint
+    #[clap(long, env, default_value_t = 32)]
+    rpc_max_idle_connections: usize,
+    /// Maximum number of requests sent per second to the endpoints of each network. Unlimited if
+    /// not set
+    #[clap(long, env)]
+    rpc_max_requests_per_second: Option<NonZeroU32>,
+    /// Interval in seconds at which the health and latest block of every RPC endpoint are checked.
+    /// Disabled if 0
+    #[clap(long, env, default_value_t = 10)]
+    rpc_health_check_interval: u64,
+}
+
+impl RpcConfiguration {
+    /// Creates the transport shared by all the components talking to the network at `urls`.
+    pub fn transport(&self, urls: Vec<Url>) -> Result<FailoverTransport> {
+        let config = RpcClientConfig {
+            request_timeout: Duration::from_secs(self.rpc_request_timeout),
+            connect_timeout: Duration::from_secs(self.rpc_connect_timeout),
+            max_idle_connections: self.rpc_max_idle_connections,
+            max_requests_per_second: self.rpc_max_requests_per_second,
+            health_check_interval: (self.rpc_health_check_interval > 0)
+                .then(|| Duration::from_secs(self.rpc_health_check_interval)),
+        };
+
+        FailoverTransport::with_config(urls, &config)
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct SupervisionConfiguration {
+    /// What to do when a pipeline stage exits unexpectedly
+    #[clap(long, env, value_enum, default_value_t = StageFailurePolicy::Exit)]
+    on_stage_failure: StageFailurePolicy,
+    /// Maximum number of consecutive restarts before giving up
+    #[clap(long, env, default_value_t = 5)]
+    max_restarts: u32,
+    /// Seconds to wait before restarting the pipeline
+    #[clap(long, env, default_value_t = 10)]
+    restart_backoff_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum StageFailurePolicy {
+    /// Tear the pipeline down and exit with a non-zero status
+    Exit,
+    /// Restart the whole pipeline from the last settled block
+    Restart,
+}
+
+impl SupervisionConfiguration {
+    pub fn restart_policy(&self) -> RestartPolicy {
+        match self.on_stage_failure {
+            StageFailurePolicy::Exit => RestartPolicy::Exit,
+            StageFailurePolicy::Restart => RestartPolicy::Restart {
+                max_restarts: self.max_restarts,
+                backoff: Duration::from_secs(self.restart_backoff_secs),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct HealthConfiguration {
+    /// Address to serve the `/health`, `/ready` and `/metrics` endpoints on. Disabled if not set
+    #[clap(long, env)]
+    health_addr: Option<SocketAddr>,
+    /// Number of unsettled rollup blocks above which Saya is reported as not ready
+    #[clap(long, env, default_value_t = 100)]
+    health_max_settlement_lag: u64,
+}
+
+impl HealthConfiguration {
+    /// Starts the health server if enabled, returning the reporter to be fed by the orchestrator
+    /// along with the handle for shutting the server down.
+    pub async fn start(&self) -> Result<Option<(HealthReporter, ShutdownHandle)>> {
+        let Some(health_addr) = self.health_addr else {
+            return Ok(None);
+        };
+
+        let reporter = HealthReporter::new(self.health_max_settlement_lag);
+        let server = HealthServerBuilder::new(health_addr, reporter.clone())
+            .build()
+            .await?;
+        let server_shutdown = server.shutdown_handle();
+        server.start();
+
+        Ok(Some((reporter, server_shutdown)))
+    }
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct AdminConfiguration {
+    /// Enable the admin JSON-RPC server for pausing, resuming and retrying blocks at runtime
+    #[clap(long, env, default_value_t = false)]
+    admin: bool,
+    /// Address for the admin JSON-RPC server to listen on
+    #[clap(long, env, default_value = "127.0.0.1:5051")]
+    admin_addr: SocketAddr,
+}
+
+impl AdminConfiguration {
+    /// Starts the admin server if enabled, returning the handle for shutting the server down.
+    pub async fn start(
+        &self,
+        db_path: &str,
+        pause_handle: PauseHandle,
+        orchestrator: ShutdownHandle,
+    ) -> Result<Option<ShutdownHandle>> {
+        if !self.admin {
+            return Ok(None);
+        }
+
+        let db = SqliteDb::new(db_path).await?;
+        let server = AdminServerBuilder::new(self.admin_addr, db, pause_handle, orchestrator)
+            .build()
+            .await?;
+        let server_shutdown = server.shutdown_handle();
+        server.start();
+
+        Ok(Some(server_shutdown))
+    }
+}
@@ -0,0 +1,54 @@
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::QueryRecord;
+
+mod server;
+pub use server::{AdminServer, AdminServerBuilder};
+
+/// Admin API for controlling a running Saya instance. All methods live under the `saya`
+/// namespace (e.g. `saya_pause`).
+#[rpc(server, namespace = "saya")]
+pub trait AdminApi {
+    /// Stops ingesting new blocks. Blocks already in the pipeline keep being processed.
+    ///
+    /// Returns whether ingestion was already paused.
+    #[method(name = "pause")]
+    async fn pause(&self) -> RpcResult<bool>;
+
+    /// Resumes ingesting new blocks.
+    ///
+    /// Returns whether ingestion was paused.
+    #[method(name = "resume")]
+    async fn resume(&self) -> RpcResult<bool>;
+
+    /// Drops all progress made on a block and queues it to go through the pipeline again.
+    ///
+    /// Blocks whose layout bridge proof has been generated can't be retried, as they may already
+    /// have been forwarded to settlement in order.
+    #[method(name = "retryBlock")]
+    async fn retry_block(&self, block_number: u32) -> RpcResult<()>;
+
+    /// Returns the status of a block that has not been settled yet.
+    #[method(name = "getBlockStatus")]
+    async fn get_block_status(&self, block_number: u32) -> RpcResult<String>;
+
+    /// Lists failed blocks that are waiting to be retried.
+    #[method(name = "listFailedBlocks")]
+    async fn list_failed_blocks(&self) -> RpcResult<Vec<FailedBlock>>;
+
+    /// Lists the proving queries submitted for a block along with their reported cost, including
+    /// for settled blocks.
+    #[method(name = "getQueryRecords")]
+    async fn get_query_records(&self, block_number: u32) -> RpcResult<Vec<QueryRecord>>;
+
+    /// Requests the orchestrator to shut down gracefully.
+    #[method(name = "shutdown")]
+    async fn shutdown(&self) -> RpcResult<()>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FailedBlock {
+    pub block_number: u32,
+    pub reason: String,
+}
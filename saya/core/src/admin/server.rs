@@ -0,0 +1,317 @@
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use jsonrpsee::{
+    core::{async_trait, RpcResult},
+    server::{Server, ServerHandle},
+    types::{ErrorObject, ErrorObjectOwned},
+    RpcModule,
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+    admin::{AdminApiServer, FailedBlock},
+    service::{Daemon, FinishHandle, PauseHandle, ShutdownHandle},
+    storage::{BlockNotFound, BlockStatus, PersistantStorage, QueryRecord},
+};
+
+/// JSON-RPC error code for blocks not tracked in storage, either because they have never been
+/// ingested or because they have already been settled.
+const BLOCK_NOT_FOUND: i32 = 1;
+/// JSON-RPC error code for storage failures.
+const STORAGE_ERROR: i32 = 2;
+/// JSON-RPC error code for blocks too far in the pipeline to be retried.
+const BLOCK_NOT_RETRYABLE: i32 = 3;
+
+/// A JSON-RPC server implementing [`AdminApiServer`], backed by the orchestrator storage.
+#[derive(Debug)]
+pub struct AdminServer {
+    server: Server,
+    module: RpcModule<()>,
+    finish_handle: FinishHandle,
+}
+
+#[derive(Debug)]
+pub struct AdminServerBuilder<DB> {
+    listen_addr: SocketAddr,
+    db: DB,
+    pause_handle: PauseHandle,
+    orchestrator: ShutdownHandle,
+}
+
+#[derive(Debug)]
+struct AdminApiImpl<DB> {
+    db: DB,
+    pause_handle: PauseHandle,
+    orchestrator: ShutdownHandle,
+}
+
+impl<DB> AdminServerBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + 'static,
+{
+    /// Creates a builder for a server controlling the orchestrator behind `orchestrator`, whose
+    /// block ingestor is paused via `pause_handle`.
+    pub fn new(
+        listen_addr: SocketAddr,
+        db: DB,
+        pause_handle: PauseHandle,
+        orchestrator: ShutdownHandle,
+    ) -> Self {
+        Self {
+            listen_addr,
+            db,
+            pause_handle,
+            orchestrator,
+        }
+    }
+
+    pub async fn build(self) -> Result<AdminServer> {
+        if !self.listen_addr.ip().is_loopback() {
+            warn!(
+                listen_addr = %self.listen_addr,
+                "Admin server listening on a non-loopback address"
+            );
+        }
+
+        let server = Server::builder().build(self.listen_addr).await?;
+
+        let mut module = RpcModule::new(());
+        module.merge(
+            AdminApiImpl {
+                db: self.db,
+                pause_handle: self.pause_handle,
+                orchestrator: self.orchestrator,
+            }
+            .into_rpc(),
+        )?;
+
+        Ok(AdminServer {
+            server,
+            module,
+            finish_handle: FinishHandle::new(),
+        })
+    }
+}
+
+impl AdminServer {
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.server.local_addr()?)
+    }
+
+    async fn run(self) {
+        if let Ok(local_addr) = self.server.local_addr() {
+            info!(%local_addr, "Admin server listening");
+        }
+
+        let handle: ServerHandle = self.server.start(self.module);
+
+        tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => {
+                // Only fails if the server has already stopped.
+                let _ = handle.stop();
+            },
+            _ = handle.clone().stopped() => {},
+        }
+        handle.stopped().await;
+
+        debug!("Graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+}
+
+impl Daemon for AdminServer {
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
+
+#[async_trait]
+impl<DB> AdminApiServer for AdminApiImpl<DB>
+where
+    DB: PersistantStorage + Send + Sync + 'static,
+{
+    async fn pause(&self) -> RpcResult<bool> {
+        let was_paused = self.pause_handle.pause();
+        info!("Block ingestion pause requested via admin API");
+        Ok(was_paused)
+    }
+
+    async fn resume(&self) -> RpcResult<bool> {
+        let was_paused = self.pause_handle.resume();
+        info!("Block ingestion resume requested via admin API");
+        Ok(was_paused)
+    }
+
+    async fn retry_block(&self, block_number: u32) -> RpcResult<()> {
+        let status = self
+            .db
+            .get_status(block_number)
+            .await
+            .map_err(status_error)?;
+
+        // Blocks are proven again from scratch on retry, but blocks past the orderer are expected
+        // to keep their proofs until settled and wouldn't be emitted again by it.
+        if matches!(
+            status,
+            BlockStatus::BridgeProofGenerated | BlockStatus::VerifiedProof | BlockStatus::Settled
+        ) {
+            return Err(ErrorObject::owned(
+                BLOCK_NOT_RETRYABLE,
+                format!(
+                    "block {} is already {} and can't be retried",
+                    block_number, status
+                ),
+                None::<()>,
+            ));
+        }
+
+        self.db
+            .add_failed_block(block_number, "retry requested via admin API".to_string())
+            .await
+            .map_err(storage_error)?;
+
+        info!(block_number, %status, "Block retry requested via admin API");
+        Ok(())
+    }
+
+    async fn get_block_status(&self, block_number: u32) -> RpcResult<String> {
+        let status = self
+            .db
+            .get_status(block_number)
+            .await
+            .map_err(status_error)?;
+
+        Ok(status.to_string())
+    }
+
+    async fn list_failed_blocks(&self) -> RpcResult<Vec<FailedBlock>> {
+        let failed_blocks = self.db.get_failed_blocks().await.map_err(storage_error)?;
+
+        Ok(failed_blocks
+            .into_iter()
+            .map(|(block_number, reason)| FailedBlock {
+                block_number,
+                reason,
+            })
+            .collect())
+    }
+
+    async fn get_query_records(&self, block_number: u32) -> RpcResult<Vec<QueryRecord>> {
+        self.db
+            .get_query_records(block_number)
+            .await
+            .map_err(storage_error)
+    }
+
+    async fn shutdown(&self) -> RpcResult<()> {
+        info!("Shutdown requested via admin API");
+        self.orchestrator.shutdown();
+        Ok(())
+    }
+}
+
+/// Maps an error getting the status of a block, telling blocks not tracked in storage apart from
+/// storage failures.
+fn status_error(err: anyhow::Error) -> ErrorObjectOwned {
+    match err.downcast_ref::<BlockNotFound>() {
+        Some(not_found) => ErrorObject::owned(BLOCK_NOT_FOUND, not_found.to_string(), None::<()>),
+        None => storage_error(err),
+    }
+}
+
+fn storage_error(err: anyhow::Error) -> ErrorObjectOwned {
+    ErrorObject::owned(
+        STORAGE_ERROR,
+        format!("storage error: {:#}", err),
+        None::<()>,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonrpsee::core::{server::MethodsError, EmptyServerParams};
+
+    use super::*;
+    use crate::storage::SqliteDb;
+
+    fn error_code<T>(result: Result<T, MethodsError>) -> i32 {
+        match result {
+            Err(MethodsError::JsonRpc(error)) => error.code(),
+            _ => panic!("expected a JSON-RPC error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_admin_api() {
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        db.initialize_block(5).await.unwrap();
+
+        let pause_handle = PauseHandle::new();
+        let orchestrator = FinishHandle::new();
+        let module = AdminApiImpl {
+            db: db.clone(),
+            pause_handle: pause_handle.clone(),
+            orchestrator: orchestrator.shutdown_handle(),
+        }
+        .into_rpc();
+
+        let was_paused: bool = module
+            .call("saya_pause", EmptyServerParams::new())
+            .await
+            .unwrap();
+        assert!(!was_paused);
+        assert!(pause_handle.is_paused());
+
+        let status: String = module.call("saya_getBlockStatus", [5]).await.unwrap();
+        assert_eq!(status, "mined");
+        assert_eq!(
+            error_code(module.call::<_, String>("saya_getBlockStatus", [6]).await),
+            BLOCK_NOT_FOUND
+        );
+
+        module.call::<_, ()>("saya_retryBlock", [5]).await.unwrap();
+        let failed_blocks: Vec<FailedBlock> = module
+            .call("saya_listFailedBlocks", EmptyServerParams::new())
+            .await
+            .unwrap();
+        assert_eq!(failed_blocks.len(), 1);
+        assert_eq!(failed_blocks[0].block_number, 5);
+
+        db.initialize_block(7).await.unwrap();
+        db.set_status(7, "bridge_proof_generated".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            error_code(module.call::<_, ()>("saya_retryBlock", [7]).await),
+            BLOCK_NOT_RETRYABLE
+        );
+        let status: String = module.call("saya_getBlockStatus", [7]).await.unwrap();
+        assert_eq!(status, "bridge_proof_generated");
+
+        let query_records: Vec<QueryRecord> =
+            module.call("saya_getQueryRecords", [5]).await.unwrap();
+        assert!(query_records.is_empty());
+
+        module
+            .call::<_, ()>("saya_shutdown", EmptyServerParams::new())
+            .await
+            .unwrap();
+        assert!(orchestrator.is_shutdown_requested());
+
+        // Storage failures aren't reported as missing blocks.
+        db.pool.close().await;
+        assert_eq!(
+            error_code(module.call::<_, String>("saya_getBlockStatus", [5]).await),
+            STORAGE_ERROR
+        );
+        assert_eq!(
+            error_code(module.call::<_, ()>("saya_retryBlock", [5]).await),
+            STORAGE_ERROR
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt::Debug;
+
+use anyhow::Result;
+use starknet::{
+    core::types::{
+        BlockId, BlockWithReceipts, MaybePreConfirmedBlockWithReceipts, StateUpdate,
+        TransactionReceipt,
+    },
+    providers::Provider,
+};
+
+/// Resources of a block that batches can be bounded by.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockResources {
+    pub transactions: usize,
+    /// Number of L1 handler transactions, i.e. of L1 to L2 messages consumed by the block.
+    pub l1_handler_transactions: usize,
+    pub l2_to_l1_messages: usize,
+    /// Number of entries of the state diff of the block.
+    pub state_diff_size: usize,
+}
+
+/// Decides when a batch of blocks is closed and emitted downstream, on top of the idle timeout.
+pub trait BatchPolicy: Debug + Send + Sync {
+    /// Returns whether the batch must be closed, given the resources of its blocks in order, the
+    /// last one having just been added.
+    ///
+    /// Blocks can't be split, so a batch closed on a bound may go over it with its last block.
+    fn should_close(&self, batch: &[BlockResources]) -> bool;
+
+    /// Whether the policy looks at the transactions of the blocks, which requires fetching their
+    /// receipts.
+    fn needs_receipts(&self) -> bool {
+        true
+    }
+}
+
+/// Closes a batch once it holds the given number of blocks.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxBlocks(pub usize);
+
+/// Closes a batch once its blocks hold the given number of transactions.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxTransactions(pub usize);
+
+/// Closes a batch once its blocks hold the given number of L1 to L2 and L2 to L1 messages.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxMessages(pub usize);
+
+/// Closes a batch once the state diffs of its blocks hold the given number of entries.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxStateDiffSize(pub usize);
+
+/// Closes a batch as soon as a block consumes or sends a message, so that messaging latency
+/// doesn't depend on the other policies.
+#[derive(Debug, Clone, Copy)]
+pub struct FlushOnMessages;
+
+impl BlockResources {
+    /// Measures the resources of a block known from its state update only, without transactions.
+    pub fn from_state_update(state_update: &StateUpdate) -> Self {
+        let state_diff = &state_update.state_diff;
+        let storage_entries: usize = state_diff
+            .storage_diffs
+            .iter()
+            .map(|diff| diff.storage_entries.len())
+            .sum();
+
+        Self {
+            state_diff_size: storage_entries
+                + state_diff.deprecated_declared_classes.len()
+                + state_diff.declared_classes.len()
+                + state_diff.deployed_contracts.len()
+                + state_diff.replaced_classes.len()
+                + state_diff.nonces.len(),
+            ..Default::default()
+        }
+    }
+
+    fn add_receipts(&mut self, block: &BlockWithReceipts) {
+        for transaction in &block.transactions {
+            let messages_sent = match &transaction.receipt {
+                TransactionReceipt::Invoke(receipt) => &receipt.messages_sent,
+                TransactionReceipt::L1Handler(receipt) => {
+                    self.l1_handler_transactions += 1;
+                    &receipt.messages_sent
+                }
+                TransactionReceipt::Declare(receipt) => &receipt.messages_sent,
+                TransactionReceipt::Deploy(receipt) => &receipt.messages_sent,
+                TransactionReceipt::DeployAccount(receipt) => &receipt.messages_sent,
+            };
+
+            self.transactions += 1;
+            self.l2_to_l1_messages += messages_sent.len();
+        }
+    }
+
+    fn messages(&self) -> usize {
+        self.l1_handler_transactions + self.l2_to_l1_messages
+    }
+}
+
+/// Measures the resources of `block_number`, fetching its receipts if `with_receipts` is set.
+pub(super) async fn fetch_block_resources<P>(
+    provider: &P,
+    block_number: u64,
+    state_update: &StateUpdate,
+    with_receipts: bool,
+) -> Result<BlockResources>
+where
+    P: Provider + Sync,
+{
+    let mut resources = BlockResources::from_state_update(state_update);
+    if !with_receipts {
+        return Ok(resources);
+    }
+
+    let block = match provider
+        .get_block_with_receipts(BlockId::Number(block_number))
+        .await?
+    {
+        MaybePreConfirmedBlockWithReceipts::Block(block) => block,
+        MaybePreConfirmedBlockWithReceipts::PreConfirmedBlock(_) => {
+            anyhow::bail!("block {block_number} is not confirmed yet")
+        }
+    };
+    if block.block_hash != state_update.block_hash {
+        anyhow::bail!("block {block_number} changed while being fetched");
+    }
+
+    resources.add_receipts(&block);
+    Ok(resources)
+}
+
+fn total<F>(batch: &[BlockResources], resource: F) -> usize
+where
+    F: Fn(&BlockResources) -> usize,
+{
+    batch.iter().map(resource).sum()
+}
+
+impl BatchPolicy for MaxBlocks {
+    fn should_close(&self, batch: &[BlockResources]) -> bool {
+        batch.len() >= self.0
+    }
+
+    fn needs_receipts(&self) -> bool {
+        false
+    }
+}
+
+impl BatchPolicy for MaxTransactions {
+    fn should_close(&self, batch: &[BlockResources]) -> bool {
+        total(batch, |block| block.transactions) >= self.0
+    }
+}
+
+impl BatchPolicy for MaxMessages {
+    fn should_close(&self, batch: &[BlockResources]) -> bool {
+        total(batch, BlockResources::messages) >= self.0
+    }
+}
+
+impl BatchPolicy for MaxStateDiffSize {
+    fn should_close(&self, batch: &[BlockResources]) -> bool {
+        total(batch, |block| block.state_diff_size) >= self.0
+    }
+
+    fn needs_receipts(&self) -> bool {
+        false
+    }
+}
+
+impl BatchPolicy for FlushOnMessages {
+    fn should_close(&self, batch: &[BlockResources]) -> bool {
+        batch.last().is_some_and(|block| block.messages() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(transactions: usize, l2_to_l1_messages: usize) -> BlockResources {
+        BlockResources {
+            transactions,
+            l2_to_l1_messages,
+            state_diff_size: transactions * 2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_policies_close_on_their_bound() {
+        let batch = [block(3, 0), block(4, 0)];
+
+        assert!(MaxBlocks(2).should_close(&batch));
+        assert!(!MaxBlocks(3).should_close(&batch));
+        assert!(MaxTransactions(7).should_close(&batch));
+        assert!(!MaxTransactions(8).should_close(&batch));
+        assert!(MaxStateDiffSize(10).should_close(&batch));
+        assert!(!MaxMessages(1).should_close(&batch));
+        assert!(!FlushOnMessages.should_close(&batch));
+
+        // Only the block just added triggers a flush, earlier ones already did.
+        let batch = [block(1, 1), block(1, 0)];
+        assert!(MaxMessages(1).should_close(&batch));
+        assert!(!FlushOnMessages.should_close(&batch));
+        assert!(FlushOnMessages.should_close(&batch[..1]));
+    }
+}
@@ -0,0 +1,421 @@
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Result;
+use starknet::core::types::StateUpdate;
+use tokio::{sync::mpsc::Sender, time::sleep};
+use tracing::{debug, error, info, trace};
+
+use crate::{
+    block_ingestor::{
+        filter::{BlockFilter, BlockMerger},
+        BlockInfo, BlockIngestor, BlockIngestorBuilder,
+    },
+    service::{Daemon, FinishHandle, ShutdownHandle},
+    storage::{BlockStatus, PersistantStorage},
+};
+
+/// Interval between two checks of whether the next block has been added to the directory.
+const FILE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A block ingestor which replays blocks exported to a directory, e.g. by `saya export-blocks`,
+/// instead of fetching them from a node.
+///
+/// Each block is read from a `<block_number>.json` file holding its `StateUpdate`, serialized the
+/// same way as in the `state_updates` table. Blocks are stored in the DB and emitted in order,
+/// just like [`PollingBlockIngestor`](super::PollingBlockIngestor) does, including merging the
+/// blocks held back by a [`BlockFilter`]. When the next block hasn't been exported, the ingestor
+/// waits for its file to show up. Blocks recorded as failed by any stage are replayed again from
+/// their files, as is.
+///
+/// Only block ingestion is replayed: generating the PIEs of the blocks still requires a rollup
+/// node serving them.
+///
+/// A block file that can't be read halts ingestion with a failure, as replaying past it would
+/// make the run diverge from the recorded one.
+#[derive(Debug)]
+pub struct FileBlockIngestor<DB> {
+    dir: PathBuf,
+    current_block: u64,
+    end_block: Option<u64>,
+    channel: Sender<BlockInfo>,
+    finish_handle: FinishHandle,
+    db: DB,
+    merger: Option<BlockMerger>,
+}
+
+#[derive(Debug)]
+pub struct FileBlockIngestorBuilder<DB> {
+    dir: PathBuf,
+    start_block: Option<u64>,
+    end_block: Option<u64>,
+    channel: Option<Sender<BlockInfo>>,
+    db: DB,
+    block_filter: Option<Box<dyn BlockFilter>>,
+}
+
+/// Returns the path of the file holding the state update of `block_number` in `dir`.
+pub fn state_update_path(dir: &Path, block_number: u64) -> PathBuf {
+    dir.join(format!("{block_number}.json"))
+}
+
+/// Writes the state update of `block_number` to `dir`, in the format read by
+/// [`FileBlockIngestor`].
+pub async fn write_state_update(
+    dir: &Path,
+    block_number: u64,
+    state_update: &StateUpdate,
+) -> Result<()> {
+    let path = state_update_path(dir, block_number);
+    let serialized = serde_json::to_vec(state_update)?;
+    tokio::fs::write(&path, serialized)
+        .await
+        .map_err(|err| anyhow::anyhow!("failed to write {}: {}", path.display(), err))?;
+
+    Ok(())
+}
+
+/// Reads the state update of `block_number` from `dir`, returning `None` if it hasn't been
+/// exported.
+pub async fn read_state_update(dir: &Path, block_number: u64) -> Result<Option<StateUpdate>> {
+    let path = state_update_path(dir, block_number);
+    let serialized = match tokio::fs::read(&path).await {
+        Ok(serialized) => serialized,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => anyhow::bail!("failed to read {}: {}", path.display(), err),
+    };
+    let state_update = serde_json::from_slice(&serialized)
+        .map_err(|err| anyhow::anyhow!("invalid state update in {}: {}", path.display(), err))?;
+
+    Ok(Some(state_update))
+}
+
+impl<DB> FileBlockIngestor<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    /// Checks whether `block_number` is within the range of blocks to ingest.
+    fn in_range(&self, block_number: u64) -> bool {
+        self.end_block
+            .is_none_or(|end_block| block_number <= end_block)
+    }
+
+    /// Reads the state update of `block_number` and stores it in the DB, returning `None` if the
+    /// block hasn't been exported yet.
+    async fn ingest_block(&self, block_number: u64) -> Result<Option<StateUpdate>> {
+        let Some(state_update) = read_state_update(&self.dir, block_number).await? else {
+            return Ok(None);
+        };
+
+        let block_id: u32 = block_number.try_into()?;
+        self.db.initialize_block(block_id).await?;
+        self.db
+            .add_state_update(block_id, state_update.clone())
+            .await?;
+
+        Ok(Some(state_update))
+    }
+
+    /// Sends a block downstream, returning `false` if a shutdown was requested meanwhile.
+    async fn send(&self, block: BlockInfo) -> bool {
+        let block_number = block.number;
+        tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => false,
+            result = self.channel.send(block) => {
+                if result.is_err() {
+                    error!(block_number, "Failed to send block");
+                }
+                true
+            },
+        }
+    }
+
+    /// Replays the blocks recorded as failed since the last check, which are forwarded as is.
+    ///
+    /// Returns `false` if a shutdown was requested meanwhile.
+    async fn replay_failed_blocks(&self) -> Result<bool> {
+        let failed_blocks = self.db.get_failed_blocks().await?;
+        if failed_blocks.is_empty() {
+            return Ok(true);
+        }
+
+        // Marked as handled before being replayed, as stages may record them as failed again right
+        // away.
+        let block_ids: Vec<u32> = failed_blocks.iter().map(|(id, _)| *id).collect();
+        self.db.mark_failed_blocks_as_handled(&block_ids).await?;
+
+        for block_id in block_ids {
+            let block_number = block_id as u64;
+            let Some(state_update) = self.ingest_block(block_number).await? else {
+                anyhow::bail!(
+                    "failed block {} missing from {}",
+                    block_number,
+                    self.dir.display()
+                );
+            };
+
+            debug!(block_number, "Failed block replayed, forwarding downstream");
+            let block = BlockInfo {
+                number: block_number,
+                status: BlockStatus::Mined,
+                state_update: Some(state_update),
+                first_block: None,
+            };
+            if !self.send(block).await {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    async fn run(mut self) {
+        while !self.finish_handle.is_shutdown_requested() {
+            match self.replay_failed_blocks().await {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    error!(error = %err, "Failed to replay failed blocks");
+                    self.finish_handle.fail(format!("{err:#}"));
+                    self.finish_handle.finish();
+                    return;
+                }
+            }
+
+            // Like the other ingestors, keep running once the range is exhausted until shut down,
+            // replaying the blocks failing meanwhile.
+            if self.in_range(self.current_block) {
+                let block_number = self.current_block;
+                match self.ingest_block(block_number).await {
+                    Ok(Some(state_update)) => {
+                        trace!(block_number, "Block replayed, forwarding downstream");
+
+                        let new_block = BlockInfo {
+                            number: block_number,
+                            status: BlockStatus::Mined,
+                            state_update: Some(state_update),
+                            first_block: None,
+                        };
+                        let new_block = match &mut self.merger {
+                            Some(merger) => merger.merge(new_block),
+                            None => Some(new_block),
+                        };
+                        if let Some(new_block) = new_block {
+                            if !self.send(new_block).await {
+                                break;
+                            }
+                        }
+
+                        if self.end_block == Some(block_number) {
+                            info!(end_block = block_number, "Last block of the range replayed");
+                        }
+                        self.current_block += 1;
+                        continue;
+                    }
+                    Ok(None) => trace!(block_number, "Block not exported yet"),
+                    Err(err) => {
+                        error!(block_number, error = %err, "Failed to replay block");
+                        self.finish_handle.fail(format!("{err:#}"));
+                        self.finish_handle.finish();
+                        return;
+                    }
+                }
+            }
+
+            tokio::select! {
+                _ = self.finish_handle.shutdown_requested() => break,
+                _ = sleep(FILE_CHECK_INTERVAL) => {},
+            }
+        }
+
+        debug!("Graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+}
+
+impl<DB> FileBlockIngestorBuilder<DB> {
+    /// Creates a builder replaying the blocks exported to `dir`.
+    pub fn new(dir: PathBuf, db: DB) -> Self {
+        Self {
+            dir,
+            start_block: None,
+            end_block: None,
+            channel: None,
+            db,
+            block_filter: None,
+        }
+    }
+
+    /// Holds back the blocks `filter` doesn't keep, emitting them along with the next kept block.
+    pub fn block_filter<F>(mut self, filter: F) -> Self
+    where
+        F: BlockFilter + 'static,
+    {
+        self.block_filter = Some(Box::new(filter));
+        self
+    }
+}
+
+impl<DB> BlockIngestorBuilder for FileBlockIngestorBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Ingestor = FileBlockIngestor<DB>;
+
+    fn build(self) -> Result<Self::Ingestor> {
+        Ok(FileBlockIngestor {
+            dir: self.dir,
+            current_block: self
+                .start_block
+                .ok_or_else(|| anyhow::anyhow!("`start_block` not set"))?,
+            end_block: self.end_block,
+            channel: self
+                .channel
+                .ok_or_else(|| anyhow::anyhow!("`channel` not set"))?,
+            finish_handle: FinishHandle::new(),
+            db: self.db,
+            merger: self
+                .block_filter
+                .map(|filter| BlockMerger::new(filter, self.end_block)),
+        })
+    }
+
+    fn start_block(mut self, start_block: u64) -> Self {
+        self.start_block = Some(start_block);
+        self
+    }
+
+    fn end_block(mut self, end_block: u64) -> Self {
+        self.end_block = Some(end_block);
+        self
+    }
+
+    fn channel(mut self, channel: Sender<BlockInfo>) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+}
+
+impl<DB> BlockIngestor for FileBlockIngestor<DB> where
+    DB: PersistantStorage + Send + Sync + Clone + 'static
+{
+}
+
+impl<DB> Daemon for FileBlockIngestor<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet::core::types::StateDiff;
+    use starknet_types_core::felt::Felt;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::storage::SqliteDb;
+
+    fn state_update(block_hash: u64) -> StateUpdate {
+        StateUpdate {
+            block_hash: Felt::from(block_hash),
+            new_root: Felt::ZERO,
+            old_root: Felt::ZERO,
+            state_diff: StateDiff {
+                storage_diffs: vec![],
+                deprecated_declared_classes: vec![],
+                declared_classes: vec![],
+                deployed_contracts: vec![],
+                replaced_classes: vec![],
+                nonces: vec![],
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replays_exported_blocks_in_order() {
+        let dir = std::env::temp_dir().join(format!("saya-file-ingestor-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for block_number in 1..=3 {
+            write_state_update(&dir, block_number, &state_update(block_number * 10))
+                .await
+                .unwrap();
+        }
+
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let (tx, mut rx) = mpsc::channel(4);
+        let ingestor = FileBlockIngestorBuilder::new(dir.clone(), db.clone())
+            .start_block(2)
+            .channel(tx)
+            .build()
+            .unwrap();
+        let shutdown_handle = ingestor.shutdown_handle();
+        ingestor.start();
+
+        for block_number in 2..=3 {
+            let block = rx.recv().await.unwrap();
+            assert_eq!(block.number, block_number);
+            assert_eq!(
+                block.state_update.unwrap().block_hash,
+                Felt::from(block_number * 10)
+            );
+        }
+        assert_eq!(
+            db.get_state_update(3).await.unwrap().block_hash,
+            Felt::from(30)
+        );
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replays_failed_blocks() {
+        let dir =
+            std::env::temp_dir().join(format!("saya-file-ingestor-failed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for block_number in 1..=2 {
+            write_state_update(&dir, block_number, &state_update(block_number * 10))
+                .await
+                .unwrap();
+        }
+
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let (tx, mut rx) = mpsc::channel(4);
+        let ingestor = FileBlockIngestorBuilder::new(dir.clone(), db.clone())
+            .start_block(1)
+            .end_block(2)
+            .channel(tx)
+            .build()
+            .unwrap();
+        let shutdown_handle = ingestor.shutdown_handle();
+        ingestor.start();
+
+        assert_eq!(rx.recv().await.unwrap().number, 1);
+        assert_eq!(rx.recv().await.unwrap().number, 2);
+
+        // A stage fails block 1 once the range has been replayed.
+        db.add_failed_block(1, "failed".to_string()).await.unwrap();
+        let block = rx.recv().await.unwrap();
+        assert_eq!(block.number, 1);
+        assert_eq!(block.state_update.unwrap().block_hash, Felt::from(10));
+        assert!(db.get_failed_blocks().await.unwrap().is_empty());
+        assert!(rx.try_recv().is_err());
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+        assert_eq!(shutdown_handle.failure(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt::Debug;
+
+use tracing::trace;
+
+use crate::block_ingestor::{BlockInfo, BlockResources};
+
+/// Maximum number of blocks merged together, bounding the size of the SNOS run proving them.
+const MAX_MERGED_BLOCKS: u64 = 100;
+
+/// Decides which blocks are worth proving on their own.
+///
+/// Blocks can't simply be dropped, as Piltover only accepts state transitions starting right after
+/// the last settled block. Blocks a filter doesn't keep are instead held back by the ingestor and
+/// emitted along with the next kept block, as a batch proven by a single SNOS run (see
+/// [`BlockInfo::first_block`]).
+pub trait BlockFilter: Debug + Send + Sync {
+    /// Returns whether `block` is emitted, along with the blocks held back before it.
+    fn keep(&self, block: &BlockInfo) -> bool;
+}
+
+/// Merges blocks with an empty state diff into the next block changing the state, so that no-op
+/// blocks aren't proven on their own.
+#[derive(Debug, Clone, Copy)]
+pub struct MergeEmptyBlocks;
+
+impl BlockFilter for MergeEmptyBlocks {
+    fn keep(&self, block: &BlockInfo) -> bool {
+        block.state_update.as_ref().is_none_or(|state_update| {
+            BlockResources::from_state_update(state_update).state_diff_size > 0
+        })
+    }
+}
+
+/// Applies a [`BlockFilter`] to blocks given in order.
+#[derive(Debug)]
+pub(super) struct BlockMerger {
+    filter: Box<dyn BlockFilter>,
+    end_block: Option<u64>,
+    first_held_block: Option<u64>,
+}
+
+impl BlockMerger {
+    pub(super) fn new(filter: Box<dyn BlockFilter>, end_block: Option<u64>) -> Self {
+        Self {
+            filter,
+            end_block,
+            first_held_block: None,
+        }
+    }
+
+    /// Returns the block to emit once `block` has been ingested, covering the blocks held back
+    /// before it, or `None` if `block` is held back as well.
+    ///
+    /// The last block of the range is always emitted, so that no block is held back forever.
+    pub(super) fn merge(&mut self, mut block: BlockInfo) -> Option<BlockInfo> {
+        let first_block = *self.first_held_block.get_or_insert(block.number);
+        let keep = self.end_block == Some(block.number)
+            || block.number - first_block + 1 >= MAX_MERGED_BLOCKS
+            || self.filter.keep(&block);
+        if !keep {
+            trace!(block_number = block.number, "Block held back by the filter");
+            return None;
+        }
+
+        self.first_held_block = None;
+        block.first_block = (first_block != block.number).then_some(first_block);
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet::core::types::{NonceUpdate, StateDiff, StateUpdate};
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+    use crate::storage::BlockStatus;
+
+    fn block(number: u64, empty: bool) -> BlockInfo {
+        let nonces = if empty {
+            vec![]
+        } else {
+            vec![NonceUpdate {
+                contract_address: Felt::ONE,
+                nonce: Felt::from(number),
+            }]
+        };
+
+        BlockInfo {
+            number,
+            status: BlockStatus::Mined,
+            state_update: Some(StateUpdate {
+                block_hash: Felt::from(number),
+                new_root: Felt::ZERO,
+                old_root: Felt::ZERO,
+                state_diff: StateDiff {
+                    storage_diffs: vec![],
+                    deprecated_declared_classes: vec![],
+                    declared_classes: vec![],
+                    deployed_contracts: vec![],
+                    replaced_classes: vec![],
+                    nonces,
+                },
+            }),
+            first_block: None,
+        }
+    }
+
+    #[test]
+    fn test_empty_blocks_are_merged_into_the_next_block() {
+        let mut merger = BlockMerger::new(Box::new(MergeEmptyBlocks), Some(6));
+
+        let empty = [false, true, true, false, true, true];
+        let emitted: Vec<_> = (1..=6)
+            .zip(empty)
+            .filter_map(|(number, empty)| merger.merge(block(number, empty)))
+            .map(|block| (block.first_block, block.number))
+            .collect();
+
+        // The last block of the range flushes the blocks held back.
+        assert_eq!(emitted, [(None, 1), (Some(2), 4), (Some(5), 6)]);
+    }
+}
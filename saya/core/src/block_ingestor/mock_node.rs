@@ -0,0 +1,336 @@
+//! A mock Starknet node serving a chain of empty blocks to the block ingestors under test.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use jsonrpsee::{
+    server::{Server, ServerHandle},
+    types::{ErrorObject, ErrorObjectOwned, Params},
+    RpcModule,
+};
+use serde_json::{json, Value};
+use soketto::handshake;
+use starknet::core::types::{StateDiff, StateUpdate};
+use starknet_types_core::felt::Felt;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tokio_util::{compat::TokioAsyncReadCompatExt, sync::CancellationToken};
+use url::Url;
+
+/// State of the mock chain, shared with the test driving it.
+#[derive(Debug)]
+pub(super) struct MockChain {
+    latest_block: AtomicU64,
+    /// First block replaced by a reorg, if any.
+    fork: AtomicU64,
+    /// Number of upcoming state update requests answered as pre-confirmed, by block.
+    pre_confirmed: Mutex<HashMap<u64, usize>>,
+    /// Number of upcoming state update requests failing, by block.
+    failures: Mutex<HashMap<u64, usize>>,
+    /// Number of state update requests received, by block.
+    requests: Mutex<HashMap<u64, usize>>,
+    /// Heads notified to the new heads subscribers.
+    new_heads: watch::Sender<Option<u64>>,
+}
+
+impl MockChain {
+    pub(super) fn new(latest_block: u64) -> Arc<Self> {
+        Arc::new(Self {
+            latest_block: AtomicU64::new(latest_block),
+            fork: AtomicU64::new(u64::MAX),
+            pre_confirmed: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+            requests: Mutex::new(HashMap::new()),
+            new_heads: watch::Sender::new(None),
+        })
+    }
+
+    /// Sets the latest block reported by `starknet_blockNumber`.
+    pub(super) fn set_latest_block(&self, block_number: u64) {
+        self.latest_block.store(block_number, Ordering::SeqCst);
+    }
+
+    /// Answers the next `times` state update requests for `block_number` as pre-confirmed.
+    pub(super) fn pre_confirm(&self, block_number: u64, times: usize) {
+        self.pre_confirmed
+            .lock()
+            .unwrap()
+            .insert(block_number, times);
+    }
+
+    /// Fails the next `times` state update requests for `block_number`.
+    pub(super) fn fail(&self, block_number: u64, times: usize) {
+        self.failures.lock().unwrap().insert(block_number, times);
+    }
+
+    /// Returns the number of state update requests received for `block_number`.
+    pub(super) fn requests(&self, block_number: u64) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .get(&block_number)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Notifies `block_number` as a new head to the subscribers.
+    pub(super) fn notify_new_head(&self, block_number: u64) {
+        self.new_heads.send_replace(Some(block_number));
+    }
+
+    /// Replaces the chain from `block_number` onwards with blocks of different hashes.
+    pub(super) fn reorg(&self, block_number: u64) {
+        self.fork.store(block_number, Ordering::SeqCst);
+    }
+
+    fn block_hash(&self, block_number: u64) -> Felt {
+        if block_number >= self.fork.load(Ordering::SeqCst) {
+            Felt::from(0x2000 + block_number)
+        } else {
+            Felt::from(0x1000 + block_number)
+        }
+    }
+
+    /// Decrements the counter of `block_number`, returning whether it was set.
+    fn take(counters: &Mutex<HashMap<u64, usize>>, block_number: u64) -> bool {
+        match counters.lock().unwrap().get_mut(&block_number) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn get_state_update(&self, block_number: u64) -> Result<Value, ErrorObjectOwned> {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry(block_number)
+            .or_default() += 1;
+
+        if Self::take(&self.failures, block_number) {
+            return Err(ErrorObject::owned(-32603, "Internal error", None::<()>));
+        }
+
+        let state_update = StateUpdate {
+            block_hash: self.block_hash(block_number),
+            new_root: Felt::ZERO,
+            old_root: Felt::ZERO,
+            state_diff: StateDiff {
+                storage_diffs: vec![],
+                deprecated_declared_classes: vec![],
+                declared_classes: vec![],
+                deployed_contracts: vec![],
+                replaced_classes: vec![],
+                nonces: vec![],
+            },
+        };
+        let mut state_update = serde_json::to_value(state_update).unwrap();
+
+        // Pre-confirmed state updates are the same, minus the fields of a closed block.
+        if Self::take(&self.pre_confirmed, block_number) {
+            let fields = state_update.as_object_mut().unwrap();
+            fields.remove("block_hash");
+            fields.remove("new_root");
+        }
+
+        Ok(state_update)
+    }
+
+    fn get_block_with_tx_hashes(&self, block_number: u64) -> Value {
+        let gas_price = json!({ "price_in_fri": "0x1", "price_in_wei": "0x1" });
+        let parent_hash = match block_number {
+            0 => Felt::ZERO,
+            block_number => self.block_hash(block_number - 1),
+        };
+
+        json!({
+            "status": "ACCEPTED_ON_L2",
+            "block_hash": format!("{:#x}", self.block_hash(block_number)),
+            "parent_hash": format!("{:#x}", parent_hash),
+            "block_number": block_number,
+            "new_root": "0x0",
+            "timestamp": 0,
+            "sequencer_address": "0x0",
+            "l1_gas_price": gas_price,
+            "l2_gas_price": gas_price,
+            "l1_data_gas_price": gas_price,
+            "l1_da_mode": "BLOB",
+            "starknet_version": "0.14.0",
+            "transaction_commitment": "0x0",
+            "event_commitment": "0x0",
+            "receipt_commitment": "0x0",
+            "state_diff_commitment": "0x0",
+            "event_count": 0,
+            "transaction_count": 0,
+            "state_diff_length": 0,
+            "transactions": [],
+        })
+    }
+}
+
+/// Extracts the number of the block requested, whether params are passed by name or position.
+fn block_number(params: Params) -> Result<u64, ErrorObjectOwned> {
+    let params: Value = params.parse()?;
+    let block_id = match &params {
+        Value::Array(params) => params.first(),
+        params => params.get("block_id"),
+    };
+
+    block_id
+        .and_then(|block_id| block_id.get("block_number"))
+        .and_then(Value::as_u64)
+        .ok_or_else(|| ErrorObject::owned(-32602, "Invalid block id", None::<()>))
+}
+
+/// Starts a node serving `chain` over HTTP.
+pub(super) async fn start_node(chain: Arc<MockChain>) -> (Url, ServerHandle) {
+    let mut module = RpcModule::from_arc(chain);
+    module
+        .register_method("starknet_blockNumber", |_, chain, _| {
+            chain.latest_block.load(Ordering::SeqCst)
+        })
+        .unwrap();
+    module
+        .register_method("starknet_getStateUpdate", |params, chain, _| {
+            chain.get_state_update(block_number(params)?)
+        })
+        .unwrap();
+    module
+        .register_method("starknet_getBlockWithTxHashes", |params, chain, _| {
+            block_number(params).map(|block_number| chain.get_block_with_tx_hashes(block_number))
+        })
+        .unwrap();
+
+    let server = Server::builder().build("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", server.local_addr().unwrap());
+    (url.parse().unwrap(), server.start(module))
+}
+
+/// A node notifying the new heads of a chain over WebSocket, in the format of Starknet nodes
+/// rather than `jsonrpsee` subscriptions.
+pub(super) struct NewHeadsNode {
+    addr: SocketAddr,
+    shutdown: CancellationToken,
+    server: JoinHandle<()>,
+}
+
+impl NewHeadsNode {
+    /// Starts a node notifying the new heads of `chain`, listening on `addr`.
+    pub(super) async fn start(chain: Arc<MockChain>, addr: &str) -> Self {
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shutdown = CancellationToken::new();
+
+        let server = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move {
+                loop {
+                    let stream = tokio::select! {
+                        _ = shutdown.cancelled() => break,
+                        stream = listener.accept() => stream.unwrap().0,
+                    };
+                    tokio::spawn(Self::serve(chain.clone(), stream, shutdown.clone()));
+                }
+            }
+        });
+
+        Self {
+            addr,
+            shutdown,
+            server,
+        }
+    }
+
+    pub(super) fn url(&self) -> Url {
+        format!("ws://{}", self.addr).parse().unwrap()
+    }
+
+    pub(super) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stops the node, closing all the connections.
+    pub(super) async fn stop(self) {
+        self.shutdown.cancel();
+        self.server.await.unwrap();
+    }
+
+    /// Answers subscription requests on a connection, then notifies the new heads to it.
+    async fn serve(chain: Arc<MockChain>, stream: TcpStream, shutdown: CancellationToken) {
+        let mut server = handshake::Server::new(stream.compat());
+        let Ok(request) = server.receive_request().await else {
+            return;
+        };
+        let key = request.key();
+        let accept = handshake::server::Response::Accept {
+            key,
+            protocol: None,
+        };
+        if server.send_response(&accept).await.is_err() {
+            return;
+        }
+        let (mut sender, mut receiver) = server.into_builder().finish();
+
+        // Requests are read on their own task, as receiving a message isn't cancellation safe.
+        let (request_tx, mut requests) = mpsc::channel(1);
+        let reader = tokio::spawn(async move {
+            let mut message = Vec::new();
+            while receiver.receive_data(&mut message).await.is_ok() {
+                let request: Value = serde_json::from_slice(&message).unwrap();
+                message.clear();
+                if request_tx.send(request).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut new_heads = chain.new_heads.subscribe();
+        let mut subscribed = false;
+        loop {
+            let message = tokio::select! {
+                _ = shutdown.cancelled() => break,
+                request = requests.recv() => {
+                    let Some(request) = request else { break };
+                    subscribed = true;
+                    new_heads.mark_changed();
+                    json!({ "jsonrpc": "2.0", "id": request["id"], "result": "0x1" })
+                }
+                changed = new_heads.changed(), if subscribed => {
+                    if changed.is_err() {
+                        break;
+                    }
+                    let Some(block_number) = *new_heads.borrow_and_update() else {
+                        continue;
+                    };
+                    json!({
+                        "jsonrpc": "2.0",
+                        "method": "starknet_subscriptionNewHeads",
+                        "params": {
+                            "subscription_id": "0x1",
+                            "result": { "block_number": block_number },
+                        },
+                    })
+                }
+            };
+
+            if sender.send_text(message.to_string()).await.is_err() || sender.flush().await.is_err()
+            {
+                break;
+            }
+        }
+
+        reader.abort();
+    }
+}
@@ -1,16 +1,39 @@
 use anyhow::Result;
 
+use serde::{Deserialize, Serialize};
 use starknet::core::types::StateUpdate;
 use tokio::sync::mpsc::Sender;
 
+mod batch_policy;
+mod file;
+mod filter;
+#[cfg(test)]
+mod mock_node;
 mod polling;
+mod reorg;
+mod subscription;
 
+pub use batch_policy::{
+    BatchPolicy, BlockResources, FlushOnMessages, MaxBlocks, MaxMessages, MaxStateDiffSize,
+    MaxTransactions,
+};
+pub use file::{
+    read_state_update, state_update_path, write_state_update, FileBlockIngestor,
+    FileBlockIngestorBuilder,
+};
+pub use filter::{BlockFilter, MergeEmptyBlocks};
 pub use polling::{
     BatchingPollingBlockIngestor, BatchingPollingBlockIngestorBuilder, PollingBlockIngestor,
     PollingBlockIngestorBuilder,
 };
+pub use reorg::ReorgError;
+pub use subscription::{SubscriptionBlockIngestor, SubscriptionBlockIngestorBuilder};
 
-use crate::{service::Daemon, storage::BlockStatus};
+use crate::{
+    health::HealthReporter,
+    service::{Daemon, PauseHandle},
+    storage::BlockStatus,
+};
 
 pub trait BlockIngestorBuilder {
     type Ingestor: BlockIngestor;
@@ -19,7 +42,31 @@ pub trait BlockIngestorBuilder {
 
     fn start_block(self, start_block: u64) -> Self;
 
+    /// Sets the last block to ingest, after which no new block is dispatched. Blocks that fail
+    /// within the range are still retried.
+    fn end_block(self, end_block: u64) -> Self;
+
     fn channel(self, channel: Sender<BlockInfo>) -> Self;
+
+    /// Sets a reporter for the ingestor to publish the rollup tip to.
+    ///
+    /// The default implementation is a no-op for ingestors that don't track the rollup tip.
+    fn health_reporter(self, _reporter: HealthReporter) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
+
+    /// Sets a handle for pausing the ingestion of new blocks at runtime.
+    ///
+    /// The default implementation is a no-op for ingestors that can't be paused.
+    fn pause_handle(self, _pause_handle: PauseHandle) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
 }
 
 /// Like [`BlockIngestorBuilder`] but emits ordered *batches* of blocks downstream.
@@ -32,14 +79,42 @@ pub trait BatchingBlockIngestorBuilder {
 
     fn start_block(self, start_block: u64) -> Self;
 
+    /// Sets the last block to ingest, after which no new block is dispatched. Blocks that fail
+    /// within the range are still retried.
+    fn end_block(self, end_block: u64) -> Self;
+
     fn channel(self, channel: Sender<Vec<BlockInfo>>) -> Self;
+
+    /// Sets a reporter for the ingestor to publish the rollup tip to.
+    ///
+    /// The default implementation is a no-op for ingestors that don't track the rollup tip.
+    fn health_reporter(self, _reporter: HealthReporter) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
+
+    /// Sets a handle for pausing the ingestion of new blocks at runtime.
+    ///
+    /// The default implementation is a no-op for ingestors that can't be paused.
+    fn pause_handle(self, _pause_handle: PauseHandle) -> Self
+    where
+        Self: Sized,
+    {
+        self
+    }
 }
 
 pub trait BlockIngestor: Daemon {}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockInfo {
     pub number: u64,
     pub status: BlockStatus,
     pub state_update: Option<StateUpdate>,
+    /// First block of the batch proven by a single SNOS run along with `number`, which is the last
+    /// one. `None` for a block proven on its own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_block: Option<u64>,
 }
@@ -1,26 +1,33 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use starknet::{
-    core::types::BlockId,
-    providers::{jsonrpc::HttpTransport, JsonRpcClient, Provider},
+    core::types::{BlockId, MaybePreConfirmedStateUpdate, StateUpdate},
+    providers::{JsonRpcClient, Provider},
 };
 use tokio::{
     sync::{
         mpsc::{self, Sender},
-        Mutex,
+        watch,
     },
-    task,
     time::sleep,
 };
-use tracing::{debug, error, trace};
-use url::Url;
+use tracing::{debug, error, info, trace, warn};
 
 use crate::{
     block_ingestor::{
+        batch_policy::{self, BatchPolicy, BlockResources, MaxBlocks},
+        filter::{BlockFilter, BlockMerger},
+        reorg::{self, ReorgError},
         BatchingBlockIngestorBuilder, BlockInfo, BlockIngestor, BlockIngestorBuilder,
     },
-    service::{Daemon, FinishHandle, ShutdownHandle},
+    health::HealthReporter,
+    metrics,
+    rpc::FailoverTransport,
+    service::{
+        Daemon, FinishHandle, PauseHandle, ShutdownHandle, WorkerHandle, WorkerPool,
+        WorkerPoolConfig,
+    },
     storage::{BlockStatus, PersistantStorage},
 };
 
@@ -28,33 +35,85 @@ const BLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(5);
 const TASK_BUFFER_SIZE: usize = 4;
 const MAX_RETRIES: usize = 3;
 
+/// Bounds on the delay between two checks of whether a pre-confirmed block has been confirmed.
+const CONFIRMATION_BASE_DELAY: Duration = Duration::from_secs(1);
+const CONFIRMATION_MAX_DELAY: Duration = Duration::from_secs(30);
+
 /// A block ingestor which collects new blocks by polling a Starknet RPC endpoint.
 ///
 /// Responsibilities:
 /// - Track the current block and advance it as the chain progresses.
-/// - Re-queue blocks that previously failed.
-/// - Fetch the `StateUpdate` for each block and store it in the DB.
-/// - Emit `BlockInfo { status: Mined }` downstream for further processing.
+/// - Stop advancing past the end block, if any.
+/// - Re-queue blocks that previously failed or that an operator asked to retry.
+/// - Stop dispatching new blocks while paused.
+/// - Fetch the `StateUpdate` for each block once confirmed and store it in the DB, recording
+///   blocks that can't be fetched as failed.
+/// - Check that each block extends its neighbours, halting on a rollup reorg.
+/// - Emit `BlockInfo { status: Mined }` downstream for further processing, in order and merged
+///   with the blocks held back before them if a [`BlockFilter`] is set.
 ///
 /// PIE generation is intentionally **not** done here. It is the responsibility
 /// of the next pipeline stage (e.g. `SnosPieGenerator`).
 #[derive(Debug)]
 pub struct PollingBlockIngestor<DB> {
-    rpc_url: Url,
+    provider: Arc<JsonRpcClient<FailoverTransport>>,
     current_block: u64,
+    end_block: Option<u64>,
     channel: Sender<BlockInfo>,
     finish_handle: FinishHandle,
     db: DB,
-    workers_count: usize,
+    workers: WorkerPoolConfig,
+    health_reporter: Option<HealthReporter>,
+    pause_handle: PauseHandle,
+    new_heads: Option<watch::Receiver<Option<u64>>>,
+    block_filter: Option<Box<dyn BlockFilter>>,
 }
 
 #[derive(Debug)]
 pub struct PollingBlockIngestorBuilder<DB> {
-    rpc_url: Url,
+    provider: Arc<JsonRpcClient<FailoverTransport>>,
     start_block: Option<u64>,
+    end_block: Option<u64>,
     channel: Option<Sender<BlockInfo>>,
     db: DB,
-    workers_count: usize,
+    workers: WorkerPoolConfig,
+    health_reporter: Option<HealthReporter>,
+    pause_handle: PauseHandle,
+    new_heads: Option<watch::Receiver<Option<u64>>>,
+    block_filter: Option<Box<dyn BlockFilter>>,
+}
+
+/// Fetches the state update of a block, waiting for the block to be confirmed if it's still
+/// pre-confirmed.
+///
+/// Returns `None` if a shutdown is requested while waiting.
+async fn fetch_state_update(
+    provider: &JsonRpcClient<FailoverTransport>,
+    block_number: u64,
+    finish_handle: &FinishHandle,
+) -> Result<Option<StateUpdate>> {
+    let mut delay = CONFIRMATION_BASE_DELAY;
+    loop {
+        let state_update = crate::utils::retry_with_backoff(
+            || provider.get_state_update(BlockId::Number(block_number)),
+            "get_state_update",
+            MAX_RETRIES as u32,
+            Duration::from_secs(5),
+        )
+        .await?;
+
+        match state_update {
+            MaybePreConfirmedStateUpdate::Update(state_update) => return Ok(Some(state_update)),
+            MaybePreConfirmedStateUpdate::PreConfirmedUpdate(_) => {
+                debug!(block_number, ?delay, "Block not confirmed yet, waiting");
+                tokio::select! {
+                    _ = finish_handle.shutdown_requested() => return Ok(None),
+                    _ = sleep(delay) => {}
+                }
+                delay = (delay * 2).min(CONFIRMATION_MAX_DELAY);
+            }
+        }
+    }
 }
 
 impl<DB> PollingBlockIngestor<DB>
@@ -62,10 +121,8 @@ where
     DB: PersistantStorage + Send + Sync + Clone + 'static,
 {
     async fn get_latest_block(&self) -> Option<u64> {
-        let provider = JsonRpcClient::new(HttpTransport::new(self.rpc_url.clone()));
-
         let block_number = crate::utils::retry_with_backoff(
-            || provider.block_number(),
+            || self.provider.block_number(),
             "get_latest_block",
             MAX_RETRIES as u32,
             Duration::from_secs(5),
@@ -73,7 +130,12 @@ where
         .await;
 
         match block_number {
-            Ok(block_number) => Some(block_number),
+            Ok(block_number) => {
+                if let Some(reporter) = &self.health_reporter {
+                    reporter.record_rollup_tip(block_number);
+                }
+                Some(block_number)
+            }
             Err(err) => {
                 error!(error = ?err, "Failed to fetch latest block");
                 None
@@ -81,19 +143,115 @@ where
         }
     }
 
+    /// Returns the latest block, as last published by the new heads subscription if any.
+    async fn latest_block(&mut self) -> Option<u64> {
+        let Some(new_heads) = &mut self.new_heads else {
+            return self.get_latest_block().await;
+        };
+
+        let latest_block = *new_heads.borrow_and_update();
+        if let (Some(block_number), Some(reporter)) = (latest_block, &self.health_reporter) {
+            reporter.record_rollup_tip(block_number);
+        }
+        latest_block
+    }
+
+    /// Waits for the new heads subscription to publish a new block, or for the next polling
+    /// interval, returning `false` if the ingestor should stop.
+    async fn wait_new_block(&mut self) -> bool {
+        let new_heads = self.new_heads.as_mut();
+        let subscription_closed = async move {
+            match new_heads {
+                Some(new_heads) => new_heads.changed().await.is_err(),
+                None => std::future::pending().await,
+            }
+        };
+
+        let subscription_closed = tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => return false,
+            _ = sleep(BLOCK_CHECK_INTERVAL) => false,
+            closed = subscription_closed => closed,
+        };
+
+        if subscription_closed {
+            warn!("New heads subscription closed, falling back to polling");
+            self.new_heads = None;
+        }
+        true
+    }
+
+    /// Checks whether `block_number` is within the range of blocks to ingest.
+    fn in_range(&self, block_number: u64) -> bool {
+        self.end_block
+            .is_none_or(|end_block| block_number <= end_block)
+    }
+
+    /// Waits for ingestion to be resumed if paused, returning `false` if the ingestor should stop.
+    async fn wait_resumed(&self) -> bool {
+        if !self.pause_handle.is_paused() {
+            return true;
+        }
+
+        info!(next_block = self.current_block, "Block ingestion paused");
+        tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => false,
+            _ = self.pause_handle.resumed() => {
+                info!(next_block = self.current_block, "Block ingestion resumed");
+                true
+            },
+        }
+    }
+
+    /// Queues a block for the workers, returning `false` if the ingestor should stop.
+    ///
+    /// Waiting on a shutdown request alongside the send makes sure the ingestor never gets stuck
+    /// on a full queue after its workers have exited.
+    async fn dispatch(&self, task_tx: &mpsc::Sender<u64>, block_number: u64) -> bool {
+        tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => false,
+            result = task_tx.send(block_number) => result.is_ok(),
+        }
+    }
+
+    /// Fetches, verifies and stores the state update of a block.
+    ///
+    /// Returns `None` if a shutdown is requested before the block is confirmed.
+    async fn ingest_block(
+        provider: &JsonRpcClient<FailoverTransport>,
+        db: &DB,
+        block_number: u64,
+        finish_handle: &FinishHandle,
+    ) -> Result<Option<StateUpdate>> {
+        let block_id: u32 = block_number.try_into()?;
+        db.initialize_block(block_id).await?;
+
+        let Some(state_update) = fetch_state_update(provider, block_number, finish_handle).await?
+        else {
+            return Ok(None);
+        };
+
+        let hashes = reorg::fetch_block_hashes(provider, block_number, &state_update).await?;
+        reorg::check_continuity(db, block_number, hashes).await?;
+
+        db.add_state_update(block_id, state_update.clone()).await?;
+        Ok(Some(state_update))
+    }
+
     /// Worker function: fetches the state update for a block and emits `BlockInfo { status: Mined }`.
+    ///
+    /// Blocks that can't be fetched are recorded as failed, for the ingestor to retry them.
     async fn worker(
-        task_rx: Arc<Mutex<mpsc::Receiver<u64>>>,
+        worker: WorkerHandle<u64>,
         finish_handle: FinishHandle,
-        rpc_url: Url,
+        provider: Arc<JsonRpcClient<FailoverTransport>>,
         channel: mpsc::Sender<BlockInfo>,
         db: DB,
     ) where
         DB: PersistantStorage + Send + Sync + 'static,
     {
         loop {
-            let block_number = if let Some(n) = task_rx.lock().await.recv().await {
-                n
+            let (block_number, _task) = if let Some(task) = worker.recv().await {
+                task
             } else {
                 break;
             };
@@ -102,32 +260,35 @@ where
                 break;
             }
 
-            db.initialize_block(block_number.try_into().unwrap())
-                .await
-                .unwrap();
-
-            let state_update = &JsonRpcClient::new(HttpTransport::new(rpc_url.clone()))
-                .get_state_update(BlockId::Number(block_number))
-                .await
-                .unwrap();
-
-            let state_update = match state_update {
-                starknet::core::types::MaybePreConfirmedStateUpdate::Update(u) => u,
-                starknet::core::types::MaybePreConfirmedStateUpdate::PreConfirmedUpdate(_) => {
-                    panic!("PreConfirmedStateUpdate not supported")
-                }
-            };
-
-            db.add_state_update(block_number.try_into().unwrap(), state_update.clone())
-                .await
-                .unwrap();
+            let state_update =
+                match Self::ingest_block(&provider, &db, block_number, &finish_handle).await {
+                    Ok(Some(state_update)) => state_update,
+                    Ok(None) => break,
+                    Err(err) if err.is::<ReorgError>() => {
+                        error!(block_number, error = %err, "Rollup reorg detected, halting");
+                        finish_handle.abort(format!("{err:#}"));
+                        break;
+                    }
+                    Err(err) => {
+                        error!(block_number, error = %err, "Failed to ingest block");
+                        let failed = match block_number.try_into() {
+                            Ok(block_id) => db.add_failed_block(block_id, format!("{err:#}")).await,
+                            Err(err) => Err(err.into()),
+                        };
+                        if let Err(err) = failed {
+                            error!(block_number, error = %err, "Failed to record failed block");
+                        }
+                        continue;
+                    }
+                };
 
             trace!(block_number, "Block mined, forwarding downstream");
 
             let new_block = BlockInfo {
                 number: block_number,
                 status: BlockStatus::Mined,
-                state_update: Some(state_update.clone()),
+                state_update: Some(state_update),
+                first_block: None,
             };
 
             if channel.send(new_block).await.is_err() {
@@ -139,67 +300,173 @@ where
     /// Continuously fetches the latest available block and sends it to the worker queue.
     async fn run(mut self) {
         let (task_tx, task_rx) = mpsc::channel(TASK_BUFFER_SIZE);
-        let mut workers = Vec::new();
-        let task_rx = Arc::new(Mutex::new(task_rx));
-
-        for _ in 0..self.workers_count {
-            let worker_task_rx = task_rx.clone();
-            let finish_handle = self.finish_handle.clone();
-            let rpc_url = self.rpc_url.clone();
-            let channel = self.channel.clone();
-
-            workers.push(task::spawn(Self::worker(
-                worker_task_rx,
-                finish_handle,
-                rpc_url,
-                channel,
-                self.db.clone(),
-            )));
-        }
+        metrics::observe_channel("ingestor_tasks", &task_tx);
 
-        while !self.finish_handle.is_shutdown_requested() {
-            match self.get_latest_block().await {
-                Some(latest_block) if latest_block >= self.current_block => {
-                    if let Ok(mut failed_blocks) = self.db.get_failed_blocks().await {
-                        let block_ids: Vec<u32> = failed_blocks.iter().map(|(id, _)| *id).collect();
-                        for (block_id, _) in failed_blocks.drain(..) {
-                            if task_tx.send(block_id as u64).await.is_err() {
-                                return;
-                            }
-                        }
-                        self.db
-                            .mark_failed_blocks_as_handled(&block_ids)
-                            .await
-                            .unwrap();
+        let pool = WorkerPool::new(
+            "ingestor",
+            task_rx,
+            self.workers,
+            self.finish_handle.clone(),
+        );
+        // Workers complete blocks out of order, so filtered blocks go through a single task
+        // merging them in order.
+        let (channel, merging) = match self.block_filter.take() {
+            Some(filter) => {
+                let (block_tx, block_rx) = mpsc::channel(TASK_BUFFER_SIZE);
+                let merger = BlockMerger::new(filter, self.end_block);
+                let merging = tokio::spawn(Self::merge_blocks(
+                    block_rx,
+                    self.channel.clone(),
+                    merger,
+                    self.current_block,
+                ));
+                (block_tx, Some(merging))
+            }
+            None => (self.channel.clone(), None),
+        };
+
+        let finish_handle = self.finish_handle.clone();
+        let provider = self.provider.clone();
+        let db = self.db.clone();
+        let workers = tokio::spawn(pool.run(move |worker| {
+            Self::worker(
+                worker,
+                finish_handle.clone(),
+                provider.clone(),
+                channel.clone(),
+                db.clone(),
+            )
+        }));
+
+        'ingest: while !self.finish_handle.is_shutdown_requested() {
+            if !self.wait_resumed().await {
+                break;
+            }
+
+            // Failed blocks are re-queued even when no new block is available so that retries
+            // requested by an operator are picked up on an idle chain. They're marked as handled
+            // before being dispatched, as workers may record them as failed again right away.
+            if let Ok(mut failed_blocks) = self.db.get_failed_blocks().await {
+                let block_ids: Vec<u32> = failed_blocks.iter().map(|(id, _)| *id).collect();
+                if let Err(err) = self.db.mark_failed_blocks_as_handled(&block_ids).await {
+                    error!(error = %err, "Failed to mark failed blocks as handled");
+                }
+                for (block_id, _) in failed_blocks.drain(..) {
+                    if !self.dispatch(&task_tx, block_id as u64).await {
+                        break 'ingest;
                     }
-                    if task_tx.send(self.current_block).await.is_err() {
-                        return;
+                }
+            }
+
+            match self.latest_block().await {
+                Some(latest_block)
+                    if latest_block >= self.current_block && self.in_range(self.current_block) =>
+                {
+                    if !self.dispatch(&task_tx, self.current_block).await {
+                        break;
+                    }
+                    if self.end_block == Some(self.current_block) {
+                        info!(
+                            end_block = self.current_block,
+                            "Last block of the range dispatched"
+                        );
                     }
                     self.current_block += 1;
                 }
                 _ => {
-                    sleep(BLOCK_CHECK_INTERVAL).await;
+                    if !self.wait_new_block().await {
+                        break;
+                    }
                 }
             }
         }
 
         drop(task_tx);
-        futures_util::future::join_all(workers).await;
+        let _ = workers.await;
+        if let Some(merging) = merging {
+            let _ = merging.await;
+        }
         debug!("Graceful shutdown finished");
         self.finish_handle.finish();
     }
+
+    /// Reorders the blocks emitted by the workers and merges them through `merger`, until the
+    /// workers have exited.
+    ///
+    /// Blocks behind `next_block` are blocks retried after being emitted, which are forwarded as
+    /// is.
+    async fn merge_blocks(
+        mut blocks: mpsc::Receiver<BlockInfo>,
+        channel: mpsc::Sender<BlockInfo>,
+        mut merger: BlockMerger,
+        mut next_block: u64,
+    ) {
+        let mut pending = BTreeMap::new();
+
+        while let Some(block) = blocks.recv().await {
+            let mut ready = vec![];
+            if block.number < next_block {
+                ready.push(block);
+            } else {
+                pending.insert(block.number, block);
+                while let Some(block) = pending.remove(&next_block) {
+                    next_block += 1;
+                    ready.extend(merger.merge(block));
+                }
+            }
+
+            for block in ready {
+                let block_number = block.number;
+                if channel.send(block).await.is_err() {
+                    error!(block_number, "Failed to send block");
+                }
+            }
+        }
+    }
 }
 
 impl<DB> PollingBlockIngestorBuilder<DB> {
-    pub fn new(rpc_url: Url, db: DB, workers_count: usize) -> Self {
+    /// Creates a builder fetching blocks through `rpc`, with a single client shared by all the
+    /// workers.
+    pub fn new(rpc: FailoverTransport, db: DB, workers: WorkerPoolConfig) -> Self {
+        Self::with_provider(Arc::new(JsonRpcClient::new(rpc)), db, workers)
+    }
+
+    /// Creates a builder fetching blocks through an existing `provider`.
+    pub(super) fn with_provider(
+        provider: Arc<JsonRpcClient<FailoverTransport>>,
+        db: DB,
+        workers: WorkerPoolConfig,
+    ) -> Self {
         Self {
-            rpc_url,
+            provider,
             start_block: None,
+            end_block: None,
             channel: None,
             db,
-            workers_count,
+            workers,
+            health_reporter: None,
+            pause_handle: PauseHandle::new(),
+            new_heads: None,
+            block_filter: None,
         }
     }
+
+    /// Holds back the blocks `filter` doesn't keep, emitting them along with the next kept block.
+    pub fn block_filter<F>(mut self, filter: F) -> Self
+    where
+        F: BlockFilter + 'static,
+    {
+        self.block_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Makes the ingestor follow the block numbers published to `new_heads` instead of polling
+    /// the latest block, until the sender is dropped.
+    pub(super) fn new_heads(mut self, new_heads: watch::Receiver<Option<u64>>) -> Self {
+        self.new_heads = Some(new_heads);
+        self
+    }
 }
 
 impl<DB> BlockIngestorBuilder for PollingBlockIngestorBuilder<DB>
@@ -210,16 +477,21 @@ where
 
     fn build(self) -> Result<Self::Ingestor> {
         Ok(PollingBlockIngestor {
-            rpc_url: self.rpc_url,
+            provider: self.provider,
             db: self.db,
             current_block: self
                 .start_block
                 .ok_or_else(|| anyhow::anyhow!("`start_block` not set"))?,
+            end_block: self.end_block,
             channel: self
                 .channel
                 .ok_or_else(|| anyhow::anyhow!("`channel` not set"))?,
             finish_handle: FinishHandle::new(),
-            workers_count: self.workers_count,
+            workers: self.workers,
+            health_reporter: self.health_reporter,
+            pause_handle: self.pause_handle,
+            new_heads: self.new_heads,
+            block_filter: self.block_filter,
         })
     }
 
@@ -228,10 +500,25 @@ where
         self
     }
 
+    fn end_block(mut self, end_block: u64) -> Self {
+        self.end_block = Some(end_block);
+        self
+    }
+
     fn channel(mut self, channel: Sender<BlockInfo>) -> Self {
         self.channel = Some(channel);
         self
     }
+
+    fn health_reporter(mut self, reporter: HealthReporter) -> Self {
+        self.health_reporter = Some(reporter);
+        self
+    }
+
+    fn pause_handle(mut self, pause_handle: PauseHandle) -> Self {
+        self.pause_handle = pause_handle;
+        self
+    }
 }
 
 impl<DB> BlockIngestor for PollingBlockIngestor<DB> where
@@ -248,7 +535,7 @@ where
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
 
@@ -259,39 +546,89 @@ where
 /// Blocks are fetched sequentially, so batches are always contiguous and in order — no
 /// downstream reordering stage is needed.  A batch is emitted when either:
 /// - `batch_size` blocks have accumulated, or
+/// - any of the additional [`BatchPolicy`]s closes it, or
 /// - `idle_timeout` elapses without a new block becoming available on-chain.
+///
+/// Ingestion halts with a failure as soon as a fetched block doesn't extend the blocks fetched
+/// before it.
 #[derive(Debug)]
 pub struct BatchingPollingBlockIngestor<DB> {
-    rpc_url: Url,
+    provider: Arc<JsonRpcClient<FailoverTransport>>,
     current_block: u64,
+    end_block: Option<u64>,
     channel: tokio::sync::mpsc::Sender<Vec<BlockInfo>>,
     finish_handle: FinishHandle,
     db: DB,
-    batch_size: usize,
+    batch_policies: Vec<Box<dyn BatchPolicy>>,
     idle_timeout: Duration,
+    health_reporter: Option<HealthReporter>,
+    pause_handle: PauseHandle,
+}
+
+/// Blocks accumulated for the next batch, along with their resources.
+#[derive(Debug, Default)]
+struct PendingBatch {
+    blocks: Vec<BlockInfo>,
+    resources: Vec<BlockResources>,
 }
 
 #[derive(Debug)]
 pub struct BatchingPollingBlockIngestorBuilder<DB> {
-    rpc_url: Url,
+    provider: Arc<JsonRpcClient<FailoverTransport>>,
     start_block: Option<u64>,
+    end_block: Option<u64>,
     channel: Option<tokio::sync::mpsc::Sender<Vec<BlockInfo>>>,
     db: DB,
-    batch_size: usize,
+    batch_policies: Vec<Box<dyn BatchPolicy>>,
     idle_timeout: Duration,
+    health_reporter: Option<HealthReporter>,
+    pause_handle: PauseHandle,
+}
+
+impl PendingBatch {
+    fn push(&mut self, block: BlockInfo, resources: BlockResources) {
+        self.blocks.push(block);
+        self.resources.push(resources);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Takes the blocks out of the batch, leaving it empty.
+    fn take(&mut self) -> Vec<BlockInfo> {
+        self.resources.clear();
+        std::mem::take(&mut self.blocks)
+    }
 }
 
 impl<DB> BatchingPollingBlockIngestorBuilder<DB> {
-    pub fn new(rpc_url: Url, db: DB, batch_size: usize, idle_timeout: Duration) -> Self {
+    pub fn new(rpc: FailoverTransport, db: DB, batch_size: usize, idle_timeout: Duration) -> Self {
         Self {
-            rpc_url,
+            provider: Arc::new(JsonRpcClient::new(rpc)),
             start_block: None,
+            end_block: None,
             channel: None,
             db,
-            batch_size,
+            batch_policies: vec![Box::new(MaxBlocks(batch_size))],
             idle_timeout,
+            health_reporter: None,
+            pause_handle: PauseHandle::new(),
         }
     }
+
+    /// Adds a policy closing batches before `batch_size` blocks have accumulated.
+    pub fn batch_policy<P>(mut self, policy: P) -> Self
+    where
+        P: BatchPolicy + 'static,
+    {
+        self.batch_policies.push(Box::new(policy));
+        self
+    }
 }
 
 impl<DB> BatchingBlockIngestorBuilder for BatchingPollingBlockIngestorBuilder<DB>
@@ -302,17 +639,20 @@ where
 
     fn build(self) -> Result<Self::Ingestor> {
         Ok(BatchingPollingBlockIngestor {
-            rpc_url: self.rpc_url,
+            provider: self.provider,
             db: self.db,
             current_block: self
                 .start_block
                 .ok_or_else(|| anyhow::anyhow!("`start_block` not set"))?,
+            end_block: self.end_block,
             channel: self
                 .channel
                 .ok_or_else(|| anyhow::anyhow!("`channel` not set"))?,
             finish_handle: FinishHandle::new(),
-            batch_size: self.batch_size,
+            batch_policies: self.batch_policies,
             idle_timeout: self.idle_timeout,
+            health_reporter: self.health_reporter,
+            pause_handle: self.pause_handle,
         })
     }
 
@@ -321,10 +661,25 @@ where
         self
     }
 
+    fn end_block(mut self, end_block: u64) -> Self {
+        self.end_block = Some(end_block);
+        self
+    }
+
     fn channel(mut self, channel: tokio::sync::mpsc::Sender<Vec<BlockInfo>>) -> Self {
         self.channel = Some(channel);
         self
     }
+
+    fn health_reporter(mut self, reporter: HealthReporter) -> Self {
+        self.health_reporter = Some(reporter);
+        self
+    }
+
+    fn pause_handle(mut self, pause_handle: PauseHandle) -> Self {
+        self.pause_handle = pause_handle;
+        self
+    }
 }
 
 impl<DB> BlockIngestor for BatchingPollingBlockIngestor<DB> where
@@ -341,7 +696,7 @@ where
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
 
@@ -350,10 +705,8 @@ where
     DB: PersistantStorage + Send + Sync + Clone + 'static,
 {
     async fn get_latest_block(&self) -> Option<u64> {
-        let provider = JsonRpcClient::new(HttpTransport::new(self.rpc_url.clone()));
-
         let block_number = crate::utils::retry_with_backoff(
-            || provider.block_number(),
+            || self.provider.block_number(),
             "get_latest_block",
             MAX_RETRIES as u32,
             Duration::from_secs(5),
@@ -361,7 +714,12 @@ where
         .await;
 
         match block_number {
-            Ok(n) => Some(n),
+            Ok(n) => {
+                if let Some(reporter) = &self.health_reporter {
+                    reporter.record_rollup_tip(n);
+                }
+                Some(n)
+            }
             Err(err) => {
                 error!(error = ?err, "Failed to fetch latest block");
                 None
@@ -369,43 +727,82 @@ where
         }
     }
 
-    /// Fetches the state update for `block_number`, stores it in the DB, and returns a
-    /// [`BlockInfo`].  Returns an error if the RPC call fails after retries.
-    async fn fetch_block(&self, block_number: u64) -> Result<BlockInfo> {
-        let provider = JsonRpcClient::new(HttpTransport::new(self.rpc_url.clone()));
+    /// Checks whether `block_number` is within the range of blocks to ingest.
+    fn in_range(&self, block_number: u64) -> bool {
+        self.end_block
+            .is_none_or(|end_block| block_number <= end_block)
+    }
+
+    /// Waits for ingestion to be resumed if paused, returning `false` if the ingestor should stop.
+    async fn wait_resumed(&self) -> bool {
+        if !self.pause_handle.is_paused() {
+            return true;
+        }
+
+        info!(next_block = self.current_block, "Block ingestion paused");
+        tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => false,
+            _ = self.pause_handle.resumed() => {
+                info!(next_block = self.current_block, "Block ingestion resumed");
+                true
+            },
+        }
+    }
+
+    /// Checks whether any of the batch policies closes the pending batch.
+    fn should_close(&self, pending: &PendingBatch) -> bool {
+        self.batch_policies
+            .iter()
+            .any(|policy| policy.should_close(&pending.resources))
+    }
+
+    /// Fetches the state update for `block_number` once confirmed, stores it in the DB, and
+    /// returns a [`BlockInfo`] along with the block resources.  Returns `None` if a shutdown is
+    /// requested before the block is confirmed, an error if the RPC call fails after retries, or
+    /// a [`ReorgError`] if the block doesn't extend the blocks fetched so far.
+    async fn fetch_block(&self, block_number: u64) -> Result<Option<(BlockInfo, BlockResources)>> {
+        let provider = &*self.provider;
 
         self.db.initialize_block(block_number.try_into()?).await?;
 
-        let state_update = crate::utils::retry_with_backoff(
-            || provider.get_state_update(BlockId::Number(block_number)),
-            "get_state_update",
-            MAX_RETRIES as u32,
-            Duration::from_secs(5),
+        let Some(state_update) =
+            fetch_state_update(provider, block_number, &self.finish_handle).await?
+        else {
+            return Ok(None);
+        };
+
+        let hashes = reorg::fetch_block_hashes(provider, block_number, &state_update).await?;
+        reorg::check_continuity(&self.db, block_number, hashes).await?;
+
+        let with_receipts = self
+            .batch_policies
+            .iter()
+            .any(|policy| policy.needs_receipts());
+        let resources = batch_policy::fetch_block_resources(
+            provider,
+            block_number,
+            &state_update,
+            with_receipts,
         )
         .await?;
 
-        let state_update = match state_update {
-            starknet::core::types::MaybePreConfirmedStateUpdate::Update(u) => u,
-            starknet::core::types::MaybePreConfirmedStateUpdate::PreConfirmedUpdate(_) => {
-                anyhow::bail!("PreConfirmedStateUpdate not supported for block {block_number}")
-            }
-        };
-
         self.db
             .add_state_update(block_number.try_into()?, state_update.clone())
             .await?;
 
         trace!(block_number, "Block fetched, buffering for next batch");
 
-        Ok(BlockInfo {
+        let info = BlockInfo {
             number: block_number,
             status: BlockStatus::Mined,
             state_update: Some(state_update),
-        })
+            first_block: None,
+        };
+        Ok(Some((info, resources)))
     }
 
     async fn run(mut self) {
-        let mut pending: Vec<BlockInfo> = Vec::new();
+        let mut pending = PendingBatch::default();
         let mut idle_deadline = tokio::time::Instant::now() + self.idle_timeout;
 
         'outer: loop {
@@ -413,22 +810,32 @@ where
                 break;
             }
 
+            if !self.wait_resumed().await {
+                break;
+            }
+
             // Re-queue blocks that failed in a previous run.
             match self.db.get_failed_blocks().await {
                 Ok(failed_blocks) if !failed_blocks.is_empty() => {
                     let block_ids: Vec<u32> = failed_blocks.iter().map(|(id, _)| *id).collect();
                     for (block_id, _) in failed_blocks {
                         match self.fetch_block(block_id as u64).await {
-                            Ok(info) => {
+                            Ok(Some((info, resources))) => {
                                 idle_deadline = tokio::time::Instant::now() + self.idle_timeout;
-                                pending.push(info);
-                                if pending.len() >= self.batch_size {
-                                    let batch = std::mem::take(&mut pending);
+                                pending.push(info, resources);
+                                if self.should_close(&pending) {
+                                    let batch = pending.take();
                                     if self.channel.send(batch).await.is_err() {
                                         break 'outer;
                                     }
                                 }
                             }
+                            Ok(None) => break 'outer,
+                            Err(e) if e.is::<ReorgError>() => {
+                                error!(block_id, error = %e, "Rollup reorg detected, halting");
+                                self.finish_handle.fail(format!("{e:#}"));
+                                break 'outer;
+                            }
                             Err(e) => {
                                 error!(block_id, error = %e, "Failed to re-fetch failed block");
                             }
@@ -454,20 +861,36 @@ where
                 }
             };
 
-            if latest >= self.current_block {
+            if latest >= self.current_block && self.in_range(self.current_block) {
                 match self.fetch_block(self.current_block).await {
-                    Ok(info) => {
+                    Ok(Some((info, resources))) => {
                         idle_deadline = tokio::time::Instant::now() + self.idle_timeout;
-                        pending.push(info);
+                        pending.push(info, resources);
+                        if self.end_block == Some(self.current_block) {
+                            info!(
+                                end_block = self.current_block,
+                                "Last block of the range fetched"
+                            );
+                        }
                         self.current_block += 1;
 
-                        if pending.len() >= self.batch_size {
-                            let batch = std::mem::take(&mut pending);
+                        if self.should_close(&pending) {
+                            let batch = pending.take();
                             if self.channel.send(batch).await.is_err() {
                                 break 'outer;
                             }
                         }
                     }
+                    Ok(None) => break 'outer,
+                    Err(e) if e.is::<ReorgError>() => {
+                        error!(
+                            block_number = self.current_block,
+                            error = %e,
+                            "Rollup reorg detected, halting"
+                        );
+                        self.finish_handle.fail(format!("{e:#}"));
+                        break 'outer;
+                    }
                     Err(e) => {
                         error!(
                             block_number = self.current_block,
@@ -480,6 +903,13 @@ where
                         }
                     }
                 }
+            } else if !self.in_range(self.current_block) && !pending.is_empty() {
+                // No more blocks are coming within the range, so there's no point in waiting for
+                // the idle timeout.
+                let batch = pending.take();
+                if self.channel.send(batch).await.is_err() {
+                    break 'outer;
+                }
             } else {
                 tokio::select! {
                     _ = self.finish_handle.shutdown_requested() => break 'outer,
@@ -487,7 +917,7 @@ where
                     _ = tokio::time::sleep_until(idle_deadline) => {
                         if !pending.is_empty() {
                             debug!(count = pending.len(), "Idle timeout — flushing partial batch");
-                            let batch = std::mem::take(&mut pending);
+                            let batch = pending.take();
                             if self.channel.send(batch).await.is_err() {
                                 break 'outer;
                             }
@@ -500,10 +930,183 @@ where
 
         // Flush any blocks accumulated before shutdown.
         if !pending.is_empty() {
-            let _ = self.channel.send(pending).await;
+            let _ = self.channel.send(pending.take()).await;
         }
 
         debug!("BatchingPollingBlockIngestor graceful shutdown finished");
         self.finish_handle.finish();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use starknet_types_core::felt::Felt;
+    use url::Url;
+
+    use super::*;
+    use crate::{
+        block_ingestor::mock_node::{start_node, MockChain},
+        storage::SqliteDb,
+    };
+
+    fn start_ingestor(
+        url: Url,
+        db: SqliteDb,
+        start_block: u64,
+        end_block: Option<u64>,
+    ) -> (mpsc::Receiver<BlockInfo>, ShutdownHandle) {
+        let (tx, rx) = mpsc::channel(8);
+        let mut builder = PollingBlockIngestorBuilder::new(
+            FailoverTransport::new([url]).unwrap(),
+            db,
+            WorkerPoolConfig::fixed(1),
+        )
+        .start_block(start_block)
+        .channel(tx);
+        if let Some(end_block) = end_block {
+            builder = builder.end_block(end_block);
+        }
+
+        let ingestor = builder.build().unwrap();
+        let shutdown_handle = ingestor.shutdown_handle();
+        ingestor.start();
+        (rx, shutdown_handle)
+    }
+
+    #[tokio::test]
+    async fn test_stops_at_end_block_and_retries_failed_blocks() {
+        let chain = MockChain::new(5);
+        // Block 2 fails on every attempt of its first fetch.
+        chain.fail(2, MAX_RETRIES);
+        let (url, _node) = start_node(chain.clone()).await;
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let (mut rx, shutdown_handle) = start_ingestor(url, db.clone(), 1, Some(2));
+
+        assert_eq!(rx.recv().await.unwrap().number, 1);
+        assert_eq!(rx.recv().await.unwrap().number, 2);
+        assert_eq!(chain.requests(2), MAX_RETRIES + 1);
+
+        // Blocks past the end block are never fetched, even though the chain has them.
+        assert_eq!(chain.requests(3), 0);
+        assert!(rx.try_recv().is_err());
+        assert!(db.get_status(3).await.is_err());
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+    }
+
+    #[tokio::test]
+    async fn test_halts_on_reorg() {
+        let chain = MockChain::new(1);
+        let (url, _node) = start_node(chain.clone()).await;
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let (mut rx, shutdown_handle) = start_ingestor(url, db.clone(), 1, None);
+
+        assert_eq!(rx.recv().await.unwrap().number, 1);
+
+        // Block 2 is produced on top of a replacement of block 1.
+        chain.reorg(1);
+        chain.set_latest_block(2);
+
+        shutdown_handle.finished().await;
+        assert!(shutdown_handle.failure().unwrap().contains("reorg"));
+        assert!(db.get_failed_blocks().await.unwrap().is_empty());
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_waits_for_pre_confirmed_blocks() {
+        let chain = MockChain::new(1);
+        chain.pre_confirm(1, 1);
+        let (url, _node) = start_node(chain.clone()).await;
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let (mut rx, shutdown_handle) = start_ingestor(url, db.clone(), 1, None);
+
+        let block = rx.recv().await.unwrap();
+        assert_eq!(block.number, 1);
+        assert_eq!(block.state_update.unwrap().block_hash, Felt::from(0x1001));
+        assert_eq!(chain.requests(1), 2);
+        assert_eq!(
+            db.get_state_update(1).await.unwrap().block_hash,
+            Felt::from(0x1001)
+        );
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+    }
+
+    #[tokio::test]
+    async fn test_records_blocks_failing_to_be_fetched() {
+        let chain = MockChain::new(1);
+        chain.fail(1, MAX_RETRIES);
+        let (url, _node) = start_node(chain.clone()).await;
+        let provider = Arc::new(JsonRpcClient::new(FailoverTransport::new([url]).unwrap()));
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let (block_tx, mut block_rx) = mpsc::channel(1);
+        let (task_tx, task_rx) = mpsc::channel(1);
+        let finish_handle = FinishHandle::new();
+
+        let pool = WorkerPool::new(
+            "ingestor",
+            task_rx,
+            WorkerPoolConfig::fixed(1),
+            finish_handle.clone(),
+        );
+        let workers = tokio::spawn(pool.run({
+            let db = db.clone();
+            move |worker| {
+                PollingBlockIngestor::worker(
+                    worker,
+                    finish_handle.clone(),
+                    provider.clone(),
+                    block_tx.clone(),
+                    db.clone(),
+                )
+            }
+        }));
+
+        task_tx.send(1).await.unwrap();
+        drop(task_tx);
+        workers.await.unwrap();
+
+        let failed_blocks = db.get_failed_blocks().await.unwrap();
+        assert_eq!(failed_blocks.len(), 1);
+        assert_eq!(failed_blocks[0].0, 1);
+        assert!(block_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_batching_waits_for_pre_confirmed_blocks() {
+        let chain = MockChain::new(1);
+        chain.pre_confirm(1, 1);
+        let (url, _node) = start_node(chain.clone()).await;
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let (tx, mut rx) = mpsc::channel(8);
+        let ingestor = BatchingPollingBlockIngestorBuilder::new(
+            FailoverTransport::new([url]).unwrap(),
+            db.clone(),
+            1,
+            Duration::from_secs(60),
+        )
+        .start_block(1)
+        .channel(tx)
+        .build()
+        .unwrap();
+        let shutdown_handle = ingestor.shutdown_handle();
+        ingestor.start();
+
+        let batch = rx.recv().await.unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].number, 1);
+        assert_eq!(
+            batch[0].state_update.as_ref().unwrap().block_hash,
+            Felt::from(0x1001)
+        );
+        assert_eq!(chain.requests(1), 2);
+        assert!(db.get_failed_blocks().await.unwrap().is_empty());
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+        assert_eq!(shutdown_handle.failure(), None);
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::Result;
+use starknet::{
+    core::types::{BlockId, MaybePreConfirmedBlockWithTxHashes, StateUpdate},
+    providers::Provider,
+};
+
+use crate::storage::{BlockHashes, PersistantStorage};
+
+/// Error raised when an ingested block doesn't extend the blocks ingested before it, i.e. the
+/// rollup chain has been reorganized under the pipeline.
+///
+/// There's no way of telling which of the blocks in flight are still valid, so ingestion must be
+/// halted for an operator to look into it.
+#[derive(Debug, thiserror::Error)]
+#[error("rollup reorg detected at block {block_number}: {reason}")]
+pub struct ReorgError {
+    pub block_number: u64,
+    reason: String,
+}
+
+impl ReorgError {
+    fn new<R: Into<String>>(block_number: u64, reason: R) -> Self {
+        Self {
+            block_number,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Fetches the hashes of `block_number`, making sure `state_update` belongs to that same block.
+pub(super) async fn fetch_block_hashes<P>(
+    provider: &P,
+    block_number: u64,
+    state_update: &StateUpdate,
+) -> Result<BlockHashes>
+where
+    P: Provider + Sync,
+{
+    let block = match provider
+        .get_block_with_tx_hashes(BlockId::Number(block_number))
+        .await?
+    {
+        MaybePreConfirmedBlockWithTxHashes::Block(block) => block,
+        MaybePreConfirmedBlockWithTxHashes::PreConfirmedBlock(_) => {
+            anyhow::bail!("block {block_number} is not confirmed yet")
+        }
+    };
+
+    // The block may have been replaced in between the two requests.
+    if block.block_hash != state_update.block_hash {
+        return Err(ReorgError::new(
+            block_number,
+            format!(
+                "state update is for block {:#x} instead of {:#x}",
+                state_update.block_hash, block.block_hash
+            ),
+        )
+        .into());
+    }
+
+    Ok(BlockHashes {
+        block_hash: block.block_hash,
+        parent_hash: block.parent_hash,
+    })
+}
+
+/// Records the hashes of `block_number`, checking that the block is linked to its parent and its
+/// child, if already ingested.
+///
+/// Checking both sides makes sure blocks ingested out of order are verified too. Hashes are
+/// recorded before being checked so that of two neighbouring blocks ingested concurrently, at
+/// least the last one sees the other.
+pub(super) async fn check_continuity<DB>(
+    db: &DB,
+    block_number: u64,
+    hashes: BlockHashes,
+) -> Result<()>
+where
+    DB: PersistantStorage,
+{
+    let block_id: u32 = block_number.try_into()?;
+
+    db.add_block_hashes(block_id, hashes).await?;
+    if let Some(recorded) = db.get_block_hashes(block_id).await? {
+        if recorded.block_hash != hashes.block_hash {
+            return Err(ReorgError::new(
+                block_number,
+                format!(
+                    "block hash changed from {:#x} to {:#x}",
+                    recorded.block_hash, hashes.block_hash
+                ),
+            )
+            .into());
+        }
+    }
+
+    if let Some(parent_id) = block_id.checked_sub(1) {
+        if let Some(parent) = db.get_block_hashes(parent_id).await? {
+            if parent.block_hash != hashes.parent_hash {
+                return Err(ReorgError::new(
+                    block_number,
+                    format!(
+                        "parent hash {:#x} doesn't match the hash {:#x} of block {}",
+                        hashes.parent_hash, parent.block_hash, parent_id
+                    ),
+                )
+                .into());
+            }
+        }
+    }
+
+    if let Some(child) = db.get_block_hashes(block_id + 1).await? {
+        if child.parent_hash != hashes.block_hash {
+            return Err(ReorgError::new(
+                block_number,
+                format!(
+                    "block hash {:#x} doesn't match the parent hash {:#x} of block {}",
+                    hashes.block_hash,
+                    child.parent_hash,
+                    block_id + 1
+                ),
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use starknet_types_core::felt::Felt;
+
+    use super::*;
+    use crate::storage::SqliteDb;
+
+    fn hashes(block_hash: u64, parent_hash: u64) -> BlockHashes {
+        BlockHashes {
+            block_hash: Felt::from(block_hash),
+            parent_hash: Felt::from(parent_hash),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_continuity_detects_broken_links() {
+        let db = SqliteDb::new(":memory:").await.unwrap();
+
+        // Blocks ingested out of order are checked against both neighbours.
+        check_continuity(&db, 1, hashes(10, 0)).await.unwrap();
+        check_continuity(&db, 3, hashes(30, 20)).await.unwrap();
+        check_continuity(&db, 2, hashes(20, 10)).await.unwrap();
+        check_continuity(&db, 2, hashes(20, 10)).await.unwrap();
+
+        let err = check_continuity(&db, 4, hashes(40, 31)).await.unwrap_err();
+        assert_eq!(err.downcast_ref::<ReorgError>().unwrap().block_number, 4);
+
+        let err = check_continuity(&db, 2, hashes(21, 10)).await.unwrap_err();
+        assert!(err.is::<ReorgError>());
+    }
+}
@@ -0,0 +1,354 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Result;
+use jsonrpsee::{
+    core::client::{ClientT, Subscription, SubscriptionClientT},
+    rpc_params,
+    ws_client::{PingConfig, WsClient, WsClientBuilder},
+};
+use serde::Deserialize;
+use starknet::providers::{JsonRpcClient, Provider};
+use tokio::{
+    sync::{mpsc::Sender, watch},
+    time::sleep,
+};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::{
+    block_ingestor::{
+        BlockFilter, BlockInfo, BlockIngestor, BlockIngestorBuilder, PollingBlockIngestor,
+        PollingBlockIngestorBuilder,
+    },
+    health::HealthReporter,
+    rpc::FailoverTransport,
+    service::{Daemon, PauseHandle, ShutdownHandle, WorkerPoolConfig},
+    storage::PersistantStorage,
+};
+
+/// Interval between two attempts at subscribing to new heads, during which the latest block is
+/// polled instead.
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(5);
+
+const SUBSCRIBE_NEW_HEADS: &str = "starknet_subscribeNewHeads";
+const NEW_HEADS_NOTIFICATION: &str = "starknet_subscriptionNewHeads";
+
+/// A block ingestor which learns about new blocks through the `starknet_subscribeNewHeads`
+/// WebSocket API, instead of polling the latest block.
+///
+/// Blocks are fetched and emitted the same way as [`PollingBlockIngestor`] does. Whenever the
+/// subscription can't be established or gets disconnected, the latest block is polled over HTTP
+/// until subscribing succeeds again.
+#[derive(Debug)]
+pub struct SubscriptionBlockIngestor<DB> {
+    ingestor: PollingBlockIngestor<DB>,
+    subscriber: NewHeadsSubscriber,
+}
+
+#[derive(Debug)]
+pub struct SubscriptionBlockIngestorBuilder<DB> {
+    builder: PollingBlockIngestorBuilder<DB>,
+    provider: Arc<JsonRpcClient<FailoverTransport>>,
+    ws_url: Url,
+}
+
+/// Publishes the latest block number to the ingestor for as long as the ingestor is alive.
+#[derive(Debug)]
+struct NewHeadsSubscriber {
+    provider: Arc<JsonRpcClient<FailoverTransport>>,
+    ws_url: Url,
+    new_heads: watch::Sender<Option<u64>>,
+}
+
+#[derive(Deserialize)]
+struct NewHeadsNotification {
+    result: NewHead,
+}
+
+#[derive(Deserialize)]
+struct NewHead {
+    block_number: u64,
+}
+
+impl<DB> SubscriptionBlockIngestorBuilder<DB> {
+    /// Creates a builder fetching blocks through `rpc` and subscribing to new heads at `ws_url`.
+    pub fn new(rpc: FailoverTransport, ws_url: Url, db: DB, workers: WorkerPoolConfig) -> Self {
+        let provider = Arc::new(JsonRpcClient::new(rpc));
+
+        Self {
+            builder: PollingBlockIngestorBuilder::with_provider(provider.clone(), db, workers),
+            provider,
+            ws_url,
+        }
+    }
+
+    /// Holds back the blocks `filter` doesn't keep, emitting them along with the next kept block.
+    pub fn block_filter<F>(mut self, filter: F) -> Self
+    where
+        F: BlockFilter + 'static,
+    {
+        self.builder = self.builder.block_filter(filter);
+        self
+    }
+}
+
+impl<DB> BlockIngestorBuilder for SubscriptionBlockIngestorBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    type Ingestor = SubscriptionBlockIngestor<DB>;
+
+    fn build(self) -> Result<Self::Ingestor> {
+        let (new_heads_tx, new_heads_rx) = watch::channel(None);
+
+        Ok(SubscriptionBlockIngestor {
+            ingestor: self.builder.new_heads(new_heads_rx).build()?,
+            subscriber: NewHeadsSubscriber {
+                provider: self.provider,
+                ws_url: self.ws_url,
+                new_heads: new_heads_tx,
+            },
+        })
+    }
+
+    fn start_block(mut self, start_block: u64) -> Self {
+        self.builder = self.builder.start_block(start_block);
+        self
+    }
+
+    fn end_block(mut self, end_block: u64) -> Self {
+        self.builder = self.builder.end_block(end_block);
+        self
+    }
+
+    fn channel(mut self, channel: Sender<BlockInfo>) -> Self {
+        self.builder = self.builder.channel(channel);
+        self
+    }
+
+    fn health_reporter(mut self, reporter: HealthReporter) -> Self {
+        self.builder = self.builder.health_reporter(reporter);
+        self
+    }
+
+    fn pause_handle(mut self, pause_handle: PauseHandle) -> Self {
+        self.builder = self.builder.pause_handle(pause_handle);
+        self
+    }
+}
+
+impl NewHeadsSubscriber {
+    /// Follows new heads until the ingestor drops its end of the channel.
+    async fn run(self) {
+        let mut polling = false;
+
+        'subscribe: loop {
+            let subscription = tokio::select! {
+                _ = self.new_heads.closed() => break,
+                subscription = self.subscribe() => subscription,
+            };
+
+            // Only transitions to polling are worth a warning, not each failed attempt.
+            match subscription {
+                Ok((_client, mut notifications)) => {
+                    info!(ws_url = %self.ws_url, "Subscribed to new heads");
+
+                    // Heads are only notified for blocks produced from now on.
+                    self.poll().await;
+
+                    loop {
+                        let notification = tokio::select! {
+                            _ = self.new_heads.closed() => break 'subscribe,
+                            notification = notifications.next() => notification,
+                        };
+
+                        match notification {
+                            Some(Ok(notification)) => {
+                                self.publish(notification.result.block_number)
+                            }
+                            Some(Err(err)) => {
+                                warn!(error = %err, "Invalid new heads notification")
+                            }
+                            None => break,
+                        }
+                    }
+
+                    warn!("New heads subscription closed, falling back to polling");
+                    polling = true;
+                }
+                Err(err) if !polling => {
+                    warn!(
+                        error = %err,
+                        "Failed to subscribe to new heads, falling back to polling"
+                    );
+                    polling = true;
+                }
+                Err(err) => debug!(error = %err, "Failed to subscribe to new heads"),
+            }
+
+            tokio::select! {
+                _ = self.new_heads.closed() => break,
+                _ = sleep(RESUBSCRIBE_INTERVAL) => {},
+            }
+            self.poll().await;
+        }
+
+        debug!("New heads subscriber finished");
+    }
+
+    /// Subscribes to new heads, returning the client along with the notifications, as dropping
+    /// the client closes the subscription.
+    async fn subscribe(&self) -> Result<(WsClient, Subscription<NewHeadsNotification>)> {
+        let client = WsClientBuilder::default()
+            .enable_ws_ping(PingConfig::default())
+            .build(self.ws_url.as_str())
+            .await?;
+
+        // Starknet notifications don't follow the format of `jsonrpsee` subscriptions, so they're
+        // listened to by method name instead.
+        let notifications = client
+            .subscribe_to_method::<NewHeadsNotification>(NEW_HEADS_NOTIFICATION)
+            .await?;
+        let _: serde_json::Value = client.request(SUBSCRIBE_NEW_HEADS, rpc_params![]).await?;
+
+        Ok((client, notifications))
+    }
+
+    /// Publishes the latest block as reported over HTTP.
+    async fn poll(&self) {
+        match self.provider.block_number().await {
+            Ok(block_number) => self.publish(block_number),
+            Err(err) => warn!(error = %err, "Failed to fetch latest block"),
+        }
+    }
+
+    fn publish(&self, block_number: u64) {
+        self.new_heads.send_if_modified(|latest_block| {
+            let modified = *latest_block != Some(block_number);
+            *latest_block = Some(block_number);
+            modified
+        });
+    }
+}
+
+impl<DB> BlockIngestor for SubscriptionBlockIngestor<DB> where
+    DB: PersistantStorage + Send + Sync + Clone + 'static
+{
+}
+
+impl<DB> Daemon for SubscriptionBlockIngestor<DB>
+where
+    DB: PersistantStorage + Send + Sync + Clone + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.ingestor.shutdown_handle()
+    }
+
+    fn start(self) {
+        // The subscriber winds down on its own once the ingestor has finished and dropped the
+        // receiving end of the new heads channel.
+        tokio::spawn(self.subscriber.run());
+        self.ingestor.start();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{
+        block_ingestor::mock_node::{start_node, MockChain, NewHeadsNode},
+        storage::SqliteDb,
+    };
+
+    async fn start_ingestor(url: Url, ws_url: Url) -> (mpsc::Receiver<BlockInfo>, ShutdownHandle) {
+        let (tx, rx) = mpsc::channel(8);
+        let ingestor = SubscriptionBlockIngestorBuilder::new(
+            FailoverTransport::new([url]).unwrap(),
+            ws_url,
+            SqliteDb::new(":memory:").await.unwrap(),
+            WorkerPoolConfig::fixed(1),
+        )
+        .start_block(1)
+        .channel(tx)
+        .build()
+        .unwrap();
+
+        let shutdown_handle = ingestor.shutdown_handle();
+        ingestor.start();
+        (rx, shutdown_handle)
+    }
+
+    #[tokio::test]
+    async fn test_follows_new_heads() {
+        let chain = MockChain::new(1);
+        let (url, _node) = start_node(chain.clone()).await;
+        let ws_node = NewHeadsNode::start(chain.clone(), "127.0.0.1:0").await;
+        let (mut rx, shutdown_handle) = start_ingestor(url, ws_node.url()).await;
+
+        assert_eq!(rx.recv().await.unwrap().number, 1);
+
+        // Blocks only notified over WebSocket are ingested right away.
+        chain.notify_new_head(3);
+        assert_eq!(rx.recv().await.unwrap().number, 2);
+        assert_eq!(rx.recv().await.unwrap().number, 3);
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+    }
+
+    #[tokio::test]
+    async fn test_polls_while_disconnected_and_resubscribes() {
+        let chain = MockChain::new(1);
+        let (url, _node) = start_node(chain.clone()).await;
+        let ws_node = NewHeadsNode::start(chain.clone(), "127.0.0.1:0").await;
+        let ws_addr = ws_node.addr().to_string();
+        let (mut rx, shutdown_handle) = start_ingestor(url, ws_node.url()).await;
+
+        assert_eq!(rx.recv().await.unwrap().number, 1);
+
+        // The latest block is polled over HTTP while the subscription is down.
+        ws_node.stop().await;
+        chain.set_latest_block(2);
+        assert_eq!(rx.recv().await.unwrap().number, 2);
+
+        // Heads are followed again once the node is back.
+        let _ws_node = NewHeadsNode::start(chain.clone(), &ws_addr).await;
+        chain.notify_new_head(3);
+        assert_eq!(rx.recv().await.unwrap().number, 3);
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+    }
+
+    #[tokio::test]
+    async fn test_polls_once_new_heads_closed() {
+        let chain = MockChain::new(1);
+        let (url, _node) = start_node(chain.clone()).await;
+        let (new_heads_tx, new_heads_rx) = watch::channel(Some(1));
+        let (tx, mut rx) = mpsc::channel(8);
+        let ingestor = PollingBlockIngestorBuilder::new(
+            FailoverTransport::new([url]).unwrap(),
+            SqliteDb::new(":memory:").await.unwrap(),
+            WorkerPoolConfig::fixed(1),
+        )
+        .new_heads(new_heads_rx)
+        .start_block(1)
+        .channel(tx)
+        .build()
+        .unwrap();
+        let shutdown_handle = ingestor.shutdown_handle();
+        ingestor.start();
+
+        assert_eq!(rx.recv().await.unwrap().number, 1);
+
+        // The ingestor takes over polling the latest block when the subscriber goes away.
+        chain.set_latest_block(2);
+        drop(new_heads_tx);
+        assert_eq!(rx.recv().await.unwrap().number, 2);
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+    }
+}
@@ -0,0 +1,195 @@
+use std::sync::{Arc, RwLock};
+
+use serde::Serialize;
+use starknet_types_core::felt::Felt;
+
+use crate::{
+    service::{ChildServices, ShutdownHandle},
+    settlement::SettlementCursor,
+};
+
+mod server;
+pub use server::{HealthServer, HealthServerBuilder};
+
+/// A shared view on the state of a running orchestrator, fed by the orchestrator and its services
+/// and served by [`HealthServer`].
+///
+/// Cheap to clone. All clones report into the same state, which makes it possible to keep a single
+/// reporter across orchestrator restarts.
+#[derive(Debug, Clone)]
+pub struct HealthReporter {
+    state: Arc<RwLock<HealthState>>,
+}
+
+#[derive(Debug)]
+struct HealthState {
+    services: Vec<(&'static str, ShutdownHandle)>,
+    last_settled_block: Option<u64>,
+    last_settlement_transaction: Option<Felt>,
+    rollup_tip: Option<u64>,
+    max_settlement_lag: u64,
+}
+
+/// Point-in-time health report.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthStatus {
+    /// Whether all services are running.
+    pub live: bool,
+    /// Whether the instance is live and settlement keeps up with the rollup.
+    pub ready: bool,
+    pub services: Vec<ServiceStatus>,
+    pub last_settled_block: Option<u64>,
+    pub last_settlement_transaction: Option<String>,
+    pub rollup_tip: Option<u64>,
+    /// Number of rollup blocks not settled yet. Unknown until the rollup tip has been fetched.
+    pub settlement_lag: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceStatus {
+    pub name: &'static str,
+    pub state: ServiceState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceState {
+    Running,
+    ShuttingDown,
+    Finished,
+    Failed,
+}
+
+impl HealthReporter {
+    /// Creates a new reporter, flagging the instance as not ready once more than
+    /// `max_settlement_lag` rollup blocks are waiting to be settled.
+    pub fn new(max_settlement_lag: u64) -> Self {
+        Self {
+            state: Arc::new(RwLock::new(HealthState {
+                services: Vec::new(),
+                last_settled_block: None,
+                last_settlement_transaction: None,
+                rollup_tip: None,
+                max_settlement_lag,
+            })),
+        }
+    }
+
+    /// Sets the services whose liveness is reported, replacing any previously set ones.
+    pub fn set_services(&self, services: &ChildServices) {
+        self.state.write().unwrap().services = services
+            .iter()
+            .map(|(name, handle)| (name, handle.clone()))
+            .collect();
+    }
+
+    /// Records the last block settled before the orchestrator started.
+    pub fn record_settled_block(&self, block_number: u64) {
+        self.state.write().unwrap().last_settled_block = Some(block_number);
+    }
+
+    /// Records a new block settled by the running orchestrator.
+    pub fn record_settlement(&self, cursor: &SettlementCursor) {
+        let mut state = self.state.write().unwrap();
+        state.last_settled_block = Some(cursor.block_number);
+        state.last_settlement_transaction = Some(cursor.transaction_hash);
+    }
+
+    /// Records the latest block known to the rollup network.
+    pub fn record_rollup_tip(&self, block_number: u64) {
+        self.state.write().unwrap().rollup_tip = Some(block_number);
+    }
+
+    pub fn status(&self) -> HealthStatus {
+        let state = self.state.read().unwrap();
+
+        let services: Vec<ServiceStatus> = state
+            .services
+            .iter()
+            .map(|(name, handle)| {
+                let failure = handle.failure();
+                let state = if failure.is_some() {
+                    ServiceState::Failed
+                } else if handle.is_finished() {
+                    ServiceState::Finished
+                } else if handle.is_shutdown_requested() {
+                    ServiceState::ShuttingDown
+                } else {
+                    ServiceState::Running
+                };
+
+                ServiceStatus {
+                    name,
+                    state,
+                    failure,
+                }
+            })
+            .collect();
+
+        let live = !services.is_empty()
+            && services
+                .iter()
+                .all(|service| service.state == ServiceState::Running);
+
+        // Nothing has been settled at all if no block is settled yet, hence the `+ 1`.
+        let settlement_lag = state.rollup_tip.map(|tip| match state.last_settled_block {
+            Some(settled) => tip.saturating_sub(settled),
+            None => tip + 1,
+        });
+
+        let ready = live && settlement_lag.is_some_and(|lag| lag <= state.max_settlement_lag);
+
+        HealthStatus {
+            live,
+            ready,
+            services,
+            last_settled_block: state.last_settled_block,
+            last_settlement_transaction: state
+                .last_settlement_transaction
+                .map(|hash| format!("{:#064x}", hash)),
+            rollup_tip: state.rollup_tip,
+            settlement_lag,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::FinishHandle;
+
+    #[test]
+    fn test_readiness_follows_settlement_lag() {
+        let ingestor = FinishHandle::new();
+        let reporter = HealthReporter::new(2);
+        reporter.set_services(&ChildServices::new().with("ingestor", ingestor.shutdown_handle()));
+
+        // Lag is unknown until the rollup tip is fetched.
+        let status = reporter.status();
+        assert!(status.live);
+        assert!(!status.ready);
+
+        reporter.record_settled_block(10);
+        reporter.record_rollup_tip(12);
+        let status = reporter.status();
+        assert_eq!(status.settlement_lag, Some(2));
+        assert!(status.ready);
+
+        reporter.record_rollup_tip(13);
+        assert!(!reporter.status().ready);
+
+        reporter.record_settlement(&SettlementCursor {
+            block_number: 13,
+            transaction_hash: Felt::ONE,
+        });
+        assert!(reporter.status().ready);
+
+        ingestor.finish();
+        let status = reporter.status();
+        assert!(!status.live);
+        assert!(!status.ready);
+        assert_eq!(status.services[0].state, ServiceState::Finished);
+    }
+}
@@ -0,0 +1,130 @@
+use std::net::SocketAddr;
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use tokio::net::TcpListener;
+use tracing::{debug, error, info};
+
+use crate::{
+    health::HealthReporter,
+    metrics,
+    service::{Daemon, FinishHandle, ShutdownHandle},
+};
+
+/// An HTTP server exposing the state of a [`HealthReporter`].
+///
+/// Endpoints:
+/// - `GET /health`: liveness; `200` while all services are running, `503` otherwise.
+/// - `GET /ready`: readiness; `200` while live and settlement lag is within the threshold, `503`
+///   otherwise.
+/// - `GET /metrics`: Prometheus metrics in the text exposition format.
+///
+/// Health endpoints return the full [`HealthStatus`](crate::health::HealthStatus) as JSON.
+#[derive(Debug)]
+pub struct HealthServer {
+    listener: TcpListener,
+    reporter: HealthReporter,
+    finish_handle: FinishHandle,
+}
+
+#[derive(Debug)]
+pub struct HealthServerBuilder {
+    listen_addr: SocketAddr,
+    reporter: HealthReporter,
+}
+
+impl HealthServerBuilder {
+    pub fn new(listen_addr: SocketAddr, reporter: HealthReporter) -> Self {
+        Self {
+            listen_addr,
+            reporter,
+        }
+    }
+
+    pub async fn build(self) -> Result<HealthServer> {
+        let listener = TcpListener::bind(self.listen_addr).await?;
+
+        Ok(HealthServer {
+            listener,
+            reporter: self.reporter,
+            finish_handle: FinishHandle::new(),
+        })
+    }
+}
+
+impl HealthServer {
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    async fn run(self) {
+        let app = Router::new()
+            .route("/health", get(health))
+            .route("/ready", get(ready))
+            .route("/metrics", get(export_metrics))
+            .with_state(self.reporter);
+
+        if let Ok(local_addr) = self.listener.local_addr() {
+            info!(%local_addr, "Health server listening");
+        }
+
+        let finish_handle = self.finish_handle.clone();
+        let result = axum::serve(self.listener, app)
+            .with_graceful_shutdown(async move { finish_handle.shutdown_requested().await })
+            .await;
+
+        if let Err(err) = result {
+            error!(error = %err, "Health server failed");
+            self.finish_handle
+                .fail(format!("health server failed: {}", err));
+        }
+
+        debug!("Graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+}
+
+impl Daemon for HealthServer {
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
+
+async fn health(State(reporter): State<HealthReporter>) -> impl IntoResponse {
+    let status = reporter.status();
+    let code = if status.live {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (code, Json(status))
+}
+
+async fn ready(State(reporter): State<HealthReporter>) -> impl IntoResponse {
+    let status = reporter.status();
+    let code = if status.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (code, Json(status))
+}
+
+async fn export_metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::gather(),
+    )
+}
@@ -28,6 +28,18 @@ pub mod orchestrator;
 /// Types related to handling long-running background services.
 pub mod service;
 
+/// Health and readiness reporting over HTTP.
+pub mod health;
+
+/// Prometheus metrics for pipeline stages.
+pub mod metrics;
+
+/// Admin JSON-RPC API for controlling a running instance.
+pub mod admin;
+
+/// Starknet JSON-RPC transport failing over between several endpoints.
+pub mod rpc;
+
 /// Shared utilities (retry helpers).
 pub mod utils;
 
@@ -0,0 +1,239 @@
+use std::{
+    sync::{LazyLock, Mutex},
+    time::Instant,
+};
+
+use prometheus::{
+    core::Collector, CounterVec, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec,
+    IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use starknet_types_core::felt::Felt;
+use tokio::sync::mpsc::{Sender, WeakSender};
+
+use crate::storage::BlockStatus;
+
+/// Buckets for stage latencies, in seconds. Stages range from sub-second reordering to proving
+/// jobs taking up to an hour.
+const STAGE_DURATION_BUCKETS: &[f64] = &[
+    0.1, 0.5, 1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1800.0, 3600.0, 7200.0,
+];
+
+/// Number of FRI in one STRK.
+const FRI_PER_STRK: f64 = 1e18;
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(|| {
+    Registry::new_custom(Some("saya".to_string()), None).expect("valid metrics prefix")
+});
+
+static BLOCKS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register(
+        IntCounterVec::new(
+            Opts::new("blocks_total", "Number of blocks that reached each status"),
+            &["status"],
+        )
+        .unwrap(),
+    )
+});
+
+static STAGE_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register(
+        HistogramVec::new(
+            HistogramOpts::new(
+                "stage_duration_seconds",
+                "Time spent by a block in each pipeline stage",
+            )
+            .buckets(STAGE_DURATION_BUCKETS.to_vec()),
+            &["stage"],
+        )
+        .unwrap(),
+    )
+});
+
+static CHANNEL_OCCUPANCY: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register(
+        IntGaugeVec::new(
+            Opts::new(
+                "channel_occupancy",
+                "Number of items waiting in inter-stage channels",
+            ),
+            &["channel"],
+        )
+        .unwrap(),
+    )
+});
+
+static ORDERER_PENDING: LazyLock<IntGauge> = LazyLock::new(|| {
+    register(
+        IntGauge::new(
+            "orderer_pending_blocks",
+            "Number of out-of-order blocks buffered by the block orderer",
+        )
+        .unwrap(),
+    )
+});
+
+static STAGE_WORKERS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    register(
+        IntGaugeVec::new(
+            Opts::new("stage_workers", "Number of workers of each pipeline stage"),
+            &["stage"],
+        )
+        .unwrap(),
+    )
+});
+
+static SETTLEMENT_FEES: LazyLock<CounterVec> = LazyLock::new(|| {
+    register(
+        CounterVec::new(
+            Opts::new(
+                "settlement_fees_strk_total",
+                "Fees paid on the settlement layer, in STRK",
+            ),
+            &["kind"],
+        )
+        .unwrap(),
+    )
+});
+
+/// Measures the time spent by a block in a pipeline stage.
+///
+/// The duration is only recorded on [`StageTimer::observe`]. Dropping the timer discards it so
+/// that interrupted or failed attempts don't skew stage latencies.
+#[derive(Debug)]
+pub struct StageTimer {
+    histogram: Histogram,
+    started_at: Instant,
+}
+
+/// Channels observed for occupancy, sampled lazily on every [`gather`].
+///
+/// Only weak senders are kept so that observing a channel never prevents it from closing.
+#[allow(clippy::type_complexity)]
+static CHANNELS: LazyLock<Mutex<Vec<(&'static str, Box<dyn Fn() -> Option<usize> + Send>)>>> =
+    LazyLock::new(Default::default);
+
+fn register<C>(collector: C) -> C
+where
+    C: Collector + Clone + 'static,
+{
+    REGISTRY
+        .register(Box::new(collector.clone()))
+        .expect("metric registered only once");
+    collector
+}
+
+/// Records a block reaching `status`.
+pub fn record_block_status(status: &BlockStatus) {
+    BLOCKS.with_label_values(&[&status.to_string()]).inc();
+}
+
+/// Starts timing a block going through `stage`.
+pub fn stage_timer(stage: &str) -> StageTimer {
+    StageTimer {
+        histogram: STAGE_DURATION.with_label_values(&[stage]),
+        started_at: Instant::now(),
+    }
+}
+
+/// Records the number of blocks buffered by the block orderer.
+pub fn set_orderer_pending(pending: usize) {
+    ORDERER_PENDING.set(pending as i64);
+}
+
+/// Records the number of workers currently running for `stage`.
+pub fn set_stage_workers(stage: &str, workers: usize) {
+    STAGE_WORKERS
+        .with_label_values(&[stage])
+        .set(workers as i64);
+}
+
+/// Records a settlement layer fee of `amount` FRI, labeled with what it was paid for.
+pub fn record_settlement_fee(kind: &str, amount: Felt) {
+    let amount = amount
+        .to_bytes_be()
+        .iter()
+        .fold(0.0, |acc, byte| acc * 256.0 + *byte as f64);
+
+    SETTLEMENT_FEES
+        .with_label_values(&[kind])
+        .inc_by(amount / FRI_PER_STRK);
+}
+
+/// Observes the occupancy of the channel behind `sender` under the name `channel`, replacing any
+/// channel previously observed under the same name.
+pub fn observe_channel<T>(channel: &'static str, sender: &Sender<T>)
+where
+    T: Send + 'static,
+{
+    let sender: WeakSender<T> = sender.downgrade();
+    let occupancy = move || {
+        sender
+            .upgrade()
+            .map(|sender| sender.max_capacity() - sender.capacity())
+    };
+
+    let mut channels = CHANNELS.lock().unwrap();
+    channels.retain(|(name, _)| *name != channel);
+    channels.push((channel, Box::new(occupancy)));
+}
+
+impl StageTimer {
+    /// Records the time elapsed since the timer was started.
+    pub fn observe(self) {
+        self.histogram
+            .observe(self.started_at.elapsed().as_secs_f64());
+    }
+}
+
+/// Gathers all metrics in the Prometheus text exposition format.
+pub fn gather() -> String {
+    {
+        let mut channels = CHANNELS.lock().unwrap();
+        channels.retain(|(name, occupancy)| match occupancy() {
+            Some(occupancy) => {
+                CHANNEL_OCCUPANCY
+                    .with_label_values(&[name])
+                    .set(occupancy as i64);
+                true
+            }
+            None => {
+                let _ = CHANNEL_OCCUPANCY.remove_label_values(&[name]);
+                false
+            }
+        });
+    }
+
+    // Make sure all metrics are registered even if nothing has been recorded yet.
+    LazyLock::force(&BLOCKS);
+    LazyLock::force(&STAGE_DURATION);
+    LazyLock::force(&ORDERER_PENDING);
+    LazyLock::force(&STAGE_WORKERS);
+    LazyLock::force(&SETTLEMENT_FEES);
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&REGISTRY.gather(), &mut buffer)
+        .expect("text encoding never fails");
+    String::from_utf8(buffer).expect("text encoding is valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_occupancy_follows_channel() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<u64>(4);
+        observe_channel("test_channel", &tx);
+
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+        assert!(gather().contains("saya_channel_occupancy{channel=\"test_channel\"} 2"));
+
+        rx.try_recv().unwrap();
+        assert!(gather().contains("saya_channel_occupancy{channel=\"test_channel\"} 1"));
+
+        drop(tx);
+        assert!(!gather().contains("channel=\"test_channel\""));
+    }
+}
@@ -1,19 +1,21 @@
 use anyhow::Result;
 use tokio::sync::mpsc::Receiver;
-use tracing::{debug, info};
+use tracing::{debug, error, info};
 
 use crate::{
     block_ingestor::{BlockInfo, BlockIngestor, BlockIngestorBuilder},
     data_availability::DataAvailabilityCursor,
-    prover::{BlockOrderer, BlockOrdererBuilder, PipelineStageBuilder},
-    service::{Daemon, FinishHandle, ShutdownHandle},
+    health::HealthReporter,
+    metrics,
+    prover::{BlockOrderer, BlockOrdererBuilder, MapStage, MapStageBuilder, PipelineStageBuilder},
+    service::{ChildServices, Daemon, FinishHandle, ShutdownHandle},
     settlement::{SettlementBackend, SettlementBackendBuilder, SettlementCursor},
 };
 
 /// Size of the `BlockInfo` channel between ingestor and the orderer.
 const BLOCK_INGESTOR_BUFFER_SIZE: usize = 4;
 
-/// Size of the `BlockInfo` channel between orderer and the bridge task.
+/// Size of the `BlockInfo` channel between orderer and the adapter stage.
 const ORDERER_BUFFER_SIZE: usize = 4;
 
 /// Size of the `DataAvailabilityCursor` channel fed into settlement.
@@ -32,29 +34,38 @@ const SETTLE_CURSOR_BUFFER_SIZE: usize = 4;
 /// 2. A **settlement backend** — submits the state-root transition on the base layer.
 ///
 /// The ingestor output (`BlockInfo`) is forwarded to the settlement backend through an internal
-/// adapter task that wraps each item into a `DataAvailabilityCursor` with no DA pointer, keeping
+/// adapter stage that wraps each item into a `DataAvailabilityCursor` with no DA pointer, keeping
 /// the settlement trait interface unchanged.
 #[derive(Debug)]
 pub struct PersistentTeeOrchestrator<I, S> {
     cursor_channel: Receiver<SettlementCursor>,
     ingestor: I,
     orderer: BlockOrderer<BlockInfo>,
+    adapter: MapStage<BlockInfo, DataAvailabilityCursor<BlockInfo>, CursorAdapter>,
     settlement: S,
     finish_handle: FinishHandle,
+    health_reporter: Option<HealthReporter>,
+    next_block: u64,
+    end_block: Option<u64>,
 }
 
 #[derive(Debug)]
 pub struct PersistentTeeOrchestratorBuilder<I, S> {
     ingestor_builder: I,
     settlement_builder: S,
+    health_reporter: Option<HealthReporter>,
+    end_block: Option<u64>,
 }
 
+type CursorAdapter = fn(BlockInfo) -> DataAvailabilityCursor<BlockInfo>;
+
 struct PersistentTeeOrchestratorState {
     cursor_channel: Receiver<SettlementCursor>,
-    ingestor_handle: ShutdownHandle,
-    orderer_handle: ShutdownHandle,
-    settlement_handle: ShutdownHandle,
+    children: ChildServices,
     finish_handle: FinishHandle,
+    health_reporter: Option<HealthReporter>,
+    next_block: u64,
+    end_block: Option<u64>,
 }
 
 impl<I, S> PersistentTeeOrchestratorBuilder<I, S> {
@@ -62,8 +73,23 @@ impl<I, S> PersistentTeeOrchestratorBuilder<I, S> {
         Self {
             ingestor_builder,
             settlement_builder,
+            health_reporter: None,
+            end_block: None,
         }
     }
+
+    /// Sets a reporter for publishing the state of the orchestrator.
+    pub fn health_reporter(mut self, reporter: HealthReporter) -> Self {
+        self.health_reporter = Some(reporter);
+        self
+    }
+
+    /// Sets the last block to process, after which the orchestrator shuts down once the block
+    /// has been settled.
+    pub fn end_block(mut self, end_block: u64) -> Self {
+        self.end_block = Some(end_block);
+        self
+    }
 }
 
 impl<I, S> PersistentTeeOrchestratorBuilder<I, S>
@@ -74,13 +100,17 @@ where
     pub async fn build(self) -> Result<PersistentTeeOrchestrator<I::Ingestor, S::Backend>> {
         let (new_block_tx, new_block_rx) =
             tokio::sync::mpsc::channel::<BlockInfo>(BLOCK_INGESTOR_BUFFER_SIZE);
-        let (ordered_tx, mut ordered_rx) =
-            tokio::sync::mpsc::channel::<BlockInfo>(ORDERER_BUFFER_SIZE);
+        let (ordered_tx, ordered_rx) = tokio::sync::mpsc::channel::<BlockInfo>(ORDERER_BUFFER_SIZE);
         let (da_cursor_tx, da_cursor_rx) =
             tokio::sync::mpsc::channel::<DataAvailabilityCursor<BlockInfo>>(DA_CURSOR_BUFFER_SIZE);
         let (settle_cursor_tx, settle_cursor_rx) =
             tokio::sync::mpsc::channel::<SettlementCursor>(SETTLE_CURSOR_BUFFER_SIZE);
 
+        metrics::observe_channel("new_blocks", &new_block_tx);
+        metrics::observe_channel("ordered_blocks", &ordered_tx);
+        metrics::observe_channel("da_cursors", &da_cursor_tx);
+        metrics::observe_channel("settlement_cursors", &settle_cursor_tx);
+
         let settlement = self
             .settlement_builder
             .da_channel(da_cursor_rx)
@@ -94,8 +124,22 @@ where
         let start_block = settlement.get_block_number().await? + 1;
         let start_block: u64 = start_block.try_into()?;
 
-        let ingestor = self
-            .ingestor_builder
+        let ingestor_builder = match &self.health_reporter {
+            Some(reporter) => {
+                if start_block > 0 {
+                    reporter.record_settled_block(start_block - 1);
+                }
+                self.ingestor_builder.health_reporter(reporter.clone())
+            }
+            None => self.ingestor_builder,
+        };
+
+        let ingestor_builder = match self.end_block {
+            Some(end_block) => ingestor_builder.end_block(end_block),
+            None => ingestor_builder,
+        };
+
+        let ingestor = ingestor_builder
             .start_block(start_block)
             .channel(new_block_tx)
             .build()
@@ -107,27 +151,23 @@ where
             .output_channel(ordered_tx)
             .build()?;
 
-        // Adapter task: wraps each in-order `BlockInfo` in a `DataAvailabilityCursor` with no DA
-        // pointer so the settlement backend can remain unaware of the TEE-specific flow.
-        tokio::spawn(async move {
-            while let Some(block_info) = ordered_rx.recv().await {
-                let cursor = DataAvailabilityCursor {
-                    block_number: block_info.number,
-                    pointer: None,
-                    full_payload: block_info,
-                };
-                if da_cursor_tx.send(cursor).await.is_err() {
-                    break;
-                }
-            }
-        });
+        // Wraps each in-order `BlockInfo` in a `DataAvailabilityCursor` with no DA pointer so the
+        // settlement backend can remain unaware of the TEE-specific flow.
+        let adapter = MapStageBuilder::new(into_da_cursor as CursorAdapter)
+            .input_channel(ordered_rx)
+            .output_channel(da_cursor_tx)
+            .build()?;
 
         Ok(PersistentTeeOrchestrator {
             cursor_channel: settle_cursor_rx,
             ingestor,
             orderer,
+            adapter,
             settlement,
             finish_handle: FinishHandle::new(),
+            health_reporter: self.health_reporter,
+            next_block: start_block,
+            end_block: self.end_block,
         })
     }
 }
@@ -135,34 +175,52 @@ where
 impl PersistentTeeOrchestratorState {
     async fn run(mut self) {
         loop {
-            // TODO: handle unexpected exit of descendant services
+            if let Some(end_block) = self.end_block {
+                if self.next_block > end_block {
+                    info!(end_block, "Last block of the range settled, shutting down");
+                    break;
+                }
+            }
+
             let new_cursor = tokio::select! {
                 _ = self.finish_handle.shutdown_requested() => break,
+                (service, failure) = self.children.exited() => {
+                    error!(service, ?failure, "Service exited unexpectedly");
+                    self.finish_handle.fail(format!(
+                        "`{}` exited unexpectedly: {}",
+                        service,
+                        failure.as_deref().unwrap_or("no failure reported")
+                    ));
+                    break;
+                },
                 new_cursor = self.cursor_channel.recv() => new_cursor,
             };
 
-            // The settlement backend won't drop the sender while running.
-            let new_cursor = new_cursor.unwrap();
+            let Some(new_cursor) = new_cursor else {
+                error!("Settlement cursor channel closed unexpectedly");
+                self.finish_handle
+                    .fail("settlement cursor channel closed unexpectedly");
+                break;
+            };
+
+            if let Some(reporter) = &self.health_reporter {
+                reporter.record_settlement(&new_cursor);
+            }
 
             info!(
                 block_number = new_cursor.block_number,
                 transaction_hash = %format!("{:#064x}", new_cursor.transaction_hash),
                 "Chain advanced to new block"
             );
+
+            self.next_block = new_cursor.block_number + 1;
         }
 
         // Request graceful shutdown for all descendant services.
-        self.ingestor_handle.shutdown();
-        self.orderer_handle.shutdown();
-        self.settlement_handle.shutdown();
+        self.children.shutdown();
 
         // Wait for all descendant services to finish.
-        futures_util::future::join_all([
-            self.ingestor_handle.finished(),
-            self.orderer_handle.finished(),
-            self.settlement_handle.finished(),
-        ])
-        .await;
+        self.children.finished().await;
 
         debug!("Graceful shutdown finished");
         self.finish_handle.finish();
@@ -181,16 +239,34 @@ where
     fn start(self) {
         let state = PersistentTeeOrchestratorState {
             cursor_channel: self.cursor_channel,
-            ingestor_handle: self.ingestor.shutdown_handle(),
-            orderer_handle: self.orderer.shutdown_handle(),
-            settlement_handle: self.settlement.shutdown_handle(),
+            children: ChildServices::new()
+                .with("ingestor", self.ingestor.shutdown_handle())
+                .with("orderer", self.orderer.shutdown_handle())
+                .with("adapter", self.adapter.shutdown_handle())
+                .with("settlement", self.settlement.shutdown_handle()),
             finish_handle: self.finish_handle,
+            health_reporter: self.health_reporter,
+            next_block: self.next_block,
+            end_block: self.end_block,
         };
 
+        if let Some(reporter) = &state.health_reporter {
+            reporter.set_services(&state.children);
+        }
+
         self.ingestor.start();
         self.orderer.start();
+        self.adapter.start();
         self.settlement.start();
 
-        tokio::spawn(state.run());
+        state.finish_handle.clone().spawn(state.run());
+    }
+}
+
+fn into_da_cursor(block_info: BlockInfo) -> DataAvailabilityCursor<BlockInfo> {
+    DataAvailabilityCursor {
+        block_number: block_info.number,
+        pointer: None,
+        full_payload: block_info,
     }
 }
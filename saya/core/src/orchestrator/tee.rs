@@ -16,12 +16,14 @@
 
 use anyhow::Result;
 use tokio::sync::mpsc::Receiver;
-use tracing::{debug, info};
+use tracing::{debug, error, info};
 
 use crate::{
     block_ingestor::{BatchingBlockIngestorBuilder, BlockInfo, BlockIngestor},
+    health::HealthReporter,
+    metrics,
     prover::{tee::TeeProof, PipelineStage, PipelineStageBuilder},
-    service::{Daemon, FinishHandle, ShutdownHandle},
+    service::{ChildServices, Daemon, FinishHandle, ShutdownHandle},
     settlement::{SettlementBackend, SettlementCursor, TeeSettlementBackendBuilder},
     tee::TeeAttestation,
 };
@@ -40,6 +42,9 @@ pub struct TeeOrchestrator<I, A, P, S> {
     prover: P,
     settlement: S,
     finish_handle: FinishHandle,
+    health_reporter: Option<HealthReporter>,
+    next_block: u64,
+    end_block: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -48,15 +53,17 @@ pub struct TeeOrchestratorBuilder<I, A, P, S> {
     attestor_builder: A,
     prover_builder: P,
     settlement_builder: S,
+    health_reporter: Option<HealthReporter>,
+    end_block: Option<u64>,
 }
 
 struct TeeOrchestratorState {
     cursor_channel: Receiver<SettlementCursor>,
-    ingestor_handle: ShutdownHandle,
-    attestor_handle: ShutdownHandle,
-    prover_handle: ShutdownHandle,
-    settlement_handle: ShutdownHandle,
+    children: ChildServices,
     finish_handle: FinishHandle,
+    health_reporter: Option<HealthReporter>,
+    next_block: u64,
+    end_block: Option<u64>,
 }
 
 impl<I, A, P, S> TeeOrchestratorBuilder<I, A, P, S> {
@@ -71,8 +78,23 @@ impl<I, A, P, S> TeeOrchestratorBuilder<I, A, P, S> {
             attestor_builder,
             prover_builder,
             settlement_builder,
+            health_reporter: None,
+            end_block: None,
         }
     }
+
+    /// Sets a reporter for publishing the state of the orchestrator.
+    pub fn health_reporter(mut self, reporter: HealthReporter) -> Self {
+        self.health_reporter = Some(reporter);
+        self
+    }
+
+    /// Sets the last block to process, after which the orchestrator shuts down once the block
+    /// has been settled.
+    pub fn end_block(mut self, end_block: u64) -> Self {
+        self.end_block = Some(end_block);
+        self
+    }
 }
 
 impl<I, A, AV, P, PV, S> TeeOrchestratorBuilder<I, A, P, S>
@@ -93,6 +115,11 @@ where
         let (settle_cursor_tx, settle_cursor_rx) =
             tokio::sync::mpsc::channel::<SettlementCursor>(SETTLE_CURSOR_BUFFER_SIZE);
 
+        metrics::observe_channel("new_blocks", &new_block_tx);
+        metrics::observe_channel("attestations", &attestation_tx);
+        metrics::observe_channel("proofs", &proof_tx);
+        metrics::observe_channel("settlement_cursors", &settle_cursor_tx);
+
         let settlement = self
             .settlement_builder
             .proof_channel(proof_rx)
@@ -103,8 +130,22 @@ where
         let start_block = settlement.get_block_number().await? + 1;
         let start_block: u64 = start_block.try_into()?;
 
-        let ingestor = self
-            .ingestor_builder
+        let ingestor_builder = match &self.health_reporter {
+            Some(reporter) => {
+                if start_block > 0 {
+                    reporter.record_settled_block(start_block - 1);
+                }
+                self.ingestor_builder.health_reporter(reporter.clone())
+            }
+            None => self.ingestor_builder,
+        };
+
+        let ingestor_builder = match self.end_block {
+            Some(end_block) => ingestor_builder.end_block(end_block),
+            None => ingestor_builder,
+        };
+
+        let ingestor = ingestor_builder
             .start_block(start_block)
             .channel(new_block_tx)
             .build()?;
@@ -128,6 +169,9 @@ where
             prover,
             settlement,
             finish_handle: FinishHandle::new(),
+            health_reporter: self.health_reporter,
+            next_block: start_block,
+            end_block: self.end_block,
         })
     }
 }
@@ -135,32 +179,49 @@ where
 impl TeeOrchestratorState {
     async fn run(mut self) {
         loop {
+            if let Some(end_block) = self.end_block {
+                if self.next_block > end_block {
+                    info!(end_block, "Last block of the range settled, shutting down");
+                    break;
+                }
+            }
+
             let new_cursor = tokio::select! {
                 _ = self.finish_handle.shutdown_requested() => break,
+                (service, failure) = self.children.exited() => {
+                    error!(service, ?failure, "Service exited unexpectedly");
+                    self.finish_handle.fail(format!(
+                        "`{}` exited unexpectedly: {}",
+                        service,
+                        failure.as_deref().unwrap_or("no failure reported")
+                    ));
+                    break;
+                },
                 new_cursor = self.cursor_channel.recv() => new_cursor,
             };
 
-            let new_cursor = new_cursor.unwrap();
+            let Some(new_cursor) = new_cursor else {
+                error!("Settlement cursor channel closed unexpectedly");
+                self.finish_handle
+                    .fail("settlement cursor channel closed unexpectedly");
+                break;
+            };
+
+            if let Some(reporter) = &self.health_reporter {
+                reporter.record_settlement(&new_cursor);
+            }
 
             info!(
                 block_number = new_cursor.block_number,
                 transaction_hash = %format!("{:#064x}", new_cursor.transaction_hash),
                 "Chain advanced to new block"
             );
-        }
 
-        self.ingestor_handle.shutdown();
-        self.attestor_handle.shutdown();
-        self.prover_handle.shutdown();
-        self.settlement_handle.shutdown();
+            self.next_block = new_cursor.block_number + 1;
+        }
 
-        futures_util::future::join_all([
-            self.ingestor_handle.finished(),
-            self.attestor_handle.finished(),
-            self.prover_handle.finished(),
-            self.settlement_handle.finished(),
-        ])
-        .await;
+        self.children.shutdown();
+        self.children.finished().await;
 
         debug!("Graceful shutdown finished");
         self.finish_handle.finish();
@@ -181,18 +242,26 @@ where
     fn start(self) {
         let state = TeeOrchestratorState {
             cursor_channel: self.cursor_channel,
-            ingestor_handle: self.ingestor.shutdown_handle(),
-            attestor_handle: self.attestor.shutdown_handle(),
-            prover_handle: self.prover.shutdown_handle(),
-            settlement_handle: self.settlement.shutdown_handle(),
+            children: ChildServices::new()
+                .with("ingestor", self.ingestor.shutdown_handle())
+                .with("attestor", self.attestor.shutdown_handle())
+                .with("prover", self.prover.shutdown_handle())
+                .with("settlement", self.settlement.shutdown_handle()),
             finish_handle: self.finish_handle,
+            health_reporter: self.health_reporter,
+            next_block: self.next_block,
+            end_block: self.end_block,
         };
 
+        if let Some(reporter) = &state.health_reporter {
+            reporter.set_services(&state.children);
+        }
+
         self.ingestor.start();
         self.attestor.start();
         self.prover.start();
         self.settlement.start();
 
-        tokio::spawn(state.run());
+        state.finish_handle.clone().spawn(state.run());
     }
 }
@@ -0,0 +1,380 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::Result;
+use tokio::{
+    sync::mpsc::{Receiver, Sender},
+    time::{sleep_until, Instant},
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    block_ingestor::BlockInfo,
+    prover::{HasBlockNumber, PipelineStage, PipelineStageBuilder},
+    service::{Daemon, FinishHandle, ShutdownHandle},
+    storage::PersistantStorage,
+};
+
+/// A pipeline stage grouping contiguous blocks into batches proven by a single SNOS run.
+///
+/// Blocks are buffered until `max_blocks` contiguous blocks are available from the next block to
+/// batch, or until no block has been received for `idle_timeout`, in which case the contiguous
+/// blocks available so far make up the batch. A batch is emitted as the `BlockInfo` of its last
+/// block, with `first_block` set to its first one, and then goes through the rest of the pipeline
+/// as a single unit.
+///
+/// Batches are recorded in the DB before being emitted, so that blocks re-emitted after a restart
+/// are grouped the same way, and that the ingestor retrying the last block of a failed batch
+/// retries the whole batch.
+///
+/// Blocks merged by the ingestor (see [`BlockFilter`](crate::block_ingestor::BlockFilter)) arrive
+/// with `first_block` already set, and are never split: a batch holding them may go over
+/// `max_blocks`.
+///
+/// With `max_blocks` set to 1, blocks are forwarded as they come, merged ones being recorded as
+/// batches all the same.
+#[derive(Debug)]
+pub struct BlockBatcher<DB> {
+    input_channel: Receiver<BlockInfo>,
+    output_channel: Sender<BlockInfo>,
+    finish_handle: FinishHandle,
+    db: DB,
+    max_blocks: u64,
+    idle_timeout: Duration,
+    start_block: u64,
+}
+
+#[derive(Debug)]
+pub struct BlockBatcherBuilder<DB> {
+    input_channel: Option<Receiver<BlockInfo>>,
+    output_channel: Option<Sender<BlockInfo>>,
+    db: DB,
+    max_blocks: u64,
+    idle_timeout: Duration,
+    start_block: Option<u64>,
+}
+
+impl<DB> BlockBatcherBuilder<DB> {
+    /// Creates a builder batching up to `max_blocks` blocks, flushing a partial batch once no
+    /// block has been received for `idle_timeout`.
+    pub fn new(db: DB, max_blocks: u64, idle_timeout: Duration) -> Self {
+        Self {
+            input_channel: None,
+            output_channel: None,
+            db,
+            max_blocks,
+            idle_timeout,
+            start_block: None,
+        }
+    }
+}
+
+impl<DB> PipelineStageBuilder for BlockBatcherBuilder<DB>
+where
+    DB: PersistantStorage + Send + Sync + 'static,
+{
+    type Stage = BlockBatcher<DB>;
+
+    fn build(self) -> Result<Self::Stage> {
+        if self.max_blocks == 0 {
+            anyhow::bail!("`max_blocks` must be at least 1");
+        }
+
+        Ok(BlockBatcher {
+            input_channel: self
+                .input_channel
+                .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
+            output_channel: self
+                .output_channel
+                .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
+            finish_handle: FinishHandle::new(),
+            db: self.db,
+            max_blocks: self.max_blocks,
+            idle_timeout: self.idle_timeout,
+            start_block: self
+                .start_block
+                .ok_or_else(|| anyhow::anyhow!("`start_block` not set on BlockBatcher"))?,
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<BlockInfo>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<BlockInfo>) -> Self {
+        self.output_channel = Some(output_channel);
+        self
+    }
+
+    fn start_block(mut self, start_block: u64) -> Self {
+        self.start_block = Some(start_block);
+        self
+    }
+}
+
+impl<DB> PipelineStage for BlockBatcher<DB>
+where
+    DB: PersistantStorage + Send + Sync + 'static,
+{
+    type Input = BlockInfo;
+    type Output = BlockInfo;
+}
+
+impl<DB> Daemon for BlockBatcher<DB>
+where
+    DB: PersistantStorage + Send + Sync + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
+
+impl<DB> BlockBatcher<DB>
+where
+    DB: PersistantStorage + Send + Sync + 'static,
+{
+    async fn run(mut self) {
+        let result = if self.max_blocks == 1 {
+            self.forward().await
+        } else {
+            self.batch().await
+        };
+
+        if let Err(err) = result {
+            error!(error = %err, "Block batcher failed");
+            self.finish_handle.fail(format!("{err:#}"));
+        }
+
+        debug!("BlockBatcher graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+
+    /// Forwards blocks as they come, for blocks to be proven one by one.
+    async fn forward(&mut self) -> Result<()> {
+        loop {
+            let block = tokio::select! {
+                _ = self.finish_handle.shutdown_requested() => break,
+                block = self.input_channel.recv() => match block {
+                    Some(block) => block,
+                    None => break,
+                },
+            };
+
+            let emitted = match block.first_block {
+                Some(first_block) => self.emit_batch(block, first_block).await?,
+                // The last block of merged blocks being retried retries all of them.
+                None => match self.db.get_block_batch(block.number.try_into()?).await? {
+                    Some((first_block, last_block)) if last_block as u64 == block.number => {
+                        self.emit_batch(block, first_block as u64).await?
+                    }
+                    _ => self.emit(block).await,
+                },
+            };
+            if !emitted {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn batch(&mut self) -> Result<()> {
+        let mut pending: BTreeMap<u64, BlockInfo> = BTreeMap::new();
+        let mut next_block = self.start_block;
+        let mut idle_deadline = Instant::now() + self.idle_timeout;
+
+        loop {
+            // Emit the batches already complete before waiting for more blocks. A batch recorded
+            // by a previous run is only emitted once all of its blocks are available.
+            let recorded_last_block = loop {
+                let recorded_last_block = self.recorded_last_block(next_block).await?;
+                let last_block = recorded_last_block.unwrap_or(next_block + self.max_blocks - 1);
+
+                match take_batch(&mut pending, next_block, last_block) {
+                    Some(batch) => {
+                        let batch_last_block = batch.number;
+                        if !self.emit_batch(batch, next_block).await? {
+                            return Ok(());
+                        }
+                        next_block = batch_last_block + 1;
+                    }
+                    None => break recorded_last_block,
+                }
+            };
+
+            let can_flush = recorded_last_block.is_none() && pending.contains_key(&next_block);
+            let block = tokio::select! {
+                _ = self.finish_handle.shutdown_requested() => break,
+                _ = sleep_until(idle_deadline), if can_flush => {
+                    let last_block = contiguous_last_block(&pending, next_block);
+                    let batch = take_batch(&mut pending, next_block, last_block)
+                        .expect("contiguous blocks are pending");
+                    if !self.emit_batch(batch, next_block).await? {
+                        break;
+                    }
+                    next_block = last_block + 1;
+                    continue;
+                },
+                block = self.input_channel.recv() => match block {
+                    Some(block) => block,
+                    None => break,
+                },
+            };
+            idle_deadline = Instant::now() + self.idle_timeout;
+
+            // Blocks behind the cursor are the last blocks of failed batches being retried, which
+            // are re-emitted as the whole batch.
+            if block.number < next_block {
+                self.retry(block).await?;
+                continue;
+            }
+
+            pending.insert(block.first_block_number(), block);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the last block of the batch recorded from `first_block`, if any.
+    async fn recorded_last_block(&self, first_block: u64) -> Result<Option<u64>> {
+        let batch = self.db.get_block_batch(first_block.try_into()?).await?;
+
+        Ok(batch.and_then(|(recorded_first_block, last_block)| {
+            (recorded_first_block as u64 == first_block).then_some(last_block as u64)
+        }))
+    }
+
+    async fn retry(&self, block: BlockInfo) -> Result<()> {
+        let block_number = block.number;
+        match self.db.get_block_batch(block_number.try_into()?).await? {
+            Some((first_block, last_block)) if last_block as u64 == block_number => {
+                info!(
+                    first_block,
+                    last_block = block_number,
+                    "Retrying batch of blocks"
+                );
+                self.emit_batch(block, first_block as u64).await?;
+            }
+            _ => warn!(block_number, "Dropping block already batched"),
+        }
+
+        Ok(())
+    }
+
+    /// Records the batch ending with `last_block` and emits it, returning whether the output
+    /// channel is still open.
+    async fn emit_batch(&self, mut last_block: BlockInfo, first_block: u64) -> Result<bool> {
+        self.db
+            .add_block_batch(first_block.try_into()?, last_block.number.try_into()?)
+            .await?;
+
+        debug!(
+            first_block,
+            last_block = last_block.number,
+            "Batch of blocks closed"
+        );
+        last_block.first_block = (first_block != last_block.number).then_some(first_block);
+
+        Ok(self.emit(last_block).await)
+    }
+
+    async fn emit(&self, block: BlockInfo) -> bool {
+        tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => false,
+            result = self.output_channel.send(block) => {
+                if result.is_err() {
+                    debug!("BlockBatcher output channel closed");
+                }
+                result.is_ok()
+            },
+        }
+    }
+}
+
+/// Removes blocks `first_block..=last_block` from `pending` if they're all there, returning the
+/// last one.
+///
+/// `pending` is keyed by the first block of each entry, so that merged blocks are taken whole,
+/// possibly going past `last_block`.
+fn take_batch(
+    pending: &mut BTreeMap<u64, BlockInfo>,
+    first_block: u64,
+    last_block: u64,
+) -> Option<BlockInfo> {
+    let mut next_block = first_block;
+    while next_block <= last_block {
+        next_block = pending.get(&next_block)?.number + 1;
+    }
+
+    let mut batch = pending.split_off(&first_block);
+    let mut rest = batch.split_off(&next_block);
+    pending.append(&mut rest);
+    batch.pop_last().map(|(_, block)| block)
+}
+
+/// Returns the last block of the run of contiguous blocks starting at `first_block`, which must be
+/// pending.
+fn contiguous_last_block(pending: &BTreeMap<u64, BlockInfo>, first_block: u64) -> u64 {
+    let mut last_block = pending[&first_block].number;
+    while let Some(block) = pending.get(&(last_block + 1)) {
+        last_block = block.number;
+    }
+    last_block
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::storage::{BlockStatus, SqliteDb};
+
+    fn block(number: u64) -> BlockInfo {
+        BlockInfo {
+            number,
+            status: BlockStatus::Mined,
+            state_update: None,
+            first_block: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batches_contiguous_blocks() {
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        let (input_tx, input_rx) = mpsc::channel(4);
+        let (output_tx, mut output_rx) = mpsc::channel(4);
+        let batcher = BlockBatcherBuilder::new(db.clone(), 2, Duration::from_millis(50))
+            .start_block(5)
+            .input_channel(input_rx)
+            .output_channel(output_tx)
+            .build()
+            .unwrap();
+        let shutdown_handle = batcher.shutdown_handle();
+        batcher.start();
+
+        for block_number in [6, 5, 7] {
+            input_tx.send(block(block_number)).await.unwrap();
+        }
+
+        let batch = output_rx.recv().await.unwrap();
+        assert_eq!((batch.number, batch.first_block), (6, Some(5)));
+
+        // The partial batch is flushed once no more blocks come in.
+        let batch = output_rx.recv().await.unwrap();
+        assert_eq!((batch.number, batch.first_block), (7, None));
+
+        // Retrying the last block of a batch retries the whole batch.
+        input_tx.send(block(6)).await.unwrap();
+        let batch = output_rx.recv().await.unwrap();
+        assert_eq!((batch.number, batch.first_block), (6, Some(5)));
+        assert_eq!(db.get_block_batch(5).await.unwrap(), Some((5, 6)));
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+        assert_eq!(shutdown_handle.failure(), None);
+    }
+}
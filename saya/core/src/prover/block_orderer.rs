@@ -2,9 +2,10 @@ use std::{collections::BTreeMap, marker::PhantomData};
 
 use anyhow::Result;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tracing::debug;
+use tracing::{debug, warn};
 
 use crate::{
+    metrics::{self, StageTimer},
     prover::{HasBlockNumber, PipelineStage, PipelineStageBuilder},
     service::{Daemon, FinishHandle, ShutdownHandle},
 };
@@ -13,6 +14,9 @@ use crate::{
 ///
 /// Workers may complete blocks out of order. `BlockOrderer` buffers items in a `BTreeMap` and
 /// emits them strictly in ascending block-number order, starting from `start_block`.
+///
+/// An item covering a batch of blocks is emitted once the item ending right before its first
+/// block has been, and is followed by the item starting right after its last block.
 #[derive(Debug)]
 pub struct BlockOrderer<T> {
     input_channel: Receiver<T>,
@@ -94,25 +98,28 @@ impl<T: HasBlockNumber + Send + 'static> Daemon for BlockOrderer<T> {
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
 
 impl<T: HasBlockNumber + Send + 'static> BlockOrderer<T> {
     async fn run(mut self) {
-        let mut pending: BTreeMap<u64, T> = BTreeMap::new();
+        let mut pending: BTreeMap<u64, (StageTimer, T)> = BTreeMap::new();
         let mut next_expected = self.start_block;
 
         loop {
             // Drain buffered items in order before waiting for more.
-            while let Some(item) = pending.remove(&next_expected) {
+            while let Some((stage_timer, item)) = pending.remove(&next_expected) {
+                stage_timer.observe();
+                let item_last_block = item.block_number();
                 if self.output_channel.send(item).await.is_err() {
                     debug!("BlockOrderer output channel closed");
                     self.finish_handle.finish();
                     return;
                 }
-                next_expected += 1;
+                next_expected = item_last_block + 1;
             }
+            metrics::set_orderer_pending(pending.len());
 
             // Wait for the next upstream item.
             let item = tokio::select! {
@@ -123,7 +130,20 @@ impl<T: HasBlockNumber + Send + 'static> BlockOrderer<T> {
                 },
             };
 
-            pending.insert(item.block_number(), item);
+            // Items behind the cursor are blocks retried after already being emitted. Buffering them
+            // would leak as they can never be drained.
+            if item.first_block_number() < next_expected {
+                warn!(
+                    block_number = item.block_number(),
+                    next_expected, "Dropping block already emitted by the orderer"
+                );
+                continue;
+            }
+
+            pending.insert(
+                item.first_block_number(),
+                (metrics::stage_timer("block_orderer"), item),
+            );
         }
 
         debug!("BlockOrderer graceful shutdown finished");
@@ -0,0 +1,586 @@
+//! Generic pipeline stages for plugging custom logic into a pipeline without writing a dedicated
+//! [`PipelineStage`].
+
+use std::{fmt, marker::PhantomData};
+
+use anyhow::Result;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{debug, error};
+
+use crate::{
+    prover::{PipelineStage, PipelineStageBuilder},
+    service::{ChildServices, Daemon, FinishHandle, ShutdownHandle},
+};
+
+/// Size of the channel between a [`FanOut`] and each of its branches.
+const BRANCH_BUFFER_SIZE: usize = 4;
+
+/// A pipeline stage applying a function to each item.
+#[derive(Debug)]
+pub struct MapStage<I, O, F> {
+    input_channel: Receiver<I>,
+    output_channel: Sender<O>,
+    map: F,
+    finish_handle: FinishHandle,
+}
+
+#[derive(Debug)]
+pub struct MapStageBuilder<I, O, F> {
+    input_channel: Option<Receiver<I>>,
+    output_channel: Option<Sender<O>>,
+    map: F,
+}
+
+/// A pipeline stage only forwarding the items matching a predicate.
+#[derive(Debug)]
+pub struct FilterStage<T, F> {
+    input_channel: Receiver<T>,
+    output_channel: Sender<T>,
+    filter: F,
+    finish_handle: FinishHandle,
+}
+
+#[derive(Debug)]
+pub struct FilterStageBuilder<T, F> {
+    input_channel: Option<Receiver<T>>,
+    output_channel: Option<Sender<T>>,
+    filter: F,
+}
+
+/// A pipeline stage calling a function on each item before forwarding it unchanged, e.g. for
+/// auditing or exporting proofs.
+#[derive(Debug)]
+pub struct TapStage<T, F> {
+    input_channel: Receiver<T>,
+    output_channel: Sender<T>,
+    tap: F,
+    finish_handle: FinishHandle,
+}
+
+#[derive(Debug)]
+pub struct TapStageBuilder<T, F> {
+    input_channel: Option<Receiver<T>>,
+    output_channel: Option<Sender<T>>,
+    tap: F,
+}
+
+/// A pipeline stage broadcasting each item to several downstream stages (branches), whose outputs
+/// are merged into the output of the fan-out.
+///
+/// Items are sent to all branches before the next item is received, so the slowest branch applies
+/// backpressure to the whole fan-out. A branch exiting on its own makes the fan-out fail.
+pub struct FanOut<T, O> {
+    input_channel: Receiver<T>,
+    branches: Vec<(Sender<T>, Branch)>,
+    finish_handle: FinishHandle,
+    _phantom: PhantomData<O>,
+}
+
+pub struct FanOutBuilder<T, O> {
+    input_channel: Option<Receiver<T>>,
+    output_channel: Option<Sender<O>>,
+    start_block: Option<u64>,
+    branches: Vec<BranchBuilder<T, O>>,
+}
+
+/// Builds a branch from its input channel, output channel and start block.
+type BranchBuilder<T, O> =
+    Box<dyn FnOnce(Receiver<T>, Sender<O>, Option<u64>) -> Result<Branch> + Send>;
+
+/// A built branch of a [`FanOut`], with its concrete stage type erased.
+struct Branch {
+    shutdown_handle: ShutdownHandle,
+    start: Box<dyn FnOnce() + Send>,
+}
+
+struct FanOutState<T> {
+    input_channel: Receiver<T>,
+    branch_channels: Vec<Sender<T>>,
+    children: ChildServices,
+    finish_handle: FinishHandle,
+}
+
+impl<I, O, F> MapStageBuilder<I, O, F> {
+    pub fn new(map: F) -> Self {
+        Self {
+            input_channel: None,
+            output_channel: None,
+            map,
+        }
+    }
+}
+
+impl<I, O, F> PipelineStageBuilder for MapStageBuilder<I, O, F>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    F: FnMut(I) -> O + Send + 'static,
+{
+    type Stage = MapStage<I, O, F>;
+
+    fn build(self) -> Result<Self::Stage> {
+        Ok(MapStage {
+            input_channel: self
+                .input_channel
+                .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
+            output_channel: self
+                .output_channel
+                .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
+            map: self.map,
+            finish_handle: FinishHandle::new(),
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<I>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<O>) -> Self {
+        self.output_channel = Some(output_channel);
+        self
+    }
+}
+
+impl<I, O, F> PipelineStage for MapStage<I, O, F>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    F: FnMut(I) -> O + Send + 'static,
+{
+    type Input = I;
+    type Output = O;
+}
+
+impl<I, O, F> Daemon for MapStage<I, O, F>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+    F: FnMut(I) -> O + Send + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        let mut map = self.map;
+        self.finish_handle.clone().spawn(forward(
+            self.input_channel,
+            self.output_channel,
+            self.finish_handle,
+            move |item| Some(map(item)),
+        ));
+    }
+}
+
+impl<T, F> FilterStageBuilder<T, F> {
+    pub fn new(filter: F) -> Self {
+        Self {
+            input_channel: None,
+            output_channel: None,
+            filter,
+        }
+    }
+}
+
+impl<T, F> PipelineStageBuilder for FilterStageBuilder<T, F>
+where
+    T: Send + 'static,
+    F: FnMut(&T) -> bool + Send + 'static,
+{
+    type Stage = FilterStage<T, F>;
+
+    fn build(self) -> Result<Self::Stage> {
+        Ok(FilterStage {
+            input_channel: self
+                .input_channel
+                .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
+            output_channel: self
+                .output_channel
+                .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
+            filter: self.filter,
+            finish_handle: FinishHandle::new(),
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<T>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<T>) -> Self {
+        self.output_channel = Some(output_channel);
+        self
+    }
+}
+
+impl<T, F> PipelineStage for FilterStage<T, F>
+where
+    T: Send + 'static,
+    F: FnMut(&T) -> bool + Send + 'static,
+{
+    type Input = T;
+    type Output = T;
+}
+
+impl<T, F> Daemon for FilterStage<T, F>
+where
+    T: Send + 'static,
+    F: FnMut(&T) -> bool + Send + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        let mut filter = self.filter;
+        self.finish_handle.clone().spawn(forward(
+            self.input_channel,
+            self.output_channel,
+            self.finish_handle,
+            move |item| filter(&item).then_some(item),
+        ));
+    }
+}
+
+impl<T, F> TapStageBuilder<T, F> {
+    pub fn new(tap: F) -> Self {
+        Self {
+            input_channel: None,
+            output_channel: None,
+            tap,
+        }
+    }
+}
+
+impl<T, F> PipelineStageBuilder for TapStageBuilder<T, F>
+where
+    T: Send + 'static,
+    F: FnMut(&T) + Send + 'static,
+{
+    type Stage = TapStage<T, F>;
+
+    fn build(self) -> Result<Self::Stage> {
+        Ok(TapStage {
+            input_channel: self
+                .input_channel
+                .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
+            output_channel: self
+                .output_channel
+                .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?,
+            tap: self.tap,
+            finish_handle: FinishHandle::new(),
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<T>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<T>) -> Self {
+        self.output_channel = Some(output_channel);
+        self
+    }
+}
+
+impl<T, F> PipelineStage for TapStage<T, F>
+where
+    T: Send + 'static,
+    F: FnMut(&T) + Send + 'static,
+{
+    type Input = T;
+    type Output = T;
+}
+
+impl<T, F> Daemon for TapStage<T, F>
+where
+    T: Send + 'static,
+    F: FnMut(&T) + Send + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        let mut tap = self.tap;
+        self.finish_handle.clone().spawn(forward(
+            self.input_channel,
+            self.output_channel,
+            self.finish_handle,
+            move |item| {
+                tap(&item);
+                Some(item)
+            },
+        ));
+    }
+}
+
+impl<T, O> FanOutBuilder<T, O> {
+    pub fn new() -> Self {
+        Self {
+            input_channel: None,
+            output_channel: None,
+            start_block: None,
+            branches: Vec::new(),
+        }
+    }
+
+    /// Adds a downstream stage receiving a copy of every item.
+    pub fn branch<B>(mut self, builder: B) -> Self
+    where
+        B: PipelineStageBuilder + Send + 'static,
+        B::Stage: PipelineStage<Input = T, Output = O> + 'static,
+    {
+        self.branches.push(Box::new(
+            move |input_channel, output_channel, start_block| {
+                let builder = match start_block {
+                    Some(start_block) => builder.start_block(start_block),
+                    None => builder,
+                };
+                let stage = builder
+                    .input_channel(input_channel)
+                    .output_channel(output_channel)
+                    .build()?;
+
+                Ok(Branch {
+                    shutdown_handle: stage.shutdown_handle(),
+                    start: Box::new(move || stage.start()),
+                })
+            },
+        ));
+        self
+    }
+}
+
+impl<T, O> Default for FanOutBuilder<T, O> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, O> PipelineStageBuilder for FanOutBuilder<T, O>
+where
+    T: Clone + Send + 'static,
+    O: Send + 'static,
+{
+    type Stage = FanOut<T, O>;
+
+    fn build(self) -> Result<Self::Stage> {
+        let input_channel = self
+            .input_channel
+            .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?;
+        let output_channel = self
+            .output_channel
+            .ok_or_else(|| anyhow::anyhow!("`output_channel` not set"))?;
+        if self.branches.is_empty() {
+            anyhow::bail!("no branch added to FanOut");
+        }
+
+        let start_block = self.start_block;
+        let branches = self
+            .branches
+            .into_iter()
+            .map(|build_branch| {
+                let (branch_tx, branch_rx) = tokio::sync::mpsc::channel::<T>(BRANCH_BUFFER_SIZE);
+                Ok((
+                    branch_tx,
+                    build_branch(branch_rx, output_channel.clone(), start_block)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(FanOut {
+            input_channel,
+            branches,
+            finish_handle: FinishHandle::new(),
+            _phantom: PhantomData,
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<T>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<O>) -> Self {
+        self.output_channel = Some(output_channel);
+        self
+    }
+
+    fn start_block(mut self, start_block: u64) -> Self {
+        self.start_block = Some(start_block);
+        self
+    }
+}
+
+impl<T, O> PipelineStage for FanOut<T, O>
+where
+    T: Clone + Send + 'static,
+    O: Send + 'static,
+{
+    type Input = T;
+    type Output = O;
+}
+
+impl<T, O> Daemon for FanOut<T, O>
+where
+    T: Clone + Send + 'static,
+    O: Send + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        let mut children = ChildServices::new();
+        let mut branch_channels = Vec::with_capacity(self.branches.len());
+        for (index, (branch_channel, branch)) in self.branches.into_iter().enumerate() {
+            children = children.with(format!("branch-{}", index), branch.shutdown_handle);
+            branch_channels.push(branch_channel);
+            (branch.start)();
+        }
+
+        let state = FanOutState {
+            input_channel: self.input_channel,
+            branch_channels,
+            children,
+            finish_handle: self.finish_handle,
+        };
+        state.finish_handle.clone().spawn(state.run());
+    }
+}
+
+impl<T: Clone> FanOutState<T> {
+    async fn run(mut self) {
+        loop {
+            let item = tokio::select! {
+                _ = self.finish_handle.shutdown_requested() => break,
+                (branch, failure) = self.children.exited() => {
+                    error!(branch, ?failure, "Fan-out branch exited unexpectedly");
+                    self.finish_handle.fail(format!(
+                        "{} exited unexpectedly: {}",
+                        branch,
+                        failure.as_deref().unwrap_or("no failure reported")
+                    ));
+                    break;
+                },
+                item = self.input_channel.recv() => match item {
+                    Some(item) => item,
+                    None => break,
+                },
+            };
+
+            for branch_channel in &self.branch_channels {
+                // A closed channel means the branch has exited, which is handled above.
+                let _ = branch_channel.send(item.clone()).await;
+            }
+        }
+
+        // Closing the branch channels lets branches drain in-flight items before shutting down.
+        drop(self.branch_channels);
+        self.children.shutdown();
+        self.children.finished().await;
+
+        debug!("Graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+}
+
+impl<T, O> fmt::Debug for FanOut<T, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FanOut")
+            .field("branches", &self.branches.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T, O> fmt::Debug for FanOutBuilder<T, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FanOutBuilder")
+            .field("branches", &self.branches.len())
+            .field("start_block", &self.start_block)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Forwards items from `input_channel` to `output_channel` through `f`, dropping the items for
+/// which it returns `None`, until either channel is closed or a shutdown is requested.
+async fn forward<I, O, F>(
+    mut input_channel: Receiver<I>,
+    output_channel: Sender<O>,
+    finish_handle: FinishHandle,
+    mut f: F,
+) where
+    F: FnMut(I) -> Option<O>,
+{
+    loop {
+        let item = tokio::select! {
+            _ = finish_handle.shutdown_requested() => break,
+            item = input_channel.recv() => match item {
+                Some(item) => item,
+                None => break,
+            },
+        };
+
+        if let Some(item) = f(item) {
+            if output_channel.send(item).await.is_err() {
+                debug!("Output channel closed");
+                break;
+            }
+        }
+    }
+
+    debug!("Graceful shutdown finished");
+    finish_handle.finish();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::prover::PipelineChainBuilder;
+
+    #[tokio::test]
+    async fn test_combinators() {
+        let (input_tx, input_rx) = tokio::sync::mpsc::channel::<u64>(8);
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel::<String>(8);
+        let tapped = Arc::new(Mutex::new(Vec::new()));
+
+        let pipeline = PipelineChainBuilder::new(
+            PipelineChainBuilder::new(
+                FilterStageBuilder::new(|n: &u64| *n < 2),
+                TapStageBuilder::new({
+                    let tapped = tapped.clone();
+                    move |n: &u64| tapped.lock().unwrap().push(*n)
+                }),
+            ),
+            FanOutBuilder::new()
+                .branch(MapStageBuilder::new(|n: u64| format!("a{}", n)))
+                .branch(MapStageBuilder::new(|n: u64| format!("b{}", n))),
+        )
+        .input_channel(input_rx)
+        .output_channel(output_tx)
+        .build()
+        .unwrap();
+        let shutdown_handle = pipeline.shutdown_handle();
+        pipeline.start();
+
+        for n in 0..4 {
+            input_tx.send(n).await.unwrap();
+        }
+
+        let mut outputs = Vec::new();
+        for _ in 0..4 {
+            outputs.push(output_rx.recv().await.unwrap());
+        }
+        outputs.sort();
+        assert_eq!(outputs, ["a0", "a1", "b0", "b1"]);
+        assert_eq!(*tapped.lock().unwrap(), [0, 1]);
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+        assert_eq!(shutdown_handle.failure(), None);
+    }
+}
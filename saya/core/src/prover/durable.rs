@@ -0,0 +1,332 @@
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tracing::{debug, error, info};
+
+use crate::{
+    prover::{HasBlockNumber, PipelineStage, PipelineStageBuilder},
+    service::{ChildServices, Daemon, FinishHandle, ShutdownHandle},
+    storage::QueueStorage,
+};
+
+/// Size of the channel between the durable queue and the wrapped stage.
+const QUEUE_BUFFER_SIZE: usize = 4;
+
+/// A pipeline stage persisting the input of another stage in a durable queue before handing it
+/// over.
+///
+/// Blocks stay in the queue until the next durable stage of the pipeline queues them, or until
+/// they're removed from storage (i.e. settled or marked as failed). On start, the queued blocks
+/// are replayed into the wrapped stage, so that a restarted pipeline resumes from where each block
+/// was left off. Blocks already queued at the same or a later `position` are skipped, which makes
+/// re-emitting blocks from upstream harmless.
+///
+/// Positions must increase along the pipeline.
+#[derive(Debug)]
+pub struct DurableStage<S, DB>
+where
+    S: PipelineStage,
+{
+    input_channel: Receiver<S::Input>,
+    stage_channel: Sender<S::Input>,
+    stage: S,
+    db: DB,
+    queue: &'static str,
+    position: u32,
+    finish_handle: FinishHandle,
+}
+
+#[derive(Debug)]
+pub struct DurableStageBuilder<B, DB>
+where
+    B: PipelineStageBuilder,
+{
+    builder: B,
+    input_channel: Option<Receiver<<B::Stage as PipelineStage>::Input>>,
+    db: DB,
+    queue: &'static str,
+    position: u32,
+}
+
+struct DurableStageState<T, DB> {
+    input_channel: Receiver<T>,
+    stage_channel: Sender<T>,
+    db: DB,
+    queue: &'static str,
+    position: u32,
+    children: ChildServices,
+    finish_handle: FinishHandle,
+}
+
+impl<B, DB> DurableStageBuilder<B, DB>
+where
+    B: PipelineStageBuilder,
+{
+    /// Wraps the stage built by `builder` behind the durable queue named `queue`, at `position`
+    /// in the pipeline.
+    pub fn new(builder: B, db: DB, queue: &'static str, position: u32) -> Self {
+        Self {
+            builder,
+            input_channel: None,
+            db,
+            queue,
+            position,
+        }
+    }
+}
+
+impl<B, S, DB> PipelineStageBuilder for DurableStageBuilder<B, DB>
+where
+    B: PipelineStageBuilder<Stage = S>,
+    S: PipelineStage,
+    S::Input: Serialize + DeserializeOwned + HasBlockNumber,
+    DB: QueueStorage + Send + Sync + 'static,
+{
+    type Stage = DurableStage<S, DB>;
+
+    fn build(self) -> Result<Self::Stage> {
+        let (stage_tx, stage_rx) = tokio::sync::mpsc::channel::<S::Input>(QUEUE_BUFFER_SIZE);
+
+        Ok(DurableStage {
+            input_channel: self
+                .input_channel
+                .ok_or_else(|| anyhow::anyhow!("`input_channel` not set"))?,
+            stage_channel: stage_tx,
+            stage: self.builder.input_channel(stage_rx).build()?,
+            db: self.db,
+            queue: self.queue,
+            position: self.position,
+            finish_handle: FinishHandle::new(),
+        })
+    }
+
+    fn input_channel(mut self, input_channel: Receiver<S::Input>) -> Self {
+        self.input_channel = Some(input_channel);
+        self
+    }
+
+    fn output_channel(mut self, output_channel: Sender<S::Output>) -> Self {
+        self.builder = self.builder.output_channel(output_channel);
+        self
+    }
+
+    fn start_block(mut self, start_block: u64) -> Self {
+        self.builder = self.builder.start_block(start_block);
+        self
+    }
+}
+
+impl<T, DB> DurableStageState<T, DB>
+where
+    T: Serialize + DeserializeOwned + HasBlockNumber,
+    DB: QueueStorage,
+{
+    async fn run(mut self) {
+        if let Err(err) = self.replay().await {
+            error!(queue = self.queue, error = %err, "Failed to replay durable queue");
+            self.finish_handle.fail(format!(
+                "failed to replay `{}` queue: {:#}",
+                self.queue, err
+            ));
+        } else {
+            loop {
+                let item = tokio::select! {
+                    _ = self.finish_handle.shutdown_requested() => break,
+                    (stage, failure) = self.children.exited() => {
+                        error!(stage, ?failure, "Pipeline stage exited unexpectedly");
+                        self.finish_handle.fail(format!(
+                            "{} stage exited unexpectedly: {}",
+                            stage,
+                            failure.as_deref().unwrap_or("no failure reported")
+                        ));
+                        break;
+                    },
+                    item = self.input_channel.recv() => match item {
+                        Some(item) => item,
+                        None => break,
+                    },
+                };
+
+                let block_number = item.block_number();
+                let queued = match serde_json::to_vec(&item) {
+                    Ok(payload) => self.enqueue(block_number, payload).await,
+                    Err(err) => Err(err.into()),
+                };
+                match queued {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        debug!(
+                            queue = self.queue,
+                            block_number, "Block already queued, skipping"
+                        );
+                        continue;
+                    }
+                    Err(err) => {
+                        error!(
+                            queue = self.queue,
+                            block_number,
+                            error = %err,
+                            "Failed to queue block"
+                        );
+                        self.finish_handle.fail(format!(
+                            "failed to queue block {} in `{}` queue: {:#}",
+                            block_number, self.queue, err
+                        ));
+                        break;
+                    }
+                }
+
+                if !self.forward(item).await {
+                    break;
+                }
+            }
+        }
+
+        // Closing the channel lets the stage drain in-flight blocks before shutting down.
+        drop(self.stage_channel);
+        self.children.shutdown();
+        self.children.finished().await;
+
+        debug!("Graceful shutdown finished");
+        self.finish_handle.finish();
+    }
+
+    /// Hands the blocks left in the queue by a previous run over to the stage.
+    async fn replay(&self) -> Result<()> {
+        let queued = self.db.get_queue(self.queue).await?;
+        if !queued.is_empty() {
+            info!(
+                queue = self.queue,
+                blocks = queued.len(),
+                "Replaying queued blocks"
+            );
+        }
+
+        for (_, payload) in queued {
+            if !self.forward(serde_json::from_slice(&payload)?).await {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn enqueue(&self, block_number: u64, payload: Vec<u8>) -> Result<bool> {
+        self.db
+            .enqueue(block_number.try_into()?, self.queue, self.position, payload)
+            .await
+    }
+
+    /// Sends an item to the stage, returning `false` if the stage or the queue is shutting down.
+    async fn forward(&self, item: T) -> bool {
+        tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => false,
+            sent = self.stage_channel.send(item) => sent.is_ok(),
+        }
+    }
+}
+
+impl<S, DB> PipelineStage for DurableStage<S, DB>
+where
+    S: PipelineStage,
+    S::Input: Serialize + DeserializeOwned + HasBlockNumber,
+    DB: QueueStorage + Send + Sync + 'static,
+{
+    type Input = S::Input;
+    type Output = S::Output;
+}
+
+impl<S, DB> Daemon for DurableStage<S, DB>
+where
+    S: PipelineStage,
+    S::Input: Serialize + DeserializeOwned + HasBlockNumber,
+    DB: QueueStorage + Send + Sync + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        let state = DurableStageState {
+            input_channel: self.input_channel,
+            stage_channel: self.stage_channel,
+            db: self.db,
+            queue: self.queue,
+            position: self.position,
+            children: ChildServices::new().with(self.queue, self.stage.shutdown_handle()),
+            finish_handle: self.finish_handle,
+        };
+
+        self.stage.start();
+
+        state.finish_handle.clone().spawn(state.run());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        block_ingestor::BlockInfo,
+        prover::MapStageBuilder,
+        storage::{BlockStatus, PersistantStorage, SqliteDb},
+    };
+
+    fn block(number: u64) -> BlockInfo {
+        BlockInfo {
+            number,
+            status: BlockStatus::Mined,
+            state_update: None,
+            first_block: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_durable_stage_resumes_queued_blocks() {
+        let db = SqliteDb::new(":memory:").await.unwrap();
+        for block_number in 1..=3 {
+            db.initialize_block(block_number).await.unwrap();
+        }
+
+        // A previous run left block 1 in the queue while block 2 had moved further down.
+        let payload = serde_json::to_vec(&block(1)).unwrap();
+        db.enqueue(1, "test", 0, payload).await.unwrap();
+        let payload = serde_json::to_vec(&block(2)).unwrap();
+        db.enqueue(2, "next", 1, payload).await.unwrap();
+
+        let (input_tx, input_rx) = tokio::sync::mpsc::channel::<BlockInfo>(4);
+        let (output_tx, mut output_rx) = tokio::sync::mpsc::channel::<u64>(4);
+        let stage = DurableStageBuilder::new(
+            MapStageBuilder::new(|block: BlockInfo| block.number),
+            db.clone(),
+            "test",
+            0,
+        )
+        .input_channel(input_rx)
+        .output_channel(output_tx)
+        .build()
+        .unwrap();
+        let shutdown_handle = stage.shutdown_handle();
+        stage.start();
+
+        // Upstream re-emits all blocks after a restart.
+        for block_number in 1..=3 {
+            input_tx.send(block(block_number)).await.unwrap();
+        }
+
+        assert_eq!(output_rx.recv().await, Some(1));
+        assert_eq!(output_rx.recv().await, Some(3));
+
+        let queued: Vec<u32> = db
+            .get_queue("test")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(block_number, _)| block_number)
+            .collect();
+        assert_eq!(queued, [1, 3]);
+
+        shutdown_handle.shutdown();
+        shutdown_handle.finished().await;
+        assert_eq!(shutdown_handle.failure(), None);
+    }
+}
@@ -14,6 +14,18 @@ pub use recursive::{PipelineChain, PipelineChainBuilder};
 mod block_orderer;
 pub use block_orderer::{BlockOrderer, BlockOrdererBuilder};
 
+mod block_batcher;
+pub use block_batcher::{BlockBatcher, BlockBatcherBuilder};
+
+mod durable;
+pub use durable::{DurableStage, DurableStageBuilder};
+
+mod combinators;
+pub use combinators::{
+    FanOut, FanOutBuilder, FilterStage, FilterStageBuilder, MapStage, MapStageBuilder, TapStage,
+    TapStageBuilder,
+};
+
 pub mod tee;
 pub use tee::{TeeProof, TeeProver, TeeProverBuilder};
 
@@ -23,20 +35,36 @@ use crate::data_availability::SovereignPacket;
 use swiftness_stark::types::StarkProof;
 
 /// Implemented by pipeline items that carry a block number.
+///
+/// Items covering a batch of blocks carry the last block of the batch.
 pub trait HasBlockNumber {
     fn block_number(&self) -> u64;
+
+    /// Returns the first block covered by the item, which is `block_number` unless the item
+    /// covers a batch of blocks.
+    fn first_block_number(&self) -> u64 {
+        self.block_number()
+    }
 }
 
 impl HasBlockNumber for BlockInfo {
     fn block_number(&self) -> u64 {
         self.number
     }
+
+    fn first_block_number(&self) -> u64 {
+        self.first_block.unwrap_or(self.number)
+    }
 }
 
 impl<P> HasBlockNumber for SnosProof<P> {
     fn block_number(&self) -> u64 {
         self.block_number
     }
+
+    fn first_block_number(&self) -> u64 {
+        self.first_block.unwrap_or(self.block_number)
+    }
 }
 
 pub trait PipelineStageBuilder {
@@ -68,6 +96,9 @@ pub trait PipelineStage: Daemon {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SnosProof<P> {
     pub block_number: u64,
+    /// First block of the batch proven along with `block_number`, see [`BlockInfo::first_block`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_block: Option<u64>,
     pub proof: P,
 }
 
@@ -1,10 +1,10 @@
 use anyhow::Result;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tracing::debug;
+use tracing::{debug, error};
 
 use crate::{
     prover::{PipelineStage, PipelineStageBuilder},
-    service::{Daemon, FinishHandle, ShutdownHandle},
+    service::{ChildServices, Daemon, FinishHandle, ShutdownHandle},
 };
 
 const BRIDGE_BUFFER_SIZE: usize = 4;
@@ -23,8 +23,7 @@ pub struct PipelineChainBuilder<U, D> {
 }
 
 struct PipelineChainState {
-    upstream_handle: ShutdownHandle,
-    downstream_handle: ShutdownHandle,
+    children: ChildServices,
     finish_handle: FinishHandle,
 }
 
@@ -83,18 +82,24 @@ where
 
 impl PipelineChainState {
     async fn run(self) {
-        self.finish_handle.shutdown_requested().await;
+        tokio::select! {
+            _ = self.finish_handle.shutdown_requested() => {},
+            (stage, failure) = self.children.exited() => {
+                // Propagate the failure so that the chain as a whole is seen as exited unexpectedly.
+                error!(stage, ?failure, "Pipeline stage exited unexpectedly");
+                self.finish_handle.fail(format!(
+                    "{} stage exited unexpectedly: {}",
+                    stage,
+                    failure.as_deref().unwrap_or("no failure reported")
+                ));
+            },
+        }
 
         // Request graceful shutdown for all descendant services
-        self.upstream_handle.shutdown();
-        self.downstream_handle.shutdown();
+        self.children.shutdown();
 
         // Wait for all descendant services to finish graceful shutdown
-        futures_util::future::join_all([
-            self.upstream_handle.finished(),
-            self.downstream_handle.finished(),
-        ])
-        .await;
+        self.children.finished().await;
 
         debug!("Graceful shutdown finished");
         self.finish_handle.finish();
@@ -121,14 +126,15 @@ where
 
     fn start(self) {
         let state = PipelineChainState {
-            upstream_handle: self.upstream.shutdown_handle(),
-            downstream_handle: self.downstream.shutdown_handle(),
+            children: ChildServices::new()
+                .with("upstream", self.upstream.shutdown_handle())
+                .with("downstream", self.downstream.shutdown_handle()),
             finish_handle: self.finish_handle,
         };
 
         self.upstream.start();
         self.downstream.start();
 
-        tokio::spawn(state.run());
+        state.finish_handle.clone().spawn(state.run());
     }
 }
@@ -49,6 +49,10 @@ impl HasBlockNumber for TeeProof {
     fn block_number(&self) -> u64 {
         self.blocks.last().expect("non-empty proof batch").number
     }
+
+    fn first_block_number(&self) -> u64 {
+        self.blocks.first().expect("non-empty proof batch").number
+    }
 }
 
 /// Placeholder prover that passes attestation fields through without generating a real proof.
@@ -110,7 +114,7 @@ impl Daemon for TeeProver {
     }
 
     fn start(self) {
-        tokio::spawn(self.run());
+        self.finish_handle.clone().spawn(self.run());
     }
 }
 
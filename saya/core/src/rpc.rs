@@ -0,0 +1,703 @@
+use std::{
+    fmt::Display,
+    future::Future,
+    num::NonZeroU32,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use futures_util::future::join_all;
+use jsonrpsee::core::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use starknet::providers::{
+    jsonrpc::{
+        HttpTransport, HttpTransportError, JsonRpcError, JsonRpcMethod, JsonRpcResponse,
+        JsonRpcTransport,
+    },
+    ProviderRequestData,
+};
+use tracing::{debug, info, warn};
+use url::Url;
+
+/// Time during which an endpoint that failed is only tried after all healthy endpoints.
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// Number of blocks an endpoint can be behind the most advanced one before it is only tried after
+/// the endpoints that are up to date.
+const MAX_BLOCK_LAG: u64 = 5;
+
+/// JSON-RPC error code of an internal error.
+const INTERNAL_ERROR: i64 = -32603;
+
+/// JSON-RPC error codes reserved for implementation-defined server errors, used by nodes and
+/// gateways to report being overloaded or unavailable.
+const SERVER_ERRORS: std::ops::RangeInclusive<i64> = -32099..=-32000;
+
+/// A JSON-RPC transport spreading requests over several endpoints of the same network.
+///
+/// Requests go to the first healthy endpoint, in the order the endpoints were given. An endpoint
+/// failing to answer (network error, invalid response or timeout) or answering with an internal or
+/// server error is marked unhealthy and the request fails over to the next endpoint. Unhealthy
+/// endpoints are tried again once their cooldown expires, or as a last resort when all endpoints
+/// are unhealthy.
+///
+/// When a health check interval is configured, every endpoint is also asked for its latest block
+/// at that interval: endpoints not answering are marked unhealthy, those answering are marked
+/// healthy again, and healthy endpoints lagging more than [`MAX_BLOCK_LAG`] blocks behind are
+/// only tried after the ones that are up to date.
+///
+/// Other JSON-RPC errors are valid answers and never cause a failover. Clones share the health of
+/// the endpoints.
+///
+/// All endpoints share a single HTTP client, so that clones used across components reuse the same
+/// connection pool and rate limit.
+#[derive(Debug, Clone)]
+pub struct FailoverTransport {
+    endpoints: Arc<[Endpoint]>,
+    request_timeout: Duration,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+/// Settings of the HTTP client behind a [`FailoverTransport`].
+#[derive(Debug, Clone)]
+pub struct RpcClientConfig {
+    /// Time an endpoint is given to answer a request before the next endpoint is tried.
+    pub request_timeout: Duration,
+    /// Time given to establish a connection to an endpoint.
+    pub connect_timeout: Duration,
+    /// Maximum number of idle connections kept open to each endpoint.
+    pub max_idle_connections: usize,
+    /// Maximum number of requests sent per second over all endpoints. Unlimited if not set.
+    pub max_requests_per_second: Option<NonZeroU32>,
+    /// Interval at which the health of the endpoints is checked in the background. Endpoints are
+    /// only checked when used if not set.
+    pub health_check_interval: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: Url,
+    client: reqwest::Client,
+    transport: HttpTransport,
+    unhealthy_until: Mutex<Option<Instant>>,
+    latest_block: Mutex<Option<u64>>,
+}
+
+/// Where an endpoint stands among the candidates of a request, the most preferred first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Standing {
+    Synced,
+    Lagging,
+    Unhealthy,
+}
+
+/// Spaces requests evenly so that no more than a given number are sent per second.
+#[derive(Debug)]
+struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<tokio::time::Instant>,
+}
+
+/// A request for a method outside of the Starknet JSON-RPC specification.
+#[derive(Debug, Serialize)]
+struct CustomRequest<'a, P> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: P,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FailoverTransportError {
+    #[error("request to {url} failed: {source}")]
+    Http {
+        url: Url,
+        source: HttpTransportError,
+    },
+    #[error("request to {url} failed: {source}")]
+    Request { url: Url, source: reqwest::Error },
+    #[error("request to {url} failed: {message}")]
+    Client { url: Url, message: String },
+    #[error("request to {url} timed out after {timeout:?}")]
+    Timeout { url: Url, timeout: Duration },
+    #[error("{url} is unavailable: JSON-RPC error {code}: {message}")]
+    Unavailable {
+        url: Url,
+        code: i64,
+        message: String,
+    },
+}
+
+impl Default for RpcClientConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            max_idle_connections: 32,
+            max_requests_per_second: None,
+            health_check_interval: None,
+        }
+    }
+}
+
+impl FailoverTransport {
+    /// Creates a transport over `urls`, in order of preference, with the default settings.
+    pub fn new<I>(urls: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Url>,
+    {
+        Self::with_config(urls, &RpcClientConfig::default())
+    }
+
+    /// Creates a transport over `urls`, in order of preference.
+    pub fn with_config<I>(urls: I, config: &RpcClientConfig) -> Result<Self>
+    where
+        I: IntoIterator<Item = Url>,
+    {
+        let client = reqwest::Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .pool_max_idle_per_host(config.max_idle_connections)
+            .build()?;
+
+        let endpoints: Arc<[Endpoint]> = urls
+            .into_iter()
+            .map(|url| Endpoint {
+                transport: HttpTransport::new_with_client(url.clone(), client.clone()),
+                client: client.clone(),
+                url,
+                unhealthy_until: Mutex::new(None),
+                latest_block: Mutex::new(None),
+            })
+            .collect();
+        if endpoints.is_empty() {
+            anyhow::bail!("no RPC endpoint given");
+        }
+
+        let transport = Self {
+            endpoints,
+            request_timeout: config.request_timeout,
+            rate_limiter: config
+                .max_requests_per_second
+                .map(|max_requests_per_second| Arc::new(RateLimiter::new(max_requests_per_second))),
+        };
+        if let Some(interval) = config.health_check_interval {
+            transport.spawn_health_check(interval)?;
+        }
+
+        Ok(transport)
+    }
+
+    /// Returns the URL of the endpoint requests currently go to, for clients that can only talk
+    /// to a single endpoint.
+    pub fn url(&self) -> &Url {
+        let endpoint = self.candidates()[0];
+        &endpoint.url
+    }
+
+    /// Sends a request for a method outside of the Starknet JSON-RPC specification, such as the
+    /// `tee_` methods of Katana, with the same failover, rate limit and timeout as other requests.
+    pub async fn send_custom_request<P, R>(
+        &self,
+        method: &str,
+        params: P,
+    ) -> Result<JsonRpcResponse<R>, FailoverTransportError>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let request = CustomRequest {
+            jsonrpc: "2.0",
+            id: 1,
+            method,
+            params,
+        };
+
+        self.try_endpoints(
+            |endpoint| endpoint.send_custom_request(&request),
+            unavailable_error,
+        )
+        .await
+    }
+
+    /// Runs `request` against the URL of each endpoint in turn until it succeeds, for clients
+    /// that can only be built from a URL. Endpoints failing are marked unhealthy as with any
+    /// other request.
+    pub async fn try_urls<'a, F, Fut, T, E>(
+        &'a self,
+        request: F,
+    ) -> Result<T, FailoverTransportError>
+    where
+        F: Fn(&'a Url) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        self.try_endpoints(
+            |endpoint| {
+                let response = request(&endpoint.url);
+                async move {
+                    response
+                        .await
+                        .map_err(|error| FailoverTransportError::Client {
+                            url: endpoint.url.clone(),
+                            message: error.to_string(),
+                        })
+                }
+            },
+            |_| None,
+        )
+        .await
+    }
+
+    /// Returns the endpoints in the order they should be tried: healthy and up to date ones
+    /// first, then healthy ones lagging behind, then unhealthy ones, each in configured order.
+    fn candidates(&self) -> Vec<&Endpoint> {
+        let now = Instant::now();
+        let highest_block = self
+            .endpoints
+            .iter()
+            .filter_map(|endpoint| *endpoint.latest_block.lock().unwrap())
+            .max();
+
+        let mut candidates: Vec<_> = self.endpoints.iter().collect();
+        candidates.sort_by_key(|endpoint| endpoint.standing(now, highest_block));
+        candidates
+    }
+
+    /// Checks the health of all endpoints every `interval` until all clones of the transport are
+    /// dropped.
+    fn spawn_health_check(&self, interval: Duration) -> Result<()> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|_| anyhow::anyhow!("RPC health checks need a tokio runtime"))?;
+
+        let endpoints = Arc::downgrade(&self.endpoints);
+        let request_timeout = self.request_timeout;
+        let rate_limiter = self.rate_limiter.clone();
+        runtime.spawn(async move {
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                ticks.tick().await;
+                let Some(endpoints) = Weak::upgrade(&endpoints) else {
+                    break;
+                };
+
+                join_all(endpoints.iter().map(|endpoint| async {
+                    if let Some(rate_limiter) = &rate_limiter {
+                        rate_limiter.acquire().await;
+                    }
+                    endpoint.check_health(request_timeout).await
+                }))
+                .await;
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Sends a request to each endpoint in turn until one of them answers with a response
+    /// `unavailable` finds no internal or server error in.
+    ///
+    /// When all endpoints are unavailable, the last response carrying such an error is returned so
+    /// that the caller gets the JSON-RPC error.
+    async fn try_endpoints<'a, F, Fut, T, U>(
+        &'a self,
+        request: F,
+        unavailable: U,
+    ) -> Result<T, FailoverTransportError>
+    where
+        F: Fn(&'a Endpoint) -> Fut,
+        Fut: Future<Output = Result<T, FailoverTransportError>>,
+        U: Fn(&T) -> Option<&JsonRpcError>,
+    {
+        let candidates = self.candidates();
+        let mut last_error = None;
+        let mut last_unavailable_response = None;
+
+        for (index, endpoint) in candidates.iter().enumerate() {
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire().await;
+            }
+
+            let response = tokio::time::timeout(self.request_timeout, request(endpoint)).await;
+            let error = match response {
+                Ok(Ok(response)) => match unavailable(&response) {
+                    None => {
+                        endpoint.mark_healthy();
+                        return Ok(response);
+                    }
+                    Some(error) => {
+                        let error = FailoverTransportError::Unavailable {
+                            url: endpoint.url.clone(),
+                            code: error.code,
+                            message: error.message.clone(),
+                        };
+                        last_unavailable_response = Some(response);
+                        error
+                    }
+                },
+                Ok(Err(error)) => error,
+                Err(_) => FailoverTransportError::Timeout {
+                    url: endpoint.url.clone(),
+                    timeout: self.request_timeout,
+                },
+            };
+
+            endpoint.mark_unhealthy();
+            if let Some(next) = candidates.get(index + 1) {
+                warn!(error = %error, next_url = %next.url, "RPC endpoint failed, failing over");
+            }
+            last_error = Some(error);
+        }
+
+        match last_unavailable_response {
+            Some(response) => Ok(response),
+            None => Err(last_error.expect("at least one endpoint")),
+        }
+    }
+}
+
+impl Endpoint {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until
+            .lock()
+            .unwrap()
+            .is_none_or(|unhealthy_until| now >= unhealthy_until)
+    }
+
+    fn standing(&self, now: Instant, highest_block: Option<u64>) -> Standing {
+        if !self.is_healthy(now) {
+            return Standing::Unhealthy;
+        }
+
+        match (*self.latest_block.lock().unwrap(), highest_block) {
+            (Some(latest_block), Some(highest_block))
+                if highest_block - latest_block > MAX_BLOCK_LAG =>
+            {
+                Standing::Lagging
+            }
+            _ => Standing::Synced,
+        }
+    }
+
+    fn mark_healthy(&self) {
+        if self.unhealthy_until.lock().unwrap().take().is_some() {
+            info!(url = %self.url, "RPC endpoint recovered");
+        }
+    }
+
+    fn mark_unhealthy(&self) {
+        *self.unhealthy_until.lock().unwrap() = Some(Instant::now() + UNHEALTHY_COOLDOWN);
+    }
+
+    async fn send_request<P, R>(
+        &self,
+        method: JsonRpcMethod,
+        params: P,
+    ) -> Result<JsonRpcResponse<R>, FailoverTransportError>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        self.transport
+            .send_request(method, params)
+            .await
+            .map_err(|source| self.http_error(source))
+    }
+
+    async fn send_requests(
+        &self,
+        requests: &[ProviderRequestData],
+    ) -> Result<Vec<JsonRpcResponse<serde_json::Value>>, FailoverTransportError> {
+        self.transport
+            .send_requests(requests)
+            .await
+            .map_err(|source| self.http_error(source))
+    }
+
+    async fn send_custom_request<P, R>(
+        &self,
+        request: &CustomRequest<'_, P>,
+    ) -> Result<JsonRpcResponse<R>, FailoverTransportError>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let response = async {
+            self.client
+                .post(self.url.clone())
+                .json(request)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await
+        };
+
+        response
+            .await
+            .map_err(|source| FailoverTransportError::Request {
+                url: self.url.clone(),
+                source,
+            })
+    }
+
+    fn http_error(&self, source: HttpTransportError) -> FailoverTransportError {
+        FailoverTransportError::Http {
+            url: self.url.clone(),
+            source,
+        }
+    }
+
+    /// Asks the endpoint for its latest block, marking it healthy if it answers in time and
+    /// unhealthy otherwise.
+    async fn check_health(&self, timeout: Duration) {
+        let response = tokio::time::timeout(
+            timeout,
+            self.send_request::<_, u64>(JsonRpcMethod::BlockNumber, serde_json::json!([])),
+        )
+        .await;
+
+        let error = match response {
+            Ok(Ok(JsonRpcResponse::Success { result, .. })) => {
+                *self.latest_block.lock().unwrap() = Some(result);
+                self.mark_healthy();
+                return;
+            }
+            Ok(Ok(JsonRpcResponse::Error { error, .. })) => FailoverTransportError::Unavailable {
+                url: self.url.clone(),
+                code: error.code,
+                message: error.message,
+            },
+            Ok(Err(error)) => error,
+            Err(_) => FailoverTransportError::Timeout {
+                url: self.url.clone(),
+                timeout,
+            },
+        };
+
+        if self.is_healthy(Instant::now()) {
+            warn!(error = %error, "RPC endpoint health check failed");
+        } else {
+            debug!(error = %error, "RPC endpoint still unhealthy");
+        }
+        self.mark_unhealthy();
+    }
+}
+
+/// Returns the internal or server error a response carries, if any.
+fn unavailable_error<T>(response: &JsonRpcResponse<T>) -> Option<&JsonRpcError> {
+    match response {
+        JsonRpcResponse::Error { error, .. }
+            if error.code == INTERNAL_ERROR || SERVER_ERRORS.contains(&error.code) =>
+        {
+            Some(error)
+        }
+        _ => None,
+    }
+}
+
+impl RateLimiter {
+    fn new(max_requests_per_second: NonZeroU32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / max_requests_per_second.get(),
+            next_slot: Mutex::new(tokio::time::Instant::now()),
+        }
+    }
+
+    /// Waits for the next free slot.
+    async fn acquire(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(tokio::time::Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+#[async_trait]
+impl JsonRpcTransport for FailoverTransport {
+    type Error = FailoverTransportError;
+
+    async fn send_request<P, R>(
+        &self,
+        method: JsonRpcMethod,
+        params: P,
+    ) -> Result<JsonRpcResponse<R>, Self::Error>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        self.try_endpoints(
+            |endpoint| endpoint.send_request(method, &params),
+            unavailable_error,
+        )
+        .await
+    }
+
+    async fn send_requests<R>(
+        &self,
+        requests: R,
+    ) -> Result<Vec<JsonRpcResponse<serde_json::Value>>, Self::Error>
+    where
+        R: AsRef<[ProviderRequestData]> + Send + Sync,
+    {
+        self.try_endpoints(
+            |endpoint| endpoint.send_requests(requests.as_ref()),
+            |responses| responses.iter().find_map(unavailable_error),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use jsonrpsee::{
+        server::{Server, ServerHandle},
+        types::ErrorObjectOwned,
+        RpcModule,
+    };
+    use starknet::providers::{JsonRpcClient, Provider};
+
+    use super::*;
+
+    async fn start_node(block_number: u64) -> (Url, ServerHandle) {
+        start_flaky_node(block_number, Arc::new(AtomicBool::new(true))).await
+    }
+
+    /// Starts a node answering with an internal error while `available` is false.
+    async fn start_flaky_node(
+        block_number: u64,
+        available: Arc<AtomicBool>,
+    ) -> (Url, ServerHandle) {
+        let mut module = RpcModule::new(());
+        module
+            .register_method("starknet_blockNumber", move |_, _, _| {
+                if available.load(Ordering::SeqCst) {
+                    Ok(block_number)
+                } else {
+                    Err(ErrorObjectOwned::owned(
+                        INTERNAL_ERROR as i32,
+                        "node unavailable",
+                        None::<()>,
+                    ))
+                }
+            })
+            .unwrap();
+
+        let server = Server::builder().build("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        (url.parse().unwrap(), server.start(module))
+    }
+
+    /// Waits for requests to go to `url`, returning whether they do in time.
+    async fn wait_for_url(transport: &FailoverTransport, url: &Url) -> bool {
+        for _ in 0..100 {
+            if transport.url() == url {
+                return true;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_next_endpoint() {
+        let (down_url, down_node) = start_node(1).await;
+        let (up_url, _up_node) = start_node(2).await;
+        down_node.stop().unwrap();
+        down_node.stopped().await;
+
+        let transport = FailoverTransport::new([down_url.clone(), up_url.clone()]).unwrap();
+        let provider = JsonRpcClient::new(transport.clone());
+
+        assert_eq!(provider.block_number().await.unwrap(), 2);
+        assert_eq!(transport.url(), &up_url);
+
+        // Requests keep going to the healthy endpoint.
+        assert_eq!(provider.block_number().await.unwrap(), 2);
+        assert!(!transport.endpoints[0].is_healthy(Instant::now()));
+    }
+
+    #[tokio::test]
+    async fn test_custom_request_fails_over() {
+        let (down_url, down_node) = start_node(1).await;
+        let (up_url, _up_node) = start_node(2).await;
+        down_node.stop().unwrap();
+        down_node.stopped().await;
+
+        let transport = FailoverTransport::new([down_url, up_url]).unwrap();
+        let response = transport
+            .send_custom_request::<_, u64>("starknet_blockNumber", serde_json::json!([]))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            response,
+            JsonRpcResponse::Success { result: 2, .. }
+        ));
+        assert!(!transport.endpoints[0].is_healthy(Instant::now()));
+    }
+
+    #[tokio::test]
+    async fn test_failover_on_internal_error() {
+        let (failing_url, _failing_node) =
+            start_flaky_node(1, Arc::new(AtomicBool::new(false))).await;
+        let (up_url, _up_node) = start_node(2).await;
+
+        let transport = FailoverTransport::new([failing_url.clone(), up_url.clone()]).unwrap();
+        let provider = JsonRpcClient::new(transport.clone());
+
+        assert_eq!(provider.block_number().await.unwrap(), 2);
+        assert_eq!(transport.url(), &up_url);
+        assert!(!transport.endpoints[0].is_healthy(Instant::now()));
+
+        // The JSON-RPC error reaches the caller when no endpoint can answer.
+        let transport = FailoverTransport::new([failing_url]).unwrap();
+        assert!(JsonRpcClient::new(transport).block_number().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_health_check_orders_endpoints() {
+        let available = Arc::new(AtomicBool::new(true));
+        let (lagging_url, _lagging_node) = start_node(1).await;
+        let (flaky_url, _flaky_node) = start_flaky_node(10, available.clone()).await;
+        let (up_url, _up_node) = start_node(10).await;
+
+        let config = RpcClientConfig {
+            health_check_interval: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let transport = FailoverTransport::with_config(
+            [lagging_url, flaky_url.clone(), up_url.clone()],
+            &config,
+        )
+        .unwrap();
+
+        // The lagging endpoint is skipped.
+        assert!(wait_for_url(&transport, &flaky_url).await);
+
+        // An endpoint is marked down and up again without any request going through it.
+        available.store(false, Ordering::SeqCst);
+        assert!(wait_for_url(&transport, &up_url).await);
+        available.store(true, Ordering::SeqCst);
+        assert!(wait_for_url(&transport, &flaky_url).await);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_spaces_requests() {
+        let rate_limiter = RateLimiter::new(NonZeroU32::new(4).unwrap());
+        let start = tokio::time::Instant::now();
+
+        for _ in 0..5 {
+            rate_limiter.acquire().await;
+        }
+
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+}
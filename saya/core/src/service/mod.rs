@@ -1,5 +1,21 @@
+use std::{
+    any::Any,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
+mod pause;
+pub use pause::PauseHandle;
+
+mod supervisor;
+pub use supervisor::{ChildServices, RestartPolicy, Supervisor};
+
+mod worker_pool;
+pub use worker_pool::{TaskGuard, WorkerHandle, WorkerPool, WorkerPoolConfig};
+
 /// Long-running background services that support graceful shutdown.
 pub trait Daemon: Send {
     fn shutdown_handle(&self) -> ShutdownHandle;
@@ -14,6 +30,7 @@ pub trait Daemon: Send {
 pub struct FinishHandle {
     cancellation: CancellationToken,
     finish: CancellationToken,
+    failure: Arc<Mutex<Option<String>>>,
 }
 
 /// A type for requesting cancellation of background running services and waiting for them to have
@@ -22,6 +39,7 @@ pub struct FinishHandle {
 pub struct ShutdownHandle {
     cancellation: CancellationToken,
     finish: CancellationToken,
+    failure: Arc<Mutex<Option<String>>>,
 }
 
 impl FinishHandle {
@@ -33,6 +51,7 @@ impl FinishHandle {
         ShutdownHandle {
             cancellation: self.cancellation.clone(),
             finish: self.finish.clone(),
+            failure: self.failure.clone(),
         }
     }
 
@@ -41,6 +60,73 @@ impl FinishHandle {
         self.finish.cancel();
     }
 
+    /// Records the reason why the service is ending execution abnormally.
+    ///
+    /// Must be called before [`finish`] for the failure to be visible to anyone waiting on
+    /// [`ShutdownHandle::finished`]. Only the first recorded failure is kept.
+    pub fn fail<R: Into<String>>(&self, reason: R) {
+        let mut failure = self.failure.lock().unwrap();
+        if failure.is_none() {
+            *failure = Some(reason.into());
+        }
+    }
+
+    /// Records a failure and requests a shutdown of the service, for when a worker hits an error
+    /// the service can't recover from.
+    pub fn abort<R: Into<String>>(&self, reason: R) {
+        self.fail(reason);
+        self.cancellation.cancel();
+    }
+
+    /// Spawns the main task of the service.
+    ///
+    /// Should the task panic, the panic is recorded as a failure and the service is signaled as
+    /// finished, so that a crashed service never goes unnoticed.
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let finish_handle = self.clone();
+        let task = tokio::spawn(task);
+
+        tokio::spawn(async move {
+            if let Err(err) = task.await {
+                if err.is_panic() {
+                    finish_handle.fail(format!(
+                        "task panicked: {}",
+                        panic_message(err.into_panic())
+                    ));
+                    finish_handle.cancellation.cancel();
+                    finish_handle.finish();
+                }
+            }
+        });
+    }
+
+    /// Spawns a worker task of the service.
+    ///
+    /// Should the worker panic, the panic is recorded as a failure and a shutdown of the service
+    /// is requested, so that the service winds down instead of running with fewer workers.
+    pub fn spawn_worker<F>(&self, task: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let finish_handle = self.clone();
+        let task = tokio::spawn(task);
+
+        tokio::spawn(async move {
+            if let Err(err) = task.await {
+                if err.is_panic() {
+                    finish_handle.fail(format!(
+                        "worker panicked: {}",
+                        panic_message(err.into_panic())
+                    ));
+                    finish_handle.cancellation.cancel();
+                }
+            }
+        })
+    }
+
     /// Checks whether any shutdown request has been made via [`shutdown`].
     pub fn is_shutdown_requested(&self) -> bool {
         self.cancellation.is_cancelled()
@@ -68,4 +154,29 @@ impl ShutdownHandle {
     pub async fn finished(&self) {
         self.finish.cancelled().await
     }
+
+    /// Checks whether a shutdown has been requested via [`shutdown`].
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Checks whether the service has finished execution.
+    pub fn is_finished(&self) -> bool {
+        self.finish.is_cancelled()
+    }
+
+    /// Returns the failure recorded by the service via [`FinishHandle::fail`], if any.
+    pub fn failure(&self) -> Option<String> {
+        self.failure.lock().unwrap().clone()
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
 }
@@ -0,0 +1,74 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// A handle for pausing and resuming services at runtime without shutting them down.
+///
+/// Cheap to clone. All clones control the same state, which makes it possible to keep a single
+/// handle across orchestrator restarts. It's up to each service to decide where it stops when
+/// paused; block ingestors stop dispatching new blocks, letting in-flight blocks drain.
+#[derive(Debug, Clone)]
+pub struct PauseHandle {
+    paused: Arc<watch::Sender<bool>>,
+}
+
+impl PauseHandle {
+    pub fn new() -> Self {
+        Self {
+            paused: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    /// Requests services to pause, returning whether they were already paused.
+    pub fn pause(&self) -> bool {
+        self.paused.send_replace(true)
+    }
+
+    /// Requests services to resume, returning whether they were paused.
+    pub fn resume(&self) -> bool {
+        self.paused.send_replace(false)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Waits asynchronously until services are not paused. Resolves immediately if not paused.
+    pub async fn resumed(&self) {
+        let mut paused = self.paused.subscribe();
+        // The sender is kept alive by `self`, so this can't fail.
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+}
+
+impl Default for PauseHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resumed_waits_for_resume() {
+        let handle = PauseHandle::new();
+        handle.resumed().await;
+
+        assert!(!handle.pause());
+        assert!(handle.is_paused());
+
+        let waiter = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.resumed().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+
+        assert!(handle.resume());
+        waiter.await.unwrap();
+    }
+}
@@ -0,0 +1,290 @@
+use std::{borrow::Cow, future::Future, time::Duration};
+
+use anyhow::Result;
+use tokio::time::Instant;
+use tracing::{debug, error, warn};
+
+use crate::service::{Daemon, FinishHandle, ShutdownHandle};
+
+/// A supervised service that has been running for at least this long is considered healthy again,
+/// resetting the consecutive restart counter.
+const RESTART_RESET_WINDOW: Duration = Duration::from_secs(600);
+
+/// The set of descendant services spawned by an orchestrator, tracked by name.
+///
+/// Descendant services are only ever expected to finish after the orchestrator has requested them
+/// to, so any of them finishing on its own is treated as an unexpected exit.
+#[derive(Debug, Default, Clone)]
+pub struct ChildServices {
+    children: Vec<(Cow<'static, str>, ShutdownHandle)>,
+}
+
+/// What to do when a supervised service finishes with a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Tear everything down and surface the failure.
+    Exit,
+    /// Rebuild and restart the service, giving up after `max_restarts` consecutive failures.
+    Restart {
+        max_restarts: u32,
+        backoff: Duration,
+    },
+}
+
+/// A service that builds and runs another service, applying a [`RestartPolicy`] whenever it
+/// finishes with a failure.
+///
+/// The service is rebuilt from scratch via the factory on each restart, so it picks up from
+/// whatever state has been persisted (e.g. the last settled block) instead of in-memory state.
+#[derive(Debug)]
+pub struct Supervisor<F> {
+    factory: F,
+    policy: RestartPolicy,
+    finish_handle: FinishHandle,
+}
+
+impl ChildServices {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<N>(mut self, name: N, handle: ShutdownHandle) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        self.children.push((name.into(), handle));
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ShutdownHandle)> {
+        self.children
+            .iter()
+            .map(|(name, handle)| (name.as_ref(), handle))
+    }
+
+    /// Waits asynchronously for any of the services to finish, returning its name and the
+    /// failure it recorded, if any.
+    ///
+    /// Never resolves if no service is tracked.
+    pub async fn exited(&self) -> (&str, Option<String>) {
+        if self.children.is_empty() {
+            return std::future::pending().await;
+        }
+
+        let (_, index, _) = futures_util::future::select_all(
+            self.children
+                .iter()
+                .map(|(_, handle)| Box::pin(handle.finished())),
+        )
+        .await;
+
+        let (name, handle) = &self.children[index];
+        (name.as_ref(), handle.failure())
+    }
+
+    /// Requests graceful shutdown for all services.
+    pub fn shutdown(&self) {
+        for (_, handle) in self.children.iter() {
+            handle.shutdown();
+        }
+    }
+
+    /// Waits asynchronously for all services to finish.
+    pub async fn finished(&self) {
+        futures_util::future::join_all(self.children.iter().map(|(_, handle)| handle.finished()))
+            .await;
+    }
+}
+
+impl<F, Fut, D> Supervisor<F>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<D>> + Send + 'static,
+    D: Daemon + 'static,
+{
+    pub fn new(policy: RestartPolicy, factory: F) -> Self {
+        Self {
+            factory,
+            policy,
+            finish_handle: FinishHandle::new(),
+        }
+    }
+
+    async fn run(mut self) {
+        let mut restarts = 0;
+
+        loop {
+            let started_at = Instant::now();
+
+            let failure = match (self.factory)().await {
+                Ok(service) => {
+                    let service_handle = service.shutdown_handle();
+                    service.start();
+
+                    tokio::select! {
+                        _ = self.finish_handle.shutdown_requested() => {
+                            service_handle.shutdown();
+                            service_handle.finished().await;
+                            break;
+                        },
+                        _ = service_handle.finished() => {},
+                    }
+
+                    match service_handle.failure() {
+                        Some(failure) => failure,
+                        None => {
+                            debug!("Supervised service finished");
+                            break;
+                        }
+                    }
+                }
+                Err(err) => format!("failed to build service: {err:#}"),
+            };
+
+            if started_at.elapsed() >= RESTART_RESET_WINDOW {
+                restarts = 0;
+            }
+
+            match self.policy {
+                RestartPolicy::Restart {
+                    max_restarts,
+                    backoff,
+                } if restarts < max_restarts => {
+                    restarts += 1;
+                    warn!(
+                        %failure,
+                        restarts,
+                        max_restarts,
+                        "Supervised service failed, restarting in {}s",
+                        backoff.as_secs()
+                    );
+
+                    tokio::select! {
+                        _ = self.finish_handle.shutdown_requested() => break,
+                        _ = tokio::time::sleep(backoff) => {},
+                    }
+                }
+                _ => {
+                    error!(%failure, "Supervised service failed, giving up");
+                    self.finish_handle.fail(failure);
+                    break;
+                }
+            }
+        }
+
+        self.finish_handle.finish();
+    }
+}
+
+impl<F, Fut, D> Daemon for Supervisor<F>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<D>> + Send + 'static,
+    D: Daemon + 'static,
+{
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        self.finish_handle.shutdown_handle()
+    }
+
+    fn start(self) {
+        self.finish_handle.clone().spawn(self.run());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    struct CrashingService {
+        finish_handle: FinishHandle,
+    }
+
+    impl Daemon for CrashingService {
+        fn shutdown_handle(&self) -> ShutdownHandle {
+            self.finish_handle.shutdown_handle()
+        }
+
+        fn start(self) {
+            self.finish_handle.spawn(async { panic!("boom") });
+        }
+    }
+
+    fn crashing_service_factory(
+        builds: Arc<AtomicU32>,
+    ) -> impl FnMut() -> std::future::Ready<Result<CrashingService>> + Send + 'static {
+        move || {
+            builds.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(Ok(CrashingService {
+                finish_handle: FinishHandle::new(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_child_services_detect_crash() {
+        let crashing = CrashingService {
+            finish_handle: FinishHandle::new(),
+        };
+        let children = ChildServices::new().with("crashing", crashing.shutdown_handle());
+        crashing.start();
+
+        let (name, failure) = children.exited().await;
+        assert_eq!(name, "crashing");
+        assert!(failure.unwrap().contains("boom"));
+    }
+
+    #[tokio::test]
+    async fn test_child_services_report_exited_child() {
+        let running = FinishHandle::new();
+        let crashing = CrashingService {
+            finish_handle: FinishHandle::new(),
+        };
+        let children = ChildServices::new()
+            .with("child-0", running.shutdown_handle())
+            .with(format!("child-{}", 1), crashing.shutdown_handle());
+        crashing.start();
+
+        let (name, failure) = children.exited().await;
+        assert_eq!(name, "child-1");
+        assert!(failure.unwrap().contains("boom"));
+        assert!(!running.is_shutdown_requested());
+    }
+
+    #[tokio::test]
+    async fn test_supervisor_exit_policy() {
+        let builds = Arc::new(AtomicU32::new(0));
+        let supervisor = Supervisor::new(
+            RestartPolicy::Exit,
+            crashing_service_factory(builds.clone()),
+        );
+        let handle = supervisor.shutdown_handle();
+        supervisor.start();
+
+        handle.finished().await;
+        assert_eq!(builds.load(Ordering::SeqCst), 1);
+        assert!(handle.failure().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_supervisor_restart_policy_gives_up() {
+        let builds = Arc::new(AtomicU32::new(0));
+        let supervisor = Supervisor::new(
+            RestartPolicy::Restart {
+                max_restarts: 2,
+                backoff: Duration::ZERO,
+            },
+            crashing_service_factory(builds.clone()),
+        );
+        let handle = supervisor.shutdown_handle();
+        supervisor.start();
+
+        handle.finished().await;
+        assert_eq!(builds.load(Ordering::SeqCst), 3);
+        assert!(handle.failure().unwrap().contains("boom"));
+    }
+}
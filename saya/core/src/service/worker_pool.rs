@@ -0,0 +1,343 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::{stream::FuturesUnordered, StreamExt};
+use tokio::sync::{mpsc::Receiver, Mutex as AsyncMutex};
+use tracing::{debug, info};
+
+use crate::{metrics, service::FinishHandle};
+
+/// Interval between two scaling decisions.
+const SCALING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Minimum time a worker stays idle before it's retired. The actual timeout grows with the stage
+/// latency so that slow stages don't shed workers in between two blocks.
+const MIN_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Weight of the latest sample in the exponential moving average of the stage latency.
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Bounds on the number of workers of a [`WorkerPool`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkerPoolConfig {
+    initial_workers: usize,
+    min_workers: usize,
+    max_workers: usize,
+}
+
+/// A pool of workers pulling tasks from a shared channel, resized at runtime within the bounds
+/// of its [`WorkerPoolConfig`].
+///
+/// The pool grows when tasks are queued while all workers are busy, up to one worker per busy or
+/// queued task. It shrinks by retiring workers that stay idle for twice the measured task
+/// latency. A pool with equal bounds keeps a fixed number of workers.
+#[derive(Debug)]
+pub struct WorkerPool<T> {
+    stage: &'static str,
+    tasks: Arc<AsyncMutex<Receiver<T>>>,
+    config: WorkerPoolConfig,
+    state: Arc<PoolState>,
+    finish_handle: FinishHandle,
+}
+
+/// The handle of a single worker of a [`WorkerPool`], for pulling tasks from the pool.
+///
+/// Dropping the handle removes the worker from the pool.
+#[derive(Debug)]
+pub struct WorkerHandle<T> {
+    tasks: Arc<AsyncMutex<Receiver<T>>>,
+    min_workers: usize,
+    state: Arc<PoolState>,
+    retired: AtomicBool,
+}
+
+/// Marks a worker as busy until dropped, at which point the task latency is recorded.
+#[derive(Debug)]
+pub struct TaskGuard {
+    state: Arc<PoolState>,
+    started_at: Instant,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    stage: &'static str,
+    workers: AtomicUsize,
+    busy: AtomicUsize,
+    latency: Mutex<Option<Duration>>,
+}
+
+impl WorkerPoolConfig {
+    /// A pool keeping `workers` workers at all times.
+    pub fn fixed(workers: usize) -> Self {
+        Self::adaptive(workers, workers, workers)
+    }
+
+    /// A pool starting with `initial_workers`, resized between `min_workers` and `max_workers`.
+    ///
+    /// The pool always keeps at least one worker.
+    pub fn adaptive(initial_workers: usize, min_workers: usize, max_workers: usize) -> Self {
+        let min_workers = min_workers.max(1);
+        let max_workers = max_workers.max(min_workers);
+
+        Self {
+            initial_workers: initial_workers.clamp(min_workers, max_workers),
+            min_workers,
+            max_workers,
+        }
+    }
+
+    pub fn initial_workers(&self) -> usize {
+        self.initial_workers
+    }
+
+    fn is_adaptive(&self) -> bool {
+        self.min_workers != self.max_workers
+    }
+}
+
+impl<T> WorkerPool<T>
+where
+    T: Send + 'static,
+{
+    /// Creates a pool of workers for `stage`, pulling tasks from `tasks`. Worker panics are
+    /// reported through `finish_handle`.
+    pub fn new(
+        stage: &'static str,
+        tasks: Receiver<T>,
+        config: WorkerPoolConfig,
+        finish_handle: FinishHandle,
+    ) -> Self {
+        Self {
+            stage,
+            tasks: Arc::new(AsyncMutex::new(tasks)),
+            config,
+            state: Arc::new(PoolState {
+                stage,
+                workers: AtomicUsize::new(0),
+                busy: AtomicUsize::new(0),
+                latency: Mutex::new(None),
+            }),
+            finish_handle,
+        }
+    }
+
+    /// Runs `worker` on each worker of the pool, scaling the pool until all workers have exited.
+    ///
+    /// Workers are expected to exit once [`WorkerHandle::recv`] returns `None` or a shutdown has
+    /// been requested.
+    pub async fn run<F, Fut>(self, worker: F)
+    where
+        F: Fn(WorkerHandle<T>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut workers = FuturesUnordered::new();
+        for _ in 0..self.config.initial_workers {
+            workers.push(self.spawn(&worker));
+        }
+
+        let mut scaling = tokio::time::interval(SCALING_INTERVAL);
+        loop {
+            tokio::select! {
+                finished = workers.next() => {
+                    if finished.is_none() {
+                        break;
+                    }
+                },
+                _ = scaling.tick(), if self.config.is_adaptive() => {
+                    for _ in 0..self.missing_workers() {
+                        workers.push(self.spawn(&worker));
+                    }
+                },
+            }
+        }
+    }
+
+    fn spawn<F, Fut>(&self, worker: &F) -> tokio::task::JoinHandle<()>
+    where
+        F: Fn(WorkerHandle<T>) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let workers = self.state.workers.fetch_add(1, Ordering::SeqCst) + 1;
+        metrics::set_stage_workers(self.stage, workers);
+
+        self.finish_handle.spawn_worker(worker(WorkerHandle {
+            tasks: self.tasks.clone(),
+            min_workers: self.config.min_workers,
+            state: self.state.clone(),
+            retired: AtomicBool::new(false),
+        }))
+    }
+
+    /// Returns the number of workers to add for all busy and queued tasks to have a worker.
+    fn missing_workers(&self) -> usize {
+        if self.finish_handle.is_shutdown_requested() {
+            return 0;
+        }
+
+        // An idle worker waits for tasks while holding the lock, in which case nothing is queued.
+        let queued = match self.tasks.try_lock() {
+            Ok(tasks) if !tasks.is_closed() => tasks.len(),
+            _ => return 0,
+        };
+        if queued == 0 {
+            return 0;
+        }
+
+        let workers = self.state.workers.load(Ordering::SeqCst);
+        let busy = self.state.busy.load(Ordering::SeqCst);
+        let target = (busy + queued).clamp(self.config.min_workers, self.config.max_workers);
+        let missing = target.saturating_sub(workers);
+
+        if missing > 0 {
+            info!(
+                stage = self.stage,
+                workers,
+                busy,
+                queued,
+                new_workers = missing,
+                "Scaling up stage workers"
+            );
+        }
+
+        missing
+    }
+}
+
+impl<T> WorkerHandle<T> {
+    /// Receives the next task, or `None` if the worker should exit because the channel is closed
+    /// or the pool is shrinking.
+    ///
+    /// The worker is considered busy for as long as the returned [`TaskGuard`] is alive.
+    pub async fn recv(&self) -> Option<(T, TaskGuard)> {
+        loop {
+            let idle_timeout = self.state.idle_timeout();
+            let task =
+                tokio::time::timeout(idle_timeout, async { self.tasks.lock().await.recv().await })
+                    .await;
+
+            match task {
+                Ok(Some(task)) => {
+                    self.state.busy.fetch_add(1, Ordering::SeqCst);
+                    return Some((
+                        task,
+                        TaskGuard {
+                            state: self.state.clone(),
+                            started_at: Instant::now(),
+                        },
+                    ));
+                }
+                Ok(None) => return None,
+                Err(_) if self.retire() => return None,
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Removes the worker from the pool unless the pool is at its minimum size.
+    fn retire(&self) -> bool {
+        let retired =
+            self.state
+                .workers
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |workers| {
+                    (workers > self.min_workers).then(|| workers - 1)
+                });
+
+        match retired {
+            Ok(workers) => {
+                self.retired.store(true, Ordering::SeqCst);
+                metrics::set_stage_workers(self.state.stage, workers - 1);
+                debug!(
+                    stage = self.state.stage,
+                    workers = workers - 1,
+                    "Retiring idle stage worker"
+                );
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl<T> Drop for WorkerHandle<T> {
+    fn drop(&mut self) {
+        if !self.retired.load(Ordering::SeqCst) {
+            let workers = self.state.workers.fetch_sub(1, Ordering::SeqCst) - 1;
+            metrics::set_stage_workers(self.state.stage, workers);
+        }
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.state.busy.fetch_sub(1, Ordering::SeqCst);
+        self.state.record_latency(self.started_at.elapsed());
+    }
+}
+
+impl PoolState {
+    fn record_latency(&self, sample: Duration) {
+        let mut latency = self.latency.lock().unwrap();
+        *latency = Some(match *latency {
+            Some(latency) => {
+                latency.mul_f64(1.0 - LATENCY_SMOOTHING) + sample.mul_f64(LATENCY_SMOOTHING)
+            }
+            None => sample,
+        });
+    }
+
+    fn idle_timeout(&self) -> Duration {
+        match *self.latency.lock().unwrap() {
+            Some(latency) => (latency * 2).max(MIN_IDLE_TIMEOUT),
+            None => MIN_IDLE_TIMEOUT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_worker_pool_scales_with_backlog() {
+        let (task_tx, task_rx) = tokio::sync::mpsc::channel::<u64>(4);
+        let (release_tx, release_rx) = tokio::sync::watch::channel(false);
+        let pool = WorkerPool::new(
+            "test",
+            task_rx,
+            WorkerPoolConfig::adaptive(1, 1, 3),
+            FinishHandle::new(),
+        );
+        let state = pool.state.clone();
+
+        let pool = tokio::spawn(pool.run(move |worker: WorkerHandle<u64>| {
+            let mut release_rx = release_rx.clone();
+            async move {
+                while let Some((_task, _guard)) = worker.recv().await {
+                    let _ = release_rx.wait_for(|released| *released).await;
+                }
+            }
+        }));
+
+        // One task keeps the only worker busy while the others queue up.
+        for task in 0..4 {
+            task_tx.send(task).await.unwrap();
+        }
+        tokio::time::sleep(SCALING_INTERVAL * 2).await;
+        assert_eq!(state.workers.load(Ordering::SeqCst), 3);
+
+        // Idle workers are retired down to the minimum.
+        release_tx.send(true).unwrap();
+        tokio::time::sleep(MIN_IDLE_TIMEOUT * 2).await;
+        assert_eq!(state.workers.load(Ordering::SeqCst), 1);
+
+        drop(task_tx);
+        pool.await.unwrap();
+        assert_eq!(state.workers.load(Ordering::SeqCst), 0);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::data_availability::DataAvailabilityPointer;
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use starknet::core::types::StateUpdate;
+use starknet_types_core::felt::Felt;
 use std::future::Future;
 
 mod in_memory;
@@ -31,13 +33,56 @@ pub enum Step {
     Snos,
     Bridge,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Query {
     SnosProof,
     BridgeProof,
     BridgeTrace,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+impl std::fmt::Display for Query {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Query::SnosProof => write!(f, "snos_proof"),
+            Query::BridgeProof => write!(f, "bridge_proof"),
+            Query::BridgeTrace => write!(f, "bridge_trace"),
+        }
+    }
+}
+
+impl From<&str> for Query {
+    fn from(s: &str) -> Self {
+        match s {
+            "snos_proof" => Query::SnosProof,
+            "bridge_proof" => Query::BridgeProof,
+            "bridge_trace" => Query::BridgeTrace,
+            _ => panic!("Invalid query type"),
+        }
+    }
+}
+
+/// A query submitted to a proving service for a block, recorded for accounting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryRecord {
+    pub query_id: String,
+    pub block_number: u32,
+    pub query_type: Query,
+    /// The job size declared to the proving service.
+    pub job_size: String,
+    /// Unix timestamp in seconds.
+    pub submitted_at: i64,
+    /// Unix timestamp in seconds at which the query ended, successfully or not.
+    pub completed_at: Option<i64>,
+    /// The cost reported by the proving service once the query ended, if any.
+    pub cost: Option<f64>,
+    /// Whether the query was given up on before it ended, in which case its cost is unknown.
+    pub abandoned: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum BlockStatus {
     Mined,
     SnosPieGenerated,
@@ -89,6 +134,19 @@ impl From<&str> for BlockStatus {
     }
 }
 
+/// Error returned for blocks not tracked in storage, which callers can tell apart from storage
+/// failures by downcasting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[error("block {0} not found")]
+pub struct BlockNotFound(pub u32);
+
+/// The hashes linking a rollup block to its parent, for detecting reorgs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockHashes {
+    pub block_hash: Felt,
+    pub parent_hash: Felt,
+}
+
 pub trait PersistantStorage {
     fn initialize_block(&self, block_number: u32) -> impl Future<Output = Result<()>> + Send;
 
@@ -120,6 +178,8 @@ pub trait PersistantStorage {
         step: Step,
     ) -> impl Future<Output = Result<Vec<u8>>> + Send;
 
+    /// Stores the ID of the latest query submitted for a block, counting it as one more attempt
+    /// of the block's query of this type.
     fn add_query_id(
         &self,
         block_number: u32,
@@ -133,12 +193,29 @@ pub trait PersistantStorage {
         query_type: Query,
     ) -> impl Future<Output = Result<String>> + Send;
 
+    /// Counts one more attempt of the block's query of this type, for queries which couldn't be
+    /// submitted.
+    fn add_query_attempt(
+        &self,
+        block_number: u32,
+        query_type: Query,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Returns the number of attempts of the block's query of this type, submitted or not, which
+    /// is reset along with the block once it's removed or marked as failed.
+    fn get_query_attempts(
+        &self,
+        block_number: u32,
+        query_type: Query,
+    ) -> impl Future<Output = Result<u32>> + Send;
+
     fn set_status(
         &self,
         block_number: u32,
         status: String,
     ) -> impl Future<Output = Result<()>> + Send;
 
+    /// Returns the status of a block, failing with [`BlockNotFound`] if it isn't tracked.
     fn get_status(&self, block_number: u32) -> impl Future<Output = Result<BlockStatus>> + Send;
 
     fn get_first_db_block(&self) -> impl Future<Output = Result<u32>> + Send;
@@ -166,4 +243,90 @@ pub trait PersistantStorage {
         &self,
         block_number: u32,
     ) -> impl Future<Output = Result<StateUpdate>> + Send;
+
+    /// Records the hashes of an ingested block, keeping the ones already recorded if any.
+    ///
+    /// Hashes outlive the block itself so that the next blocks can still be checked against it,
+    /// and are only pruned once a later block is removed.
+    fn add_block_hashes(
+        &self,
+        block_number: u32,
+        hashes: BlockHashes,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    fn get_block_hashes(
+        &self,
+        block_number: u32,
+    ) -> impl Future<Output = Result<Option<BlockHashes>>> + Send;
+
+    /// Records that blocks `first_block..=last_block` are proven together, keeping the batch
+    /// already recorded from `first_block` if any.
+    ///
+    /// Batches are pruned once their last block is removed.
+    fn add_block_batch(
+        &self,
+        first_block: u32,
+        last_block: u32,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Returns the first and last blocks of the recorded batch containing `block_number`.
+    fn get_block_batch(
+        &self,
+        block_number: u32,
+    ) -> impl Future<Output = Result<Option<(u32, u32)>>> + Send;
+
+    /// Records a submitted query. Records outlive their block, so that the cost of settled blocks
+    /// can still be accounted for.
+    fn add_query_record(&self, record: QueryRecord) -> impl Future<Output = Result<()>> + Send;
+
+    /// Records the end of a query along with its reported cost.
+    fn complete_query_record(
+        &self,
+        query_id: &str,
+        completed_at: i64,
+        cost: Option<f64>,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Records a query given up on before it ended, so that it's no longer considered in flight.
+    fn abandon_query_record(
+        &self,
+        query_id: &str,
+        abandoned_at: i64,
+    ) -> impl Future<Output = Result<()>> + Send;
+
+    /// Returns the queries submitted for a block, in submission order.
+    fn get_query_records(
+        &self,
+        block_number: u32,
+    ) -> impl Future<Output = Result<Vec<QueryRecord>>> + Send;
+
+    /// Returns the total reported cost of the queries which ended at or after `since`, a Unix
+    /// timestamp in seconds.
+    fn get_query_cost_since(&self, since: i64) -> impl Future<Output = Result<f64>> + Send;
+
+    /// Returns the estimated cost of the queries in flight, each costing the average reported
+    /// cost of the ended queries of the same type and job size, or nothing if there's none.
+    fn get_in_flight_query_cost(&self) -> impl Future<Output = Result<f64>> + Send;
+}
+
+/// Storage for the durable queues between pipeline stages (see
+/// [`DurableStage`](crate::prover::DurableStage)).
+///
+/// A block sits in at most one queue at a time and only ever moves to queues at later positions
+/// in the pipeline. Queued blocks are dropped along with the block itself, i.e. once settled or
+/// marked as failed.
+pub trait QueueStorage {
+    /// Moves a block into `queue`, unless it's already queued at the same or a later `position`.
+    ///
+    /// Returns whether the block has been queued.
+    fn enqueue(
+        &self,
+        block_number: u32,
+        queue: &str,
+        position: u32,
+        payload: Vec<u8>,
+    ) -> impl Future<Output = Result<bool>> + Send;
+
+    /// Returns the blocks in `queue` with their payloads, in ascending block order.
+    fn get_queue(&self, queue: &str) -> impl Future<Output = Result<Vec<(u32, Vec<u8>)>>> + Send;
 }
@@ -50,6 +50,11 @@ impl SqliteDb {
             Self::create_job_id_table(&pool).await?;
             Self::create_failed_blocks_table(&pool).await?;
             Self::create_state_update_table(&pool).await?;
+            Self::create_queue_items_table(&pool).await?;
+            Self::create_block_hashes_table(&pool).await?;
+            Self::create_block_batches_table(&pool).await?;
+            Self::create_query_records_table(&pool).await?;
+            Self::create_query_attempts_table(&pool).await?;
         } else {
             trace!("Table 'blocks' with correct structure found.");
         }
@@ -159,4 +164,89 @@ impl SqliteDb {
         .await?;
         Ok(())
     }
+
+    pub async fn create_queue_items_table(pool: &Pool<Sqlite>) -> Result<(), Error> {
+        query(
+            r#"
+            CREATE TABLE IF NOT EXISTS queue_items (
+              block_id INTEGER PRIMARY KEY REFERENCES blocks(block_id) ON DELETE CASCADE,
+              queue TEXT NOT NULL,
+              position INTEGER NOT NULL,
+              payload BLOB NOT NULL
+            );
+            "#,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn create_block_hashes_table(pool: &Pool<Sqlite>) -> Result<(), Error> {
+        // Not tied to `blocks`, as the hashes of the last removed block are needed to check the
+        // continuity of the next one.
+        query(
+            r#"
+            CREATE TABLE IF NOT EXISTS block_hashes (
+              block_id INTEGER PRIMARY KEY,
+              block_hash BLOB NOT NULL,
+              parent_hash BLOB NOT NULL
+            );
+            "#,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn create_block_batches_table(pool: &Pool<Sqlite>) -> Result<(), Error> {
+        // Not tied to `blocks`, as a batch whose last block failed is retried as a whole.
+        query(
+            r#"
+            CREATE TABLE IF NOT EXISTS block_batches (
+              first_block INTEGER PRIMARY KEY,
+              last_block INTEGER NOT NULL
+            );
+            "#,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn create_query_records_table(pool: &Pool<Sqlite>) -> Result<(), Error> {
+        // Not tied to `blocks`, as the cost of proving is accounted for after settlement.
+        query(
+            r#"
+            CREATE TABLE IF NOT EXISTS query_records (
+              query_id TEXT PRIMARY KEY,
+              block_id INTEGER NOT NULL,
+              query_type TEXT NOT NULL,
+              job_size TEXT NOT NULL,
+              submitted_at INTEGER NOT NULL,
+              completed_at INTEGER,
+              cost REAL,
+              abandoned INTEGER NOT NULL DEFAULT 0
+            );
+            "#,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn create_query_attempts_table(pool: &Pool<Sqlite>) -> Result<(), Error> {
+        query(
+            r#"
+            CREATE TABLE IF NOT EXISTS query_attempts (
+              block_id INTEGER NOT NULL REFERENCES blocks(block_id) ON DELETE CASCADE,
+              query_type TEXT NOT NULL,
+              attempts INTEGER NOT NULL,
+              PRIMARY KEY (block_id, query_type)
+            );
+            "#,
+        )
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
 }
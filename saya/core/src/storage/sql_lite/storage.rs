@@ -1,8 +1,10 @@
 use super::SqliteDb;
-use crate::storage::{BlockStatus, Query};
-use crate::storage::{PersistantStorage, Step};
+use crate::metrics;
+use crate::storage::{BlockHashes, BlockNotFound, BlockStatus, Query, QueryRecord};
+use crate::storage::{PersistantStorage, QueueStorage, Step};
 use sqlx::query;
 use sqlx::Row;
+use starknet_types_core::felt::Felt;
 
 impl PersistantStorage for SqliteDb {
     async fn add_pie(
@@ -47,6 +49,8 @@ impl PersistantStorage for SqliteDb {
             .execute(&mut *tx)
             .await?;
         tx.commit().await?;
+
+        metrics::record_block_status(&BlockStatus::from(new_status));
         Ok(())
     }
 
@@ -106,6 +110,8 @@ impl PersistantStorage for SqliteDb {
             .execute(&mut *tx)
             .await?;
         tx.commit().await?;
+
+        metrics::record_block_status(&BlockStatus::from(new_status));
         Ok(())
     }
 
@@ -165,12 +171,23 @@ impl PersistantStorage for SqliteDb {
         .execute(&mut *tx)
         .await?;
 
+        query(
+            "INSERT INTO query_attempts (block_id, query_type, attempts) VALUES (?, ?, 1) \
+            ON CONFLICT (block_id, query_type) DO UPDATE SET attempts = attempts + 1;",
+        )
+        .bind(block_number)
+        .bind(query_type.to_string())
+        .execute(&mut *tx)
+        .await?;
+
         query("UPDATE blocks SET status = ? WHERE block_id = ?;")
             .bind(new_status)
             .bind(block_number)
             .execute(&mut *tx)
             .await?;
         tx.commit().await?;
+
+        metrics::record_block_status(&BlockStatus::from(new_status));
         Ok(())
     }
 
@@ -200,20 +217,57 @@ impl PersistantStorage for SqliteDb {
         Ok(query_id)
     }
 
+    async fn add_query_attempt(
+        &self,
+        block_number: u32,
+        query_type: Query,
+    ) -> Result<(), anyhow::Error> {
+        query(
+            "INSERT INTO query_attempts (block_id, query_type, attempts) VALUES (?, ?, 1) \
+            ON CONFLICT (block_id, query_type) DO UPDATE SET attempts = attempts + 1;",
+        )
+        .bind(block_number)
+        .bind(query_type.to_string())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_query_attempts(
+        &self,
+        block_number: u32,
+        query_type: Query,
+    ) -> Result<u32, anyhow::Error> {
+        let row =
+            query("SELECT attempts FROM query_attempts WHERE block_id = ?1 AND query_type = ?2")
+                .bind(block_number)
+                .bind(query_type.to_string())
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(match row {
+            Some(row) => row.try_get(0)?,
+            None => 0,
+        })
+    }
+
     async fn set_status(&self, block_number: u32, status: String) -> Result<(), anyhow::Error> {
         query("UPDATE blocks SET status = ?1 WHERE block_id = ?2")
-            .bind(status)
+            .bind(&status)
             .bind(block_number)
             .execute(&self.pool)
             .await?;
+
+        metrics::record_block_status(&BlockStatus::from(status.as_str()));
         Ok(())
     }
 
     async fn get_status(&self, block_number: u32) -> Result<BlockStatus, anyhow::Error> {
         let row = query("SELECT status FROM blocks WHERE block_id = ?1")
             .bind(block_number)
-            .fetch_one(&self.pool)
-            .await?;
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(BlockNotFound(block_number))?;
 
         let status: String = row.try_get(0)?;
         let status = BlockStatus::from(status.as_str());
@@ -222,19 +276,37 @@ impl PersistantStorage for SqliteDb {
     async fn initialize_block(&self, block_number: u32) -> anyhow::Result<()> {
         let mut tx = self.pool.begin().await?;
 
-        query("INSERT OR IGNORE INTO blocks (block_id, status) VALUES (?1, 'mined')")
-            .bind(block_number)
-            .execute(&mut *tx)
-            .await?;
+        let inserted =
+            query("INSERT OR IGNORE INTO blocks (block_id, status) VALUES (?1, 'mined')")
+                .bind(block_number)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected()
+                > 0;
         tx.commit().await?;
+
+        if inserted {
+            metrics::record_block_status(&BlockStatus::Mined);
+        }
         Ok(())
     }
 
     async fn remove_block(&self, block_number: u32) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
         query("DELETE FROM blocks WHERE block_id = ?1")
             .bind(block_number)
-            .execute(&self.pool)
+            .execute(&mut *tx)
+            .await?;
+        // The hashes of the removed block itself are kept for checking the next block against.
+        query("DELETE FROM block_hashes WHERE block_id < ?1")
+            .bind(block_number)
+            .execute(&mut *tx)
+            .await?;
+        query("DELETE FROM block_batches WHERE last_block <= ?1")
+            .bind(block_number)
+            .execute(&mut *tx)
             .await?;
+        tx.commit().await?;
         Ok(())
     }
 
@@ -271,6 +343,8 @@ impl PersistantStorage for SqliteDb {
             .await?;
 
         tx.commit().await?;
+
+        metrics::record_block_status(&BlockStatus::Failed);
         Ok(())
     }
 
@@ -334,6 +408,202 @@ impl PersistantStorage for SqliteDb {
         let state_update: starknet::core::types::StateUpdate = serde_json::from_slice(&serialized)?;
         Ok(state_update)
     }
+
+    async fn add_block_hashes(&self, block_number: u32, hashes: BlockHashes) -> anyhow::Result<()> {
+        query(
+            "INSERT OR IGNORE INTO block_hashes (block_id, block_hash, parent_hash) VALUES (?, ?, ?);",
+        )
+        .bind(block_number)
+        .bind(hashes.block_hash.to_bytes_be().to_vec())
+        .bind(hashes.parent_hash.to_bytes_be().to_vec())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_block_hashes(&self, block_number: u32) -> anyhow::Result<Option<BlockHashes>> {
+        let row = query("SELECT block_hash, parent_hash FROM block_hashes WHERE block_id = ?1")
+            .bind(block_number)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let block_hash: Vec<u8> = row.try_get(0)?;
+        let parent_hash: Vec<u8> = row.try_get(1)?;
+        Ok(Some(BlockHashes {
+            block_hash: Felt::from_bytes_be_slice(&block_hash),
+            parent_hash: Felt::from_bytes_be_slice(&parent_hash),
+        }))
+    }
+
+    async fn add_block_batch(&self, first_block: u32, last_block: u32) -> anyhow::Result<()> {
+        query("INSERT OR IGNORE INTO block_batches (first_block, last_block) VALUES (?, ?);")
+            .bind(first_block)
+            .bind(last_block)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_block_batch(&self, block_number: u32) -> anyhow::Result<Option<(u32, u32)>> {
+        let row = query(
+            "SELECT first_block, last_block FROM block_batches \
+            WHERE first_block <= ?1 AND last_block >= ?1",
+        )
+        .bind(block_number)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let first_block: u32 = row.try_get(0)?;
+        let last_block: u32 = row.try_get(1)?;
+        Ok(Some((first_block, last_block)))
+    }
+
+    async fn add_query_record(&self, record: QueryRecord) -> anyhow::Result<()> {
+        query(
+            "INSERT OR REPLACE INTO query_records \
+            (query_id, block_id, query_type, job_size, submitted_at, completed_at, cost, \
+            abandoned) VALUES (?, ?, ?, ?, ?, ?, ?, ?);",
+        )
+        .bind(record.query_id)
+        .bind(record.block_number)
+        .bind(record.query_type.to_string())
+        .bind(record.job_size)
+        .bind(record.submitted_at)
+        .bind(record.completed_at)
+        .bind(record.cost)
+        .bind(record.abandoned)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn complete_query_record(
+        &self,
+        query_id: &str,
+        completed_at: i64,
+        cost: Option<f64>,
+    ) -> anyhow::Result<()> {
+        query("UPDATE query_records SET completed_at = ?, cost = ? WHERE query_id = ?;")
+            .bind(completed_at)
+            .bind(cost)
+            .bind(query_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn abandon_query_record(&self, query_id: &str, abandoned_at: i64) -> anyhow::Result<()> {
+        query("UPDATE query_records SET completed_at = ?, abandoned = 1 WHERE query_id = ?;")
+            .bind(abandoned_at)
+            .bind(query_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_query_records(&self, block_number: u32) -> anyhow::Result<Vec<QueryRecord>> {
+        let rows = query(
+            "SELECT query_id, block_id, query_type, job_size, submitted_at, completed_at, cost, \
+            abandoned FROM query_records WHERE block_id = ?1 ORDER BY submitted_at, rowid",
+        )
+        .bind(block_number)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut records = Vec::with_capacity(rows.len());
+        for row in rows {
+            let query_type: String = row.try_get(2)?;
+            records.push(QueryRecord {
+                query_id: row.try_get(0)?,
+                block_number: row.try_get(1)?,
+                query_type: Query::from(query_type.as_str()),
+                job_size: row.try_get(3)?,
+                submitted_at: row.try_get(4)?,
+                completed_at: row.try_get(5)?,
+                cost: row.try_get(6)?,
+                abandoned: row.try_get(7)?,
+            });
+        }
+        Ok(records)
+    }
+
+    async fn get_query_cost_since(&self, since: i64) -> anyhow::Result<f64> {
+        let row =
+            query("SELECT COALESCE(SUM(cost), 0.0) FROM query_records WHERE completed_at >= ?1")
+                .bind(since)
+                .fetch_one(&self.pool)
+                .await?;
+        let cost: f64 = row.try_get(0)?;
+        Ok(cost)
+    }
+
+    async fn get_in_flight_query_cost(&self) -> anyhow::Result<f64> {
+        let row = query(
+            "SELECT COALESCE(SUM(( \
+                SELECT AVG(ended.cost) FROM query_records AS ended \
+                WHERE ended.query_type = in_flight.query_type \
+                AND ended.job_size = in_flight.job_size \
+            )), 0.0) FROM query_records AS in_flight WHERE in_flight.completed_at IS NULL",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        let cost: f64 = row.try_get(0)?;
+        Ok(cost)
+    }
+}
+
+impl QueueStorage for SqliteDb {
+    async fn enqueue(
+        &self,
+        block_number: u32,
+        queue: &str,
+        position: u32,
+        payload: Vec<u8>,
+    ) -> anyhow::Result<bool> {
+        // Blocks only move forward so that a block re-emitted by an upstream stage (e.g. the
+        // ingestor after a restart) doesn't go through the stages it has already passed again.
+        let queued = query(
+            r#"
+            INSERT INTO queue_items (block_id, queue, position, payload) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (block_id) DO UPDATE SET
+                queue = excluded.queue,
+                position = excluded.position,
+                payload = excluded.payload
+            WHERE queue_items.position < excluded.position;
+            "#,
+        )
+        .bind(block_number)
+        .bind(queue)
+        .bind(position)
+        .bind(payload)
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0;
+        Ok(queued)
+    }
+
+    async fn get_queue(&self, queue: &str) -> anyhow::Result<Vec<(u32, Vec<u8>)>> {
+        let rows =
+            query("SELECT block_id, payload FROM queue_items WHERE queue = ?1 ORDER BY block_id")
+                .bind(queue)
+                .fetch_all(&self.pool)
+                .await?;
+
+        let mut items = Vec::with_capacity(rows.len());
+        for row in rows {
+            let block_id: u32 = row.try_get(0)?;
+            let payload: Vec<u8> = row.try_get(1)?;
+            items.push((block_id, payload));
+        }
+        Ok(items)
+    }
 }
 
 #[cfg(test)]
@@ -493,6 +763,35 @@ mod tests {
         assert_eq!(result_bridge, bridge_query_id);
     }
 
+    #[tokio::test]
+    async fn test_query_attempts_are_reset_with_block() {
+        let db = SqliteDb::new(IN_MEMORY_DB).await.unwrap();
+        db.initialize_block(1).await.unwrap();
+        assert_eq!(db.get_query_attempts(1, Query::SnosProof).await.unwrap(), 0);
+
+        for query_id in ["snos_1", "snos_2"] {
+            db.add_query_id(1, query_id.to_string(), Query::SnosProof)
+                .await
+                .unwrap();
+        }
+        db.add_query_id(1, "trace_1".to_string(), Query::BridgeTrace)
+            .await
+            .unwrap();
+        db.add_query_attempt(1, Query::BridgeTrace).await.unwrap();
+        assert_eq!(db.get_query_attempts(1, Query::SnosProof).await.unwrap(), 2);
+        assert_eq!(
+            db.get_query_attempts(1, Query::BridgeTrace).await.unwrap(),
+            2
+        );
+
+        db.add_failed_block(1, "failed".to_string()).await.unwrap();
+        assert_eq!(db.get_query_attempts(1, Query::SnosProof).await.unwrap(), 0);
+        assert_eq!(
+            db.get_query_attempts(1, Query::BridgeTrace).await.unwrap(),
+            0
+        );
+    }
+
     #[tokio::test]
     async fn test_set_and_get_status() {
         let db = SqliteDb::new(IN_MEMORY_DB).await.unwrap();
@@ -654,6 +953,34 @@ mod tests {
         assert_eq!(result2, query_id_2);
     }
 
+    #[tokio::test]
+    async fn test_enqueue_only_moves_blocks_forward() {
+        let db = SqliteDb::new(IN_MEMORY_DB).await.unwrap();
+
+        db.initialize_block(1).await.unwrap();
+        db.initialize_block(2).await.unwrap();
+
+        assert!(db.enqueue(1, "snos_pie", 0, vec![1]).await.unwrap());
+        assert!(db.enqueue(2, "snos_pie", 0, vec![2]).await.unwrap());
+        assert!(db.enqueue(1, "snos_proof", 1, vec![3]).await.unwrap());
+
+        // Re-emitting a block already queued at the same or a later position is a no-op.
+        assert!(!db.enqueue(1, "snos_pie", 0, vec![1]).await.unwrap());
+        assert!(!db.enqueue(2, "snos_pie", 0, vec![2]).await.unwrap());
+
+        assert_eq!(db.get_queue("snos_pie").await.unwrap(), vec![(2, vec![2])]);
+        assert_eq!(
+            db.get_queue("snos_proof").await.unwrap(),
+            vec![(1, vec![3])]
+        );
+
+        // Queued blocks are dropped along with the block.
+        db.remove_block(1).await.unwrap();
+        db.add_failed_block(2, "failed".to_string()).await.unwrap();
+        assert!(db.get_queue("snos_pie").await.unwrap().is_empty());
+        assert!(db.get_queue("snos_proof").await.unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_unique_constraint_enforced_on_state_updates() {
         let db = SqliteDb::new(IN_MEMORY_DB).await.unwrap();
@@ -703,4 +1030,141 @@ mod tests {
         assert_eq!(result2.new_root, state_update_2.new_root);
         assert_eq!(result2.old_root, state_update_2.old_root);
     }
+
+    #[tokio::test]
+    async fn test_block_hashes_outlive_removed_block() {
+        let db = SqliteDb::new(IN_MEMORY_DB).await.unwrap();
+        for block_number in 1..=2 {
+            db.initialize_block(block_number).await.unwrap();
+            let hashes = BlockHashes {
+                block_hash: Felt::from(block_number),
+                parent_hash: Felt::from(block_number - 1),
+            };
+            db.add_block_hashes(block_number, hashes).await.unwrap();
+        }
+
+        // Hashes recorded first are kept.
+        let hashes = BlockHashes {
+            block_hash: Felt::THREE,
+            parent_hash: Felt::ONE,
+        };
+        db.add_block_hashes(2, hashes).await.unwrap();
+        assert_eq!(
+            db.get_block_hashes(2).await.unwrap().unwrap().block_hash,
+            Felt::TWO
+        );
+
+        // Only hashes of blocks before the removed one are pruned.
+        db.remove_block(1).await.unwrap();
+        db.remove_block(2).await.unwrap();
+        assert_eq!(db.get_block_hashes(1).await.unwrap(), None);
+        assert_eq!(
+            db.get_block_hashes(2).await.unwrap(),
+            Some(BlockHashes {
+                block_hash: Felt::TWO,
+                parent_hash: Felt::ONE,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_batches_outlive_failed_blocks() {
+        let db = SqliteDb::new(IN_MEMORY_DB).await.unwrap();
+        for block_number in 1..=3 {
+            db.initialize_block(block_number).await.unwrap();
+        }
+        db.add_block_batch(1, 3).await.unwrap();
+
+        // Batches recorded first are kept.
+        db.add_block_batch(1, 2).await.unwrap();
+        assert_eq!(db.get_block_batch(2).await.unwrap(), Some((1, 3)));
+        assert_eq!(db.get_block_batch(4).await.unwrap(), None);
+
+        db.add_failed_block(3, "failed".to_string()).await.unwrap();
+        assert_eq!(db.get_block_batch(3).await.unwrap(), Some((1, 3)));
+
+        db.remove_block(3).await.unwrap();
+        assert_eq!(db.get_block_batch(1).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_query_records_outlive_removed_block() {
+        let db = SqliteDb::new(IN_MEMORY_DB).await.unwrap();
+        db.initialize_block(1).await.unwrap();
+        for (query_id, submitted_at) in [("query-1", 100), ("query-2", 200)] {
+            db.add_query_record(QueryRecord {
+                query_id: query_id.to_string(),
+                block_number: 1,
+                query_type: Query::SnosProof,
+                job_size: "S".to_string(),
+                submitted_at,
+                completed_at: None,
+                cost: None,
+                abandoned: false,
+            })
+            .await
+            .unwrap();
+        }
+
+        db.complete_query_record("query-1", 150, Some(1.5))
+            .await
+            .unwrap();
+        db.complete_query_record("query-2", 300, Some(2.0))
+            .await
+            .unwrap();
+        db.remove_block(1).await.unwrap();
+
+        let records = db.get_query_records(1).await.unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].query_id, "query-1");
+        assert_eq!(records[0].query_type, Query::SnosProof);
+        assert_eq!(records[0].completed_at, Some(150));
+        assert_eq!(records[1].cost, Some(2.0));
+
+        assert_eq!(db.get_query_cost_since(0).await.unwrap(), 3.5);
+        assert_eq!(db.get_query_cost_since(200).await.unwrap(), 2.0);
+        assert_eq!(db.get_query_cost_since(400).await.unwrap(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_query_cost_is_estimated() {
+        let db = SqliteDb::new(IN_MEMORY_DB).await.unwrap();
+        let queries = [
+            ("ended-1", Query::SnosProof, "S"),
+            ("ended-2", Query::SnosProof, "S"),
+            ("in-flight-1", Query::SnosProof, "S"),
+            ("in-flight-2", Query::SnosProof, "M"),
+            ("abandoned", Query::SnosProof, "S"),
+        ];
+        for (query_id, query_type, job_size) in queries {
+            db.add_query_record(QueryRecord {
+                query_id: query_id.to_string(),
+                block_number: 1,
+                query_type,
+                job_size: job_size.to_string(),
+                submitted_at: 100,
+                completed_at: None,
+                cost: None,
+                abandoned: false,
+            })
+            .await
+            .unwrap();
+        }
+        db.complete_query_record("ended-1", 200, Some(1.0))
+            .await
+            .unwrap();
+        db.complete_query_record("ended-2", 200, Some(3.0))
+            .await
+            .unwrap();
+        db.abandon_query_record("abandoned", 300).await.unwrap();
+
+        // Only the query with ended queries of the same job size has an estimated cost.
+        assert_eq!(db.get_in_flight_query_cost().await.unwrap(), 2.0);
+        assert_eq!(db.get_query_cost_since(0).await.unwrap(), 4.0);
+
+        let records = db.get_query_records(1).await.unwrap();
+        assert!(records[4].abandoned);
+        assert_eq!(records[4].completed_at, Some(300));
+        assert_eq!(records[4].cost, None);
+    }
 }
@@ -12,12 +12,22 @@ impl SqliteDb {
         let job_ids_table = Self::check_ids_table(pool).await?;
         let failed_blocks_table = Self::check_failed_blocks_table(pool).await?;
         let state_updates_table = Self::check_state_updates_table(pool).await?;
+        let queue_items_table = Self::check_queue_items_table(pool).await?;
+        let block_hashes_table = Self::check_block_hashes_table(pool).await?;
+        let block_batches_table = Self::check_block_batches_table(pool).await?;
+        let query_records_table = Self::check_query_records_table(pool).await?;
+        let query_attempts_table = Self::check_query_attempts_table(pool).await?;
         Ok(blocks_table
             && proofs_table
             && pies_table
             && job_ids_table
             && failed_blocks_table
-            && state_updates_table)
+            && state_updates_table
+            && queue_items_table
+            && block_hashes_table
+            && block_batches_table
+            && query_records_table
+            && query_attempts_table)
     }
 
     /// Function to check if the blocks table has the correct columns
@@ -159,6 +169,123 @@ impl SqliteDb {
         Ok(has_id && has_block_id && has_state_update)
     }
 
+    /// Function to check if the queue_items table has the correct columns
+    pub(crate) async fn check_queue_items_table(pool: &Pool<Sqlite>) -> Result<bool, Error> {
+        let columns = sqlx::query("PRAGMA table_info(queue_items);")
+            .fetch_all(pool)
+            .await?;
+        let mut has_block_id = false;
+        let mut has_queue = false;
+        let mut has_position = false;
+        let mut has_payload = false;
+        for column in columns {
+            let name: String = column.get("name");
+            match name.as_str() {
+                "block_id" => has_block_id = true,
+                "queue" => has_queue = true,
+                "position" => has_position = true,
+                "payload" => has_payload = true,
+                _ => {}
+            }
+        }
+        Ok(has_block_id && has_queue && has_position && has_payload)
+    }
+
+    /// Function to check if the block_hashes table has the correct columns
+    pub(crate) async fn check_block_hashes_table(pool: &Pool<Sqlite>) -> Result<bool, Error> {
+        let columns = sqlx::query("PRAGMA table_info(block_hashes);")
+            .fetch_all(pool)
+            .await?;
+        let mut has_block_id = false;
+        let mut has_block_hash = false;
+        let mut has_parent_hash = false;
+        for column in columns {
+            let name: String = column.get("name");
+            match name.as_str() {
+                "block_id" => has_block_id = true,
+                "block_hash" => has_block_hash = true,
+                "parent_hash" => has_parent_hash = true,
+                _ => {}
+            }
+        }
+        Ok(has_block_id && has_block_hash && has_parent_hash)
+    }
+
+    /// Function to check if the block_batches table has the correct columns
+    pub(crate) async fn check_block_batches_table(pool: &Pool<Sqlite>) -> Result<bool, Error> {
+        let columns = sqlx::query("PRAGMA table_info(block_batches);")
+            .fetch_all(pool)
+            .await?;
+        let mut has_first_block = false;
+        let mut has_last_block = false;
+        for column in columns {
+            let name: String = column.get("name");
+            match name.as_str() {
+                "first_block" => has_first_block = true,
+                "last_block" => has_last_block = true,
+                _ => {}
+            }
+        }
+        Ok(has_first_block && has_last_block)
+    }
+
+    /// Function to check if the query_records table has the correct columns
+    pub(crate) async fn check_query_records_table(pool: &Pool<Sqlite>) -> Result<bool, Error> {
+        let columns = sqlx::query("PRAGMA table_info(query_records);")
+            .fetch_all(pool)
+            .await?;
+        let mut has_query_id = false;
+        let mut has_block_id = false;
+        let mut has_query_type = false;
+        let mut has_job_size = false;
+        let mut has_submitted_at = false;
+        let mut has_completed_at = false;
+        let mut has_cost = false;
+        let mut has_abandoned = false;
+        for column in columns {
+            let name: String = column.get("name");
+            match name.as_str() {
+                "query_id" => has_query_id = true,
+                "block_id" => has_block_id = true,
+                "query_type" => has_query_type = true,
+                "job_size" => has_job_size = true,
+                "submitted_at" => has_submitted_at = true,
+                "completed_at" => has_completed_at = true,
+                "cost" => has_cost = true,
+                "abandoned" => has_abandoned = true,
+                _ => {}
+            }
+        }
+        Ok(has_query_id
+            && has_block_id
+            && has_query_type
+            && has_job_size
+            && has_submitted_at
+            && has_completed_at
+            && has_cost
+            && has_abandoned)
+    }
+
+    /// Function to check if the query_attempts table has the correct columns
+    pub(crate) async fn check_query_attempts_table(pool: &Pool<Sqlite>) -> Result<bool, Error> {
+        let columns = sqlx::query("PRAGMA table_info(query_attempts);")
+            .fetch_all(pool)
+            .await?;
+        let mut has_block_id = false;
+        let mut has_query_type = false;
+        let mut has_attempts = false;
+        for column in columns {
+            let name: String = column.get("name");
+            match name.as_str() {
+                "block_id" => has_block_id = true,
+                "query_type" => has_query_type = true,
+                "attempts" => has_attempts = true,
+                _ => {}
+            }
+        }
+        Ok(has_block_id && has_query_type && has_attempts)
+    }
+
     /// Function to check if the tables exist
     pub(crate) async fn check_tables_exist(pool: &Pool<Sqlite>) -> Result<bool, Error> {
         let expected_tables = vec![
@@ -168,6 +295,11 @@ impl SqliteDb {
             "job_ids",
             "failed_blocks",
             "state_updates",
+            "queue_items",
+            "block_hashes",
+            "block_batches",
+            "query_records",
+            "query_attempts",
         ];
         for table in expected_tables {
             let exists =
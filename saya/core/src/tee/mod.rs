@@ -65,4 +65,11 @@ impl HasBlockNumber for TeeAttestation {
             .expect("non-empty attestation batch")
             .number
     }
+
+    fn first_block_number(&self) -> u64 {
+        self.blocks
+            .first()
+            .expect("non-empty attestation batch")
+            .number
+    }
 }
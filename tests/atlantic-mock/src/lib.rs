@@ -0,0 +1,399 @@
+//! A local mock of the [Atlantic](https://docs.herodotus.cloud/atlantic-api/introduction) prover
+//! API, for testing the Atlantic provers without the network.
+//!
+//! Endpoints:
+//! - `POST /atlantic-query?apiKey=<KEY>`: submits a multipart query, answering with its ID.
+//! - `GET /atlantic-query/{id}`: the status of a query, along with its metadata URLs once done
+//!   and its credits once ended.
+//! - `GET /queries/{id}/{file}`: the `proof.json` and `pie.cairo0.zip` metadata of a done query.
+//!
+//! Queries go through `RECEIVED` and `IN_PROGRESS` before ending as configured by the
+//! [`QueryOutcome`]s given to the mock, advancing each time their status is polled.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use axum::{
+    extract::{Multipart, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{net::TcpListener, task::JoinHandle};
+use url::Url;
+
+/// A mock Atlantic server listening on a local port, stopped once dropped.
+#[derive(Debug)]
+pub struct MockAtlantic {
+    url: Url,
+    state: Arc<Mutex<MockState>>,
+    server: JoinHandle<()>,
+}
+
+/// How a query submitted to the mock ends.
+#[derive(Debug, Clone)]
+pub enum QueryOutcome {
+    /// The query is done, serving `proof` and `pie` as its metadata.
+    Done { proof: String, pie: Vec<u8> },
+    /// The query fails.
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum QueryStatus {
+    Received,
+    InProgress,
+    Done,
+    Failed,
+}
+
+/// A query submitted to the mock.
+#[derive(Debug, Clone)]
+pub struct SubmittedQuery {
+    pub id: String,
+    /// The text fields of the submission, e.g. `layout`, `externalId` or `declaredJobSize`.
+    pub fields: HashMap<String, String>,
+    /// The files of the submission, e.g. `pieFile`, `programFile` or `inputFile`.
+    pub files: HashMap<String, Vec<u8>>,
+    pub outcome: QueryOutcome,
+    /// Number of times the status of the query has been polled.
+    pub polls: usize,
+}
+
+#[derive(Debug)]
+struct MockState {
+    api_key: String,
+    next_id: usize,
+    queries: Vec<SubmittedQuery>,
+    outcomes: VecDeque<QueryOutcome>,
+    default_outcome: QueryOutcome,
+    status_polls: usize,
+    failing_submissions: usize,
+    credits_used: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubmissionParams {
+    api_key: Option<String>,
+}
+
+impl MockAtlantic {
+    /// Starts a mock accepting submissions authenticated with `api_key`.
+    ///
+    /// Queries are done with an empty proof and PIE unless configured otherwise.
+    pub async fn start(api_key: impl Into<String>) -> Result<Self> {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+
+        let state = Arc::new(Mutex::new(MockState {
+            api_key: api_key.into(),
+            next_id: 0,
+            queries: vec![],
+            outcomes: VecDeque::new(),
+            default_outcome: QueryOutcome::Done {
+                proof: String::new(),
+                pie: vec![],
+            },
+            status_polls: 0,
+            failing_submissions: 0,
+            credits_used: None,
+        }));
+
+        let app = Router::new()
+            .route("/atlantic-query", post(submit_query))
+            .route("/atlantic-query/{id}", get(get_query))
+            .route("/queries/{id}/{file}", get(get_metadata))
+            .with_state(Shared {
+                url: url.clone(),
+                state: state.clone(),
+            });
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Ok(Self { url, state, server })
+    }
+
+    /// The URL to use as the Atlantic API base.
+    pub fn url(&self) -> Url {
+        self.url.clone()
+    }
+
+    /// Queues the outcome of the next submitted query. Queries submitted once all queued outcomes
+    /// have been used end with the default outcome.
+    pub fn push_outcome(&self, outcome: QueryOutcome) {
+        self.lock().outcomes.push_back(outcome);
+    }
+
+    pub fn set_default_outcome(&self, outcome: QueryOutcome) {
+        self.lock().default_outcome = outcome;
+    }
+
+    /// Sets the number of polls a query spends in each of the `RECEIVED` and `IN_PROGRESS`
+    /// statuses before ending, 0 by default.
+    pub fn set_status_polls(&self, status_polls: usize) {
+        self.lock().status_polls = status_polls;
+    }
+
+    /// Makes the next `count` submissions fail with an internal server error.
+    pub fn fail_next_submissions(&self, count: usize) {
+        self.lock().failing_submissions = count;
+    }
+
+    /// Sets the credits reported as used by queries once they ended, not reported by default.
+    pub fn set_credits_used(&self, credits_used: f64) {
+        self.lock().credits_used = Some(credits_used);
+    }
+
+    /// Registers a query as if it had been submitted before, e.g. by a previous run whose query
+    /// ID has been persisted, returning its ID.
+    pub fn insert_query(&self, outcome: QueryOutcome) -> String {
+        self.lock()
+            .add_query(HashMap::new(), HashMap::new(), outcome)
+    }
+
+    /// The queries submitted so far, in order.
+    pub fn queries(&self) -> Vec<SubmittedQuery> {
+        self.lock().queries.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+}
+
+impl Drop for MockAtlantic {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+impl MockState {
+    fn add_query(
+        &mut self,
+        fields: HashMap<String, String>,
+        files: HashMap<String, Vec<u8>>,
+        outcome: QueryOutcome,
+    ) -> String {
+        let id = format!("mock-query-{}", self.next_id);
+        self.next_id += 1;
+        self.queries.push(SubmittedQuery {
+            id: id.clone(),
+            fields,
+            files,
+            outcome,
+            polls: 0,
+        });
+        id
+    }
+}
+
+impl SubmittedQuery {
+    fn status(&self, status_polls: usize) -> QueryStatus {
+        if self.polls < status_polls {
+            QueryStatus::Received
+        } else if self.polls < 2 * status_polls {
+            QueryStatus::InProgress
+        } else {
+            match self.outcome {
+                QueryOutcome::Done { .. } => QueryStatus::Done,
+                QueryOutcome::Failed => QueryStatus::Failed,
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Shared {
+    url: Url,
+    state: Arc<Mutex<MockState>>,
+}
+
+async fn submit_query(
+    State(shared): State<Shared>,
+    Query(params): Query<SubmissionParams>,
+    mut multipart: Multipart,
+) -> Response {
+    let mut fields = HashMap::new();
+    let mut files = HashMap::new();
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => return err.into_response(),
+        };
+        let name = field.name().unwrap_or_default().to_string();
+        let is_file = field.file_name().is_some();
+        let data = match field.bytes().await {
+            Ok(data) => data.to_vec(),
+            Err(err) => return err.into_response(),
+        };
+
+        if is_file {
+            files.insert(name, data);
+        } else {
+            fields.insert(name, String::from_utf8_lossy(&data).into_owned());
+        }
+    }
+
+    let mut state = shared.state.lock().unwrap();
+    if params.api_key.as_deref() != Some(state.api_key.as_str()) {
+        return (StatusCode::UNAUTHORIZED, "invalid API key").into_response();
+    }
+    if state.failing_submissions > 0 {
+        state.failing_submissions -= 1;
+        return (StatusCode::INTERNAL_SERVER_ERROR, "submission failed").into_response();
+    }
+
+    let outcome = state
+        .outcomes
+        .pop_front()
+        .unwrap_or_else(|| state.default_outcome.clone());
+    let id = state.add_query(fields, files, outcome);
+
+    Json(json!({ "atlanticQueryId": id })).into_response()
+}
+
+async fn get_query(State(shared): State<Shared>, Path(id): Path<String>) -> Response {
+    let mut state = shared.state.lock().unwrap();
+    let status_polls = state.status_polls;
+    let Some(query) = state.queries.iter_mut().find(|query| query.id == id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let status = query.status(status_polls);
+    query.polls += 1;
+
+    let metadata_urls: Vec<String> = match status {
+        QueryStatus::Done => ["proof.json", "pie.cairo0.zip"]
+            .iter()
+            .map(|file| format!("{}queries/{}/{}", shared.url, id, file))
+            .collect(),
+        _ => vec![],
+    };
+    let credits_used = match status {
+        QueryStatus::Done | QueryStatus::Failed => state.credits_used,
+        QueryStatus::Received | QueryStatus::InProgress => None,
+    };
+
+    Json(json!({
+        "atlanticQuery": { "id": id, "status": status, "creditsUsed": credits_used },
+        "metadataUrls": metadata_urls,
+    }))
+    .into_response()
+}
+
+async fn get_metadata(
+    State(shared): State<Shared>,
+    Path((id, file)): Path<(String, String)>,
+) -> Response {
+    let state = shared.state.lock().unwrap();
+    let status_polls = state.status_polls;
+    let query = match state.queries.iter().find(|query| query.id == id) {
+        Some(query) if query.status(status_polls) == QueryStatus::Done => query,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let QueryOutcome::Done { proof, pie } = &query.outcome else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match file.as_str() {
+        "proof.json" => proof.clone().into_response(),
+        "pie.cairo0.zip" => pie.clone().into_response(),
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::multipart::{Form, Part};
+    use serde_json::Value;
+
+    use super::*;
+
+    async fn submit(mock: &MockAtlantic, api_key: &str) -> reqwest::Response {
+        let mut url = mock.url().join("atlantic-query").unwrap();
+        url.query_pairs_mut().append_pair("apiKey", api_key);
+        let form = Form::new()
+            .part("pieFile", Part::bytes(vec![1, 2, 3]).file_name("pie.zip"))
+            .text("layout", "dynamic")
+            .text("externalId", "snos-1");
+
+        reqwest::Client::new()
+            .post(url)
+            .multipart(form)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    async fn poll(mock: &MockAtlantic, id: &str) -> Value {
+        reqwest::get(mock.url().join(&format!("atlantic-query/{id}")).unwrap())
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_query_goes_through_statuses_and_serves_proof() {
+        let mock = MockAtlantic::start("key").await.unwrap();
+        mock.set_status_polls(1);
+        mock.set_credits_used(2.5);
+        mock.push_outcome(QueryOutcome::Done {
+            proof: "{\"proof\":1}".to_string(),
+            pie: vec![4, 5],
+        });
+
+        let response: Value = submit(&mock, "key").await.json().await.unwrap();
+        let id = response["atlanticQueryId"].as_str().unwrap();
+
+        let query = &mock.queries()[0];
+        assert_eq!(query.fields["layout"], "dynamic");
+        assert_eq!(query.fields["externalId"], "snos-1");
+        assert_eq!(query.files["pieFile"], vec![1, 2, 3]);
+
+        let received = poll(&mock, id).await;
+        assert_eq!(received["atlanticQuery"]["status"], "RECEIVED");
+        assert!(received["atlanticQuery"]["creditsUsed"].is_null());
+        assert_eq!(
+            poll(&mock, id).await["atlanticQuery"]["status"],
+            "IN_PROGRESS"
+        );
+        let done = poll(&mock, id).await;
+        assert_eq!(done["atlanticQuery"]["status"], "DONE");
+        assert_eq!(done["atlanticQuery"]["creditsUsed"], 2.5);
+
+        let metadata_urls = done["metadataUrls"].as_array().unwrap();
+        let proof_url = metadata_urls[0].as_str().unwrap();
+        assert!(proof_url.ends_with("proof.json"));
+        let proof = reqwest::get(proof_url).await.unwrap().text().await.unwrap();
+        assert_eq!(proof, "{\"proof\":1}");
+    }
+
+    #[tokio::test]
+    async fn test_failed_and_rejected_submissions() {
+        let mock = MockAtlantic::start("key").await.unwrap();
+        mock.push_outcome(QueryOutcome::Failed);
+        mock.fail_next_submissions(1);
+
+        assert_eq!(submit(&mock, "other").await.status(), 401);
+        assert_eq!(submit(&mock, "key").await.status(), 500);
+
+        let response: Value = submit(&mock, "key").await.json().await.unwrap();
+        let id = response["atlanticQueryId"].as_str().unwrap();
+        let failed = poll(&mock, id).await;
+        assert_eq!(failed["atlanticQuery"]["status"], "FAILED");
+        assert!(failed["metadataUrls"].as_array().unwrap().is_empty());
+    }
+}